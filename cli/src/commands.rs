@@ -0,0 +1,554 @@
+///
+/// Runs the subcommands of the command line tool.
+///
+use std::{ env, io::{ stdin, IsTerminal, Read } };
+use zeroize::Zeroizing;
+
+use secure_notes_core::{
+    types::{
+        vault_index::VaultIndex, vault_index_entry::VaultIndexEntry,
+        note_metadata::NoteMetadata, vault_error::VaultError,
+    },
+    utils::{
+        get_default_vault_name, create_default_vault_file, unix_now,
+        crypto::{ SecretString, VaultKey },
+        export::export_notes,
+        git::{
+            enable_git, set_git_remote, clone_vault, commit_vault, git_sync, history,
+            read_index_at, read_note_at,
+        },
+        import::{
+            TextFormat, enex::import_enex, standard_notes::import_standard_notes,
+            simplenote::import_simplenote, files::import_files,
+        },
+        notes::{ read_index, read_note, save_note },
+        remote::{
+            Remote, WebDav, S3, sync_vault, pending_changes,
+            s3::S3Credentials,
+        },
+        storage::{ FileStorage, VaultStorage },
+        sync::reconcile,
+        vault::{ check_vault_name, create_vault, get_vault_storage, list_vaults, unlock_vault },
+    },
+};
+
+use crate::{
+    Cli, Command, VaultCommand, NoteCommand, GitCommand, ImportFormat, RemoteArgs,
+    lan,
+    edit::edit_text,
+    password::{ read_password, read_new_password },
+};
+
+/// Environment variable with the password for the WebDAV server.
+const REMOTE_PASSWORD_ENV: &str = "SECURE_NOTES_REMOTE_PASSWORD";
+
+/// Environment variables with the keys of the S3 bucket, the same as the AWS
+/// tools'.
+const ACCESS_KEY_ENV: &str = "AWS_ACCESS_KEY_ID";
+const SECRET_KEY_ENV: &str = "AWS_SECRET_ACCESS_KEY";
+const SESSION_TOKEN_ENV: &str = "AWS_SESSION_TOKEN";
+
+/// An unlocked vault.
+struct OpenVault {
+    storage: FileStorage,
+    key: VaultKey,
+}
+
+pub fn run(cli: &Cli) -> Result<(), String> {
+    match &cli.command {
+        Command::Vault(VaultCommand::Create { name }) => create(cli, name),
+        Command::Vault(VaultCommand::List) => list(),
+        Command::Note(NoteCommand::Ls) => note_ls(&open_vault(cli)?),
+        Command::Note(NoteCommand::Cat { note, at }) => {
+            note_cat(&open_vault(cli)?, note, at.as_deref())
+        }
+
+        Command::Note(NoteCommand::Add { name, folder }) => {
+            note_add(&open_vault(cli)?, name, folder.as_deref())
+        }
+
+        Command::Note(NoteCommand::Edit { note }) => note_edit(&open_vault(cli)?, note),
+
+        Command::Export { dir } => {
+            let vault = open_vault(cli)?;
+            let count = export_notes(&vault.storage, &vault.key, dir)?;
+
+            println!("Exported {} notes to {}", count, dir.display());
+
+            Ok(())
+        }
+
+        Command::Import { from, plain_text, path } => {
+            let format = match from {
+                Some(f) => *f,
+                None if path.is_dir() => ImportFormat::Files,
+                None if path.extension().is_some_and(|e| e == "enex") => ImportFormat::Enex,
+                None => {
+                    return Err(String::from(
+                        "Couldn't tell the format of the export, please pass --from"
+                    ));
+                }
+            };
+
+            let text_format = match plain_text {
+                true => TextFormat::PlainText,
+                false => TextFormat::Markdown,
+            };
+
+            let vault = open_vault(cli)?;
+
+            let report = match format {
+                ImportFormat::Enex => import_enex(&vault.storage, &vault.key, path, text_format)?,
+                ImportFormat::StandardNotes => import_standard_notes(&vault.storage, &vault.key, path)?,
+                ImportFormat::Simplenote => import_simplenote(&vault.storage, &vault.key, path)?,
+                ImportFormat::Files => import_files(&vault.storage, &vault.key, path)?,
+            };
+
+            commit_vault(&vault.storage, "Import notes")?;
+            println!("{}", report);
+
+            Ok(())
+        }
+
+        Command::Sync { remote } => sync(cli, remote),
+        Command::Gc { remote, days, list } => gc(remote, *days, *list),
+        Command::Git(command) => git(cli, command),
+        Command::Lan(command) => lan::run(cli, command),
+    }
+}
+
+fn create(cli: &Cli, name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err(String::from("Vault name cannot be empty."));
+    }
+
+    check_vault_name(name)?;
+
+    let password = read_new_password(cli.password_fd)?;
+    let storage = get_vault_storage(name)?;
+    let phrase = create_vault(
+        &storage,
+        name.to_string(),
+        &password,
+        cli.keyfile.as_deref(),
+    )?;
+
+    // The first vault is opened when no vault is given
+    if get_default_vault_name().is_err() {
+        create_default_vault_file(name)?;
+    }
+
+    println!("Created vault \"{}\".", name);
+    println!();
+    println!("Recovery phrase, write it down and keep it somewhere safe:");
+    println!();
+    println!("{}", phrase.as_str());
+
+    Ok(())
+}
+
+fn list() -> Result<(), String> {
+    let default_vault = get_default_vault_name().ok();
+
+    for name in list_vaults()? {
+        match default_vault.as_deref() == Some(name.as_str()) {
+            true => println!("* {}", name),
+            false => println!("  {}", name),
+        }
+    }
+
+    Ok(())
+}
+
+/// Name of the vault given with `--vault`, or of the default vault.
+pub(crate) fn vault_name(cli: &Cli) -> Result<String, String> {
+    match &cli.vault {
+        Some(name) => {
+            check_vault_name(name)?;
+            Ok(name.clone())
+        }
+
+        None => match get_default_vault_name() {
+            Ok(name) => Ok(name),
+            Err(_) => Err(String::from("There is no default vault, please pass --vault")),
+        },
+    }
+}
+
+/// Unlocks the vault given with `--vault`, or the default vault.
+fn open_vault(cli: &Cli) -> Result<OpenVault, String> {
+    let name = vault_name(cli)?;
+    let storage = get_vault_storage(&name)?;
+
+    let password = read_password(&format!("Password for \"{}\": ", name), cli.password_fd)?;
+    let key = unlock_vault(&storage, &name, &password, cli.keyfile.as_deref())?;
+
+    // Merge what a sync tool left in the vault before reading it
+    let report = reconcile(&storage, &key)?;
+
+    if report.merged_indexes > 0 || report.merged_notes > 0 {
+        commit_vault(&storage, "Merge conflict copies")?;
+    }
+
+    for conflict in report.conflicts {
+        eprintln!(
+            "warning: \"{}\" was edited on two devices, open the vault in the app to choose \
+            the version to keep",
+            conflict.name,
+        );
+    }
+
+    Ok(OpenVault { storage, key })
+}
+
+/// Mirrors the vault to the server, the vault stays locked.
+fn sync(cli: &Cli, args: &RemoteArgs) -> Result<(), String> {
+    let name = vault_name(cli)?;
+
+    // A vault that isn't on this device yet is downloaded
+    let storage = get_vault_storage(&name)?;
+
+    let remote: Box<dyn Remote> = match args.url.starts_with("s3://") {
+        true => Box::new(connect_s3(args)?),
+        false => Box::new(connect_webdav(cli, args)?),
+    };
+
+    match sync_vault(&name, &storage, remote.as_ref()) {
+        Ok(report) => {
+            // Like a created vault, the first vault is opened when no vault is
+            // given
+            if storage.exists("info") && get_default_vault_name().is_err() {
+                create_default_vault_file(&name)?;
+            }
+
+            println!("{}", report);
+
+            if !report.conflicts.is_empty() {
+                eprintln!(
+                    "warning: some files were changed both here and on the server, open the \
+                    vault in the app to choose the versions to keep"
+                );
+            }
+
+            Ok(())
+        }
+
+        // Nothing is lost, the next sync sends the changes
+        Err(VaultError::Remote(e)) if e.is_transient() => {
+            let pending = pending_changes(&name, &storage)?;
+
+            Err(format!("{}\nChanges queued for the next sync: {}", e, pending.len()))
+        }
+
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Runs the `git` subcommands.
+fn git(cli: &Cli, command: &GitCommand) -> Result<(), String> {
+    match command {
+        GitCommand::Init { remote } => {
+            let name = vault_name(cli)?;
+            let storage = get_vault_storage(&name)?;
+
+            if !storage.exists("info") {
+                return Err(format!("Vault \"{}\" not found", name));
+            }
+
+            enable_git(&storage)?;
+
+            if let Some(url) = remote {
+                set_git_remote(&storage, url)?;
+            }
+
+            println!("The history of \"{}\" is kept in git, every change is committed.", name);
+            println!(
+                "Removed and changed passwords still unlock the vault through its history, \
+                keep the history private."
+            );
+
+            Ok(())
+        }
+
+        GitCommand::Remote { url } => {
+            set_git_remote(&get_vault_storage(&vault_name(cli)?)?, url)?;
+
+            Ok(())
+        }
+
+        GitCommand::Clone { url, name } => {
+            check_vault_name(name)?;
+            clone_vault(&get_vault_storage(name)?, url)?;
+
+            // Like a created vault, the first vault is opened when no vault is
+            // given
+            if get_default_vault_name().is_err() {
+                create_default_vault_file(name)?;
+            }
+
+            println!("Downloaded vault \"{}\".", name);
+
+            Ok(())
+        }
+
+        GitCommand::Sync => {
+            let vault = open_vault(cli)?;
+            let report = git_sync(&vault.storage, &vault.key)?;
+
+            println!("{}", report);
+
+            if !report.conflicts.is_empty() {
+                eprintln!(
+                    "warning: some notes were changed both here and on the remote, open the \
+                    vault in the app to choose the versions to keep"
+                );
+            }
+
+            Ok(())
+        }
+
+        GitCommand::Log => {
+            for entry in history(&get_vault_storage(&vault_name(cli)?)?)? {
+                println!("{:.8}\t{}", entry.id, entry.message);
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Lists the deleted notes kept in the S3 bucket and removes the ones deleted
+/// more than `days` days ago, unless `list_only`.
+fn gc(args: &RemoteArgs, days: u64, list_only: bool) -> Result<(), String> {
+    if !args.url.starts_with("s3://") {
+        return Err(String::from("Only S3 buckets keep the deleted notes"));
+    }
+
+    let remote = connect_s3(args)?;
+
+    if list_only {
+        let now = unix_now();
+
+        for file in remote.list_deleted().map_err(|e| e.to_string())? {
+            println!("{}\tdeleted {} days ago", file.path, now.saturating_sub(file.deleted) / 86400);
+        }
+
+        return Ok(());
+    }
+
+    let report = remote.collect_garbage(days * 86400).map_err(|e| e.to_string())?;
+
+    println!("{}", report);
+
+    Ok(())
+}
+
+/// Connects to the WebDAV server, asking for the password if a user is given.
+fn connect_webdav(cli: &Cli, args: &RemoteArgs) -> Result<WebDav, String> {
+    let password = match ( &args.user, env::var(REMOTE_PASSWORD_ENV) ) {
+        ( Some(_), Ok(password) ) => Some(SecretString::new(password)),
+
+        ( Some(user), Err(_) ) => Some(read_password(
+            &format!("Password for \"{}\" on the server: ", user),
+            cli.password_fd,
+        )?),
+
+        ( None, _ ) => None,
+    };
+
+    Ok(WebDav::new(&args.url, args.user.as_deref(), password.as_ref())?)
+}
+
+/// Connects to the bucket of an "s3://<bucket>/<prefix>" URL, with the keys
+/// from the environment.
+fn connect_s3(args: &RemoteArgs) -> Result<S3, String> {
+    let ( bucket, prefix ) = match args.url.strip_prefix("s3://") {
+        Some(path) => path.split_once('/').unwrap_or(( path, "" )),
+        None => { return Err(String::from("Not an s3:// URL")); }
+    };
+
+    let ( access_key, secret_key ) = match ( env::var(ACCESS_KEY_ENV), env::var(SECRET_KEY_ENV) ) {
+        ( Ok(access_key), Ok(secret_key) ) => ( access_key, SecretString::new(secret_key) ),
+        _ => {
+            return Err(format!(
+                "Please set {} and {} to the keys of the bucket", ACCESS_KEY_ENV, SECRET_KEY_ENV,
+            ));
+        }
+    };
+
+    let credentials = S3Credentials {
+        access_key,
+        secret_key,
+        session_token: env::var(SESSION_TOKEN_ENV).ok().map(SecretString::new),
+    };
+
+    Ok(S3::new(&args.endpoint, &args.region, bucket, prefix, credentials)?)
+}
+
+/// Path of the note inside the vault, e.g. "Work/Ideas/Note".
+fn note_path(index: &VaultIndex, note: &VaultIndexEntry) -> String {
+    let mut path = index.folder_path(note.parent_folder);
+    path.push(note.name.as_str());
+
+    path.join("/")
+}
+
+/// Finds a note by its id, its path or its name.
+fn find_note(index: &VaultIndex, query: &str) -> Result<VaultIndexEntry, String> {
+    if let Ok(id) = query.parse::<u32>() {
+        if let Some(note) = index.notes.iter().find(|n| n.id == id) {
+            return Ok(note.clone());
+        }
+    }
+
+    let mut matches = index.notes.iter()
+        .filter(|n| note_path(index, n) == query)
+        .collect::<Vec<_>>();
+
+    if matches.is_empty() {
+        matches = index.notes.iter()
+            .filter(|n| n.name == query)
+            .collect();
+    }
+
+    match matches.as_slice() {
+        [] => Err(format!("Note \"{}\" not found", query)),
+        [note] => Ok((*note).clone()),
+        notes => {
+            let ids = notes.iter()
+                .map(|n| n.id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            Err(format!("There are several notes named \"{}\", use one of the ids: {}", query, ids))
+        }
+    }
+}
+
+/// Finds a folder by its path, e.g. "Work/Ideas".
+fn find_folder(index: &VaultIndex, path: &str) -> Result<u32, String> {
+    let mut parent = None;
+
+    for name in path.split('/').filter(|n| !n.is_empty()) {
+        match index.find_folder(name, parent) {
+            Some(folder) => { parent = Some(folder.id); }
+            None => { return Err(format!("Folder \"{}\" not found", path)); }
+        }
+    }
+
+    match parent {
+        Some(id) => Ok(id),
+        None => Err(String::from("Folder path cannot be empty")),
+    }
+}
+
+fn note_ls(vault: &OpenVault) -> Result<(), String> {
+    let index = read_index(&vault.storage, &vault.key)?;
+
+    let mut notes = index.notes.iter()
+        .map(|n| ( n.id, note_path(&index, n) ))
+        .collect::<Vec<_>>();
+
+    notes.sort_by(|a, b| a.1.cmp(&b.1));
+
+    for ( id, path ) in notes {
+        println!("{}\t{}", id, path);
+    }
+
+    Ok(())
+}
+
+fn note_cat(vault: &OpenVault, query: &str, revision: Option<&str>) -> Result<(), String> {
+    let text = match revision {
+        Some(revision) => {
+            let index = read_index_at(&vault.storage, &vault.key, revision)?;
+            let note = find_note(&index, query)?;
+
+            Zeroizing::new(read_note_at(&vault.storage, &vault.key, revision, note.id)?)
+        }
+
+        None => {
+            let index = read_index(&vault.storage, &vault.key)?;
+            let note = find_note(&index, query)?;
+
+            Zeroizing::new(read_note(&vault.storage, &vault.key, note.id)?)
+        }
+    };
+
+    print!("{}", text.as_str());
+
+    if !text.ends_with('\n') {
+        println!();
+    }
+
+    Ok(())
+}
+
+fn note_add(vault: &OpenVault, name: &str, folder: Option<&str>) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err(String::from("Note name cannot be empty."));
+    }
+
+    let index = read_index(&vault.storage, &vault.key)?;
+
+    let parent_folder = match folder {
+        Some(path) => Some(find_folder(&index, path)?),
+        None => None,
+    };
+
+    let text = match stdin().is_terminal() {
+        true => {
+            let text = edit_text("")?;
+
+            if text.trim().is_empty() {
+                return Err(String::from("Note is empty, nothing was added"));
+            }
+
+            text
+        }
+
+        false => {
+            let mut text = Zeroizing::new(String::new());
+
+            if let Err(e) = stdin().read_to_string(&mut text) {
+                return Err(format!("Couldn't read the note from stdin: {}", e));
+            }
+
+            SecretString::new(std::mem::take(&mut *text))
+        }
+    };
+
+    let mut entry = VaultIndexEntry {
+        id: 0,
+        name: name.to_string(),
+        parent_folder,
+        metadata: NoteMetadata::default(),
+    };
+
+    save_note(&vault.storage, &vault.key, &mut entry, &text)?;
+    commit_vault(&vault.storage, &format!("Save note {}", entry.id))?;
+
+    println!("{}", entry.id);
+
+    Ok(())
+}
+
+fn note_edit(vault: &OpenVault, query: &str) -> Result<(), String> {
+    let index = read_index(&vault.storage, &vault.key)?;
+    let mut note = find_note(&index, query)?;
+    let text = Zeroizing::new(read_note(&vault.storage, &vault.key, note.id)?);
+
+    let new_text = edit_text(&text)?;
+
+    if *new_text == *text {
+        println!("No changes");
+        return Ok(());
+    }
+
+    save_note(&vault.storage, &vault.key, &mut note, &new_text)?;
+    commit_vault(&vault.storage, &format!("Save note {}", note.id))?;
+
+    println!("Saved \"{}\"", note_path(&index, &note));
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+///
+/// Editing notes in the user's `$EDITOR`.
+///
+/// The editor can only work on a plain file, so the note is written to a
+/// temporary file that only the user can read. The file is overwritten with
+/// zeros before it's removed, so the note doesn't stay on the disk.
+///
+use std::{
+    env, fs::{ self, OpenOptions }, io::{ Seek, Write },
+    path::{ Path, PathBuf }, process,
+};
+
+use secure_notes_core::utils::crypto::{ SecretString, generate_salt };
+use tracing::warn;
+
+/// Opens `text` in the editor and returns the edited text.
+pub fn edit_text(text: &str) -> Result<SecretString, String> {
+    let dir = create_private_dir()?;
+    let mut path = dir.clone();
+    path.push("note.md");
+
+    let result = write_file(&path, text)
+        .and_then(|_| run_editor(&path))
+        .and_then(|_| match fs::read_to_string(&path) {
+            Ok(text) => Ok(SecretString::new(text)),
+            Err(e) => Err(format!("Couldn't read the edited note: {}", e)),
+        });
+
+    // Editors may leave backup files next to the note
+    if let Ok(entries) = fs::read_dir(&dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            wipe_file(&entry.path());
+        }
+    }
+
+    if let Err(e) = fs::remove_dir(&dir) {
+        warn!(path = %dir.display(), error = %e, "could not remove the temporary directory");
+    }
+
+    result
+}
+
+/// Creates a directory inside the temporary directory that only the user can
+/// access.
+fn create_private_dir() -> Result<PathBuf, String> {
+    let suffix = generate_salt().iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+
+    let mut dir = env::temp_dir();
+    dir.push(format!("secure-notes-{}", suffix));
+
+    let mut builder = fs::DirBuilder::new();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+
+    match builder.create(&dir) {
+        Ok(()) => Ok(dir),
+        Err(e) => Err(format!("Couldn't create temporary directory: {}", e)),
+    }
+}
+
+fn write_file(path: &Path, text: &str) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    match options.open(path).and_then(|mut f| f.write_all(text.as_bytes())) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("Couldn't write temporary file: {}", e)),
+    }
+}
+
+/// Runs `$VISUAL` or `$EDITOR` on the file and waits for it to exit.
+fn run_editor(path: &Path) -> Result<(), String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from(if cfg!(windows) { "notepad" } else { "vi" }));
+
+    // Editors are often given with arguments, e.g. "code --wait"
+    let mut parts = editor.split_whitespace();
+
+    let program = match parts.next() {
+        Some(p) => p,
+        None => { return Err(String::from("$EDITOR is empty")); }
+    };
+
+    match process::Command::new(program).args(parts).arg(path).status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{} exited with {}", program, status)),
+        Err(e) => Err(format!("Couldn't run {}: {}", program, e)),
+    }
+}
+
+/// Overwrites the file with zeros and removes it.
+///
+/// Editors may replace the file instead of writing into it, so whatever is
+/// at `path` when the editor exits is wiped.
+fn wipe_file(path: &Path) {
+    if let Ok(mut file) = OpenOptions::new().write(true).open(path) {
+        let len = file.metadata().map_or(0, |m| m.len());
+
+        let wiped = file.rewind()
+            .and_then(|_| file.write_all(&vec![0u8; len as usize]))
+            .and_then(|_| file.sync_all());
+
+        if let Err(e) = wiped {
+            warn!(path = %path.display(), error = %e, "could not wipe the temporary file");
+        }
+    }
+
+    if let Err(e) = fs::remove_file(path) {
+        warn!(path = %path.display(), error = %e, "could not remove the temporary file");
+    }
+}
@@ -0,0 +1,180 @@
+///
+/// Runs the `lan` subcommands: syncing a vault directly with another device on
+/// the local network.
+///
+use std::{
+    io::{ stdin, stdout, Write },
+    net::{ Ipv4Addr, SocketAddr },
+    sync::{ Arc, atomic::AtomicBool },
+    time::Duration,
+};
+
+use secure_notes_core::utils::{
+    get_default_vault_name, create_default_vault_file,
+    remote::lan::{
+        LanIdentity, LanPeer, LanServer, read_peers, unpair, sync_with_peer,
+        discovery::{ Announcement, discover },
+    },
+    storage::VaultStorage,
+    vault::get_vault_storage,
+};
+
+use crate::{ Cli, LanCommand, commands::vault_name };
+
+/// Time to listen for the devices before syncing with one.
+const DISCOVERY_TIME: Duration = Duration::from_secs(3);
+
+pub fn run(cli: &Cli, command: &LanCommand) -> Result<(), String> {
+    match command {
+        LanCommand::Serve { port, pair } => serve(cli, *port, *pair),
+
+        LanCommand::Discover { seconds } => {
+            let identity = LanIdentity::load()?;
+            let peers = read_peers();
+
+            for device in discover(Duration::from_secs(*seconds), &identity.fingerprint())? {
+                let paired = peers.iter().any(|p| p.fingerprint() == device.fingerprint);
+
+                println!(
+                    "{}\t{}\t{}{}",
+                    device.fingerprint,
+                    device.peer_address().map(|a| a.to_string()).unwrap_or_default(),
+                    device.name,
+                    if paired { "" } else { " (not paired)" },
+                );
+            }
+
+            Ok(())
+        }
+
+        LanCommand::Sync { peer, pair } => sync(cli, *peer, *pair),
+
+        LanCommand::Peers => {
+            for peer in read_peers() {
+                println!("{}\t{}", peer.fingerprint(), peer.name);
+            }
+
+            Ok(())
+        }
+
+        LanCommand::Unpair { fingerprint } => match unpair(fingerprint)? {
+            true => Ok(()),
+            false => Err(format!("No paired device has the fingerprint \"{}\"", fingerprint)),
+        },
+    }
+}
+
+/// Shares the vault until the process is stopped.
+fn serve(cli: &Cli, port: u16, pair: bool) -> Result<(), String> {
+    let name = vault_name(cli)?;
+    let storage = get_vault_storage(&name)?;
+
+    if !storage.exists("info") {
+        return Err(format!("Vault \"{}\" not found", name));
+    }
+
+    let identity = LanIdentity::load()?;
+    let fingerprint = identity.fingerprint();
+    let device = identity.name.clone();
+
+    let address = SocketAddr::from(( Ipv4Addr::UNSPECIFIED, port ));
+    let mut server = LanServer::bind(&name, storage, identity, address)?;
+
+    if pair {
+        server.allow_pairing(Box::new(confirm_pairing));
+    }
+
+    println!(
+        "Sharing \"{}\" on port {} as {} ({}), stop with Ctrl+C.",
+        name, server.port(), device, fingerprint,
+    );
+
+    server.run(Arc::new(AtomicBool::new(false)))?;
+
+    Ok(())
+}
+
+/// Syncs the vault with the device at `address`, or the one found on the
+/// network.
+fn sync(cli: &Cli, address: Option<SocketAddr>, pair: bool) -> Result<(), String> {
+    let name = vault_name(cli)?;
+
+    // A vault that isn't on this device yet is downloaded
+    let storage = get_vault_storage(&name)?;
+    let identity = LanIdentity::load()?;
+
+    let address = match address {
+        Some(address) => address,
+        None => find_device(&identity, pair)?,
+    };
+
+    let confirm = match pair {
+        true => Some(&confirm_pairing as &_),
+        false => None,
+    };
+
+    let peer = LanPeer::connect(address, &name, &identity, confirm)?;
+    let report = sync_with_peer(&name, &storage, &peer)?;
+
+    // Like a created vault, the first vault is opened when no vault is given
+    if storage.exists("info") && get_default_vault_name().is_err() {
+        create_default_vault_file(&name)?;
+    }
+
+    println!("{}", report);
+
+    if !report.conflicts.is_empty() {
+        eprintln!(
+            "warning: some files were changed both here and on \"{}\", open the vault in the \
+            app to choose the versions to keep",
+            peer.name,
+        );
+    }
+
+    Ok(())
+}
+
+/// Finds the only device to sync with on the network: a paired one, or any
+/// device when pairing.
+fn find_device(identity: &LanIdentity, pair: bool) -> Result<SocketAddr, String> {
+    let peers = read_peers();
+
+    let devices: Vec<Announcement> = discover(DISCOVERY_TIME, &identity.fingerprint())?
+        .into_iter()
+        .filter(|d| pair || peers.iter().any(|p| p.fingerprint() == d.fingerprint))
+        .collect();
+
+    match devices.as_slice() {
+        [ device ] => device.peer_address()
+            .ok_or_else(|| String::from("The device didn't give its address")),
+
+        [] if pair => Err(String::from(
+            "No device found, please run `lan serve --pair` on the other device"
+        )),
+
+        [] => Err(String::from(
+            "No paired device found, please pair with one first with `lan sync --pair`"
+        )),
+
+        _ => Err(String::from(
+            "Several devices found, please choose one with --peer, see `lan discover`"
+        )),
+    }
+}
+
+/// Asks on the terminal whether to pair with the device.
+fn confirm_pairing(device: &str, code: &str) -> bool {
+    print!(
+        "Pair with \"{}\"? Check that it shows the code {}, then type \"yes\": ",
+        device, code,
+    );
+
+    let _ = stdout().flush();
+
+    let mut answer = String::new();
+
+    match stdin().read_line(&mut answer) {
+        Ok(_) => answer.trim().eq_ignore_ascii_case("yes"),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,301 @@
+///
+/// Command line interface to the Secure Notes vaults, for scripting.
+///
+/// The password is asked on the terminal, or read from a file descriptor with
+/// `--password-fd` for automation, e.g.:
+///
+/// ```sh
+/// secure-notes --vault notes --password-fd 3 note ls 3< password.txt
+/// ```
+///
+/// `sync` mirrors a vault to a WebDAV server or an S3 bucket without unlocking
+/// it, e.g. from a cron job. `git` keeps the vault's history in git instead,
+/// every change is committed and synced through a git remote:
+///
+/// ```sh
+/// secure-notes --vault notes git init --remote /mnt/usb/notes.git
+/// secure-notes --vault notes git sync
+/// ```
+///
+/// `lan` syncs a vault directly with another device on the same network, the
+/// first time after checking that both show the same code:
+///
+/// ```sh
+/// secure-notes --vault notes lan serve --pair   # on the first device
+/// secure-notes --vault notes lan sync --pair    # on the second one
+/// ```
+///
+/// The vaults are read from the same directory as the app's, unless another
+/// one is chosen with `--data-dir` or `SECURE_NOTES_DATA_DIR`.
+///
+/// Warnings are logged to stderr, `SECURE_NOTES_LOG=debug` shows more.
+///
+mod password;
+mod edit;
+mod commands;
+mod lan;
+
+use std::{ net::SocketAddr, path::PathBuf, process::ExitCode };
+use clap::{ Args, Parser, Subcommand, ValueEnum };
+
+use secure_notes_core::utils::{ set_data_dir, logging::init_logging };
+
+#[derive(Debug, Parser)]
+#[command(name = "secure-notes", version, about = "Manage Secure Notes vaults from the command line")]
+pub struct Cli {
+    /// Vault to open, the default vault if not given
+    #[arg(long, global = true)]
+    pub vault: Option<String>,
+
+    /// Read the password from this file descriptor instead of asking for it
+    #[arg(long, global = true, value_name = "FD")]
+    pub password_fd: Option<i32>,
+
+    /// Keyfile needed to open the vault, or to protect a new vault with
+    #[arg(long, global = true)]
+    pub keyfile: Option<PathBuf>,
+
+    /// Directory the vaults are stored in, instead of the default one
+    #[arg(long, global = true, value_name = "DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create and list vaults
+    #[command(subcommand)]
+    Vault(VaultCommand),
+
+    /// Read and write the notes of a vault
+    #[command(subcommand)]
+    Note(NoteCommand),
+
+    /// Export all notes as unencrypted Markdown files
+    Export {
+        /// Directory the notes are written to
+        dir: PathBuf,
+    },
+
+    /// Import notes from other applications or from a directory of files
+    Import {
+        /// Format of the export, guessed from the path if not given
+        #[arg(long, value_enum)]
+        from: Option<ImportFormat>,
+
+        /// Convert Evernote notes to plain text instead of Markdown
+        #[arg(long)]
+        plain_text: bool,
+
+        path: PathBuf,
+    },
+
+    /// Mirror the vault to a WebDAV server or an S3 bucket, only its encrypted
+    /// files are sent
+    Sync {
+        #[command(flatten)]
+        remote: RemoteArgs,
+    },
+
+    /// List the notes deleted from the vault's S3 bucket and remove the old
+    /// ones
+    Gc {
+        #[command(flatten)]
+        remote: RemoteArgs,
+
+        /// Remove the notes deleted more than this many days ago
+        #[arg(long, default_value_t = 30)]
+        days: u64,
+
+        /// Only list the deleted notes
+        #[arg(long)]
+        list: bool,
+    },
+
+    /// Keep the vault's history in git and sync it through a git remote
+    #[command(subcommand)]
+    Git(GitCommand),
+
+    /// Sync the vault directly with another device on the local network
+    #[command(subcommand)]
+    Lan(LanCommand),
+}
+
+/// Where the vault is mirrored to.
+#[derive(Debug, Args)]
+pub struct RemoteArgs {
+    /// URL of the vault's directory on a WebDAV server, e.g.
+    /// "https://dav.example.com/vaults/notes/", or its bucket and prefix in an
+    /// S3 object storage, e.g. "s3://backups/vaults/notes"
+    pub url: String,
+
+    /// User name on the WebDAV server, the password is read from
+    /// SECURE_NOTES_REMOTE_PASSWORD, --password-fd or asked for
+    #[arg(long)]
+    pub user: Option<String>,
+
+    /// URL of the S3 server, e.g. "http://localhost:9000" for MinIO. The keys
+    /// are read from AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY
+    #[arg(long, default_value = "https://s3.amazonaws.com")]
+    pub endpoint: String,
+
+    /// Region of the S3 bucket
+    #[arg(long, default_value = "us-east-1")]
+    pub region: String,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VaultCommand {
+    /// Create a new vault
+    Create {
+        name: String,
+    },
+
+    /// List the vaults, the default vault is marked with a "*"
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GitCommand {
+    /// Make the vault a git repository, every change is committed from then on.
+    ///
+    /// Removed and changed passwords still unlock the vault through its
+    /// history.
+    Init {
+        /// URL or path of the git remote to sync with, e.g. a bare repository
+        #[arg(long)]
+        remote: Option<String>,
+    },
+
+    /// Set the git remote the vault is synced with
+    Remote {
+        url: String,
+    },
+
+    /// Download a vault from a git remote
+    Clone {
+        url: String,
+
+        /// Name of the vault on this device
+        name: String,
+    },
+
+    /// Pull the remote's changes, merge them and push the vault's
+    Sync,
+
+    /// List the vault's commits, the last one first
+    Log,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LanCommand {
+    /// Share the vault with the paired devices until stopped, it stays locked
+    Serve {
+        /// TCP port to listen on, any free port if not given
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+
+        /// Let new devices pair with this one, after checking the code
+        #[arg(long)]
+        pair: bool,
+    },
+
+    /// List the devices sharing a vault on the network
+    Discover {
+        /// Seconds to listen for the devices
+        #[arg(long, default_value_t = 3)]
+        seconds: u64,
+    },
+
+    /// Sync the vault with a device sharing it
+    Sync {
+        /// Address of the device, e.g. "192.168.1.20:47000", found on the
+        /// network if not given
+        #[arg(long, value_name = "ADDRESS")]
+        peer: Option<SocketAddr>,
+
+        /// Pair with the device if it isn't paired yet, after checking the
+        /// code
+        #[arg(long)]
+        pair: bool,
+    },
+
+    /// List the paired devices
+    Peers,
+
+    /// Forget a paired device
+    Unpair {
+        /// Fingerprint of the device, as listed by `lan peers`
+        fingerprint: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum NoteCommand {
+    /// List the notes with their ids
+    Ls,
+
+    /// Print a note
+    Cat {
+        /// Id, name or path ("Folder/Name") of the note
+        note: String,
+
+        /// Print the note as it was at this commit of the vault's git history,
+        /// e.g. "HEAD~1"
+        #[arg(long, value_name = "COMMIT")]
+        at: Option<String>,
+    },
+
+    /// Add a note, its text is read from stdin or written in $EDITOR
+    Add {
+        name: String,
+
+        /// Path of an existing folder to put the note in, e.g. "Work/Ideas"
+        #[arg(long)]
+        folder: Option<String>,
+    },
+
+    /// Edit a note in $EDITOR
+    Edit {
+        /// Id, name or path ("Folder/Name") of the note
+        note: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ImportFormat {
+    /// Evernote ".enex" export
+    Enex,
+
+    /// Decrypted Standard Notes backup
+    StandardNotes,
+
+    /// Simplenote "notes.json"
+    Simplenote,
+
+    /// Directory of ".md" and ".txt" files
+    Files,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    if let Some(data_dir) = &cli.data_dir {
+        if let Err(e) = set_data_dir(data_dir) {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let _log_guard = init_logging();
+
+    match commands::run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,66 @@
+///
+/// Reads passwords from the terminal or from a file descriptor.
+///
+use secure_notes_core::utils::crypto::SecretString;
+
+/// Reads a password, from `fd` if given, or by asking on the terminal.
+///
+/// Only the first line is read from `fd`.
+pub fn read_password(prompt: &str, fd: Option<i32>) -> Result<SecretString, String> {
+    match fd {
+        Some(fd) => read_password_from_fd(fd),
+        None => {
+            match rpassword::prompt_password(prompt) {
+                Ok(password) => Ok(SecretString::new(password)),
+                Err(e) => Err(format!("Couldn't read the password: {}", e)),
+            }
+        }
+    }
+}
+
+/// Asks for a new password twice, or reads it once from `fd`.
+pub fn read_new_password(fd: Option<i32>) -> Result<SecretString, String> {
+    let password = read_password("New password: ", fd)?;
+
+    if password.len() > 32 || password.len() < 8 {
+        return Err(String::from("Vault password must be between 8 and 32 characters."));
+    }
+
+    if fd.is_none() {
+        let confirm_password = read_password("Confirm password: ", None)?;
+
+        if *password != *confirm_password {
+            return Err(String::from("The passwords don't match."));
+        }
+    }
+
+    Ok(password)
+}
+
+#[cfg(unix)]
+fn read_password_from_fd(fd: i32) -> Result<SecretString, String> {
+    use std::{ fs::File, io::Read, mem::ManuallyDrop, os::fd::FromRawFd };
+    use zeroize::Zeroizing;
+
+    // The descriptor belongs to the caller, it must not be closed here
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    let mut bytes = Zeroizing::new(vec![]);
+
+    if let Err(e) = file.read_to_end(&mut bytes) {
+        return Err(format!("Couldn't read the password from fd {}: {}", fd, e));
+    }
+
+    let text = match std::str::from_utf8(&bytes) {
+        Ok(t) => t,
+        Err(_) => { return Err(String::from("The password is not valid UTF-8")); }
+    };
+
+    let line = text.lines().next().unwrap_or_default();
+
+    Ok(SecretString::new(line.to_string()))
+}
+
+#[cfg(not(unix))]
+fn read_password_from_fd(_fd: i32) -> Result<SecretString, String> {
+    Err(String::from("--password-fd is only supported on Unix"))
+}
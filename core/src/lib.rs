@@ -0,0 +1,51 @@
+//! Vaults, notes and their encryption.
+//!
+//! This crate holds everything Secure Notes stores on the disk, without any
+//! user interface, so that frontends (the desktop app, the `secure-notes`
+//! command line tool, ...) can be built on it.
+//!
+//! # Overview
+//!
+//! - [`utils::vault`]: creating, unlocking and recovering vaults, and
+//!   managing the key slots (passwords) that open them.
+//! - [`utils::notes`]: reading and writing the encrypted index, notes and
+//!   attachments of an unlocked vault.
+//! - [`utils::storage`]: where a vault's files are kept, a directory or
+//!   memory.
+//! - [`utils::document`]: the text of a note, as a CRDT document whose edits
+//!   merge.
+//! - [`utils::sync`]: merging the conflict copies made by sync tools when a
+//!   vault is kept in a synced folder.
+//! - [`utils::remote`]: mirroring a vault to a server, over WebDAV or S3, or
+//!   to another device on the local network.
+//! - [`utils::git`]: keeping a vault's history in git and syncing it through
+//!   a git remote.
+//! - [`utils::crypto`]: the master key, encryption and key derivation.
+//! - [`utils::import`] and [`utils::export`]: moving notes in and out of a
+//!   vault.
+//! - [`utils::settings`]: the app's settings file.
+//! - [`utils::logging`]: setting up the logs, without ever logging secrets.
+//! - [`types`]: the data stored in a vault's info and index files.
+//! - [`utils`] itself: the local directory and the default vault.
+//!
+//! Vaults, notes and encryption fail with a
+//! [`VaultError`](types::vault_error::VaultError), which tells a wrong password
+//! apart from a corrupted or unreadable vault. The importers and exporters
+//! return a message that can be shown to the user as is.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use secure_notes_core::utils::{ notes::read_index, vault::{ get_vault_storage, unlock_vault } };
+//!
+//! let storage = get_vault_storage("personal")?;
+//! let key = unlock_vault(&storage, "personal", "password", None)?;
+//!
+//! for note in read_index(&storage, &key)?.notes {
+//!     println!("{}: {}", note.id, note.name);
+//! }
+//! # Ok::<(), secure_notes_core::types::vault_error::VaultError>(())
+//! ```
+
+pub mod types;
+pub mod utils;
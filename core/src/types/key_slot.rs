@@ -0,0 +1,71 @@
+use serde::{ Serialize, Deserialize };
+
+/// One way of unlocking a vault.
+///
+/// Every key slot holds a copy of the vault's master key, encrypted with a key
+/// derived from the slot's own secret (a password, optionally with a keyfile,
+/// or a recovery phrase) using the slot's own salt and KDF parameters.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct KeySlot {
+    pub id: u32,
+
+    /// Name shown in the vault settings, e.g. who the password belongs to.
+    pub label: String,
+    pub kind: KeySlotKind,
+    pub kdf: KeySlotKdf,
+    pub salt: Vec<u8>,
+
+    /// The vault's master key, encrypted with the key derived from the slot's
+    /// secret.
+    pub wrapped_key: Vec<u8>,
+
+    /// Salt for `keyfile_check`. Empty if the slot doesn't use a keyfile.
+    #[serde(default)]
+    pub keyfile_salt: Vec<u8>,
+
+    /// Salted digest of the keyfile's digest, used to tell a wrong keyfile
+    /// apart from a wrong password.
+    #[serde(default)]
+    pub keyfile_check: Vec<u8>,
+
+    /// When the slot was added, in seconds since the unix epoch.
+    #[serde(default)]
+    pub created: u64,
+}
+
+impl KeySlot {
+    /// Whether a keyfile is needed to open this slot.
+    pub fn uses_keyfile(&self) -> bool {
+        !self.keyfile_check.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum KeySlotKind {
+    Password,
+    RecoveryPhrase,
+}
+
+/// Function used to derive the key that wraps the master key.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum KeySlotKdf {
+    /// For passwords. Costs are the same as in the argon2 crate's `Params`.
+    Argon2id {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+
+    /// For recovery phrases, which are random enough to not need a slow KDF.
+    HkdfSha256,
+}
+
+impl Default for KeySlotKdf {
+    fn default() -> Self {
+        Self::Argon2id {
+            m_cost: 16384,  // m = 16MB
+            t_cost: 8,
+            p_cost: 1,
+        }
+    }
+}
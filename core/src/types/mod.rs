@@ -0,0 +1,20 @@
+//! Data stored in the files of a vault and the app's settings, and the errors
+//! when accessing them.
+
+pub mod vault_info;
+pub mod vault_index;
+pub mod vault_index_entry;
+pub mod note_metadata;
+pub mod key_slot;
+pub mod vault_error;
+pub mod settings;
+
+/// Why the default vault couldn't be read, see
+/// [`get_default_vault_name`](crate::utils::get_default_vault_name).
+#[derive(Debug)]
+pub enum DefaultVaultFileError {
+    FileDoesNotExist,
+    FirstLineEmpty,
+    VaultDoesNotExist,
+    OSError(String),
+}
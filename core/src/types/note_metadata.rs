@@ -0,0 +1,41 @@
+use serde::{ Serialize, Deserialize };
+
+/// Extra information stored alongside a note's index entry.
+///
+/// Timestamps are seconds since the unix epoch.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct NoteMetadata {
+    #[serde(default)]
+    pub created: u64,
+
+    #[serde(default)]
+    pub updated: u64,
+
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    #[serde(default)]
+    pub attachments: Vec<NoteAttachment>,
+
+    /// Where the note was imported from, e.g. "simplenote:<id>". Used to
+    /// recognize notes that were already imported.
+    #[serde(default)]
+    pub source: Option<String>,
+
+    /// Hash of the note's encrypted file when it was last written, see
+    /// [`content_hash`](crate::utils::sync::content_hash). Tells which copy of
+    /// the index matches the note's file after the vault was synced.
+    #[serde(default)]
+    pub hash: Option<String>,
+}
+
+/// A file attached to a note.
+///
+/// The attachment's content is stored encrypted inside the "attachments"
+/// directory of the vault, in a file named after its `id`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct NoteAttachment {
+    pub id: u32,
+    pub name: String,
+    pub mime: String,
+}
@@ -0,0 +1,195 @@
+use std::{ fmt, ops::RangeInclusive, str::FromStr, time::Duration };
+use serde::{ Serialize, Deserialize };
+
+/// Settings of the app, stored in the "settings.json" file of the local
+/// directory.
+///
+/// Settings missing from the file get their default value, see
+/// [`read_settings`](crate::utils::settings::read_settings).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub editor: EditorSettings,
+    pub auto_lock: AutoLockSettings,
+    pub appearance: AppearanceSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditorSettings {
+    /// Part of the window's width taken by the explorer.
+    pub explorer_ratio: f64,
+
+    /// Size of the text of notes.
+    pub text_size: u16,
+
+    /// Save the opened note automatically after it wasn't changed for
+    /// `autosave_delay` seconds.
+    pub autosave: bool,
+    pub autosave_delay: u64,
+}
+
+/// Settings for locking the vault automatically.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoLockSettings {
+    /// Lock the vault when there is no activity for this many minutes, 0 turns
+    /// the inactivity timer off.
+    pub idle_timeout: u64,
+
+    /// Lock the vault when the window is minimized.
+    pub lock_on_minimize: bool,
+
+    /// Lock the vault when the window loses focus.
+    pub lock_on_focus_loss: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppearanceSettings {
+    /// Background of the screens and the explorer.
+    pub background: HexColor,
+
+    /// Background of the opened note.
+    pub editor_background: HexColor,
+
+    /// Background of the explorer's title bar.
+    pub title_bar_background: HexColor,
+}
+
+/// A color written as "#rrggbb" in the settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HexColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl EditorSettings {
+    pub const EXPLORER_RATIOS: RangeInclusive<f64> = 0.1..=0.9;
+    pub const TEXT_SIZES: RangeInclusive<u16> = 8..=48;
+    pub const AUTOSAVE_DELAYS: RangeInclusive<u64> = 1..=3600;
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            explorer_ratio: 0.25,
+            text_size: 16,
+            autosave: false,
+            autosave_delay: 5,
+        }
+    }
+}
+
+impl AutoLockSettings {
+    /// At most a day.
+    pub const IDLE_TIMEOUTS: RangeInclusive<u64> = 0..=24 * 60;
+
+    /// Returns the inactivity timeout, `None` if it's turned off.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        match self.idle_timeout {
+            0 => None,
+            minutes => Some(Duration::from_secs(minutes * 60)),
+        }
+    }
+}
+
+impl Default for AutoLockSettings {
+    fn default() -> Self {
+        Self {
+            idle_timeout: 5,
+            lock_on_minimize: false,
+            lock_on_focus_loss: false,
+        }
+    }
+}
+
+impl Default for AppearanceSettings {
+    fn default() -> Self {
+        Self {
+            background: HexColor::new(0x0d, 0x17, 0x1c),
+            editor_background: HexColor::new(0x0e, 0x1a, 0x24),
+            title_bar_background: HexColor::new(0x0a, 0x0d, 0x12),
+        }
+    }
+}
+
+impl Settings {
+    /// Resets the settings with invalid values to their default value and
+    /// returns a message for each of them.
+    pub fn validate(&mut self) -> Vec<String> {
+        let mut problems = vec![];
+        let editor_defaults = EditorSettings::default();
+
+        if !EditorSettings::EXPLORER_RATIOS.contains(&self.editor.explorer_ratio) {
+            problems.push(out_of_range("editor.explorer_ratio", &EditorSettings::EXPLORER_RATIOS));
+            self.editor.explorer_ratio = editor_defaults.explorer_ratio;
+        }
+
+        if !EditorSettings::TEXT_SIZES.contains(&self.editor.text_size) {
+            problems.push(out_of_range("editor.text_size", &EditorSettings::TEXT_SIZES));
+            self.editor.text_size = editor_defaults.text_size;
+        }
+
+        if !EditorSettings::AUTOSAVE_DELAYS.contains(&self.editor.autosave_delay) {
+            problems.push(out_of_range("editor.autosave_delay", &EditorSettings::AUTOSAVE_DELAYS));
+            self.editor.autosave_delay = editor_defaults.autosave_delay;
+        }
+
+        if !AutoLockSettings::IDLE_TIMEOUTS.contains(&self.auto_lock.idle_timeout) {
+            problems.push(out_of_range("auto_lock.idle_timeout", &AutoLockSettings::IDLE_TIMEOUTS));
+            self.auto_lock.idle_timeout = AutoLockSettings::default().idle_timeout;
+        }
+
+        problems
+    }
+}
+
+fn out_of_range<T: fmt::Display>(setting: &str, range: &RangeInclusive<T>) -> String {
+    format!("{} must be between {} and {}", setting, range.start(), range.end())
+}
+
+impl HexColor {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl FromStr for HexColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("\"{}\" is not a color like \"#1a2b3c\"", s);
+
+        let hex = match s.trim().strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => hex,
+            _ => { return Err(error()); }
+        };
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| error());
+
+        Ok(Self::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl TryFrom<String> for HexColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<HexColor> for String {
+    fn from(color: HexColor) -> Self {
+        color.to_string()
+    }
+}
+
+impl fmt::Display for HexColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
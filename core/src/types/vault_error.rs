@@ -0,0 +1,152 @@
+use std::{ error::Error, fmt, io };
+
+use crate::utils::remote::RemoteError;
+
+/// Error source kept by [`VaultError`] for the details of what went wrong.
+pub type ErrorSource = Box<dyn Error + Send + Sync>;
+
+/// Why reading or writing a vault failed.
+///
+/// The `Display` message can be shown to the user, the underlying error (if
+/// any) is available through [`Error::source`].
+#[derive(Debug)]
+pub enum VaultError {
+    /// The local directory of the app couldn't be found.
+    LocalDirNotFound,
+
+    /// A file or directory of the vault couldn't be read or written.
+    Io {
+        /// What was being done, e.g. "Could not read the info file".
+        context: &'static str,
+        source: io::Error,
+    },
+
+    /// A file of the vault can't be decoded, it was damaged or modified.
+    Corrupt {
+        /// Which file, e.g. "info" or "index".
+        file: &'static str,
+        source: Option<ErrorSource>,
+    },
+
+    /// The vault was written by a newer version of the app.
+    UnsupportedVersion {
+        version: u32,
+    },
+
+    /// The password doesn't open the vault.
+    WrongPassword,
+
+    /// The vault needs a keyfile and none was given.
+    KeyfileRequired,
+
+    /// The keyfile doesn't belong to the vault.
+    WrongKeyfile,
+
+    /// The recovery phrase is malformed or doesn't open the vault.
+    InvalidRecoveryPhrase(String),
+
+    /// There were too many failed attempts to unlock the vault.
+    LockedOut {
+        /// Seconds until the next attempt is allowed.
+        retry_in: u64,
+    },
+
+    /// Encrypting, decrypting or deriving a key failed for another reason than
+    /// a wrong password.
+    Crypto {
+        context: &'static str,
+        source: Option<ErrorSource>,
+    },
+
+    /// The request can't be carried out, e.g. removing the last password of
+    /// the vault.
+    Invalid(String),
+
+    /// Syncing with the server the vault is mirrored to failed.
+    Remote(RemoteError),
+
+    /// Reading or writing the vault's git history failed.
+    Git {
+        /// What was being done, e.g. "Couldn't push to the git remote".
+        context: &'static str,
+        source: git2::Error,
+    },
+}
+
+impl VaultError {
+    pub fn io(context: &'static str, source: io::Error) -> Self {
+        Self::Io { context, source }
+    }
+
+    pub fn corrupt(file: &'static str, source: impl Into<ErrorSource>) -> Self {
+        Self::Corrupt { file, source: Some(source.into()) }
+    }
+
+    pub fn crypto(context: &'static str, source: impl Into<ErrorSource>) -> Self {
+        Self::Crypto { context, source: Some(source.into()) }
+    }
+
+    pub fn git(context: &'static str, source: git2::Error) -> Self {
+        Self::Git { context, source }
+    }
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LocalDirNotFound => f.write_str("Could not find local directory"),
+            Self::Io { context, .. } => f.write_str(context),
+            Self::Corrupt { file, .. } => write!(f, "The {} file of the vault is corrupted", file),
+
+            Self::UnsupportedVersion { version } => write!(
+                f,
+                "The vault was created by a newer version of Secure Notes (vault version {})",
+                version,
+            ),
+
+            Self::WrongPassword => f.write_str("Wrong password"),
+            Self::KeyfileRequired => f.write_str("This vault needs its keyfile to be opened"),
+            Self::WrongKeyfile => f.write_str("Wrong keyfile"),
+            Self::InvalidRecoveryPhrase(message) => f.write_str(message),
+
+            Self::LockedOut { retry_in } => write!(
+                f, "Too many failed attempts, try again in {} s", retry_in,
+            ),
+
+            Self::Crypto { context, .. } => f.write_str(context),
+            Self::Invalid(message) => f.write_str(message),
+            Self::Remote(e) => e.fmt(f),
+            Self::Git { context, source } => write!(f, "{}: {}", context, source.message()),
+        }
+    }
+}
+
+impl Error for VaultError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+
+            Self::Corrupt { source, .. } | Self::Crypto { source, .. } => {
+                source.as_deref().map(|s| s as &(dyn Error + 'static))
+            }
+
+            Self::Remote(e) => Some(e),
+            Self::Git { source, .. } => Some(source),
+
+            _ => None,
+        }
+    }
+}
+
+impl From<RemoteError> for VaultError {
+    fn from(error: RemoteError) -> Self {
+        Self::Remote(error)
+    }
+}
+
+/// For the code that still reports errors as plain messages.
+impl From<VaultError> for String {
+    fn from(error: VaultError) -> Self {
+        error.to_string()
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{ Serialize, Deserialize };
+
+use super::vault_index_entry::VaultIndexEntry;
+use crate::utils::unix_now;
+
+/// Contents of the vault's index file: the folders and notes of the vault.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultIndex {
+    pub folders: Vec<VaultIndexEntry>,
+    pub notes: Vec<VaultIndexEntry>,
+
+    /// Folders and notes deleted from the vault, so that merging a copy of the
+    /// index from another device doesn't bring them back.
+    #[serde(default)]
+    pub deleted: Vec<DeletedEntry>,
+}
+
+/// A folder or note that was deleted from the vault.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct DeletedEntry {
+    pub id: u32,
+
+    /// When the entry was created, tells it apart from another entry that got
+    /// the same id on another device.
+    pub created: u64,
+
+    /// When the entry was deleted.
+    pub deleted: u64,
+}
+
+impl VaultIndex {
+    /// Returns an id that is not used by any folder, note or attachment, nor
+    /// by a deleted one.
+    pub fn next_id(&self) -> u32 {
+        let folder_ids = self.folders.iter().map(|f| f.id);
+        let note_ids = self.notes.iter().map(|n| n.id);
+        let attachment_ids = self.notes.iter()
+            .flat_map(|n| n.metadata.attachments.iter().map(|a| a.id));
+        let deleted_ids = self.deleted.iter().map(|d| d.id);
+
+        folder_ids
+            .chain(note_ids)
+            .chain(attachment_ids)
+            .chain(deleted_ids)
+            .max()
+            .map_or(1, |id| id + 1)
+    }
+
+    /// Removes the folder or note with the given id and records its deletion.
+    /// Returns the removed entry.
+    pub fn delete(&mut self, id: u32) -> Option<VaultIndexEntry> {
+        let entry = match self.folders.iter().position(|f| f.id == id) {
+            Some(i) => self.folders.remove(i),
+            None => {
+                let i = self.notes.iter().position(|n| n.id == id)?;
+                self.notes.remove(i)
+            }
+        };
+
+        self.deleted.push(DeletedEntry {
+            id,
+            created: entry.metadata.created,
+            deleted: unix_now(),
+        });
+
+        Some(entry)
+    }
+
+    /// Whether the entry was deleted from this index, and wasn't changed
+    /// since.
+    pub fn is_deleted(&self, entry: &VaultIndexEntry) -> bool {
+        self.deleted.iter().any(|d| {
+            d.id == entry.id
+                && d.created == entry.metadata.created
+                && entry.metadata.updated <= d.deleted
+        })
+    }
+
+    /// Finds the folder with the given name and parent folder.
+    pub fn find_folder(&self, name: &str, parent_folder: Option<u32>) -> Option<&VaultIndexEntry> {
+        self.folders.iter()
+            .find(|f| f.name == name && f.parent_folder == parent_folder)
+    }
+
+    /// Returns the names of the folders leading to `folder`, starting at the
+    /// top level folder.
+    pub fn folder_path(&self, folder: Option<u32>) -> Vec<&str> {
+        let mut path = vec![];
+        let mut current = folder;
+
+        // The length check guards against folders that are their own parent
+        while let Some(id) = current {
+            if path.len() > self.folders.len() {
+                break;
+            }
+
+            match self.folders.iter().find(|f| f.id == id) {
+                Some(f) => {
+                    path.push(f.name.as_str());
+                    current = f.parent_folder;
+                }
+
+                None => { break; }
+            }
+        }
+
+        path.reverse();
+
+        path
+    }
+}
@@ -0,0 +1,17 @@
+use serde::{ Serialize, Deserialize };
+
+use super::note_metadata::NoteMetadata;
+
+/// A folder or a note in the index.
+///
+/// A note's content is stored in the "notes" directory in a file named after
+/// its `id`. Entries without a `parent_folder` are at the top level.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct VaultIndexEntry {
+    pub id: u32,
+    pub name: String,
+    pub parent_folder: Option<u32>,
+
+    #[serde(default)]
+    pub metadata: NoteMetadata,
+}
@@ -0,0 +1,50 @@
+use serde::{ Serialize, Deserialize };
+
+use crate::types::key_slot::{ KeySlot, KeySlotKind };
+
+/// Version of the vault format written by this version of the app. Vaults with
+/// a higher version were created by a newer version and can't be opened.
+pub const VAULT_VERSION: u32 = 1;
+
+/// Contents of the vault's info file.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct VaultInfo {
+    pub name: String,
+
+    /// Version of the vault format, see [`VAULT_VERSION`]. Vaults created
+    /// before the version was stored have the version 0.
+    #[serde(default)]
+    pub version: u32,
+
+    /// Password hash of vaults created before key slots were introduced. It's
+    /// only kept to migrate these vaults and is empty afterwards.
+    #[serde(default)]
+    pub password: String,
+
+    /// The ways to unlock the vault, each holding a copy of the master key.
+    #[serde(default)]
+    pub key_slots: Vec<KeySlot>,
+}
+
+impl VaultInfo {
+    /// Whether a keyfile may be needed to unlock the vault.
+    pub fn uses_keyfile(&self) -> bool {
+        self.key_slots.iter()
+            .any(|s| s.kind == KeySlotKind::Password && s.uses_keyfile())
+    }
+
+    /// Whether the vault can be recovered with a recovery phrase.
+    pub fn has_recovery_phrase(&self) -> bool {
+        self.key_slots.iter().any(|s| s.kind == KeySlotKind::RecoveryPhrase)
+    }
+
+    /// Number of slots that can be unlocked with a password.
+    pub fn password_slots(&self) -> usize {
+        self.key_slots.iter().filter(|s| s.kind == KeySlotKind::Password).count()
+    }
+
+    /// Returns the id for a new key slot.
+    pub fn next_slot_id(&self) -> u32 {
+        self.key_slots.iter().map(|s| s.id).max().unwrap_or(0) + 1
+    }
+}
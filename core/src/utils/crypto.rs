@@ -0,0 +1,313 @@
+///
+/// Encryption primitives used for everything that is stored inside a vault.
+///
+/// Every vault has a random 256-bit master key. Notes, attachments and the
+/// index are encrypted with it using XChaCha20-Poly1305. The master key itself
+/// is stored in the info file's key slots, each copy encrypted with a key
+/// derived from a password using Argon2id.
+///
+/// Optionally, a keyfile can be required in addition to the password. The
+/// SHA-256 digest of the keyfile is then used as the Argon2 secret when
+/// deriving the key, so the master key can't be decrypted without it.
+///
+/// Another copy of the master key is encrypted with a key derived from the
+/// vault's recovery phrase, a list of 24 words (BIP39 English word list)
+/// encoding a random 256-bit secret.
+///
+/// Encrypted data is laid out as: `nonce (24 bytes) || ciphertext`.
+///
+/// Keys and passwords are wiped from memory when they are dropped, see
+/// [`VaultKey`] and [`SecretString`].
+///
+use std::{ fmt, fs::{ File, read }, io::Write, ops::Deref, path::Path };
+use chacha20poly1305::{
+    aead::{ Aead, AeadCore, KeyInit, OsRng },
+    XChaCha20Poly1305, XNonce, Key,
+};
+use argon2::{ password_hash::rand_core::RngCore, Argon2, Algorithm, Version, Params };
+use bip39::Mnemonic;
+use hkdf::Hkdf;
+use sha2::{ Digest, Sha256 };
+use zeroize::{ Zeroize, Zeroizing };
+
+use crate::types::{ key_slot::KeySlotKdf, vault_error::VaultError };
+
+pub const KEY_SIZE: usize = 32;
+pub const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 24;
+
+/// Size of the keyfiles generated by [`generate_keyfile`].
+const KEYFILE_SIZE: usize = 64;
+
+/// Number of words in a recovery phrase.
+pub const RECOVERY_PHRASE_WORDS: usize = 24;
+
+/// A string holding a secret (e.g. a password), wiped from memory on drop.
+///
+/// Its `Debug` output doesn't show the secret and it doesn't implement
+/// `Display`, so it can't end up in the logs by accident.
+#[derive(Clone, Default, PartialEq)]
+pub struct SecretString(Zeroizing<String>);
+
+impl SecretString {
+    pub fn new(secret: String) -> Self {
+        Self(Zeroizing::new(secret))
+    }
+}
+
+impl Deref for SecretString {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.0
+    }
+}
+
+impl Zeroize for SecretString {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString(..)")
+    }
+}
+
+/// A symmetric key used to encrypt and decrypt vault contents.
+///
+/// The key is wiped from memory when it's dropped.
+#[derive(Clone, PartialEq)]
+pub struct VaultKey([u8; KEY_SIZE]);
+
+impl VaultKey {
+    /// Generates a new random key.
+    pub fn generate() -> Self {
+        let mut key = Self([0u8; KEY_SIZE]);
+        OsRng.fill_bytes(&mut key.0);
+
+        key
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VaultError> {
+        if bytes.len() != KEY_SIZE {
+            return Err(VaultError::Crypto { context: "Invalid key length", source: None });
+        }
+
+        let mut key = Self([0u8; KEY_SIZE]);
+        key.0.copy_from_slice(bytes);
+
+        Ok(key)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for VaultKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for VaultKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("VaultKey(..)")
+    }
+}
+
+/// Generates a random salt for key derivation.
+pub fn generate_salt() -> Vec<u8> {
+    let mut salt = vec![0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    salt
+}
+
+/// Derives a key from the password and salt using Argon2id, with the costs
+/// given in `kdf`.
+///
+/// When the vault uses a keyfile, its digest (see [`read_keyfile`]) is passed
+/// as `keyfile` and used as the Argon2 secret.
+pub fn derive_key(
+    password: &str,
+    keyfile: Option<&[u8]>,
+    salt: &[u8],
+    kdf: &KeySlotKdf,
+) -> Result<VaultKey, VaultError> {
+    let params = match kdf {
+        KeySlotKdf::Argon2id { m_cost, t_cost, p_cost } => {
+            match Params::new(*m_cost, *t_cost, *p_cost, Some(KEY_SIZE)) {
+                Ok(p) => p,
+                Err(e) => {
+                    return Err(VaultError::crypto("Invalid key derivation parameters", e));
+                }
+            }
+        }
+
+        KeySlotKdf::HkdfSha256 => {
+            return Err(VaultError::Crypto {
+                context: "Passwords can't be used with this key slot",
+                source: None,
+            });
+        }
+    };
+
+    let argon = match keyfile {
+        Some(secret) => {
+            match Argon2::new_with_secret(secret, Algorithm::Argon2id, Version::V0x13, params) {
+                Ok(a) => a,
+                Err(e) => { return Err(VaultError::crypto("Invalid keyfile", e)); }
+            }
+        }
+
+        None => Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+    };
+
+    let mut key = VaultKey([0u8; KEY_SIZE]);
+
+    match argon.hash_password_into(password.as_bytes(), salt, &mut key.0) {
+        Ok(()) => Ok(key),
+        Err(e) => Err(VaultError::crypto("Could not derive key from password", e)),
+    }
+}
+
+/// Reads the keyfile and returns the SHA-256 digest of its contents.
+///
+/// Any file can be used as a keyfile, as long as it never changes.
+pub fn read_keyfile(path: &Path) -> Result<Zeroizing<Vec<u8>>, VaultError> {
+    match read(path) {
+        Ok(contents) => {
+            let contents = Zeroizing::new(contents);
+
+            Ok(Zeroizing::new(Sha256::digest(contents.as_slice()).to_vec()))
+        }
+
+        Err(e) => {
+            match e.kind() {
+                std::io::ErrorKind::NotFound => Err(VaultError::io("Keyfile not found", e)),
+                _ => Err(VaultError::io("Couldn't read the keyfile", e)),
+            }
+        }
+    }
+}
+
+/// Returns the value stored in the info file to check that the right keyfile
+/// was given, without storing the keyfile's digest itself.
+pub fn keyfile_check(keyfile: &[u8], salt: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(keyfile);
+
+    hasher.finalize().to_vec()
+}
+
+/// Writes a new keyfile with random contents at the given path.
+pub fn generate_keyfile(path: &Path) -> Result<(), VaultError> {
+    let mut contents = Zeroizing::new(vec![0u8; KEYFILE_SIZE]);
+    OsRng.fill_bytes(&mut contents);
+
+    match File::create(path) {
+        Ok(mut file) => {
+            match file.write_all(&contents).and_then(|_| file.sync_all()) {
+                Ok(()) => Ok(()),
+                Err(e) => Err(VaultError::io("Error while writing keyfile", e)),
+            }
+        }
+
+        Err(e) => Err(VaultError::io("Couldn't create keyfile", e)),
+    }
+}
+
+pub fn encrypt(key: &VaultKey, plain: &[u8]) -> Result<Vec<u8>, VaultError> {
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    match cipher.encrypt(&nonce, plain) {
+        Ok(ciphertext) => {
+            let mut data = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+            data.extend_from_slice(&nonce);
+            data.extend_from_slice(&ciphertext);
+
+            Ok(data)
+        }
+
+        Err(e) => Err(VaultError::crypto("Encryption failed", e)),
+    }
+}
+
+/// Decrypts data written by [`encrypt`].
+///
+/// Fails with [`VaultError::WrongPassword`] when the data wasn't encrypted
+/// with `key` (or was modified), it's up to the caller to tell whether that
+/// means a wrong password or a corrupted file.
+pub fn decrypt(key: &VaultKey, data: &[u8]) -> Result<Vec<u8>, VaultError> {
+    if data.len() < NONCE_SIZE {
+        return Err(VaultError::Crypto { context: "Encrypted data is too short", source: None });
+    }
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
+    let ( nonce, ciphertext ) = data.split_at(NONCE_SIZE);
+
+    match cipher.decrypt(XNonce::from_slice(nonce), ciphertext) {
+        Ok(plain) => Ok(plain),
+        Err(_) => Err(VaultError::WrongPassword),
+    }
+}
+
+/// Generates a new recovery phrase.
+pub fn generate_recovery_phrase() -> Result<SecretString, VaultError> {
+    let mut secret = Zeroizing::new([0u8; KEY_SIZE]);
+    OsRng.fill_bytes(secret.as_mut());
+
+    match Mnemonic::from_entropy(secret.as_ref()) {
+        Ok(mnemonic) => Ok(SecretString::new(mnemonic.to_string())),
+        Err(e) => Err(VaultError::crypto("Could not generate recovery phrase", e)),
+    }
+}
+
+/// Derives the key that wraps the master key from the recovery phrase.
+///
+/// The phrase already holds 256 random bits, so HKDF is used instead of the
+/// much slower password KDF.
+pub fn derive_recovery_key(phrase: &str, salt: &[u8]) -> Result<VaultKey, VaultError> {
+    let phrase = Zeroizing::new(phrase.to_lowercase());
+
+    let mnemonic = match Mnemonic::parse_normalized(&phrase) {
+        Ok(m) => m,
+        Err(bip39::Error::UnknownWord(i)) => {
+            return Err(VaultError::InvalidRecoveryPhrase(format!(
+                "\"{}\" is not a word of the recovery phrase",
+                phrase.split_whitespace().nth(i).unwrap_or_default(),
+            )));
+        }
+
+        Err(bip39::Error::InvalidChecksum) => {
+            return Err(VaultError::InvalidRecoveryPhrase(String::from(
+                "The recovery phrase is not correct, please check it for typos"
+            )));
+        }
+
+        Err(_) => { return Err(recovery_phrase_length_error()); }
+    };
+
+    if mnemonic.word_count() != RECOVERY_PHRASE_WORDS {
+        return Err(recovery_phrase_length_error());
+    }
+
+    let secret = Zeroizing::new(mnemonic.to_entropy());
+    let mut key = VaultKey([0u8; KEY_SIZE]);
+
+    match Hkdf::<Sha256>::new(Some(salt), &secret).expand(b"secure-notes recovery", &mut key.0) {
+        Ok(()) => Ok(key),
+        Err(e) => Err(VaultError::crypto("Could not derive key from recovery phrase", e)),
+    }
+}
+
+fn recovery_phrase_length_error() -> VaultError {
+    VaultError::InvalidRecoveryPhrase(format!(
+        "The recovery phrase must have {} words", RECOVERY_PHRASE_WORDS
+    ))
+}
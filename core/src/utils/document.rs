@@ -0,0 +1,222 @@
+///
+/// The text of a note as a CRDT document (see [Automerge](https://automerge.org)),
+/// so that edits made to a note on several devices merge without losing any of
+/// them.
+///
+/// A note's file holds the encrypted document, with the history of the edits
+/// made to the note: text deleted from a note stays in its file, encrypted,
+/// until the note is deleted.
+///
+/// Notes written by older versions hold their text only. They are read as a
+/// document made by a single change without a time, whose author is derived
+/// from the text: two devices converting the same note make the same document,
+/// and converting different texts never makes two changes with the same author
+/// and number, which Automerge refuses to merge.
+///
+use automerge::{ ActorId, AutoCommit, AutomergeError, ObjId, ObjType, ReadDoc, ROOT };
+use automerge::transaction::{ CommitOptions, Transactable };
+use sha2::{ Digest, Sha256 };
+
+use crate::types::vault_error::VaultError;
+
+/// The first bytes of a saved Automerge document.
+const MAGIC: [u8; 4] = [ 0x85, 0x6f, 0x4a, 0x83 ];
+
+/// Key of the text in the document's root map.
+const TEXT: &str = "text";
+
+/// The text of a note and the history of its edits.
+#[derive(Debug)]
+pub struct NoteDocument {
+    doc: AutoCommit,
+    text: ObjId,
+
+    /// Read from a note written by an older version.
+    converted: bool,
+}
+
+impl NoteDocument {
+    /// Makes a document holding `text`.
+    pub fn from_text(text: &str) -> Self {
+        // The same for every device, see the module's docs
+        let actor = Sha256::new()
+            .chain_update(b"secure-notes text\0")
+            .chain_update(text.as_bytes())
+            .finalize();
+
+        let mut doc = AutoCommit::new().with_actor(ActorId::from(&actor[..16]));
+
+        let text_id = doc.put_object(ROOT, TEXT, ObjType::Text)
+            .and_then(|id| doc.splice_text(&id, 0, 0, text).map(|()| id))
+            .expect("editing a new document doesn't fail");
+
+        doc.commit_with(CommitOptions::default().with_time(0));
+        doc.set_actor(ActorId::random());
+
+        Self { doc, text: text_id, converted: false }
+    }
+
+    /// Reads a document from the decrypted contents of a note's file, or the
+    /// text of a note written by an older version.
+    pub fn load(data: &[u8]) -> Result<Self, VaultError> {
+        if !is_document(data) {
+            return match std::str::from_utf8(data) {
+                Ok(text) => Ok(Self { converted: true, ..Self::from_text(text) }),
+                Err(e) => Err(VaultError::corrupt("note", e)),
+            };
+        }
+
+        let doc = AutoCommit::load(data).map_err(|e| VaultError::corrupt("note", e))?;
+
+        let text = match doc.get(ROOT, TEXT) {
+            Ok(Some(( _, id ))) => id,
+            Ok(None) => { return Err(VaultError::Corrupt { file: "note", source: None }); }
+            Err(e) => { return Err(VaultError::corrupt("note", e)); }
+        };
+
+        Ok(Self { doc, text, converted: false })
+    }
+
+    /// Whether the document was read from a note written by an older version,
+    /// which has no history to merge with.
+    pub fn is_converted(&self) -> bool {
+        self.converted
+    }
+
+    /// The current text of the note.
+    pub fn text(&self) -> String {
+        self.doc.text(&self.text).unwrap_or_default()
+    }
+
+    /// Changes the text to `text`. Only the difference with the current text is
+    /// recorded, so that it merges with edits made elsewhere.
+    pub fn update(&mut self, text: &str) -> Result<(), VaultError> {
+        self.doc.update_text(&self.text, text).map_err(crdt_error)
+    }
+
+    /// Length of the text, in characters.
+    pub fn len(&self) -> usize {
+        self.doc.length(&self.text)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replaces `deleted` characters from the character `position` with
+    /// `text`, as a single edit made in a text editor.
+    pub fn splice(&mut self, position: usize, deleted: usize, text: &str) -> Result<(), VaultError> {
+        self.doc.splice_text(&self.text, position, deleted as isize, text).map_err(crdt_error)
+    }
+
+    /// Adds the edits of `other` that this document doesn't have yet.
+    pub fn merge(&mut self, other: &mut NoteDocument) -> Result<(), VaultError> {
+        self.doc.merge(&mut other.doc).map(|_| ()).map_err(crdt_error)
+    }
+
+    /// The document as stored in the note's file, before encryption.
+    pub fn save(&mut self) -> Vec<u8> {
+        self.doc.save()
+    }
+}
+
+impl Default for NoteDocument {
+    fn default() -> Self {
+        Self::from_text("")
+    }
+}
+
+/// Whether the decrypted contents of a note's file are a document, and not
+/// the text of a note written by an older version.
+pub(crate) fn is_document(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+fn crdt_error(error: AutomergeError) -> VaultError {
+    VaultError::corrupt("note", error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Copies of the document on two devices.
+    fn copies(document: &mut NoteDocument) -> ( NoteDocument, NoteDocument ) {
+        let data = document.save();
+        ( NoteDocument::load(&data).unwrap(), NoteDocument::load(&data).unwrap() )
+    }
+
+    #[test]
+    fn saves_and_loads_the_text() {
+        let mut document = NoteDocument::from_text("# Groceries\n- milk");
+        document.update("# Groceries\n- milk\n- café").unwrap();
+
+        let data = document.save();
+        assert!(is_document(&data));
+
+        let loaded = NoteDocument::load(&data).unwrap();
+        assert_eq!(loaded.text(), "# Groceries\n- milk\n- café");
+        assert!(!loaded.is_converted());
+    }
+
+    #[test]
+    fn merges_concurrent_edits() {
+        let ( mut a, mut b ) = copies(&mut NoteDocument::from_text("Hello world"));
+
+        a.update("Hello brave world").unwrap();
+        b.update("Hello world!").unwrap();
+        a.merge(&mut b).unwrap();
+        b.merge(&mut a).unwrap();
+
+        assert_eq!(a.text(), "Hello brave world!");
+        assert_eq!(b.text(), "Hello brave world!");
+    }
+
+    #[test]
+    fn merges_concurrent_splices() {
+        let ( mut a, mut b ) = copies(&mut NoteDocument::from_text("Café crème"));
+        assert_eq!(a.len(), 10);
+
+        a.splice(4, 1, ", thé\n").unwrap();
+        b.splice(10, 0, " brûlée").unwrap();
+        a.merge(&mut b).unwrap();
+
+        assert_eq!(a.text(), "Café, thé\ncrème brûlée");
+        assert!(NoteDocument::default().is_empty());
+    }
+
+    #[test]
+    fn merges_the_edits_of_a_note_converted_on_two_devices() {
+        let mut a = NoteDocument::load(b"Hello world").unwrap();
+        let mut b = NoteDocument::load(b"Hello world").unwrap();
+        assert!(a.is_converted());
+        assert_eq!(a.text(), "Hello world");
+
+        a.update("Hello brave world").unwrap();
+        b.update("Hello world!").unwrap();
+        a.merge(&mut b).unwrap();
+
+        assert_eq!(a.text(), "Hello brave world!");
+    }
+
+    #[test]
+    fn merges_documents_converted_from_different_texts() {
+        let mut a = NoteDocument::load(b"one").unwrap();
+        let mut b = NoteDocument::load(b"two").unwrap();
+
+        a.merge(&mut b).unwrap();
+        assert!(a.text() == "one" || a.text() == "two");
+
+        let mut c = NoteDocument::default();
+        c.merge(&mut NoteDocument::from_text("three")).unwrap();
+    }
+
+    #[test]
+    fn refuses_damaged_notes() {
+        assert!(NoteDocument::load(&[ 0xff, 0xfe ]).is_err());
+
+        let mut data = NoteDocument::from_text("text").save();
+        data.truncate(data.len() / 2);
+        assert!(NoteDocument::load(&data).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+///
+/// Exports the notes of a vault as plain, unencrypted text files.
+///
+/// Every note is written to a ".md" file named after the note, inside
+/// directories named after its folders:
+///
+/// + <export directory>\
+///   + <folder>\
+///     + <note>.md
+///   + <note>.md
+///
+/// Notes with the same name in the same folder get a number appended, e.g.
+/// "Todo (2).md".
+///
+use std::{
+    collections::HashSet, fs::{ File, create_dir_all }, io::Write, path::Path,
+};
+use tracing::{ debug, info };
+
+use crate::utils::{ crypto::VaultKey, notes::{ read_index, read_note }, storage::VaultStorage };
+
+/// Exports all notes of the vault into `out_dir` and returns how many notes
+/// were exported.
+pub fn export_notes(storage: &dyn VaultStorage, key: &VaultKey, out_dir: &Path) -> Result<usize, String> {
+    let index = read_index(storage, key)?;
+    let mut used_paths = HashSet::new();
+    let mut exported = 0;
+
+    for note in &index.notes {
+        let mut note_dir = out_dir.to_path_buf();
+
+        for folder in index.folder_path(note.parent_folder) {
+            note_dir.push(file_name(folder));
+        }
+
+        if let Err(e) = create_dir_all(&note_dir) {
+            debug!(note = note.id, error = %e, "could not create export directory");
+            return Err(format!("Couldn't create directory {}", note_dir.display()));
+        }
+
+        let name = file_name(&note.name);
+        let mut note_path = note_dir.join(format!("{}.md", name));
+        let mut n = 2;
+
+        while used_paths.contains(&note_path) || note_path.exists() {
+            note_path = note_dir.join(format!("{} ({}).md", name, n));
+            n += 1;
+        }
+
+        let text = read_note(storage, key, note.id)?;
+
+        match File::create(&note_path) {
+            Ok(mut file) => {
+                if let Err(e) = file.write_all(text.as_bytes()) {
+                    debug!(note = note.id, error = %e, "could not write exported note");
+                    return Err(format!("Error while writing {}", note_path.display()));
+                }
+            }
+
+            Err(e) => {
+                debug!(note = note.id, error = %e, "could not create exported note");
+                return Err(format!("Couldn't create {}", note_path.display()));
+            }
+        }
+
+        used_paths.insert(note_path);
+        exported += 1;
+    }
+
+    info!(notes = exported, "exported notes");
+    Ok(exported)
+}
+
+/// Replaces the characters that aren't allowed in file names.
+fn file_name(name: &str) -> String {
+    let name = name.trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+
+    match name.trim_matches('.') {
+        "" => String::from("Untitled"),
+        _ => name,
+    }
+}
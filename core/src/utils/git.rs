@@ -0,0 +1,1104 @@
+///
+/// Keeping the history of a vault in git, and syncing it through a git remote.
+///
+/// When turned on (see [`enable_git`]), the vault's directory is a git
+/// repository and every save is committed (see [`commit_vault`]). Only the
+/// files of the vault are committed, as they are stored on the disk: the
+/// commits hold ciphertext, and their messages only name ids, e.g.
+/// "Save note 12". Conflict copies and temporary files are ignored.
+///
+/// **Removed and changed passwords still unlock the vault.** The history keeps
+/// every version of the info file, and an old one still opens with the
+/// passwords the vault had back then. As all of them hold the same master key,
+/// an old password decrypts the current notes as well, for anyone with a copy
+/// of the history, e.g. of the remote. Removing a key slot only protects the
+/// vault if its history is never shared; if a password leaked, the notes have
+/// to be moved to a new vault.
+///
+/// [`git_sync`] pulls from and pushes to the "origin" remote (see
+/// [`set_git_remote`]) with a built-in git implementation, git doesn't need to
+/// be installed. The remote can be a path, e.g. a bare repository on a USB
+/// drive, or an SSH or HTTPS URL: SSH keys are taken from the SSH agent and
+/// passwords from git's credential helpers.
+///
+/// Changes made on both sides are merged file by file. This isn't done by
+/// git merge drivers: libgit2 doesn't run them, and they couldn't be given the
+/// vault's key. Instead libgit2 merges the two commits, every conflicting
+/// file is resolved afterwards by the vault, and only then is the merge
+/// committed. This is done on a copy of the merged files in memory, the
+/// vault's files are only replaced once it succeeded:
+/// - Conflicts in the index are resolved by decrypting both sides and taking
+///   the union of their folders and notes (see
+///   [`sync`](crate::utils::sync)), except those deleted on one side and left
+///   alone on the other.
+/// - The edits made to a note on both sides are merged (see
+///   [`document`](crate::utils::document)). A note written by an older version
+///   keeps this device's version, the other one is stored next to it as a
+///   conflict copy for the user to choose from.
+/// - The key slots of the info file are merged by their id (see
+///   [`merge_vault_info`]), so that a password added or changed on either side
+///   keeps working. The sync stops if no password would be left.
+///
+use std::{ fmt, fs, path::Path };
+use git2::{
+    build::{ CheckoutBuilder, RepoBuilder }, Commit, Cred, CredentialType, FetchOptions, Index,
+    IndexAddOption, IndexConflict, IndexEntry, IndexTime, ObjectType, Oid, PushOptions,
+    RemoteCallbacks, Repository, RepositoryInitOptions, Signature, Tree, TreeWalkMode,
+    TreeWalkResult,
+};
+use tracing::{ debug, info, warn };
+
+use crate::{
+    types::{
+        vault_index::VaultIndex, vault_index_entry::VaultIndexEntry, vault_error::VaultError,
+        vault_info::VaultInfo,
+    },
+    utils::{
+        crypto::VaultKey,
+        notes::{ read_index, write_index, read_note },
+        storage::{ FileStorage, MemoryStorage, VaultStorage },
+        sync::{ merge_index, reconcile },
+        unix_now,
+        vault::{ merge_vault_info, read_vault_info, write_vault_info },
+    },
+};
+
+/// Name of the remote the vault is synced with.
+pub const REMOTE: &str = "origin";
+
+/// Branch of new repositories.
+const BRANCH: &str = "main";
+
+/// Times a push is tried again after the remote changed in the meantime.
+const PUSH_RETRIES: u32 = 3;
+
+/// Every file is binary, git must never merge two versions line by line.
+const GITATTRIBUTES: &str = "* binary\n";
+
+/// Conflict copies (see [`sync`](crate::utils::sync)) are local to the device,
+/// and hidden files are temporary files of atomic writes.
+const GITIGNORE: &str = "*.sync-conflict-*\n*conflicted copy*\n.*.tmp\n";
+
+/// A commit of the vault's history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// Id of the commit, as hex.
+    pub id: String,
+
+    /// Unix time of the commit.
+    pub time: u64,
+
+    pub message: String,
+}
+
+/// Summary of what [`git_sync`] did.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GitSyncReport {
+    /// Commits taken from the remote.
+    pub pulled: usize,
+
+    /// Commits sent to the remote.
+    pub pushed: usize,
+
+    /// Whether the history had diverged and was merged.
+    pub merged: bool,
+
+    /// Notes and attachments changed on both sides, the remote's version is
+    /// in a conflict copy.
+    pub conflicts: Vec<String>,
+}
+
+impl fmt::Display for GitSyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pulled commits:  {}", self.pulled)?;
+        write!(f, "Pushed commits:  {}", self.pushed)?;
+
+        if self.merged {
+            write!(f, "\nMerged with the remote")?;
+        }
+
+        if !self.conflicts.is_empty() {
+            write!(f, "\nConflicts:       {}", self.conflicts.len())?;
+
+            for path in &self.conflicts {
+                write!(f, "\n  - {}", path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the vault's history is kept in git.
+pub fn is_git_vault(storage: &FileStorage) -> bool {
+    storage.root().join(".git").is_dir()
+}
+
+/// Makes the vault a git repository and commits its files.
+///
+/// Nothing changes for a vault that already is one.
+pub fn enable_git(storage: &FileStorage) -> Result<(), VaultError> {
+    if !is_git_vault(storage) {
+        let mut options = RepositoryInitOptions::new();
+        options.initial_head(BRANCH);
+
+        if let Err(e) = Repository::init_opts(storage.root(), &options) {
+            return Err(VaultError::git("Couldn't create the git repository", e));
+        }
+
+        info!("turned on the vault's history");
+    }
+
+    for ( name, contents ) in [ ( ".gitattributes", GITATTRIBUTES ), ( ".gitignore", GITIGNORE ) ] {
+        if let Err(e) = fs::write(storage.root().join(name), contents) {
+            return Err(VaultError::io("Couldn't write the git settings of the vault", e));
+        }
+    }
+
+    commit_vault(storage, "Turn on history")?;
+
+    Ok(())
+}
+
+/// Downloads a vault from a git remote into `storage`, which must not exist
+/// yet.
+pub fn clone_vault(storage: &FileStorage, url: &str) -> Result<(), VaultError> {
+    if storage.root().exists() {
+        return Err(VaultError::Invalid(String::from("A vault with this name already exists")));
+    }
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks(git2::Config::open_default().ok()));
+
+    // The remote's HEAD may name another branch, e.g. in a bare repository
+    let result = RepoBuilder::new()
+        .branch(BRANCH)
+        .fetch_options(fetch_options)
+        .clone(url, storage.root());
+
+    match result {
+        Ok(_) => {
+            info!("cloned the vault");
+            Ok(())
+        }
+
+        Err(e) => Err(VaultError::git("Couldn't download the vault", e)),
+    }
+}
+
+/// Sets the URL of the remote the vault is synced with.
+pub fn set_git_remote(storage: &FileStorage, url: &str) -> Result<(), VaultError> {
+    let repo = open(storage)?;
+
+    let result = match repo.find_remote(REMOTE) {
+        Ok(_) => repo.remote_set_url(REMOTE, url),
+        Err(_) => repo.remote(REMOTE, url).map(|_| ()),
+    };
+
+    result.map_err(|e| VaultError::git("Couldn't set the git remote", e))
+}
+
+/// Commits the changes of the vault's files, if the vault's history is kept in
+/// git.
+///
+/// `message` must not tell anything about the notes but their ids. Returns
+/// whether a commit was made, there is none when nothing changed.
+pub fn commit_vault(storage: &FileStorage, message: &str) -> Result<bool, VaultError> {
+    if !is_git_vault(storage) {
+        return Ok(false);
+    }
+
+    let repo = open(storage)?;
+    let parent = head_commit(&repo)?;
+
+    let tree = stage_all(&repo)?;
+
+    if parent.as_ref().is_some_and(|p| p.tree_id() == tree.id()) {
+        return Ok(false);
+    }
+
+    let parents = parent.iter().collect::<Vec<_>>();
+    let id = commit(&repo, message, &tree, &parents)?;
+
+    debug!(commit = %id, "committed the vault");
+
+    Ok(true)
+}
+
+/// Pulls the remote's commits, merges them with the vault's and pushes the
+/// result.
+///
+/// Uncommitted changes are committed first. The key is needed to merge the
+/// index when it was changed on both sides.
+pub fn git_sync(storage: &FileStorage, key: &VaultKey) -> Result<GitSyncReport, VaultError> {
+    commit_vault(storage, "Save changes")?;
+
+    let repo = open(storage)?;
+    let branch = branch_name(&repo)?;
+    let mut report = GitSyncReport::default();
+
+    let mut remote = match repo.find_remote(REMOTE) {
+        Ok(remote) => remote,
+        Err(_) => {
+            return Err(VaultError::Invalid(String::from(
+                "The vault has no git remote, please set one first"
+            )));
+        }
+    };
+
+    for attempt in 0..=PUSH_RETRIES {
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks(repo.config().ok()));
+
+        let refspec = format!("+refs/heads/{0}:refs/remotes/{1}/{0}", branch, REMOTE);
+
+        if let Err(e) = remote.fetch(&[ refspec.as_str() ], Some(&mut fetch_options), None) {
+            return Err(VaultError::git("Couldn't pull from the git remote", e));
+        }
+
+        pull(storage, &repo, key, &branch, &mut report)?;
+
+        let ( local, upstream ) = ( head_commit(&repo)?, remote_commit(&repo, &branch)? );
+
+        let ahead = match ( &local, &upstream ) {
+            ( Some(local), Some(upstream) ) => repo.graph_ahead_behind(local.id(), upstream.id())
+                .map(|( ahead, _ )| ahead)
+                .map_err(|e| VaultError::git("Couldn't compare with the git remote", e))?,
+
+            ( Some(local), None ) => count_commits(&repo, local.id())?,
+            ( None, _ ) => 0,
+        };
+
+        if ahead == 0 {
+            return Ok(report);
+        }
+
+        let mut rejected = None;
+
+        {
+            let mut callbacks = callbacks(repo.config().ok());
+
+            callbacks.push_update_reference(|_, status| {
+                rejected = status.map(String::from);
+                Ok(())
+            });
+
+            let mut push_options = PushOptions::new();
+            push_options.remote_callbacks(callbacks);
+
+            let refspec = format!("refs/heads/{0}:refs/heads/{0}", branch);
+
+            if let Err(e) = remote.push(&[ refspec.as_str() ], Some(&mut push_options)) {
+                return Err(VaultError::git("Couldn't push to the git remote", e));
+            }
+        }
+
+        match rejected {
+            None => {
+                report.pushed += ahead;
+                info!(commits = ahead, "pushed the vault");
+                return Ok(report);
+            }
+
+            // Another device pushed since the fetch
+            Some(status) => {
+                debug!(attempt, status = %status, "push was rejected, pulling again");
+            }
+        }
+    }
+
+    Err(VaultError::Invalid(String::from(
+        "The git remote keeps changing, please sync again later"
+    )))
+}
+
+/// Lists the commits of the vault, the last one first.
+pub fn history(storage: &FileStorage) -> Result<Vec<HistoryEntry>, VaultError> {
+    let repo = open(storage)?;
+
+    let head = match head_commit(&repo)? {
+        Some(head) => head,
+        None => { return Ok(vec![]); }
+    };
+
+    let mut walk = repo.revwalk().map_err(|e| VaultError::git("Couldn't read the history", e))?;
+
+    if let Err(e) = walk.push(head.id()) {
+        return Err(VaultError::git("Couldn't read the history", e));
+    }
+
+    let mut entries = vec![];
+
+    for id in walk {
+        let commit = id
+            .and_then(|id| repo.find_commit(id))
+            .map_err(|e| VaultError::git("Couldn't read the history", e))?;
+
+        entries.push(HistoryEntry {
+            id: commit.id().to_string(),
+            time: commit.time().seconds().max(0) as u64,
+            message: commit.summary().unwrap_or_default().to_string(),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Reads the index of the vault as it was at `revision`, e.g. a commit id or
+/// "HEAD~2".
+pub fn read_index_at(
+    storage: &FileStorage,
+    key: &VaultKey,
+    revision: &str,
+) -> Result<VaultIndex, VaultError> {
+    let repo = open(storage)?;
+    let tree = revision_tree(&repo, revision)?;
+
+    read_index(&file_at(&repo, &tree, "index")?, key)
+}
+
+/// Reads the note with the given id as it was at `revision`.
+pub fn read_note_at(
+    storage: &FileStorage,
+    key: &VaultKey,
+    revision: &str,
+    id: u32,
+) -> Result<String, VaultError> {
+    let repo = open(storage)?;
+    let tree = revision_tree(&repo, revision)?;
+
+    read_note(&file_at(&repo, &tree, &format!("notes/{}", id))?, key, id)
+}
+
+fn open(storage: &FileStorage) -> Result<Repository, VaultError> {
+    match Repository::open(storage.root()) {
+        Ok(repo) => Ok(repo),
+        Err(e) => Err(VaultError::git("The vault's history is not turned on", e)),
+    }
+}
+
+/// Brings the remote's commits into the vault: the branch is moved to them if
+/// the vault has no commits of its own, otherwise they are merged.
+fn pull(
+    storage: &FileStorage,
+    repo: &Repository,
+    key: &VaultKey,
+    branch: &str,
+    report: &mut GitSyncReport,
+) -> Result<(), VaultError> {
+    let theirs = match remote_commit(repo, branch)? {
+        Some(commit) => commit,
+        None => { return Ok(()); }
+    };
+
+    let ours = match head_commit(repo)? {
+        Some(commit) => commit,
+        None => {
+            report.pulled += count_commits(repo, theirs.id())?;
+            return fast_forward(repo, branch, &theirs);
+        }
+    };
+
+    let ( ahead, behind ) = repo.graph_ahead_behind(ours.id(), theirs.id())
+        .map_err(|e| VaultError::git("Couldn't compare with the git remote", e))?;
+
+    if behind == 0 {
+        return Ok(());
+    }
+
+    report.pulled += behind;
+
+    if ahead == 0 {
+        debug!(commits = behind, "fast-forwarding the vault");
+        return fast_forward(repo, branch, &theirs);
+    }
+
+    merge(storage, repo, key, &ours, &theirs, report)
+}
+
+/// Moves the branch to `commit` and checks its files out.
+fn fast_forward(repo: &Repository, branch: &str, commit: &Commit) -> Result<(), VaultError> {
+    let tree = commit.tree().map_err(|e| VaultError::git("Couldn't read the remote's files", e))?;
+
+    checkout(repo, &tree)?;
+
+    let result = repo.reference(
+        &format!("refs/heads/{}", branch), commit.id(), true, "sync: fast-forward",
+    );
+
+    if let Err(e) = result.and_then(|_| repo.set_head(&format!("refs/heads/{}", branch))) {
+        return Err(VaultError::git("Couldn't update the vault's branch", e));
+    }
+
+    Ok(())
+}
+
+/// Merges `theirs` into `ours` and commits the result.
+fn merge(
+    storage: &FileStorage,
+    repo: &Repository,
+    key: &VaultKey,
+    ours: &Commit,
+    theirs: &Commit,
+    report: &mut GitSyncReport,
+) -> Result<(), VaultError> {
+    let mut merged = repo.merge_commits(ours, theirs, None)
+        .map_err(|e| VaultError::git("Couldn't merge with the git remote", e))?;
+
+    let conflicts = merged.conflicts()
+        .and_then(|conflicts| conflicts.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| VaultError::git("Couldn't merge with the git remote", e))?;
+
+    let mut copies = vec![];
+    let mut index_conflict = None;
+    let mut info_conflict = None;
+
+    for conflict in conflicts {
+        let path = match conflict_path(&conflict) {
+            Some(path) => path,
+            None => { continue; }
+        };
+
+        let ancestor = conflict.ancestor.as_ref().map(|a| a.id);
+        let their = conflict.their.as_ref().map(|t| t.id);
+
+        let kept = keep_ours(&mut merged, &path, conflict)
+            .map_err(|e| VaultError::git("Couldn't merge with the git remote", e))?;
+
+        let their_blob = match ( kept, their ) {
+            ( true, Some(id) ) => id,
+            _ => { continue; }
+        };
+
+        match path.as_str() {
+            "index" => { index_conflict = Some(( ancestor, their_blob )); }
+            "info" => { info_conflict = Some(( ancestor, their_blob )); }
+            _ => { copies.push(( path, their_blob )); }
+        }
+    }
+
+    // Merged before anything is written, the vault stays as it is if its key
+    // slots can't be merged
+    let info = match info_conflict {
+        Some(( Some(base), theirs )) => Some(merge_vault_info(
+            &read_blob_info(repo, base)?,
+            read_vault_info(storage)?,
+            read_blob_info(repo, theirs)?,
+        )?),
+
+        Some(( None, _ )) => {
+            return Err(VaultError::Invalid(String::from(
+                "The remote holds another vault, its info file doesn't share any history with this one"
+            )));
+        }
+
+        None => None,
+    };
+
+    let tree = merged.write_tree_to(repo)
+        .and_then(|id| repo.find_tree(id))
+        .map_err(|e| VaultError::git("Couldn't merge with the git remote", e))?;
+
+    // The conflicts are resolved in memory, the vault stays as it is if they
+    // can't be
+    let files = tree_files(repo, &tree)?;
+
+    if let Some(info) = info {
+        write_vault_info(&files, &info)?;
+        debug!("merged the key slots with the remote's");
+    }
+
+    let now = unix_now();
+    let mut written = vec![];
+
+    for ( path, blob ) in copies {
+        let copy = format!("{}.sync-conflict-{}-git", path, now);
+
+        if let Err(e) = files.write(&copy, &blob_data(repo, blob)?) {
+            return Err(VaultError::io("Couldn't write the conflict copy", e));
+        }
+
+        written.push(( path, copy ));
+    }
+
+    if let Some(( base, theirs )) = index_conflict {
+        merge_index_versions(&files, repo, key, base, theirs)?;
+    }
+
+    // Merges the edits of both sides into the notes
+    reconcile(&files, key)?;
+
+    // Copies of notes that got a new id were used up by merging the index,
+    // the others by merging the notes. The ones left aren't committed.
+    written.retain(|( _, copy )| files.exists(copy));
+    let left: Vec<String> = written.iter().map(|( _, copy )| copy.clone()).collect();
+
+    let tree = write_tree(repo, &files, &left)?;
+
+    let result = checkout(repo, &tree)
+        .and_then(|_| write_conflict_copies(storage, &files, &left))
+        .and_then(|_| commit(repo, &format!("Merge {}", REMOTE), &tree, &[ ours, theirs ]));
+
+    let id = match result {
+        Ok(id) => id,
+        Err(e) => {
+            // Back to this device's files, the sync can be tried again
+            for copy in &left {
+                let _ = storage.delete(copy);
+            }
+
+            let restored = ours.tree()
+                .map_err(|e| VaultError::git("Couldn't read the vault's last commit", e))
+                .and_then(|tree| checkout(repo, &tree));
+
+            if let Err(e) = restored {
+                warn!(error = %e, "could not restore the vault's files after a failed merge");
+            }
+
+            return Err(e);
+        }
+    };
+
+    report.conflicts.extend(written.into_iter().map(|( path, _ )| path));
+    report.merged = true;
+    info!(commit = %id, conflicts = report.conflicts.len(), "merged the vault with the remote");
+
+    Ok(())
+}
+
+/// Resolves a conflict in the index after the merge: decrypts this device's
+/// index, the remote's and the one they both come from, and writes the union
+/// of their entries.
+///
+/// Entries deleted on one side are left out if the other side didn't change
+/// them, so that deleted notes don't come back.
+fn merge_index_versions(
+    storage: &dyn VaultStorage,
+    repo: &Repository,
+    key: &VaultKey,
+    base: Option<Oid>,
+    theirs: Oid,
+) -> Result<(), VaultError> {
+    let ours = read_index(storage, key)?;
+    let mut other = read_blob_index(repo, key, theirs)?;
+
+    let base = match base {
+        Some(id) => read_blob_index(repo, key, id)?,
+        None => VaultIndex::default(),
+    };
+
+    let mut index = ours.clone();
+    drop_deleted(&mut index, &base, &other);
+    drop_deleted(&mut other, &base, &ours);
+
+    let moved = merge_index(storage, &mut index, other)?;
+    write_index(storage, key, &index)?;
+
+    for file in moved {
+        if let Err(e) = storage.delete(&file) {
+            warn!(file = %file, error = %e, "could not delete merged conflict copy");
+        }
+    }
+
+    debug!("merged the index with the remote's");
+
+    Ok(())
+}
+
+/// Drops the entries of `index` that are unchanged since `base` and were
+/// deleted in `other`.
+fn drop_deleted(index: &mut VaultIndex, base: &VaultIndex, other: &VaultIndex) {
+    let deleted = |entry: &VaultIndexEntry, base: &[VaultIndexEntry], other: &[VaultIndexEntry]| {
+        base.contains(entry) && !other.iter().any(|o| o.id == entry.id)
+    };
+
+    index.folders.retain(|f| !deleted(f, &base.folders, &other.folders));
+    index.notes.retain(|n| !deleted(n, &base.notes, &other.notes));
+}
+
+fn read_blob_index(repo: &Repository, key: &VaultKey, id: Oid) -> Result<VaultIndex, VaultError> {
+    let storage = MemoryStorage::new();
+
+    if let Err(e) = storage.write("index", &blob_data(repo, id)?) {
+        return Err(VaultError::io("Couldn't read the remote's index", e));
+    }
+
+    read_index(&storage, key)
+}
+
+fn read_blob_info(repo: &Repository, id: Oid) -> Result<VaultInfo, VaultError> {
+    let storage = MemoryStorage::new();
+
+    if let Err(e) = storage.write("info", &blob_data(repo, id)?) {
+        return Err(VaultError::io("Couldn't read the remote's info file", e));
+    }
+
+    read_vault_info(&storage)
+}
+
+/// Resolves a conflict by keeping this device's version of the file, or the
+/// one that wasn't deleted. Returns whether this device's version was kept.
+fn keep_ours(merged: &mut Index, path: &str, conflict: IndexConflict) -> Result<bool, git2::Error> {
+    merged.conflict_remove(Path::new(path))?;
+
+    let ( mut entry, ours ) = match ( conflict.our, conflict.their ) {
+        ( Some(our), _ ) => ( our, true ),
+        ( None, Some(their) ) => ( their, false ),
+        ( None, None ) => { return Ok(false); }
+    };
+
+    // Clear the stage of the conflict
+    entry.flags &= !0x3000;
+    merged.add(&entry)?;
+
+    Ok(ours)
+}
+
+fn conflict_path(conflict: &IndexConflict) -> Option<String> {
+    [ &conflict.our, &conflict.their, &conflict.ancestor ].into_iter()
+        .flatten()
+        .next()
+        .and_then(|entry| String::from_utf8(entry.path.clone()).ok())
+}
+
+/// Reads the files of `tree` into memory.
+fn tree_files(repo: &Repository, tree: &Tree) -> Result<MemoryStorage, VaultError> {
+    let files = MemoryStorage::new();
+    let mut result = Ok(());
+
+    let walked = tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        let name = match entry.name() {
+            Some(name) if entry.kind() == Some(ObjectType::Blob) => name,
+            _ => { return TreeWalkResult::Ok; }
+        };
+
+        result = blob_data(repo, entry.id()).and_then(|data| {
+            files.write(&format!("{}{}", dir, name), &data)
+                .map_err(|e| VaultError::io("Couldn't read the merged files", e))
+        });
+
+        if result.is_ok() { TreeWalkResult::Ok } else { TreeWalkResult::Abort }
+    });
+
+    result?;
+    walked.map_err(|e| VaultError::git("Couldn't read the merged files", e))?;
+
+    Ok(files)
+}
+
+/// Writes the files into a tree of the repository, except those `left_out`.
+fn write_tree<'r>(
+    repo: &'r Repository,
+    files: &MemoryStorage,
+    left_out: &[String],
+) -> Result<Tree<'r>, VaultError> {
+    let mut index = Index::new().map_err(|e| VaultError::git("Couldn't merge with the git remote", e))?;
+
+    for path in files.paths().into_iter().filter(|path| !left_out.contains(path)) {
+        let data = match files.read(&path) {
+            Ok(data) => data,
+            Err(e) => { return Err(VaultError::io("Couldn't read the merged files", e)); }
+        };
+
+        let result = repo.blob(&data).and_then(|id| index.add(&IndexEntry {
+            ctime: IndexTime::new(0, 0),
+            mtime: IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode: 0o100644,
+            uid: 0,
+            gid: 0,
+            file_size: data.len() as u32,
+            id,
+            flags: 0,
+            flags_extended: 0,
+            path: path.into_bytes(),
+        }));
+
+        if let Err(e) = result {
+            return Err(VaultError::git("Couldn't merge with the git remote", e));
+        }
+    }
+
+    index.write_tree_to(repo)
+        .and_then(|id| repo.find_tree(id))
+        .map_err(|e| VaultError::git("Couldn't merge with the git remote", e))
+}
+
+/// Writes the conflict copies made by the merge into the vault, where they
+/// aren't committed.
+fn write_conflict_copies(storage: &FileStorage, files: &MemoryStorage, copies: &[String]) -> Result<(), VaultError> {
+    for copy in copies {
+        if let Err(e) = files.read(copy).and_then(|data| storage.write(copy, &data)) {
+            return Err(VaultError::io("Couldn't write the conflict copy", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Stages the changes of all the files and returns the resulting tree.
+fn stage_all(repo: &Repository) -> Result<Tree<'_>, VaultError> {
+    let result = repo.index().and_then(|mut index| {
+        index.add_all([ "*" ], IndexAddOption::DEFAULT, None)?;
+        index.update_all([ "*" ], None)?;
+        index.write()?;
+
+        let id = index.write_tree()?;
+        repo.find_tree(id)
+    });
+
+    result.map_err(|e| VaultError::git("Couldn't stage the vault's files", e))
+}
+
+fn commit(
+    repo: &Repository,
+    message: &str,
+    tree: &Tree,
+    parents: &[ &Commit ],
+) -> Result<Oid, VaultError> {
+    // Commits don't tell who wrote them, or where
+    let result = Signature::now("Secure Notes", "secure-notes@localhost")
+        .and_then(|signature| {
+            repo.commit(Some("HEAD"), &signature, &signature, message, tree, parents)
+        });
+
+    result.map_err(|e| VaultError::git("Couldn't commit the vault", e))
+}
+
+/// Writes the files of `tree` into the vault, replacing its committed files.
+fn checkout(repo: &Repository, tree: &Tree) -> Result<(), VaultError> {
+    let mut options = CheckoutBuilder::new();
+    options.force();
+
+    match repo.checkout_tree(tree.as_object(), Some(&mut options)) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(VaultError::git("Couldn't write the merged files", e)),
+    }
+}
+
+fn head_commit(repo: &Repository) -> Result<Option<Commit<'_>>, VaultError> {
+    match repo.head() {
+        Ok(head) => head.peel_to_commit()
+            .map(Some)
+            .map_err(|e| VaultError::git("Couldn't read the vault's last commit", e)),
+
+        // No commit yet
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch || e.code() == git2::ErrorCode::NotFound => {
+            Ok(None)
+        }
+
+        Err(e) => Err(VaultError::git("Couldn't read the vault's last commit", e)),
+    }
+}
+
+fn remote_commit<'r>(repo: &'r Repository, branch: &str) -> Result<Option<Commit<'r>>, VaultError> {
+    match repo.find_reference(&format!("refs/remotes/{}/{}", REMOTE, branch)) {
+        Ok(reference) => reference.peel_to_commit()
+            .map(Some)
+            .map_err(|e| VaultError::git("Couldn't read the remote's last commit", e)),
+
+        Err(_) => Ok(None),
+    }
+}
+
+/// Name of the branch HEAD points to, even when it has no commit yet.
+fn branch_name(repo: &Repository) -> Result<String, VaultError> {
+    let head = repo.find_reference("HEAD")
+        .map_err(|e| VaultError::git("Couldn't read the vault's branch", e))?;
+
+    Ok(head.symbolic_target()
+        .and_then(|target| target.strip_prefix("refs/heads/"))
+        .unwrap_or(BRANCH)
+        .to_string())
+}
+
+fn count_commits(repo: &Repository, from: Oid) -> Result<usize, VaultError> {
+    let result = repo.revwalk().and_then(|mut walk| {
+        walk.push(from)?;
+        Ok(walk.count())
+    });
+
+    result.map_err(|e| VaultError::git("Couldn't read the history", e))
+}
+
+fn revision_tree<'r>(repo: &'r Repository, revision: &str) -> Result<Tree<'r>, VaultError> {
+    match repo.revparse_single(revision).and_then(|object| object.peel_to_tree()) {
+        Ok(tree) => Ok(tree),
+        Err(e) => Err(VaultError::git("Couldn't find this version of the vault", e)),
+    }
+}
+
+/// Puts the file at `path` of the tree into a storage of its own, to be read
+/// with the usual functions.
+fn file_at(repo: &Repository, tree: &Tree, path: &str) -> Result<MemoryStorage, VaultError> {
+    let entry = tree.get_path(Path::new(path))
+        .map_err(|e| VaultError::git("The file didn't exist in this version of the vault", e))?;
+
+    if entry.kind() != Some(ObjectType::Blob) {
+        return Err(VaultError::Invalid(format!("\"{}\" is not a file", path)));
+    }
+
+    let storage = MemoryStorage::new();
+
+    match storage.write(path, &blob_data(repo, entry.id())?) {
+        Ok(()) => Ok(storage),
+        Err(e) => Err(VaultError::io("Couldn't read the file of this version", e)),
+    }
+}
+
+fn blob_data(repo: &Repository, id: Oid) -> Result<Vec<u8>, VaultError> {
+    match repo.find_blob(id) {
+        Ok(blob) => Ok(blob.content().to_vec()),
+        Err(e) => Err(VaultError::git("Couldn't read a file from the history", e)),
+    }
+}
+
+/// Authenticates with the SSH agent, then with git's credential helpers.
+fn callbacks<'a>(config: Option<git2::Config>) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut tried = CredentialType::empty();
+
+    callbacks.credentials(move |url, user, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) && !tried.contains(CredentialType::SSH_KEY) {
+            tried |= CredentialType::SSH_KEY;
+            return Cred::ssh_key_from_agent(user.unwrap_or("git"));
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+            && !tried.contains(CredentialType::USER_PASS_PLAINTEXT)
+        {
+            tried |= CredentialType::USER_PASS_PLAINTEXT;
+
+            if let Some(config) = &config {
+                return Cred::credential_helper(config, url, user);
+            }
+        }
+
+        if allowed.contains(CredentialType::DEFAULT) && !tried.contains(CredentialType::DEFAULT) {
+            tried |= CredentialType::DEFAULT;
+            return Cred::default();
+        }
+
+        Err(git2::Error::from_str("No credentials were accepted by the git remote"))
+    });
+
+    callbacks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ collections::BTreeMap, env, path::PathBuf, process };
+
+    use super::*;
+    use crate::{ types::note_metadata::NoteMetadata, utils::notes::write_note };
+
+    /// Makes an empty directory for the test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("secure-notes-test-{}-git-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    /// Commits the files without moving any branch.
+    fn commit_files<'r>(repo: &'r Repository, files: &[( &str, &str )], parents: &[ &Commit ]) -> Commit<'r> {
+        let mut builder = repo.treebuilder(None).unwrap();
+
+        for ( path, contents ) in files {
+            builder.insert(path, repo.blob(contents.as_bytes()).unwrap(), 0o100644).unwrap();
+        }
+
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@localhost").unwrap();
+        let id = repo.commit(None, &signature, &signature, "Test", &tree, parents).unwrap();
+
+        repo.find_commit(id).unwrap()
+    }
+
+    fn note(id: u32, created: u64, updated: u64) -> VaultIndexEntry {
+        VaultIndexEntry {
+            id,
+            name: format!("Note {}", id),
+            parent_folder: None,
+            metadata: NoteMetadata { created, updated, ..NoteMetadata::default() },
+        }
+    }
+
+    fn index(notes: Vec<VaultIndexEntry>) -> VaultIndex {
+        VaultIndex { notes, ..VaultIndex::default() }
+    }
+
+    /// Encrypts the index into a blob, as it's committed.
+    fn index_blob(repo: &Repository, key: &VaultKey, index: &VaultIndex) -> Oid {
+        let storage = MemoryStorage::new();
+        write_index(&storage, key, index).unwrap();
+
+        repo.blob(&storage.read("index").unwrap()).unwrap()
+    }
+
+    fn ids(index: &VaultIndex) -> Vec<( u32, u64 )> {
+        index.notes.iter().map(|n| ( n.id, n.metadata.created )).collect()
+    }
+
+    #[test]
+    fn keep_ours_keeps_this_devices_version_or_the_one_not_deleted() {
+        let repo = Repository::init(test_dir("keep-ours")).unwrap();
+
+        let base = commit_files(&repo, &[ ( "a", "base" ), ( "b", "base" ), ( "c", "base" ) ], &[]);
+        let ours = commit_files(&repo, &[ ( "a", "ours" ), ( "c", "ours" ) ], &[ &base ]);
+        let theirs = commit_files(&repo, &[ ( "a", "theirs" ), ( "b", "theirs" ) ], &[ &base ]);
+
+        let mut merged = repo.merge_commits(&ours, &theirs, None).unwrap();
+        let conflicts = merged.conflicts().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+
+        let mut kept = BTreeMap::new();
+
+        for conflict in conflicts {
+            let path = conflict_path(&conflict).unwrap();
+            kept.insert(path.clone(), keep_ours(&mut merged, &path, conflict).unwrap());
+        }
+
+        // Changed on both, deleted here, deleted there
+        assert_eq!(kept, BTreeMap::from([
+            ( String::from("a"), true ),
+            ( String::from("b"), false ),
+            ( String::from("c"), true ),
+        ]));
+
+        assert!(!merged.has_conflicts());
+
+        let contents = |path: &str| {
+            let entry = merged.get_path(Path::new(path), 0).unwrap();
+            repo.find_blob(entry.id).unwrap().content().to_vec()
+        };
+
+        assert_eq!(contents("a"), b"ours");
+        assert_eq!(contents("b"), b"theirs");
+        assert_eq!(contents("c"), b"ours");
+    }
+
+    #[test]
+    fn merge_index_versions_keeps_the_changes_of_both_sides() {
+        let dir = test_dir("merge-index");
+        let repo = Repository::init(&dir).unwrap();
+        let storage = FileStorage::new(dir);
+        let key = VaultKey::generate();
+
+        let base = index(vec![ note(1, 10, 10), note(2, 10, 10), note(3, 10, 10) ]);
+
+        // 1 deleted here, 3 changed and 4 added
+        write_index(&storage, &key, &index(vec![ note(2, 10, 10), note(3, 10, 30), note(4, 40, 40) ])).unwrap();
+
+        // 2 and 3 deleted there, and another 4 added
+        let theirs = index(vec![ note(1, 10, 10), note(4, 50, 50) ]);
+
+        let base = index_blob(&repo, &key, &base);
+        let theirs = index_blob(&repo, &key, &theirs);
+
+        merge_index_versions(&storage, &repo, &key, Some(base), theirs).unwrap();
+
+        let merged = read_index(&storage, &key).unwrap();
+
+        assert_eq!(ids(&merged), vec![ ( 3, 10 ), ( 4, 40 ), ( 5, 50 ) ]);
+        assert_eq!(merged.notes[0].metadata.updated, 30);
+    }
+
+    #[test]
+    fn merge_index_versions_without_a_common_version_keeps_everything() {
+        let dir = test_dir("merge-index-unrelated");
+        let repo = Repository::init(&dir).unwrap();
+        let storage = FileStorage::new(dir);
+        let key = VaultKey::generate();
+
+        write_index(&storage, &key, &index(vec![ note(1, 10, 10) ])).unwrap();
+        let theirs = index_blob(&repo, &key, &index(vec![ note(2, 20, 20) ]));
+
+        merge_index_versions(&storage, &repo, &key, None, theirs).unwrap();
+
+        assert_eq!(ids(&read_index(&storage, &key).unwrap()), vec![ ( 1, 10 ), ( 2, 20 ) ]);
+    }
+
+    #[test]
+    fn merge_leaves_the_vault_as_it_was_when_it_fails() {
+        let dir = test_dir("merge-fails");
+        let storage = FileStorage::new(dir.clone());
+        let key = VaultKey::generate();
+
+        write_index(&storage, &key, &index(vec![ note(1, 10, 10) ])).unwrap();
+        enable_git(&storage).unwrap();
+
+        let repo = Repository::open(&dir).unwrap();
+        let base = head_commit(&repo).unwrap().unwrap();
+
+        write_index(&storage, &key, &index(vec![ note(1, 10, 10), note(2, 20, 20) ])).unwrap();
+        commit_vault(&storage, "Save note 2").unwrap();
+        let ours = head_commit(&repo).unwrap().unwrap();
+
+        // The remote's index was written with another key, it can't be merged
+        let other_key = VaultKey::generate();
+        let files = tree_files(&repo, &base.tree().unwrap()).unwrap();
+        write_index(&files, &other_key, &index(vec![ note(1, 10, 10), note(3, 30, 30) ])).unwrap();
+        write_note(&files, &other_key, 3, "theirs").unwrap();
+
+        let tree = write_tree(&repo, &files, &[]).unwrap();
+        let signature = Signature::now("Test", "test@localhost").unwrap();
+        let theirs = repo.commit(None, &signature, &signature, "Save note 3", &tree, &[ &base ]).unwrap();
+        let theirs = repo.find_commit(theirs).unwrap();
+
+        let mut report = GitSyncReport::default();
+        assert!(merge(&storage, &repo, &key, &ours, &theirs, &mut report).is_err());
+
+        assert!(!report.merged);
+        assert!(!storage.exists("notes/3"));
+        assert_eq!(ids(&read_index(&storage, &key).unwrap()), vec![ ( 1, 10 ), ( 2, 20 ) ]);
+        assert_eq!(head_commit(&repo).unwrap().unwrap().id(), ours.id());
+    }
+
+    #[test]
+    fn git_sync_merges_notes_added_on_two_devices() {
+        let dir = test_dir("sync");
+        let remote = dir.join("remote.git");
+        let url = remote.to_str().unwrap();
+        Repository::init_bare(&remote).unwrap();
+
+        let key = VaultKey::generate();
+        let first = FileStorage::new(dir.join("first"));
+        let second = FileStorage::new(dir.join("second"));
+
+        write_index(&first, &key, &index(vec![ note(1, 10, 10) ])).unwrap();
+        enable_git(&first).unwrap();
+        set_git_remote(&first, url).unwrap();
+
+        assert_eq!(git_sync(&first, &key).unwrap().pushed, 1);
+
+        clone_vault(&second, url).unwrap();
+
+        // A note with the same id on each device
+        for ( storage, created, text ) in [ ( &first, 20, "first" ), ( &second, 30, "second" ) ] {
+            let mut added = note(2, created, created);
+            added.metadata.hash = Some(write_note(storage, &key, 2, text).unwrap());
+
+            write_index(storage, &key, &index(vec![ note(1, 10, 10), added ])).unwrap();
+        }
+
+        assert_eq!(git_sync(&first, &key).unwrap().pushed, 1);
+
+        let report = git_sync(&second, &key).unwrap();
+        assert!(report.merged);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(( report.pulled, report.pushed ), ( 1, 2 ));
+
+        let report = git_sync(&first, &key).unwrap();
+        assert_eq!(( report.pulled, report.merged ), ( 2, false ));
+
+        for storage in [ &first, &second ] {
+            let merged = read_index(storage, &key).unwrap();
+
+            assert_eq!(ids(&merged), vec![ ( 1, 10 ), ( 2, 30 ), ( 3, 20 ) ]);
+            assert_eq!(read_note(storage, &key, 2).unwrap(), "second");
+            assert_eq!(read_note(storage, &key, 3).unwrap(), "first");
+        }
+    }
+}
@@ -0,0 +1,431 @@
+///
+/// Imports Evernote's ENEX export files.
+///
+/// An ENEX file is an XML document that contains the notes of a single
+/// notebook:
+///
+/// ```xml
+/// <en-export>
+///   <note>
+///     <title>...</title>
+///     <content><![CDATA[ ...ENML... ]]></content>
+///     <created>20240131T093000Z</created>
+///     <updated>20240131T093000Z</updated>
+///     <tag>...</tag>
+///     <resource>
+///       <data encoding="base64">...</data>
+///       <mime>image/png</mime>
+///       <resource-attributes><file-name>...</file-name></resource-attributes>
+///     </resource>
+///   </note>
+/// </en-export>
+/// ```
+///
+/// - The notebook becomes a folder named after the ENEX file.
+/// - Tags are kept as the note's tags.
+/// - Resources become encrypted attachments of the note.
+/// - Created and updated dates are kept.
+///
+use std::{
+    collections::HashMap, fs::File, io::BufReader, path::Path,
+};
+use quick_xml::{ events::Event, Reader };
+use base64::{ Engine, engine::general_purpose::STANDARD };
+use md5::{ Md5, Digest };
+use tracing::{ debug, warn };
+
+use crate::{
+    types::{
+        vault_index::VaultIndex, vault_index_entry::VaultIndexEntry,
+        note_metadata::{ NoteMetadata, NoteAttachment },
+    },
+    utils::{
+        crypto::VaultKey,
+        notes::{ read_index, write_index, write_attachment },
+        storage::VaultStorage,
+        import::{
+            enml::enml_to_text, get_or_create_folder, store_note, ImportReport, TextFormat,
+        },
+        unix_now, unix_time,
+    },
+};
+
+#[derive(Debug, Default)]
+struct EnexResource {
+    data: Vec<u8>,
+    mime: String,
+    file_name: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct EnexNote {
+    title: String,
+    content: String,
+    created: Option<u64>,
+    updated: Option<u64>,
+    tags: Vec<String>,
+    resources: Vec<EnexResource>,
+}
+
+/// Imports the notes in the ENEX file into the vault.
+///
+/// The notes are put inside a folder named after the file (which is what
+/// Evernote names the export after), the folder is created if it doesn't
+/// exist.
+pub fn import_enex(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    enex_path: &Path,
+    format: TextFormat,
+) -> Result<ImportReport, String> {
+    let notes = parse_enex(enex_path)?;
+
+    let mut index = read_index(storage, key)?;
+    let mut report = ImportReport::default();
+
+    let notebook = enex_path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("Evernote"));
+
+    let folder_id = get_or_create_folder(&mut index, &notebook, None, &mut report);
+
+    for ( i, note ) in notes.into_iter().enumerate() {
+        let title = note.title.clone();
+
+        match import_note(storage, key, &mut index, folder_id, note, format) {
+            Ok(attachments) => {
+                report.notes += 1;
+                report.attachments += attachments;
+            }
+
+            Err(e) => {
+                warn!(note = i, error = %e, "could not import note");
+                report.failed.push((title, e));
+            }
+        }
+    }
+
+    write_index(storage, key, &index)?;
+
+    Ok(report)
+}
+
+/// Writes the note and its attachments, returns the number of attachments.
+fn import_note(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    index: &mut VaultIndex,
+    folder_id: u32,
+    note: EnexNote,
+    format: TextFormat,
+) -> Result<usize, String> {
+    let mut media = HashMap::new();
+    let mut attachments = vec![];
+
+    // Attachment ids must not collide with each other before the note is
+    // added to the index.
+    let mut next_id = index.next_id();
+
+    for (i, resource) in note.resources.iter().enumerate() {
+        let hash = format!("{:x}", Md5::digest(&resource.data));
+
+        let name = resource.file_name.clone()
+            .unwrap_or_else(|| format!("attachment-{}", i + 1));
+
+        let attachment = NoteAttachment {
+            id: next_id,
+            name,
+            mime: resource.mime.clone(),
+        };
+
+        next_id += 1;
+
+        write_attachment(storage, key, attachment.id, &resource.data)?;
+
+        media.insert(hash, attachment.clone());
+        attachments.push(attachment);
+    }
+
+    let text = enml_to_text(&note.content, format, &media)?;
+    let attachment_count = attachments.len();
+
+    let now = unix_now();
+    let created = note.created.unwrap_or(now);
+
+    let title = if note.title.trim().is_empty() {
+        String::from("Untitled Note")
+    } else {
+        note.title
+    };
+
+    let entry = VaultIndexEntry {
+        id: next_id,
+        name: title,
+        parent_folder: Some(folder_id),
+        metadata: NoteMetadata {
+            created,
+            updated: note.updated.unwrap_or(created),
+            tags: note.tags,
+            attachments,
+            source: None,
+            hash: None,
+        },
+    };
+
+    store_note(storage, key, index, entry, &text)?;
+
+    Ok(attachment_count)
+}
+
+/// Parses the notes from the ENEX file.
+fn parse_enex(path: &Path) -> Result<Vec<EnexNote>, String> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            debug!(path = %path.display(), error = %e, "could not open ENEX file");
+            return Err(String::from("Couldn't open ENEX file"));
+        }
+    };
+
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    let mut buf = vec![];
+
+    let mut notes = vec![];
+    let mut note: Option<EnexNote> = None;
+    let mut resource: Option<EnexResource> = None;
+    let mut text = String::new();
+
+    loop {
+        buf.clear();
+
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                text.clear();
+
+                match e.local_name().as_ref() {
+                    b"note" => { note = Some(EnexNote::default()); }
+                    b"resource" => { resource = Some(EnexResource::default()); }
+                    _ => {}
+                }
+            }
+
+            Ok(Event::Text(e)) => {
+                match e.unescape() {
+                    Ok(t) => text.push_str(&t),
+                    Err(_) => {
+                        debug!(position = reader.buffer_position(), "could not unescape ENEX text");
+                        return Err(String::from("ENEX file is malformed"));
+                    }
+                }
+            }
+
+            Ok(Event::CData(e)) => {
+                text.push_str(&String::from_utf8_lossy(&e.into_inner()));
+            }
+
+            Ok(Event::End(e)) => {
+                let value = std::mem::take(&mut text);
+
+                if let Some(r) = resource.as_mut() {
+                    match e.local_name().as_ref() {
+                        b"data" => {
+                            let encoded = value.split_whitespace().collect::<String>();
+
+                            match STANDARD.decode(encoded) {
+                                Ok(data) => { r.data = data; }
+                                Err(e) => {
+                                    warn!(error = %e, "could not decode resource");
+                                }
+                            }
+                        }
+
+                        b"mime" => { r.mime = value.trim().to_string(); }
+
+                        b"file-name" => {
+                            let file_name = value.trim();
+
+                            if !file_name.is_empty() {
+                                r.file_name = Some(file_name.to_string());
+                            }
+                        }
+
+                        b"resource" => {
+                            if let (Some(n), Some(r)) = (note.as_mut(), resource.take()) {
+                                n.resources.push(r);
+                            }
+                        }
+
+                        _ => {}
+                    }
+
+                    continue;
+                }
+
+                if let Some(n) = note.as_mut() {
+                    match e.local_name().as_ref() {
+                        b"title" => { n.title = value.trim().to_string(); }
+                        b"content" => { n.content = value; }
+                        b"created" => { n.created = parse_enex_date(value.trim()); }
+                        b"updated" => { n.updated = parse_enex_date(value.trim()); }
+
+                        b"tag" => {
+                            let tag = value.trim();
+
+                            if !tag.is_empty() && !n.tags.iter().any(|t| t == tag) {
+                                n.tags.push(tag.to_string());
+                            }
+                        }
+
+                        b"note" => {
+                            if let Some(n) = note.take() {
+                                notes.push(n);
+                            }
+                        }
+
+                        _ => {}
+                    }
+                }
+            }
+
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+
+            Err(_) => {
+                // The parser's errors may quote the notes, so only the position
+                // is logged
+                debug!(position = reader.error_position(), "could not parse ENEX file");
+
+                return Err(String::from("ENEX file is malformed"));
+            }
+        }
+    }
+
+    Ok(notes)
+}
+
+/// Parses dates in the ENEX format, e.g. "20240131T093000Z" (always UTC).
+fn parse_enex_date(date: &str) -> Option<u64> {
+    let date = date.strip_suffix('Z').unwrap_or(date);
+    let ( day, time ) = date.split_once('T')?;
+
+    if day.len() != 8 || time.len() != 6 {
+        return None;
+    }
+
+    unix_time(
+        day.get(0..4)?.parse().ok()?,
+        day.get(4..6)?.parse().ok()?,
+        day.get(6..8)?.parse().ok()?,
+        time.get(0..2)?.parse().ok()?,
+        time.get(2..4)?.parse().ok()?,
+        time.get(4..6)?.parse().ok()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ env, fs, path::PathBuf, process };
+
+    use super::*;
+    use crate::utils::{ notes::{ read_attachment, read_note }, storage::MemoryStorage };
+
+    /// Writes the ENEX file for the test.
+    fn enex_file(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("secure-notes-test-{}-enex", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    const ENEX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE en-export SYSTEM "http://xml.evernote.com/pub/evernote-export3.dtd">
+<en-export export-date="20240201T120000Z" application="Evernote">
+  <note>
+    <title>Groceries &amp; more</title>
+    <content><![CDATA[<?xml version="1.0" encoding="UTF-8"?>
+<en-note><div><en-todo checked="true"/>Milk</div><div><en-media hash="0cc175b9c0f1b6a831c399e269772661" type="image/png"/></div></en-note>]]></content>
+    <created>20240131T093000Z</created>
+    <updated>20240131T103000Z</updated>
+    <tag>home</tag>
+    <tag>home</tag>
+    <resource>
+      <data encoding="base64">
+        YQ==
+      </data>
+      <mime>image/png</mime>
+      <resource-attributes><file-name>milk.png</file-name></resource-attributes>
+    </resource>
+  </note>
+  <note>
+    <title> </title>
+    <content><![CDATA[<en-note>Nothing else</en-note>]]></content>
+  </note>
+</en-export>
+"#;
+
+    #[test]
+    fn parses_notes() {
+        let notes = parse_enex(&enex_file("parse.enex", ENEX)).unwrap();
+        assert_eq!(notes.len(), 2);
+
+        let note = &notes[0];
+        assert_eq!(note.title, "Groceries & more");
+        assert_eq!(note.created, Some(1706693400));
+        assert_eq!(note.updated, Some(1706697000));
+        assert_eq!(note.tags, vec![ String::from("home") ]);
+        assert_eq!(note.resources.len(), 1);
+        assert_eq!(note.resources[0].data, b"a");
+        assert_eq!(note.resources[0].file_name.as_deref(), Some("milk.png"));
+
+        assert!(notes[1].title.is_empty());
+        assert_eq!(notes[1].created, None);
+    }
+
+    #[test]
+    fn refuses_malformed_files() {
+        for ( name, contents ) in [
+            ( "unclosed.enex", "<en-export><note><title>Note</title></en-export>" ),
+            ( "entity.enex", "<en-export><note><title>&unknown;</title></note></en-export>" ),
+        ] {
+            let result = parse_enex(&enex_file(name, contents));
+            assert_eq!(result.unwrap_err(), "ENEX file is malformed");
+        }
+
+        assert!(parse_enex(Path::new("/nonexistent/notes.enex")).is_err());
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_enex_date("20240131T093000Z"), Some(1706693400));
+        assert_eq!(parse_enex_date("2024-01-31T09:30:00Z"), None);
+        assert_eq!(parse_enex_date("20241331T093000Z"), None);
+    }
+
+    #[test]
+    fn imports_notes_into_a_folder_named_after_the_file() {
+        let storage = MemoryStorage::new();
+        let key = VaultKey::generate();
+        write_index(&storage, &key, &VaultIndex::default()).unwrap();
+
+        let report = import_enex(&storage, &key, &enex_file("Recipes.enex", ENEX), TextFormat::Markdown).unwrap();
+        assert_eq!(( report.notes, report.folders, report.attachments ), ( 2, 1, 1 ));
+
+        let index = read_index(&storage, &key).unwrap();
+        assert_eq!(index.folders[0].name, "Recipes");
+
+        let note = index.notes.iter().find(|n| n.name == "Groceries & more").unwrap();
+        assert_eq!(note.parent_folder, Some(index.folders[0].id));
+
+        let attachment = &note.metadata.attachments[0];
+        assert_eq!(read_attachment(&storage, &key, attachment.id).unwrap(), b"a");
+        assert_eq!(
+            read_note(&storage, &key, note.id).unwrap(),
+            format!("- [x] Milk\n![milk.png](attachment:{})", attachment.id),
+        );
+
+        assert!(index.notes.iter().any(|n| n.name == "Untitled Note"));
+    }
+}
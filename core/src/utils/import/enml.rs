@@ -0,0 +1,645 @@
+///
+/// Converts ENML (the XHTML dialect Evernote stores note content in) to
+/// Markdown or plain text.
+///
+/// Only the structure that matters for notes is kept: paragraphs, headings,
+/// emphasis, links, lists, check boxes, code blocks, quotes and tables.
+/// Styling (fonts, colors, etc.) is dropped.
+///
+use std::collections::HashMap;
+use quick_xml::{ events::{ BytesStart, Event }, Reader };
+use tracing::debug;
+
+use crate::{
+    types::note_metadata::NoteAttachment,
+    utils::import::TextFormat,
+};
+
+/// Converts the ENML document to text in the given format.
+///
+/// `media` maps the MD5 hash (hex encoded) of each resource of the note to
+/// the attachment it was stored as, so that `<en-media>` elements can be
+/// replaced with references to it.
+pub fn enml_to_text(
+    enml: &str,
+    format: TextFormat,
+    media: &HashMap<String, NoteAttachment>,
+) -> Result<String, String> {
+    let mut reader = Reader::from_str(enml);
+    reader.config_mut().check_end_names = false;
+
+    let mut converter = Converter::new(format, media);
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => converter.start(&e, false),
+            Ok(Event::Empty(e)) => converter.start(&e, true),
+            Ok(Event::End(e)) => {
+                converter.end(&String::from_utf8_lossy(e.local_name().as_ref()));
+            }
+
+            Ok(Event::Text(e)) => {
+                match e.unescape_with(resolve_html_entity) {
+                    Ok(text) => converter.text(&text),
+                    Err(_) => {
+                        debug!(position = reader.buffer_position(), "could not unescape note content");
+                        return Err(String::from("Note content is malformed"));
+                    }
+                }
+            }
+
+            Ok(Event::CData(e)) => {
+                converter.text(&String::from_utf8_lossy(&e.into_inner()));
+            }
+
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+
+            // The parser's errors may quote the note, so only the position is
+            // logged
+            Err(_) => {
+                debug!(position = reader.error_position(), "could not parse note content");
+                return Err(String::from("Note content is malformed"));
+            }
+        }
+    }
+
+    Ok(converter.finish())
+}
+
+/// Resolves the HTML entities that are commonly found in ENML.
+fn resolve_html_entity(entity: &str) -> Option<&'static str> {
+    match entity {
+        "nbsp" | "ensp" | "emsp" | "thinsp" => Some(" "),
+        "amp" => Some("&"),
+        "lt" => Some("<"),
+        "gt" => Some(">"),
+        "quot" => Some("\""),
+        "apos" => Some("'"),
+        "copy" => Some("©"),
+        "reg" => Some("®"),
+        "trade" => Some("™"),
+        "euro" => Some("€"),
+        "pound" => Some("£"),
+        "mdash" => Some("—"),
+        "ndash" => Some("–"),
+        "hellip" => Some("…"),
+        "lsquo" => Some("‘"),
+        "rsquo" => Some("’"),
+        "ldquo" => Some("“"),
+        "rdquo" => Some("”"),
+        "bull" => Some("•"),
+        "middot" => Some("·"),
+        "deg" => Some("°"),
+        "times" => Some("×"),
+        _ => None,
+    }
+}
+
+struct List {
+    /// Next number of an ordered list, `None` for unordered lists.
+    next_number: Option<u32>,
+
+    /// Indentation of the items of this list.
+    indent: String,
+}
+
+struct Converter<'a> {
+    format: TextFormat,
+    media: &'a HashMap<String, NoteAttachment>,
+
+    /// Output buffers. The first one is the note, others are pushed for
+    /// links, quotes and table cells and merged back when they end.
+    buffers: Vec<String>,
+
+    lists: Vec<List>,
+    links: Vec<String>,
+    table_rows: Vec<Vec<String>>,
+    pre_depth: usize,
+
+    /// Depth of elements whose content is skipped (e.g. `<en-crypt>`).
+    skip_depth: usize,
+
+    /// Set after a list marker is written, so that a block starting right
+    /// after it doesn't move the item's content to the next line.
+    after_marker: bool,
+}
+
+impl<'a> Converter<'a> {
+    fn new(format: TextFormat, media: &'a HashMap<String, NoteAttachment>) -> Self {
+        Self {
+            format,
+            media,
+            buffers: vec![String::new()],
+            lists: vec![],
+            links: vec![],
+            table_rows: vec![],
+            pre_depth: 0,
+            skip_depth: 0,
+            after_marker: false,
+        }
+    }
+
+    fn markdown(&self) -> bool {
+        self.format == TextFormat::Markdown
+    }
+
+    fn buffer(&mut self) -> &mut String {
+        if self.buffers.is_empty() {
+            self.buffers.push(String::new());
+        }
+
+        let last = self.buffers.len() - 1;
+        &mut self.buffers[last]
+    }
+
+    fn write(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+
+        self.after_marker = false;
+        self.buffer().push_str(s);
+    }
+
+    /// Writes inline markup, only when converting to Markdown.
+    fn markup(&mut self, s: &str) {
+        if self.markdown() {
+            self.write(s);
+        }
+    }
+
+    fn at_line_start(&mut self) -> bool {
+        let buffer = self.buffer();
+        buffer.is_empty() || buffer.ends_with('\n')
+    }
+
+    /// Makes sure that whatever is written next starts on a new line.
+    fn line_break(&mut self) {
+        if self.after_marker || self.at_line_start() {
+            return;
+        }
+
+        self.buffer().push('\n');
+    }
+
+    /// Makes sure that whatever is written next starts a new paragraph.
+    fn block_break(&mut self) {
+        if self.after_marker {
+            return;
+        }
+
+        let buffer = self.buffer();
+
+        if buffer.is_empty() || buffer.ends_with("\n\n") {
+            return;
+        }
+
+        if buffer.ends_with('\n') {
+            buffer.push('\n');
+        } else {
+            buffer.push_str("\n\n");
+        }
+    }
+
+    fn start(&mut self, e: &BytesStart, empty: bool) {
+        let name = String::from_utf8_lossy(e.local_name().as_ref()).to_lowercase();
+
+        if self.skip_depth > 0 {
+            if !empty {
+                self.skip_depth += 1;
+            }
+
+            return;
+        }
+
+        match name.as_str() {
+            "div" => self.line_break(),
+            "p" => self.block_break(),
+            "br" => {
+                self.after_marker = false;
+                self.buffer().push('\n');
+            }
+
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.block_break();
+
+                if self.markdown() {
+                    let level = name[1..].parse::<usize>().unwrap_or(1);
+                    self.write(&format!("{} ", "#".repeat(level)));
+                }
+            }
+
+            "b" | "strong" => self.markup("**"),
+            "i" | "em" => self.markup("_"),
+            "s" | "strike" | "del" => self.markup("~~"),
+            "code" if self.pre_depth == 0 => self.markup("`"),
+
+            "pre" => {
+                self.block_break();
+                self.markup("```\n");
+                self.pre_depth += 1;
+            }
+
+            "blockquote" => {
+                self.block_break();
+                self.buffers.push(String::new());
+            }
+
+            "hr" => {
+                self.block_break();
+                self.write("---");
+                self.block_break();
+            }
+
+            "ul" | "ol" => {
+                self.line_break();
+
+                let indent = match self.lists.last() {
+                    Some(parent) => {
+                        let marker_width = match parent.next_number {
+                            Some(_) => 3,
+                            None => 2,
+                        };
+
+                        format!("{}{}", parent.indent, " ".repeat(marker_width))
+                    }
+
+                    None => String::new(),
+                };
+
+                let next_number = if name == "ol" { Some(1) } else { None };
+
+                self.lists.push(List { next_number, indent });
+            }
+
+            "li" => {
+                self.line_break();
+
+                let marker = match self.lists.last_mut() {
+                    Some(list) => {
+                        let marker = match list.next_number.as_mut() {
+                            Some(n) => {
+                                *n += 1;
+                                format!("{}. ", *n - 1)
+                            }
+
+                            None => String::from("- "),
+                        };
+
+                        format!("{}{}", list.indent, marker)
+                    }
+
+                    None => String::from("- "),
+                };
+
+                self.write(&marker);
+                self.after_marker = true;
+            }
+
+            "a" => {
+                let href = e.try_get_attribute("href")
+                    .ok()
+                    .flatten()
+                    .and_then(|a| a.unescape_value_with(resolve_html_entity).ok())
+                    .map(|v| v.to_string())
+                    .unwrap_or_default();
+
+                self.links.push(href);
+                self.buffers.push(String::new());
+            }
+
+            "table" => {
+                self.block_break();
+                self.table_rows.clear();
+            }
+
+            "tr" => self.table_rows.push(vec![]),
+            "td" | "th" => self.buffers.push(String::new()),
+
+            "en-todo" => {
+                let checked = e.try_get_attribute("checked")
+                    .ok()
+                    .flatten()
+                    .map(|a| a.value.as_ref() == b"true")
+                    .unwrap_or(false);
+
+                let check_box = if checked { "[x] " } else { "[ ] " };
+
+                if self.markdown() && !self.after_marker {
+                    self.line_break();
+                    self.write("- ");
+                }
+
+                self.write(check_box);
+            }
+
+            "en-media" => {
+                let hash = e.try_get_attribute("hash")
+                    .ok()
+                    .flatten()
+                    .map(|a| String::from_utf8_lossy(a.value.as_ref()).to_lowercase())
+                    .unwrap_or_default();
+
+                let reference = match self.media.get(&hash) {
+                    Some(attachment) => {
+                        if !self.markdown() {
+                            format!("[Attachment: {}]", attachment.name)
+                        } else if attachment.mime.starts_with("image/") {
+                            format!("![{}](attachment:{})", attachment.name, attachment.id)
+                        } else {
+                            format!("[{}](attachment:{})", attachment.name, attachment.id)
+                        }
+                    }
+
+                    None => String::from("[Missing attachment]"),
+                };
+
+                self.write(&reference);
+            }
+
+            "en-crypt" => {
+                self.write("[Encrypted content]");
+
+                if !empty {
+                    self.skip_depth = 1;
+                }
+            }
+
+            _ => {}
+        }
+
+        // Empty elements (`<b/>`, `<ul/>` etc.) also need to be closed.
+        if empty && !matches!(name.as_str(), "br" | "hr" | "en-todo" | "en-media" | "en-crypt") {
+            self.end(&name);
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        if self.skip_depth > 0 {
+            self.skip_depth -= 1;
+            return;
+        }
+
+        match name.to_lowercase().as_str() {
+            "div" => self.line_break(),
+            "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => self.block_break(),
+
+            "b" | "strong" => self.markup("**"),
+            "i" | "em" => self.markup("_"),
+            "s" | "strike" | "del" => self.markup("~~"),
+            "code" if self.pre_depth == 0 => self.markup("`"),
+
+            "pre" => {
+                self.pre_depth = self.pre_depth.saturating_sub(1);
+                self.line_break();
+                self.markup("```");
+                self.block_break();
+            }
+
+            "blockquote" => {
+                if self.buffers.len() > 1 {
+                    let quote = self.buffers.pop().unwrap_or_default();
+                    let prefix = if self.markdown() { "> " } else { "    " };
+
+                    let quoted = quote.trim()
+                        .lines()
+                        .map(|line| format!("{}{}", prefix, line).trim_end().to_string())
+                        .collect::<Vec<String>>()
+                        .join("\n");
+
+                    self.write(&quoted);
+                }
+
+                self.block_break();
+            }
+
+            "ul" | "ol" => {
+                self.lists.pop();
+
+                if self.lists.is_empty() {
+                    self.block_break();
+                } else {
+                    self.line_break();
+                }
+            }
+
+            "li" => self.line_break(),
+
+            "a" => {
+                let href = self.links.pop().unwrap_or_default();
+
+                let text = if self.buffers.len() > 1 {
+                    self.buffers.pop().unwrap_or_default()
+                } else {
+                    String::new()
+                };
+
+                let text = text.trim();
+
+                let link = if self.markdown() {
+                    if text.is_empty() {
+                        format!("<{}>", href)
+                    } else if href.is_empty() {
+                        text.to_string()
+                    } else {
+                        format!("[{}]({})", text, href)
+                    }
+                } else if text.is_empty() || text == href {
+                    href
+                } else if href.is_empty() {
+                    text.to_string()
+                } else {
+                    format!("{} ({})", text, href)
+                };
+
+                self.write(&link);
+            }
+
+            "td" | "th" if self.buffers.len() > 1 => {
+                let cell = self.buffers.pop().unwrap_or_default();
+                let mut cell = cell.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+                if self.markdown() {
+                    cell = cell.replace('|', "\\|");
+                }
+
+                match self.table_rows.last_mut() {
+                    Some(row) => row.push(cell),
+                    None => self.table_rows.push(vec![cell]),
+                }
+            }
+
+            "table" => {
+                let rows = std::mem::take(&mut self.table_rows);
+                let table = self.format_table(&rows);
+
+                self.write(&table);
+                self.block_break();
+            }
+
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.skip_depth > 0 {
+            return;
+        }
+
+        if self.pre_depth > 0 {
+            self.write(text);
+            return;
+        }
+
+        let mut collapsed = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+        if collapsed.is_empty() {
+            if !text.is_empty() && !self.at_line_start() && !self.after_marker
+                && !self.buffer().ends_with(' ') {
+                self.write(" ");
+            }
+
+            return;
+        }
+
+        if text.starts_with(char::is_whitespace) && !self.at_line_start()
+            && !self.after_marker && !self.buffer().ends_with(' ') {
+            collapsed.insert(0, ' ');
+        }
+
+        if text.ends_with(char::is_whitespace) {
+            collapsed.push(' ');
+        }
+
+        self.write(&collapsed);
+    }
+
+    fn format_table(&self, rows: &[Vec<String>]) -> String {
+        let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+
+        if columns == 0 {
+            return String::new();
+        }
+
+        let mut lines = vec![];
+
+        for (i, row) in rows.iter().enumerate() {
+            let mut cells = row.clone();
+            cells.resize(columns, String::new());
+
+            if self.markdown() {
+                lines.push(format!("| {} |", cells.join(" | ")));
+
+                if i == 0 {
+                    lines.push(format!("|{}", " --- |".repeat(columns)));
+                }
+            } else {
+                lines.push(cells.join("\t"));
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    fn finish(mut self) -> String {
+        // Merge back any buffers left open by malformed content
+        while self.buffers.len() > 1 {
+            let buffer = self.buffers.pop().unwrap_or_default();
+            self.write(&buffer);
+        }
+
+        let text = self.buffers.pop().unwrap_or_default();
+        let mut result = String::with_capacity(text.len());
+        let mut blank_lines = 0;
+
+        for line in text.lines() {
+            let line = line.trim_end();
+
+            if line.is_empty() {
+                blank_lines += 1;
+
+                if blank_lines > 1 {
+                    continue;
+                }
+            } else {
+                blank_lines = 0;
+            }
+
+            result.push_str(line);
+            result.push('\n');
+        }
+
+        result.trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown(enml: &str) -> String {
+        enml_to_text(enml, TextFormat::Markdown, &HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn converts_lists() {
+        let enml = "<en-note><ul><li>Milk</li><li>Eggs<ol><li>Brown</li><li>White</li></ol></li></ul>\
+            <ol><li><div>First</div></li><li>Second</li></ol></en-note>";
+
+        assert_eq!(markdown(enml), "- Milk\n- Eggs\n  1. Brown\n  2. White\n\n1. First\n2. Second");
+    }
+
+    #[test]
+    fn converts_check_boxes() {
+        let enml = "<en-note><div><en-todo checked=\"true\"/>Done</div><div><en-todo/>To do</div>\
+            <ul><li><en-todo checked=\"false\"/>In a list</li></ul></en-note>";
+
+        assert_eq!(markdown(enml), "- [x] Done\n- [ ] To do\n- [ ] In a list");
+
+        let text = enml_to_text(enml, TextFormat::PlainText, &HashMap::new()).unwrap();
+        assert_eq!(text, "[x] Done\n[ ] To do\n- [ ] In a list");
+    }
+
+    #[test]
+    fn replaces_media_with_their_attachments() {
+        let attachment = |id, name: &str, mime: &str| NoteAttachment {
+            id,
+            name: name.to_string(),
+            mime: mime.to_string(),
+        };
+
+        let media = HashMap::from([
+            ( String::from("0cc175b9c0f1b6a831c399e269772661"), attachment(2, "photo.png", "image/png") ),
+            ( String::from("92eb5ffee6ae2fec3ad71c777531578f"), attachment(3, "report.pdf", "application/pdf") ),
+        ]);
+
+        let enml = "<en-note><div><en-media hash=\"0CC175B9C0F1B6A831C399E269772661\" type=\"image/png\"/></div>\
+            <div><en-media hash=\"92eb5ffee6ae2fec3ad71c777531578f\" type=\"application/pdf\"/></div>\
+            <div><en-media hash=\"4a8a08f09d37b73795649038408b5f33\" type=\"image/jpeg\"/></div></en-note>";
+
+        assert_eq!(
+            enml_to_text(enml, TextFormat::Markdown, &media).unwrap(),
+            "![photo.png](attachment:2)\n[report.pdf](attachment:3)\n[Missing attachment]",
+        );
+
+        assert_eq!(
+            enml_to_text(enml, TextFormat::PlainText, &media).unwrap(),
+            "[Attachment: photo.png]\n[Attachment: report.pdf]\n[Missing attachment]",
+        );
+    }
+
+    #[test]
+    fn resolves_html_entities() {
+        let enml = "<en-note><div>Tom&nbsp;&amp;&nbsp;Jerry &mdash; &ldquo;caf&#233;&rdquo; &lt;3&hellip;</div></en-note>";
+
+        assert_eq!(markdown(enml), "Tom & Jerry — “café” <3…");
+    }
+
+    #[test]
+    fn refuses_unknown_entities() {
+        let result = enml_to_text("<en-note>&unknown;</en-note>", TextFormat::Markdown, &HashMap::new());
+
+        assert_eq!(result, Err(String::from("Note content is malformed")));
+    }
+}
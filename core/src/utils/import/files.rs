@@ -0,0 +1,123 @@
+///
+/// Imports a directory of plain text and Markdown files, e.g. one created by
+/// [`export_notes`](crate::utils::export::export_notes).
+///
+/// Files ending with ".md", ".markdown" or ".txt" become notes named after
+/// the file. They are put inside a folder named after the imported directory,
+/// sub-directories become sub-folders. Other files are skipped.
+///
+use std::{ fs::{ read_dir, read_to_string }, path::Path, time::UNIX_EPOCH };
+use tracing::{ debug, warn };
+
+use crate::{
+    types::vault_index::VaultIndex,
+    utils::{
+        crypto::VaultKey,
+        notes::{ read_index, write_index },
+        storage::VaultStorage,
+        import::{ get_or_create_folder, store_imported_note, ImportReport, ImportedNote },
+    },
+};
+
+const EXTENSIONS: [&str; 3] = [ "md", "markdown", "txt" ];
+
+/// Imports the text files in the directory into the vault.
+pub fn import_files(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    dir_path: &Path,
+) -> Result<ImportReport, String> {
+    if !dir_path.is_dir() {
+        return Err(String::from("Not a directory"));
+    }
+
+    let folder_name = match dir_path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name.to_string(),
+        None => String::from("Imported Notes"),
+    };
+
+    let mut index = read_index(storage, key)?;
+    let mut report = ImportReport::default();
+    let folder_id = get_or_create_folder(&mut index, &folder_name, None, &mut report);
+
+    import_dir(storage, key, &mut index, dir_path, dir_path, folder_id, &mut report)?;
+
+    write_index(storage, key, &index)?;
+
+    Ok(report)
+}
+
+fn import_dir(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    index: &mut VaultIndex,
+    root: &Path,
+    dir_path: &Path,
+    folder_id: u32,
+    report: &mut ImportReport,
+) -> Result<(), String> {
+    let mut entries = match read_dir(dir_path) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect::<Vec<_>>(),
+        Err(e) => {
+            debug!(path = %dir_path.display(), error = %e, "could not read directory");
+            return Err(format!("Couldn't read directory {}", dir_path.display()));
+        }
+    };
+
+    entries.sort();
+
+    for path in entries {
+        let name = match path.file_stem().and_then(|n| n.to_str()) {
+            Some(n) => n.to_string(),
+            None => { continue; }
+        };
+
+        if path.is_dir() {
+            let sub_folder = get_or_create_folder(index, &name, Some(folder_id), report);
+            import_dir(storage, key, index, root, &path, sub_folder, report)?;
+            continue;
+        }
+
+        let is_text = path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()));
+
+        if !is_text {
+            report.skipped += 1;
+            continue;
+        }
+
+        let text = match read_to_string(&path) {
+            Ok(t) => t,
+            Err(e) => {
+                warn!(error = %e, "could not read file");
+                report.failed.push((name, String::from("Couldn't read the file")));
+                continue;
+            }
+        };
+
+        let updated = path.metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        let relative_path = path.strip_prefix(root).unwrap_or(&path);
+
+        let note = ImportedNote {
+            source: format!("file:{}", relative_path.to_string_lossy().replace('\\', "/")),
+            title: name.clone(),
+            text,
+            created: updated,
+            updated,
+            tags: vec![],
+        };
+
+        if let Err(e) = store_imported_note(storage, key, index, folder_id, note, report) {
+            warn!(error = %e, "could not import note");
+            report.failed.push((name, e));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,227 @@
+///
+/// Importers that bring notes from other applications into a vault.
+///
+/// Importers read the exported files, convert them and write the notes,
+/// attachments and index entries through the vault's note storage (see
+/// [`crate::utils::notes`]).
+///
+pub mod enex;
+pub mod standard_notes;
+pub mod simplenote;
+pub mod files;
+mod enml;
+
+use std::fmt;
+
+use crate::{
+    types::{
+        vault_index::VaultIndex, vault_index_entry::VaultIndexEntry,
+        note_metadata::NoteMetadata,
+    },
+    utils::{ crypto::VaultKey, notes::write_note, storage::VaultStorage, unix_now, unix_time },
+};
+
+/// The format imported rich text is converted to.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum TextFormat {
+    #[default]
+    Markdown,
+    PlainText,
+}
+
+/// Summary of what an import run did.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ImportReport {
+    /// Notes that were added to the vault.
+    pub notes: usize,
+
+    /// Notes that were imported before and have changed since.
+    pub updated: usize,
+
+    /// Notes that were imported before and haven't changed since.
+    pub unchanged: usize,
+
+    /// Notes that were left out on purpose, e.g. trashed notes.
+    pub skipped: usize,
+
+    pub folders: usize,
+    pub attachments: usize,
+
+    /// Notes that couldn't be imported along with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Imported notes:    {}", self.notes)?;
+        writeln!(f, "Updated notes:     {}", self.updated)?;
+        writeln!(f, "Already imported:  {}", self.unchanged)?;
+        writeln!(f, "Skipped notes:     {}", self.skipped)?;
+        writeln!(f, "Created folders:   {}", self.folders)?;
+        write!(f, "Attachments:       {}", self.attachments)?;
+
+        if !self.failed.is_empty() {
+            write!(f, "\nFailed notes:      {}", self.failed.len())?;
+
+            for (name, reason) in &self.failed {
+                write!(f, "\n  - {}: {}", name, reason)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A note read from an export, before it's stored in the vault.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ImportedNote {
+    /// Identifies the note in the application it was exported from, see
+    /// [`NoteMetadata::source`].
+    pub source: String,
+    pub title: String,
+    pub text: String,
+    pub created: Option<u64>,
+    pub updated: Option<u64>,
+    pub tags: Vec<String>,
+}
+
+/// Returns the id of the folder with the given name, creating it if needed.
+pub(crate) fn get_or_create_folder(
+    index: &mut VaultIndex,
+    name: &str,
+    parent_folder: Option<u32>,
+    report: &mut ImportReport,
+) -> u32 {
+    if let Some(folder) = index.find_folder(name, parent_folder) {
+        return folder.id;
+    }
+
+    let id = index.next_id();
+    let now = unix_now();
+
+    index.folders.push(VaultIndexEntry {
+        id,
+        name: String::from(name),
+        parent_folder,
+        metadata: NoteMetadata {
+            created: now,
+            updated: now,
+            ..NoteMetadata::default()
+        },
+    });
+
+    report.folders += 1;
+
+    id
+}
+
+/// Writes the note and adds its entry to the index.
+pub(crate) fn store_note(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    index: &mut VaultIndex,
+    mut entry: VaultIndexEntry,
+    text: &str,
+) -> Result<(), String> {
+    entry.metadata.hash = Some(write_note(storage, key, entry.id, text)?);
+    index.notes.push(entry);
+
+    Ok(())
+}
+
+/// Stores a note that has a source id.
+///
+/// If a note from the same source was imported before, it's only overwritten
+/// when the imported note was updated after it, so that importing the same
+/// export again doesn't create duplicates.
+pub(crate) fn store_imported_note(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    index: &mut VaultIndex,
+    folder_id: u32,
+    note: ImportedNote,
+    report: &mut ImportReport,
+) -> Result<(), String> {
+    let now = unix_now();
+    let created = note.created.unwrap_or(now);
+    let updated = note.updated.unwrap_or(created);
+
+    let title = if note.title.trim().is_empty() {
+        String::from("Untitled Note")
+    } else {
+        note.title
+    };
+
+    let existing = index.notes.iter_mut()
+        .find(|n| n.metadata.source.as_deref() == Some(note.source.as_str()));
+
+    if let Some(entry) = existing {
+        if entry.metadata.updated >= updated {
+            report.unchanged += 1;
+            return Ok(());
+        }
+
+        entry.metadata.hash = Some(write_note(storage, key, entry.id, &note.text)?);
+        entry.name = title;
+        entry.metadata.updated = updated;
+        entry.metadata.tags = note.tags;
+        report.updated += 1;
+
+        return Ok(());
+    }
+
+    let entry = VaultIndexEntry {
+        id: index.next_id(),
+        name: title,
+        parent_folder: Some(folder_id),
+        metadata: NoteMetadata {
+            created,
+            updated,
+            tags: note.tags,
+            source: Some(note.source),
+            ..NoteMetadata::default()
+        },
+    };
+
+    store_note(storage, key, index, entry, &note.text)?;
+    report.notes += 1;
+
+    Ok(())
+}
+
+/// Parses an ISO 8601 / RFC 3339 date and time, e.g.
+/// "2024-01-31T09:30:00.000Z" or "2024-01-31T15:00:00+05:30".
+pub(crate) fn parse_iso8601(date: &str) -> Option<u64> {
+    let date = date.trim();
+    let ( day, time ) = date.split_once(['T', ' '])?;
+
+    let mut day_parts = day.splitn(3, '-');
+    let year = day_parts.next()?.parse().ok()?;
+    let month = day_parts.next()?.parse().ok()?;
+    let day = day_parts.next()?.parse().ok()?;
+
+    // Split the time zone offset from the time
+    let ( time, offset ) = if let Some(t) = time.strip_suffix(['Z', 'z']) {
+        ( t, 0 )
+    } else if let Some(i) = time.rfind(['+', '-']) {
+        let ( t, o ) = time.split_at(i);
+        let sign = if o.starts_with('-') { -1 } else { 1 };
+        let ( h, m ) = o[1..].split_once(':').unwrap_or((&o[1..], "0"));
+
+        ( t, sign * (h.parse::<i64>().ok()? * 3600 + m.parse::<i64>().ok()? * 60) )
+    } else {
+        ( time, 0 )
+    };
+
+    // Fractional seconds are ignored
+    let time = time.split('.').next()?;
+
+    let mut time_parts = time.splitn(3, ':');
+    let hour = time_parts.next()?.parse().ok()?;
+    let minute = time_parts.next()?.parse().ok()?;
+    let second = time_parts.next().unwrap_or("0").parse().ok()?;
+
+    let seconds = unix_time(year, month, day, hour, minute, second)? as i64 - offset;
+
+    u64::try_from(seconds).ok()
+}
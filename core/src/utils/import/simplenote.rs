@@ -0,0 +1,225 @@
+///
+/// Imports Simplenote JSON exports.
+///
+/// The export contains a "notes.json" file that looks like:
+///
+/// ```json
+/// {
+///   "activeNotes": [
+///     {
+///       "id": "...",
+///       "content": "Title\nRest of the note",
+///       "creationDate": "2024-01-31T09:30:00.000Z",
+///       "lastModified": "2024-01-31T09:30:00.000Z",
+///       "tags": [ "..." ]
+///     }
+///   ],
+///   "trashedNotes": [ ... ]
+/// }
+/// ```
+///
+/// Simplenote has no separate title, the first line of the note is used as
+/// its name. Notes are put inside a "Simplenote" folder. Trashed notes are
+/// skipped.
+///
+use std::{ fs::read_to_string, path::Path };
+use serde::Deserialize;
+use tracing::{ debug, warn };
+
+use crate::utils::{
+    crypto::VaultKey,
+    notes::{ read_index, write_index },
+    storage::VaultStorage,
+    import::{
+        get_or_create_folder, parse_iso8601, store_imported_note,
+        ImportReport, ImportedNote,
+    },
+};
+
+const FOLDER_NAME: &str = "Simplenote";
+
+/// Longest note name taken from the first line of a note.
+const MAX_TITLE_LENGTH: usize = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    #[serde(default)]
+    active_notes: Vec<Note>,
+
+    #[serde(default)]
+    trashed_notes: Vec<Note>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Note {
+    id: String,
+
+    #[serde(default)]
+    content: String,
+
+    #[serde(default)]
+    creation_date: Option<String>,
+
+    #[serde(default)]
+    last_modified: Option<String>,
+
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Imports the notes in the Simplenote export into the vault.
+pub fn import_simplenote(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    export_path: &Path,
+) -> Result<ImportReport, String> {
+    let export: Export = match read_to_string(export_path) {
+        Ok(json) => {
+            match serde_json::from_str(&json) {
+                Ok(e) => e,
+                Err(e) => {
+                    // The error may quote the notes, so only where it happened is logged
+                    debug!(line = e.line(), column = e.column(), "could not parse Simplenote export");
+                    return Err(String::from("Not a Simplenote export"));
+                }
+            }
+        }
+
+        Err(e) => {
+            debug!(path = %export_path.display(), error = %e, "could not open Simplenote export");
+            return Err(String::from("Couldn't open the export file"));
+        }
+    };
+
+    let mut index = read_index(storage, key)?;
+    let mut report = ImportReport {
+        skipped: export.trashed_notes.len(),
+        ..ImportReport::default()
+    };
+
+    if !export.active_notes.is_empty() {
+        let folder_id = get_or_create_folder(&mut index, FOLDER_NAME, None, &mut report);
+
+        for note in export.active_notes {
+            let title = note_title(&note.content);
+
+            let mut tags = vec![];
+
+            for tag in note.tags {
+                if !tag.is_empty() && !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+
+            let imported_note = ImportedNote {
+                source: format!("simplenote:{}", note.id),
+                title: title.clone(),
+                text: note.content,
+                created: note.creation_date.as_deref().and_then(parse_iso8601),
+                updated: note.last_modified.as_deref().and_then(parse_iso8601),
+                tags,
+            };
+
+            if let Err(e) = store_imported_note(
+                storage, key, &mut index, folder_id, imported_note, &mut report
+            ) {
+                warn!(note = %note.id, error = %e, "could not import note");
+                report.failed.push((title, e));
+            }
+        }
+    }
+
+    write_index(storage, key, &index)?;
+
+    Ok(report)
+}
+
+/// Uses the first non-empty line of the note as its title.
+fn note_title(content: &str) -> String {
+    let first_line = content.lines()
+        .map(|l| l.trim().trim_start_matches('#').trim())
+        .find(|l| !l.is_empty())
+        .unwrap_or_default();
+
+    first_line.chars().take(MAX_TITLE_LENGTH).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ env, fs, path::PathBuf, process };
+
+    use super::*;
+    use crate::{ types::vault_index::VaultIndex, utils::{ notes::read_note, storage::MemoryStorage } };
+
+    /// Writes the export file for the test.
+    fn export_file(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("secure-notes-test-{}-simplenote", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    const EXPORT: &str = r#"{
+  "activeNotes": [
+    {
+      "id": "a1",
+      "content": "\n# Groceries\n- milk",
+      "creationDate": "2024-01-31T09:30:00.000Z",
+      "lastModified": "2024-01-31T10:30:00.000Z",
+      "tags": [ "home", "", "home" ]
+    },
+    { "id": "a2", "content": "" }
+  ],
+  "trashedNotes": [
+    { "id": "t1", "content": "Thrown away" }
+  ]
+}"#;
+
+    #[test]
+    fn imports_the_active_notes() {
+        let storage = MemoryStorage::new();
+        let key = VaultKey::generate();
+        write_index(&storage, &key, &VaultIndex::default()).unwrap();
+
+        let report = import_simplenote(&storage, &key, &export_file("notes.json", EXPORT)).unwrap();
+        assert_eq!(( report.notes, report.skipped, report.folders ), ( 2, 1, 1 ));
+
+        let index = read_index(&storage, &key).unwrap();
+        assert_eq!(index.folders[0].name, FOLDER_NAME);
+
+        let note = &index.notes[0];
+        assert_eq!(note.name, "Groceries");
+        assert_eq!(note.metadata.tags, vec![ String::from("home") ]);
+        assert_eq!(( note.metadata.created, note.metadata.updated ), ( 1706693400, 1706697000 ));
+        assert_eq!(read_note(&storage, &key, note.id).unwrap(), "\n# Groceries\n- milk");
+
+        assert_eq!(index.notes[1].name, "Untitled Note");
+        assert!(!index.notes.iter().any(|n| n.metadata.source.as_deref() == Some("simplenote:t1")));
+    }
+
+    #[test]
+    fn importing_again_doesnt_duplicate_the_notes() {
+        let storage = MemoryStorage::new();
+        let key = VaultKey::generate();
+        write_index(&storage, &key, &VaultIndex::default()).unwrap();
+
+        let path = export_file("twice.json", EXPORT);
+        import_simplenote(&storage, &key, &path).unwrap();
+
+        let report = import_simplenote(&storage, &key, &path).unwrap();
+        assert_eq!(( report.notes, report.unchanged, report.folders ), ( 0, 2, 0 ));
+        assert_eq!(read_index(&storage, &key).unwrap().notes.len(), 2);
+    }
+
+    #[test]
+    fn names_notes_after_their_first_line() {
+        assert_eq!(note_title("  \n## Trip to Paris ##\nDay 1"), "Trip to Paris ##");
+        assert_eq!(note_title(&"é".repeat(150)).chars().count(), MAX_TITLE_LENGTH);
+        assert_eq!(note_title("\n \n"), "");
+    }
+}
@@ -0,0 +1,316 @@
+///
+/// Imports decrypted Standard Notes backups.
+///
+/// A decrypted backup is a JSON file with a list of items. Notes and tags are
+/// both items, tags reference the notes they are applied to:
+///
+/// ```json
+/// {
+///   "version": "004",
+///   "items": [
+///     {
+///       "uuid": "...",
+///       "content_type": "Note",
+///       "created_at": "2024-01-31T09:30:00.000Z",
+///       "updated_at": "2024-01-31T09:30:00.000Z",
+///       "content": { "title": "...", "text": "...", "trashed": false }
+///     },
+///     {
+///       "uuid": "...",
+///       "content_type": "Tag",
+///       "content": {
+///         "title": "...",
+///         "references": [ { "uuid": "...", "content_type": "Note" } ]
+///       }
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Notes are put inside a "Standard Notes" folder. Trashed and deleted notes
+/// are skipped.
+///
+use std::{ collections::HashMap, fs::read_to_string, path::Path };
+use serde::Deserialize;
+use tracing::{ debug, warn };
+
+use crate::utils::{
+    crypto::VaultKey,
+    notes::{ read_index, write_index },
+    storage::VaultStorage,
+    import::{
+        get_or_create_folder, parse_iso8601, store_imported_note,
+        ImportReport, ImportedNote,
+    },
+};
+
+const FOLDER_NAME: &str = "Standard Notes";
+
+#[derive(Debug, Deserialize)]
+struct Backup {
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Item {
+    uuid: String,
+    content_type: String,
+
+    #[serde(default)]
+    created_at: Option<String>,
+
+    #[serde(default)]
+    updated_at: Option<String>,
+
+    #[serde(default)]
+    deleted: bool,
+
+    /// Encrypted backups have a string here instead of an object.
+    #[serde(default)]
+    content: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ItemContent {
+    #[serde(default)]
+    title: String,
+
+    #[serde(default)]
+    text: String,
+
+    #[serde(default)]
+    trashed: bool,
+
+    #[serde(default)]
+    references: Vec<Reference>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reference {
+    uuid: String,
+
+    #[serde(default)]
+    content_type: String,
+}
+
+/// Imports the notes in the Standard Notes backup into the vault.
+pub fn import_standard_notes(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    backup_path: &Path,
+) -> Result<ImportReport, String> {
+    let backup: Backup = match read_to_string(backup_path) {
+        Ok(json) => {
+            match serde_json::from_str(&json) {
+                Ok(b) => b,
+                Err(e) => {
+                    // The error may quote the notes, so only where it happened is logged
+                    debug!(line = e.line(), column = e.column(), "could not parse Standard Notes backup");
+                    return Err(String::from("Not a Standard Notes backup"));
+                }
+            }
+        }
+
+        Err(e) => {
+            debug!(path = %backup_path.display(), error = %e, "could not open Standard Notes backup");
+            return Err(String::from("Couldn't open the backup file"));
+        }
+    };
+
+    let mut items = vec![];
+
+    for item in backup.items {
+        if item.deleted {
+            continue;
+        }
+
+        let content = match &item.content {
+            Some(value @ serde_json::Value::Object(_)) => {
+                serde_json::from_value::<ItemContent>(value.clone()).unwrap_or_default()
+            }
+
+            Some(serde_json::Value::String(_)) => {
+                return Err(String::from(
+                    "The backup is encrypted, please export a decrypted backup"
+                ));
+            }
+
+            _ => ItemContent::default(),
+        };
+
+        items.push((item, content));
+    }
+
+    // Tags reference the notes they're applied to
+    let mut note_tags: HashMap<&str, Vec<String>> = HashMap::new();
+
+    for (item, content) in &items {
+        if item.content_type != "Tag" || content.title.is_empty() {
+            continue;
+        }
+
+        for reference in &content.references {
+            if reference.content_type != "Note" {
+                continue;
+            }
+
+            let tags = note_tags.entry(reference.uuid.as_str()).or_default();
+
+            if !tags.contains(&content.title) {
+                tags.push(content.title.clone());
+            }
+        }
+    }
+
+    let mut index = read_index(storage, key)?;
+    let mut report = ImportReport::default();
+    let mut folder_id = None;
+
+    for (item, content) in &items {
+        if item.content_type != "Note" {
+            continue;
+        }
+
+        if content.trashed {
+            report.skipped += 1;
+            continue;
+        }
+
+        let folder = match folder_id {
+            Some(id) => id,
+            None => {
+                let id = get_or_create_folder(&mut index, FOLDER_NAME, None, &mut report);
+                folder_id = Some(id);
+                id
+            }
+        };
+
+        let note = ImportedNote {
+            source: format!("standard-notes:{}", item.uuid),
+            title: content.title.clone(),
+            text: content.text.clone(),
+            created: item.created_at.as_deref().and_then(parse_iso8601),
+            updated: item.updated_at.as_deref().and_then(parse_iso8601),
+            tags: note_tags.get(item.uuid.as_str()).cloned().unwrap_or_default(),
+        };
+
+        if let Err(e) = store_imported_note(storage, key, &mut index, folder, note, &mut report) {
+            warn!(note = %item.uuid, error = %e, "could not import note");
+            report.failed.push((content.title.clone(), e));
+        }
+    }
+
+    write_index(storage, key, &index)?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ env, fs, path::PathBuf, process };
+
+    use super::*;
+    use crate::{ types::vault_index::VaultIndex, utils::{ notes::read_note, storage::MemoryStorage } };
+
+    /// Writes the backup file for the test.
+    fn backup_file(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("secure-notes-test-{}-standard-notes", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    fn empty_vault() -> ( MemoryStorage, VaultKey ) {
+        let storage = MemoryStorage::new();
+        let key = VaultKey::generate();
+        write_index(&storage, &key, &VaultIndex::default()).unwrap();
+
+        ( storage, key )
+    }
+
+    const BACKUP: &str = r#"{
+  "version": "004",
+  "items": [
+    {
+      "uuid": "note-1",
+      "content_type": "Note",
+      "created_at": "2024-01-31T09:30:00.000Z",
+      "updated_at": "2024-01-31T10:30:00.000Z",
+      "content": { "title": "Groceries", "text": "- milk", "trashed": false }
+    },
+    {
+      "uuid": "note-2",
+      "content_type": "Note",
+      "content": { "title": "Old", "text": "thrown away", "trashed": true }
+    },
+    { "uuid": "note-3", "content_type": "Note", "deleted": true },
+    {
+      "uuid": "tag-1",
+      "content_type": "Tag",
+      "content": {
+        "title": "home",
+        "references": [ { "uuid": "note-1", "content_type": "Note" }, { "uuid": "note-2", "content_type": "Note" } ]
+      }
+    },
+    { "uuid": "settings", "content_type": "SN|UserPreferences", "content": {} }
+  ]
+}"#;
+
+    #[test]
+    fn imports_the_notes_and_their_tags() {
+        let ( storage, key ) = empty_vault();
+
+        let report = import_standard_notes(&storage, &key, &backup_file("backup.json", BACKUP)).unwrap();
+        assert_eq!(( report.notes, report.skipped, report.folders ), ( 1, 1, 1 ));
+        assert!(report.failed.is_empty());
+
+        let index = read_index(&storage, &key).unwrap();
+        assert_eq!(index.folders[0].name, FOLDER_NAME);
+        assert_eq!(index.notes.len(), 1);
+
+        let note = &index.notes[0];
+        assert_eq!(note.name, "Groceries");
+        assert_eq!(note.metadata.tags, vec![ String::from("home") ]);
+        assert_eq!(( note.metadata.created, note.metadata.updated ), ( 1706693400, 1706697000 ));
+        assert_eq!(note.metadata.source.as_deref(), Some("standard-notes:note-1"));
+        assert_eq!(read_note(&storage, &key, note.id).unwrap(), "- milk");
+    }
+
+    #[test]
+    fn importing_again_only_updates_the_changed_notes() {
+        let ( storage, key ) = empty_vault();
+        let path = backup_file("twice.json", BACKUP);
+
+        import_standard_notes(&storage, &key, &path).unwrap();
+
+        let report = import_standard_notes(&storage, &key, &path).unwrap();
+        assert_eq!(( report.notes, report.unchanged, report.updated, report.folders ), ( 0, 1, 0, 0 ));
+
+        let updated = BACKUP
+            .replace("2024-01-31T10:30:00.000Z", "2024-02-01T10:30:00.000Z")
+            .replace("- milk", "- milk\\n- eggs");
+
+        let report = import_standard_notes(&storage, &key, &backup_file("updated.json", &updated)).unwrap();
+        assert_eq!(( report.notes, report.updated ), ( 0, 1 ));
+
+        let index = read_index(&storage, &key).unwrap();
+        assert_eq!(index.notes.len(), 1);
+        assert_eq!(read_note(&storage, &key, index.notes[0].id).unwrap(), "- milk\n- eggs");
+    }
+
+    #[test]
+    fn refuses_encrypted_and_other_files() {
+        let ( storage, key ) = empty_vault();
+
+        let encrypted = r#"{ "items": [ { "uuid": "note-1", "content_type": "Note", "content": "004:..." } ] }"#;
+        let result = import_standard_notes(&storage, &key, &backup_file("encrypted.json", encrypted));
+        assert_eq!(result, Err(String::from("The backup is encrypted, please export a decrypted backup")));
+
+        let result = import_standard_notes(&storage, &key, &backup_file("other.json", "[ 1, 2 ]"));
+        assert_eq!(result, Err(String::from("Not a Standard Notes backup")));
+    }
+}
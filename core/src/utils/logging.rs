@@ -0,0 +1,223 @@
+///
+/// Logging, for the frontends to set up.
+///
+/// Everything is logged with `tracing`. Logs go to stderr, filtered by the
+/// `SECURE_NOTES_LOG` environment variable (e.g. "debug", or
+/// "secure_notes_core=trace"), only warnings and errors by default.
+///
+/// Setting `SECURE_NOTES_LOG_FILE=1` also writes debug logs to the "logs"
+/// directory inside the local directory, starting a new file every day and
+/// keeping the last few. Users can turn it on to send the logs along with a
+/// bug report.
+///
+/// Passwords, keys and note contents must never be logged:
+/// - Secrets are held in [`SecretString`] and [`VaultKey`], whose `Debug`
+///   output hides them and which don't implement `Display`.
+/// - The values of fields named like a secret (see [`is_redacted`]) are never
+///   written, e.g. `debug!(text = note_text, "saved")` logs `text=[redacted]`.
+/// - Note contents and secrets are never part of a log message.
+/// - Notes are logged by their id or position, never by their title, and
+///   neither are the paths made from titles, e.g. of exported notes.
+///
+/// [`SecretString`]: crate::utils::crypto::SecretString
+/// [`VaultKey`]: crate::utils::crypto::VaultKey
+///
+use std::{ env, fmt, fs::create_dir_all, io::stderr };
+use tracing::field::Field;
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{ RollingFileAppender, Rotation },
+};
+use tracing_subscriber::{
+    fmt::{ format::{ debug_fn, FormatFields, Writer }, layer },
+    field::MakeExt, layer::SubscriberExt, util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use crate::utils::get_local_dir;
+
+/// Environment variable with the log filter.
+pub const LOG_ENV: &str = "SECURE_NOTES_LOG";
+
+/// Environment variable that turns on the log file.
+pub const LOG_FILE_ENV: &str = "SECURE_NOTES_LOG_FILE";
+
+const STDERR_FILTER: &str = "warn";
+const FILE_FILTER: &str = "warn,secure_notes_core=debug,ui=debug,secure_notes=debug";
+
+/// Number of daily log files that are kept.
+const LOG_FILES_KEPT: usize = 7;
+
+/// Field names whose values are never logged. Names ending with one of them,
+/// like "new_password" or "note_text", are redacted as well.
+const REDACTED_FIELDS: [&str; 9] = [
+    "password", "passphrase", "phrase", "secret", "key", "text", "content", "body", "title",
+];
+
+/// Sets up logging, see the module documentation.
+///
+/// The returned guard writes the remaining logs to the log file when it's
+/// dropped, so it must be kept until the app exits.
+pub fn init_logging() -> Option<WorkerGuard> {
+    let filter = env::var(LOG_ENV).ok();
+
+    let stderr_layer = layer()
+        .with_writer(stderr)
+        .fmt_fields(redacting_fields())
+        .with_filter(env_filter(filter.as_deref(), STDERR_FILTER));
+
+    let mut guard = None;
+
+    let file_layer = log_file_appender().map(|appender| {
+        let ( writer, worker_guard ) = tracing_appender::non_blocking(appender);
+        guard = Some(worker_guard);
+
+        layer()
+            .with_writer(writer)
+            .with_ansi(false)
+            .fmt_fields(redacting_fields())
+            .with_filter(env_filter(filter.as_deref(), FILE_FILTER))
+    });
+
+    if let Err(e) = tracing_subscriber::registry()
+        .with(stderr_layer)
+        .with(file_layer)
+        .try_init() {
+        eprintln!("Could not set up logging: {}", e);
+    }
+
+    guard
+}
+
+/// Whether the value of the field with this name is left out of the logs.
+pub fn is_redacted(field: &str) -> bool {
+    REDACTED_FIELDS.iter().any(|redacted| {
+        field == *redacted
+            || field.strip_suffix(redacted).is_some_and(|prefix| prefix.ends_with('_'))
+    })
+}
+
+fn env_filter(filter: Option<&str>, default: &str) -> EnvFilter {
+    match filter {
+        Some(f) => match EnvFilter::try_new(f) {
+            Ok(filter) => filter,
+            Err(e) => {
+                eprintln!("Invalid {} \"{}\": {}", LOG_ENV, f, e);
+                EnvFilter::new(default)
+            }
+        },
+
+        None => EnvFilter::new(default),
+    }
+}
+
+/// Formats the fields of an event, leaving out the values of the redacted
+/// ones.
+fn redacting_fields() -> impl for<'writer> FormatFields<'writer> + 'static {
+    debug_fn(|writer: &mut Writer<'_>, field: &Field, value: &dyn fmt::Debug| {
+        match field.name() {
+            "message" => write!(writer, "{:?}", value),
+            name if is_redacted(name) => write!(writer, "{}=[redacted]", name),
+            name => write!(writer, "{}={:?}", name, value),
+        }
+    })
+        .delimited(" ")
+}
+
+/// Opens the log file if the user turned it on.
+fn log_file_appender() -> Option<RollingFileAppender> {
+    match env::var(LOG_FILE_ENV) {
+        Ok(value) if !value.is_empty() && value != "0" => {}
+        _ => { return None; }
+    }
+
+    let mut logs_dir = get_local_dir()?;
+    logs_dir.push("logs");
+
+    if let Err(e) = create_dir_all(&logs_dir) {
+        eprintln!("Could not create {}: {}", logs_dir.display(), e);
+        return None;
+    }
+
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("secure-notes")
+        .filename_suffix("log")
+        .max_log_files(LOG_FILES_KEPT)
+        .build(&logs_dir);
+
+    match appender {
+        Ok(a) => Some(a),
+        Err(e) => {
+            eprintln!("Could not open the log file in {}: {}", logs_dir.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ io, sync::{ Arc, Mutex } };
+
+    use tracing::{ subscriber::with_default, warn };
+    use tracing_subscriber::fmt;
+
+    use super::*;
+
+    /// Collects what's logged.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn redacts_secret_fields_and_their_suffixes() {
+        for field in [ "password", "new_password", "phrase", "recovery_phrase", "key", "vault_key",
+            "note_text", "content", "body", "title", "note_title" ] {
+            assert!(is_redacted(field), "{}", field);
+        }
+
+        for field in [ "note", "vault", "error", "path", "keyboard", "monkey", "context", "subtitle" ] {
+            assert!(!is_redacted(field), "{}", field);
+        }
+    }
+
+    #[test]
+    fn leaves_the_redacted_values_out_of_the_logs() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+
+        let subscriber = fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .fmt_fields(redacting_fields())
+            .finish();
+
+        with_default(subscriber, || {
+            warn!(
+                note = 3, password = "hunter2", note_text = "Dear diary", title = "Diary",
+                "could not save note"
+            );
+        });
+
+        let logged = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+
+        assert!(logged.contains("could not save note"), "{}", logged);
+        assert!(logged.contains("note=3"), "{}", logged);
+        assert!(logged.contains("password=[redacted]"), "{}", logged);
+        assert!(logged.contains("note_text=[redacted]"), "{}", logged);
+        assert!(logged.contains("title=[redacted]"), "{}", logged);
+        assert!(!logged.contains("hunter2"), "{}", logged);
+        assert!(!logged.contains("diary"), "{}", logged);
+        assert!(!logged.contains("Diary"), "{}", logged);
+    }
+}
@@ -0,0 +1,272 @@
+//! Vaults and the local directory they are stored in.
+//!
+//! All the data lives in the local directory (see [`get_local_dir`]): the
+//! "vaults" directory, the
+//! "default-vault" file naming the vault that is opened on start, the
+//! "settings.json" file (see [`settings`]) and, when turned on, the "logs"
+//! directory (see [`logging`]).
+
+pub mod vault;
+pub mod crypto;
+pub mod notes;
+pub mod import;
+pub mod unlock_state;
+pub mod export;
+pub mod logging;
+pub mod settings;
+pub mod storage;
+pub mod sync;
+pub mod remote;
+pub mod document;
+pub mod git;
+
+use std::{
+    env, fs::{ create_dir_all, read_to_string, File }, path::{ absolute, Path, PathBuf },
+    io::Write, sync::OnceLock, time::{ SystemTime, UNIX_EPOCH },
+};
+use dirs_next::data_local_dir;
+use tracing::debug;
+
+use crate::types::{ DefaultVaultFileError, vault_error::VaultError };
+
+/// Environment variable with the directory to use as the local directory.
+pub const DATA_DIR_ENV: &str = "SECURE_NOTES_DATA_DIR";
+
+/// File next to the executable that turns on the portable mode.
+pub const PORTABLE_MARKER: &str = "secure-notes.portable";
+
+/// Directory next to the executable used as the local directory in the
+/// portable mode.
+pub const PORTABLE_DATA_DIR: &str = "secure-notes-data";
+
+/// Local directory chosen by the frontend, e.g. with a command line flag.
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Whether the app runs for the first time, i.e. no vault was created in the
+/// local directory yet.
+pub fn is_first_start() -> bool {
+    match get_local_dir() {
+        Some(mut path) => {
+            path.push("vaults");
+            !path.exists()
+        }
+
+        None => true,
+    }
+}
+
+/// Uses `path` as the local directory instead of the default one.
+///
+/// Must be called before anything else reads or writes the local directory,
+/// it can only be set once.
+pub fn set_data_dir(path: &Path) -> Result<(), VaultError> {
+    let path = match absolute(path) {
+        Ok(p) => p,
+        Err(e) => { return Err(VaultError::io("Invalid data directory", e)); }
+    };
+
+    match DATA_DIR.set(path) {
+        Ok(()) => Ok(()),
+        Err(_) => Err(VaultError::Invalid(String::from("The data directory was already set"))),
+    }
+}
+
+/// Returns the local directory all the data is stored in, which is, in this
+/// order:
+/// - the directory set with [`set_data_dir`],
+/// - the directory in the [`DATA_DIR_ENV`] environment variable,
+/// - the [`PORTABLE_DATA_DIR`] directory next to the executable, if the
+///   [`PORTABLE_MARKER`] file is there too,
+/// - the "secure-notes" directory inside the user's local data directory.
+pub fn get_local_dir() -> Option<PathBuf> {
+    if let Some(path) = DATA_DIR.get() {
+        return Some(path.clone());
+    }
+
+    if let Some(path) = env::var_os(DATA_DIR_ENV).filter(|p| !p.is_empty()) {
+        return absolute(path).ok();
+    }
+
+    if let Some(path) = get_portable_dir() {
+        return Some(path);
+    }
+
+    match data_local_dir() {
+        Some(mut path) => {
+            path.push("secure-notes");
+            Some(path)
+        }
+
+        None => None,
+    }
+}
+
+/// Returns the local directory of the portable mode, if it's turned on.
+fn get_portable_dir() -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    let exe_dir = exe.parent()?;
+
+    if exe_dir.join(PORTABLE_MARKER).is_file() {
+        Some(exe_dir.join(PORTABLE_DATA_DIR))
+    } else {
+        None
+    }
+}
+
+/// Checks if vault exists.
+///
+/// Does this by checking if a directory with the vault name exists inside the
+/// "vaults" directory in the secure-notes local directory and also checks if
+/// files "index" and "info" are also present inside the vault directory.
+pub fn vault_exists(name: &str) -> bool {
+    if let Ok(mut dir_path) = vault::get_vault_dir(name) {
+
+        if dir_path.as_path().exists() {
+            dir_path.push("index");
+
+            if dir_path.as_path().exists() {
+                dir_path.pop();
+                dir_path.push("info");
+
+                return dir_path.as_path().exists();
+            }
+
+            // TODO: Show an error that says "vault is corrupted" to the user.
+        }
+    }
+
+    false
+}
+
+/// Creates the directory at `path` and all of its parents.
+pub fn create_secure_notes_directories(path: &PathBuf) -> Result<(), VaultError> {
+    match create_dir_all(path) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(VaultError::io("Error creating the directories", e)),
+    }
+}
+
+/// Creates the file that holds the name of the default vault.
+///
+/// The file is named "default-vault" and is a text file that should contain
+/// only a single line containing the name of the default vault's directory
+/// inside the "vaults" directory.
+pub fn create_default_vault_file(name: &str) -> Result<(), VaultError> {
+    let file_path = match get_default_vault_file_path() {
+        Some(path) => path,
+        None => { return Err(VaultError::LocalDirNotFound); }
+    };
+
+    match File::create(file_path).and_then(|mut file| file.write_all(name.as_bytes())) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(VaultError::io("Could not write the default vault file", e)),
+    }
+}
+
+/// Gets the file path to the file that holds the name of the default vault
+pub fn get_default_vault_file_path() -> Option<String> {
+    match get_local_dir() {
+        Some(mut path) => {
+            path.push("default-vault");
+            path.to_str().map(String::from)
+        }
+
+        None => None,
+    }
+}
+
+/// Gets the default vault's name from the "default-vault" file.
+///
+/// If there are more than one lines/entries in the "default-vault" file, only
+/// the first line is considered.
+///
+/// For more information see: [`create_default_vault_file`].
+pub fn get_default_vault_name() -> Result<String, DefaultVaultFileError> {
+    let default_file_path = match get_default_vault_file_path() {
+        Some(f) => f,
+        None => { return Err(DefaultVaultFileError::FileDoesNotExist); }
+    };
+
+    match read_to_string(default_file_path.clone()) {
+        Ok(file_name) => {
+            let vault_name_lines = file_name.split("\n").collect::<Vec<&str>>();
+
+            if vault_name_lines.is_empty() {
+                return Err(DefaultVaultFileError::FirstLineEmpty);
+            }
+
+            let vault_name = vault_name_lines[0];
+
+            if vault_exists(vault_name) {
+                return Ok(String::from(vault_name))
+            }
+
+            Err(DefaultVaultFileError::VaultDoesNotExist)
+        }
+
+        Err(e) => {
+            debug!(error = %e, "could not read the default vault file");
+            Err(DefaultVaultFileError::OSError(e.to_string()))
+        }
+    }
+}
+
+
+/// Current time in seconds since the unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Converts a UTC date and time to seconds since the unix epoch.
+pub(crate) fn unix_time(
+    year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32
+) -> Option<u64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day)
+        || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Days from civil, see: http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let seconds = days * 86400
+        + hour as i64 * 3600
+        + minute as i64 * 60
+        + second as i64;
+
+    u64::try_from(seconds).ok()
+}
+
+/// Converts seconds since the unix epoch to the UTC date and time: the year,
+/// month, day, hour, minute and second.
+pub(crate) fn utc_date_time(time: u64) -> ( i64, u32, u32, u32, u32, u32 ) {
+    // Civil from days, see: http://howardhinnant.github.io/date_algorithms.html
+    let days = (time / 86400) as i64 + 719468;
+    let era = days / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let seconds = time % 86400;
+
+    (
+        year,
+        month as u32,
+        day as u32,
+        ( seconds / 3600 ) as u32,
+        ( seconds / 60 % 60 ) as u32,
+        ( seconds % 60 ) as u32,
+    )
+}
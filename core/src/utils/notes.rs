@@ -0,0 +1,283 @@
+///
+/// Reading and writing the encrypted contents of a vault: the index, notes and
+/// attachments.
+///
+/// All functions take the vault's storage (see
+/// [`get_vault_storage`](crate::utils::vault::get_vault_storage)) and the
+/// vault's master key.
+///
+/// Notes are stored as documents that record their edits (see
+/// [`document`](crate::utils::document)): writing a note only adds the
+/// difference with the stored text, and edits made elsewhere in the meantime
+/// are merged in.
+///
+use std::iter;
+use serde::{ Serialize, Deserialize };
+use flexbuffers::{ FlexbufferSerializer, Reader };
+use tracing::{ debug, warn };
+
+use crate::{
+    types::{
+        vault_index::VaultIndex, vault_index_entry::VaultIndexEntry,
+        vault_error::VaultError,
+    },
+    utils::{
+        crypto::{ VaultKey, encrypt, decrypt }, document::{ NoteDocument, is_document },
+        storage::VaultStorage, sync::content_hash, unix_now,
+    },
+};
+
+/// Reads and decrypts the index of the vault.
+///
+/// An empty index file (as created by older versions) is read as an empty
+/// index.
+pub fn read_index(storage: &dyn VaultStorage, key: &VaultKey) -> Result<VaultIndex, VaultError> {
+    read_index_file(storage, key, "index")
+}
+
+/// Reads an index from the file at `path`, e.g. a conflict copy of the index.
+pub(crate) fn read_index_file(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    path: &str,
+) -> Result<VaultIndex, VaultError> {
+    let bytes = match storage.read(path) {
+        Ok(b) => b,
+        Err(e) => { return Err(VaultError::io("Could not read the index file", e)); }
+    };
+
+    if bytes.is_empty() {
+        return Ok(VaultIndex::default());
+    }
+
+    let plain = decrypt_file(key, &bytes, "index")?;
+
+    match Reader::get_root(plain.as_slice()) {
+        Ok(reader) => {
+            match VaultIndex::deserialize(reader) {
+                Ok(index) => Ok(index),
+                Err(e) => Err(VaultError::corrupt("index", e)),
+            }
+        }
+
+        Err(e) => Err(VaultError::corrupt("index", e)),
+    }
+}
+
+/// Encrypts and writes the index of the vault, replacing the old one
+/// atomically.
+pub fn write_index(storage: &dyn VaultStorage, key: &VaultKey, index: &VaultIndex) -> Result<(), VaultError> {
+    let mut serializer = FlexbufferSerializer::new();
+
+    if let Err(e) = index.serialize(&mut serializer) {
+        return Err(VaultError::corrupt("index", e));
+    }
+
+    let data = encrypt(key, serializer.view())?;
+
+    match storage.replace("index", &data) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(VaultError::io("Could not write the index file", e)),
+    }
+}
+
+/// Reads and decrypts the note with the given id.
+pub fn read_note(storage: &dyn VaultStorage, key: &VaultKey, id: u32) -> Result<String, VaultError> {
+    read_note_file(storage, key, &note_path(id))
+}
+
+/// Reads a note from the file at `path`, e.g. a conflict copy of the note.
+pub(crate) fn read_note_file(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    path: &str,
+) -> Result<String, VaultError> {
+    Ok(read_document_file(storage, key, path)?.text())
+}
+
+/// Reads the note with the given id as a document, to be edited and saved with
+/// [`save_note_document`].
+pub fn read_note_document(storage: &dyn VaultStorage, key: &VaultKey, id: u32) -> Result<NoteDocument, VaultError> {
+    read_document_file(storage, key, &note_path(id))
+}
+
+/// Reads the document in the file at `path`, notes written by older versions
+/// are converted (see [`NoteDocument::is_converted`]).
+pub(crate) fn read_document_file(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    path: &str,
+) -> Result<NoteDocument, VaultError> {
+    let plain = read_encrypted(storage, path, key, "note", "Could not read the note")?;
+
+    NoteDocument::load(&plain)
+}
+
+/// Changes the text of the note with the given id to `text`, creating the note
+/// if needed.
+///
+/// Returns the hash of the written file, to be stored in the note's metadata.
+pub fn write_note(storage: &dyn VaultStorage, key: &VaultKey, id: u32, text: &str) -> Result<String, VaultError> {
+    let mut document = match storage.exists(&note_path(id)) {
+        true => read_note_document(storage, key, id)?,
+        false => NoteDocument::from_text(text),
+    };
+
+    document.update(text)?;
+
+    write_note_document(storage, key, id, &mut document)
+}
+
+/// Encrypts and writes the document of the note with the given id, replacing
+/// the old file atomically.
+///
+/// Edits in the old file that `document` doesn't have, e.g. merged from
+/// another device since it was read, are merged into `document` first.
+/// Returns the hash of the written file, to be stored in the note's metadata.
+pub fn write_note_document(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    id: u32,
+    document: &mut NoteDocument,
+) -> Result<String, VaultError> {
+    let path = note_path(id);
+
+    if storage.exists(&path) {
+        let plain = read_encrypted(storage, &path, key, "note", "Could not read the note")?;
+
+        if is_document(&plain) {
+            document.merge(&mut NoteDocument::load(&plain)?)?;
+        }
+    }
+
+    let data = encrypt(key, &document.save())?;
+
+    match storage.replace(&path, &data) {
+        Ok(()) => Ok(content_hash(&data)),
+        Err(e) => Err(VaultError::io("Could not write the note", e)),
+    }
+}
+
+/// Writes the note and adds or updates its entry in the index.
+///
+/// Notes that were never saved have the id 0, they get a new id which is
+/// stored in `entry`.
+pub fn save_note(storage: &dyn VaultStorage, key: &VaultKey, entry: &mut VaultIndexEntry, text: &str) -> Result<(), VaultError> {
+    save_entry(storage, key, entry, |id| write_note(storage, key, id, text))
+}
+
+/// Writes the note's document and adds or updates its entry in the index, like
+/// [`save_note`].
+pub fn save_note_document(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    entry: &mut VaultIndexEntry,
+    document: &mut NoteDocument,
+) -> Result<(), VaultError> {
+    save_entry(storage, key, entry, |id| write_note_document(storage, key, id, document))
+}
+
+/// Writes the note with `write` and updates its entry in the index.
+fn save_entry(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    entry: &mut VaultIndexEntry,
+    write: impl FnOnce(u32) -> Result<String, VaultError>,
+) -> Result<(), VaultError> {
+    let mut index = read_index(storage, key)?;
+    let now = unix_now();
+
+    if entry.id == 0 {
+        entry.id = index.next_id();
+        entry.metadata.created = now;
+    }
+
+    entry.metadata.updated = now;
+    entry.metadata.hash = Some(write(entry.id)?);
+
+    match index.notes.iter_mut().find(|n| n.id == entry.id) {
+        Some(indexed_entry) => { *indexed_entry = entry.clone(); }
+        None => { index.notes.push(entry.clone()); }
+    }
+
+    write_index(storage, key, &index)
+}
+
+/// Deletes the note with the given id, with its file and attachments.
+///
+/// The deletion is recorded in the index (see [`VaultIndex::delete`]), so that
+/// merging a copy of the index from another device doesn't bring the note
+/// back.
+pub fn delete_note(storage: &dyn VaultStorage, key: &VaultKey, id: u32) -> Result<(), VaultError> {
+    let mut index = read_index(storage, key)?;
+
+    // Folders have ids of their own
+    if !index.notes.iter().any(|n| n.id == id) {
+        return Err(VaultError::Invalid(String::from("Note not found")));
+    }
+
+    let attachments = index.delete(id)
+        .map(|entry| entry.metadata.attachments)
+        .unwrap_or_default();
+
+    write_index(storage, key, &index)?;
+
+    for path in iter::once(note_path(id)).chain(attachments.iter().map(|a| attachment_path(a.id))) {
+        if storage.exists(&path) {
+            if let Err(e) = storage.delete(&path) {
+                warn!(file = %path, error = %e, "could not delete the file of a deleted note");
+            }
+        }
+    }
+
+    debug!(note = id, "deleted note");
+
+    Ok(())
+}
+
+/// Reads and decrypts the attachment with the given id.
+pub fn read_attachment(storage: &dyn VaultStorage, key: &VaultKey, id: u32) -> Result<Vec<u8>, VaultError> {
+    read_encrypted(storage, &attachment_path(id), key, "attachment", "Could not read the attachment")
+}
+
+/// Encrypts and writes the attachment with the given id.
+pub fn write_attachment(storage: &dyn VaultStorage, key: &VaultKey, id: u32, data: &[u8]) -> Result<(), VaultError> {
+    let data = encrypt(key, data)?;
+
+    match storage.write(&attachment_path(id), &data) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(VaultError::io("Could not write the attachment", e)),
+    }
+}
+
+/// Path of the note's file inside the vault.
+pub fn note_path(id: u32) -> String {
+    format!("notes/{}", id)
+}
+
+/// Path of the attachment's file inside the vault.
+pub fn attachment_path(id: u32) -> String {
+    format!("attachments/{}", id)
+}
+
+/// Decrypts a file of the unlocked vault. The key is known to be right, so
+/// data that doesn't decrypt means the file is corrupted.
+fn decrypt_file(key: &VaultKey, data: &[u8], file: &'static str) -> Result<Vec<u8>, VaultError> {
+    match decrypt(key, data) {
+        Err(VaultError::WrongPassword) => Err(VaultError::Corrupt { file, source: None }),
+        result => result,
+    }
+}
+
+fn read_encrypted(
+    storage: &dyn VaultStorage,
+    path: &str,
+    key: &VaultKey,
+    file: &'static str,
+    context: &'static str,
+) -> Result<Vec<u8>, VaultError> {
+    match storage.read(path) {
+        Ok(bytes) => decrypt_file(key, &bytes, file),
+        Err(e) => Err(VaultError::io(context, e)),
+    }
+}
@@ -0,0 +1,384 @@
+///
+/// The encrypted connection between two devices: a TCP connection secured with
+/// the Noise protocol (Noise_XX_25519_ChaChaPoly_BLAKE2s).
+///
+/// The handshake authenticates both devices by their static keys, see
+/// [`LanIdentity`](super::LanIdentity). Messages of any size are split into
+/// Noise messages, each sent with its length as 2 big-endian bytes.
+///
+/// Devices that aren't paired yet check that no one is in the middle with a
+/// verification code, see [`Channel::exchange_code`].
+///
+use std::{ io::{ ErrorKind, Read, Write }, net::TcpStream, time::Duration };
+use snow::{ Builder, HandshakeState, TransportState };
+use sha2::{ Digest, Sha256 };
+
+use crate::utils::{ crypto::{ SALT_SIZE, generate_salt }, remote::RemoteError };
+
+/// The Noise handshake and ciphers.
+pub(crate) const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+
+/// Longest Noise message.
+const MAX_NOISE_MESSAGE: usize = 65535;
+
+/// Longest plaintext in a Noise message, leaving room for the tag.
+const MAX_CHUNK: usize = MAX_NOISE_MESSAGE - 16;
+
+/// Largest message accepted, the largest file that can be exchanged.
+const MAX_MESSAGE: usize = 1 << 30;
+
+/// Largest message accepted before the vault was opened, enough for the hello
+/// and the pairing.
+const MAX_HELLO_MESSAGE: usize = 64 * 1024;
+
+/// Time to wait for the other device before giving up.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An authenticated, encrypted connection.
+pub struct Channel {
+    stream: TcpStream,
+    transport: TransportState,
+
+    /// Hash of the handshake, the same on both devices, see
+    /// [`Channel::exchange_code`].
+    handshake_hash: Vec<u8>,
+
+    /// Largest message accepted, small until the vault was opened.
+    max_message: usize,
+}
+
+impl Channel {
+    /// Makes the handshake as the device that connected.
+    pub fn connect(stream: TcpStream, private_key: &[u8]) -> Result<Self, RemoteError> {
+        let mut handshake = builder(private_key)?.build_initiator().map_err(noise_error)?;
+        let mut stream = prepare(stream)?;
+
+        // -> e
+        send_handshake(&mut stream, &mut handshake)?;
+        // <- e, ee, s, es
+        receive_handshake(&mut stream, &mut handshake)?;
+        // -> s, se
+        send_handshake(&mut stream, &mut handshake)?;
+
+        Self::finish(stream, handshake)
+    }
+
+    /// Makes the handshake as the device that was connected to.
+    pub fn accept(stream: TcpStream, private_key: &[u8]) -> Result<Self, RemoteError> {
+        let mut handshake = builder(private_key)?.build_responder().map_err(noise_error)?;
+        let mut stream = prepare(stream)?;
+
+        receive_handshake(&mut stream, &mut handshake)?;
+        send_handshake(&mut stream, &mut handshake)?;
+        receive_handshake(&mut stream, &mut handshake)?;
+
+        Self::finish(stream, handshake)
+    }
+
+    fn finish(stream: TcpStream, handshake: HandshakeState) -> Result<Self, RemoteError> {
+        let handshake_hash = handshake.get_handshake_hash().to_vec();
+        let transport = handshake.into_transport_mode().map_err(noise_error)?;
+
+        Ok(Self { stream, transport, handshake_hash, max_message: MAX_HELLO_MESSAGE })
+    }
+
+    /// Static public key of the other device.
+    pub fn remote_key(&self) -> Vec<u8> {
+        self.transport.get_remote_static().map(<[u8]>::to_vec).unwrap_or_default()
+    }
+
+    /// Accepts messages as large as files, once the other device opened the
+    /// vault.
+    pub fn allow_large_messages(&mut self) {
+        self.max_message = MAX_MESSAGE;
+    }
+
+    /// Agrees on the verification code of the connection, shown on both
+    /// devices when pairing them. It's the same on both only if no one is in
+    /// the middle.
+    ///
+    /// The code is only 6 digits, so it's made from a random nonce of each
+    /// device as well as the handshake: the device that was connected to
+    /// commits to its nonce before it sees the other one, and reveals it
+    /// after. Someone in the middle can't choose its keys or nonces so that
+    /// the codes of its two connections match, every try has a chance of one
+    /// in a million and needs the users to accept.
+    pub fn exchange_code(&mut self, initiator: bool) -> Result<String, RemoteError> {
+        let nonce = generate_salt();
+
+        let ( initiator_nonce, responder_nonce ) = match initiator {
+            true => {
+                let commitment = self.receive()?;
+                self.send(&nonce)?;
+                let responder_nonce = self.receive()?;
+
+                if Sha256::digest(&responder_nonce).as_slice() != commitment.as_slice() {
+                    return Err(RemoteError::InvalidResponse(String::from(
+                        "The device didn't reveal the nonce it committed to"
+                    )));
+                }
+
+                ( nonce, responder_nonce )
+            }
+
+            false => {
+                self.send(&Sha256::digest(&nonce))?;
+                let initiator_nonce = self.receive()?;
+
+                if initiator_nonce.len() != SALT_SIZE {
+                    return Err(RemoteError::InvalidResponse(String::from("Invalid nonce")));
+                }
+
+                self.send(&nonce)?;
+
+                ( initiator_nonce, nonce )
+            }
+        };
+
+        Ok(verification_code(&self.handshake_hash, &initiator_nonce, &responder_nonce))
+    }
+
+    /// Encrypts and sends the message.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), RemoteError> {
+        let mut data = Vec::with_capacity(4 + message.len());
+        data.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        data.extend_from_slice(message);
+
+        let mut buffer = vec![ 0u8; MAX_NOISE_MESSAGE ];
+
+        for chunk in data.chunks(MAX_CHUNK) {
+            let len = self.transport.write_message(chunk, &mut buffer).map_err(noise_error)?;
+            write_frame(&mut self.stream, &buffer[..len])?;
+        }
+
+        self.stream.flush().map_err(io_error)
+    }
+
+    /// Receives and decrypts the next message.
+    pub fn receive(&mut self) -> Result<Vec<u8>, RemoteError> {
+        let mut buffer = vec![ 0u8; MAX_NOISE_MESSAGE ];
+        let mut data = vec![];
+        let mut expected = None;
+
+        while expected.is_none_or(|len| data.len() < 4 + len) {
+            let frame = read_frame(&mut self.stream)?;
+            let len = self.transport.read_message(&frame, &mut buffer).map_err(noise_error)?;
+            data.extend_from_slice(&buffer[..len]);
+
+            if expected.is_none() && data.len() >= 4 {
+                let len = u32::from_be_bytes([ data[0], data[1], data[2], data[3] ]) as usize;
+
+                if len > self.max_message {
+                    return Err(RemoteError::InvalidResponse(String::from("Message is too large")));
+                }
+
+                expected = Some(len);
+            }
+        }
+
+        data.drain(..4);
+
+        Ok(data)
+    }
+}
+
+/// The 6 digits shown to the users, as "ddd ddd".
+fn verification_code(handshake_hash: &[u8], initiator_nonce: &[u8], responder_nonce: &[u8]) -> String {
+    let hash = Sha256::new()
+        .chain_update(handshake_hash)
+        .chain_update(initiator_nonce)
+        .chain_update(responder_nonce)
+        .finalize();
+
+    let number = u32::from_be_bytes([ hash[0], hash[1], hash[2], hash[3] ]) % 1_000_000;
+
+    format!("{:03} {:03}", number / 1000, number % 1000)
+}
+
+/// Makes a new static key pair, as ( private key, public key ).
+pub(crate) fn generate_keypair() -> Result<( Vec<u8>, Vec<u8> ), RemoteError> {
+    let keypair = builder(&[])?.generate_keypair().map_err(noise_error)?;
+
+    Ok(( keypair.private, keypair.public ))
+}
+
+fn builder(private_key: &[u8]) -> Result<Builder<'_>, RemoteError> {
+    let params = NOISE_PARAMS.parse().map_err(noise_error)?;
+
+    match private_key.is_empty() {
+        true => Ok(Builder::new(params)),
+        false => Ok(Builder::new(params).local_private_key(private_key)),
+    }
+}
+
+fn prepare(stream: TcpStream) -> Result<TcpStream, RemoteError> {
+    stream.set_read_timeout(Some(TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)))
+        .and_then(|()| stream.set_nodelay(true))
+        .map_err(io_error)?;
+
+    Ok(stream)
+}
+
+fn send_handshake(stream: &mut TcpStream, handshake: &mut HandshakeState) -> Result<(), RemoteError> {
+    let mut buffer = vec![ 0u8; MAX_NOISE_MESSAGE ];
+    let len = handshake.write_message(&[], &mut buffer).map_err(noise_error)?;
+
+    write_frame(stream, &buffer[..len])
+}
+
+fn receive_handshake(stream: &mut TcpStream, handshake: &mut HandshakeState) -> Result<(), RemoteError> {
+    let mut buffer = vec![ 0u8; MAX_NOISE_MESSAGE ];
+    let frame = read_frame(stream)?;
+
+    handshake.read_message(&frame, &mut buffer).map(|_| ()).map_err(noise_error)
+}
+
+fn write_frame(stream: &mut TcpStream, frame: &[u8]) -> Result<(), RemoteError> {
+    stream.write_all(&(frame.len() as u16).to_be_bytes())
+        .and_then(|()| stream.write_all(frame))
+        .map_err(io_error)
+}
+
+fn read_frame(stream: &mut TcpStream) -> Result<Vec<u8>, RemoteError> {
+    let mut len = [ 0u8; 2 ];
+    stream.read_exact(&mut len).map_err(io_error)?;
+
+    let mut frame = vec![ 0u8; u16::from_be_bytes(len) as usize ];
+    stream.read_exact(&mut frame).map_err(io_error)?;
+
+    Ok(frame)
+}
+
+fn io_error(error: std::io::Error) -> RemoteError {
+    match error.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+            RemoteError::Offline(String::from("the device didn't answer in time"))
+        }
+
+        _ => RemoteError::Offline(error.to_string()),
+    }
+}
+
+/// A message that doesn't decrypt was changed on the way, or wasn't sent by
+/// the other device.
+fn noise_error(error: snow::Error) -> RemoteError {
+    RemoteError::InvalidResponse(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ net::TcpListener, thread };
+
+    use super::*;
+
+    /// Connects two channels over the loopback interface, as ( the device that
+    /// connected, the one connected to ).
+    fn channels() -> ( Channel, Channel ) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let ( private_key, _ ) = generate_keypair().unwrap();
+
+        let accepted = thread::spawn(move || {
+            Channel::accept(listener.accept().unwrap().0, &private_key).unwrap()
+        });
+
+        let ( private_key, _ ) = generate_keypair().unwrap();
+        let connected = Channel::connect(TcpStream::connect(address).unwrap(), &private_key).unwrap();
+
+        ( connected, accepted.join().unwrap() )
+    }
+
+    fn message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn sends_messages_of_any_size() {
+        let ( mut connected, mut accepted ) = channels();
+        accepted.allow_large_messages();
+
+        // The length prefix takes 4 bytes of the first chunk
+        let lens = [
+            0, 1, MAX_CHUNK - 4, MAX_CHUNK - 3, MAX_CHUNK, MAX_CHUNK + 1,
+            2 * MAX_CHUNK - 4, 2 * MAX_CHUNK - 3, 3 * MAX_CHUNK + 7,
+        ];
+
+        let sender = thread::spawn(move || {
+            for len in lens {
+                connected.send(&message(len)).unwrap();
+            }
+
+            connected
+        });
+
+        for len in lens {
+            assert_eq!(accepted.receive().unwrap(), message(len), "message of {} bytes", len);
+        }
+
+        // And back
+        let mut connected = sender.join().unwrap();
+        connected.allow_large_messages();
+
+        let sender = thread::spawn(move || accepted.send(&message(2 * MAX_CHUNK)).unwrap());
+        assert_eq!(connected.receive().unwrap(), message(2 * MAX_CHUNK));
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn accepts_large_messages_only_once_allowed() {
+        let ( mut connected, mut accepted ) = channels();
+
+        let sender = thread::spawn(move || {
+            connected.send(&message(MAX_HELLO_MESSAGE)).unwrap();
+            connected.send(&message(MAX_HELLO_MESSAGE + 1)).unwrap();
+        });
+
+        assert_eq!(accepted.receive().unwrap().len(), MAX_HELLO_MESSAGE);
+        assert!(matches!(accepted.receive(), Err(RemoteError::InvalidResponse(_))));
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn knows_the_key_of_the_other_device() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let ( private_key, public_key ) = generate_keypair().unwrap();
+
+        let accepted = thread::spawn(move || {
+            Channel::accept(listener.accept().unwrap().0, &private_key).unwrap().remote_key()
+        });
+
+        let ( other_private_key, other_public_key ) = generate_keypair().unwrap();
+        let connected = Channel::connect(TcpStream::connect(address).unwrap(), &other_private_key).unwrap();
+
+        assert_eq!(connected.remote_key(), public_key);
+        assert_eq!(accepted.join().unwrap(), other_public_key);
+    }
+
+    #[test]
+    fn both_devices_get_the_same_code() {
+        let ( mut connected, mut accepted ) = channels();
+
+        let responder = thread::spawn(move || accepted.exchange_code(false).unwrap());
+        let code = connected.exchange_code(true).unwrap();
+
+        assert_eq!(responder.join().unwrap(), code);
+        assert_eq!(code.len(), 7);
+        assert!(code.chars().enumerate().all(|( i, c )| if i == 3 { c == ' ' } else { c.is_ascii_digit() }));
+    }
+
+    #[test]
+    fn rejects_a_nonce_that_wasnt_committed_to() {
+        let ( mut connected, mut accepted ) = channels();
+
+        let responder = thread::spawn(move || {
+            accepted.send(&Sha256::digest(generate_salt())).unwrap();
+            accepted.receive().unwrap();
+            accepted.send(&generate_salt()).unwrap();
+        });
+
+        assert!(matches!(connected.exchange_code(true), Err(RemoteError::InvalidResponse(_))));
+        responder.join().unwrap();
+    }
+}
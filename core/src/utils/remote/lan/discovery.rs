@@ -0,0 +1,137 @@
+///
+/// Finding the devices that share a vault on the local network.
+///
+/// A device sharing a vault announces itself every [`ANNOUNCE_INTERVAL`] on a
+/// multicast group ([`GROUP`]:[`PORT`]), with the port it listens on, its
+/// name and the fingerprint of its key. Vault names are never announced, they
+/// are only sent once the connection is encrypted.
+///
+use std::{
+    collections::BTreeMap, io::ErrorKind, net::{ Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket },
+    sync::{ Arc, atomic::{ AtomicBool, Ordering } }, thread, time::{ Duration, Instant },
+};
+use serde::{ Serialize, Deserialize };
+use socket2::{ Domain, Protocol, Socket, Type };
+use tracing::{ debug, warn };
+
+use crate::types::vault_error::VaultError;
+
+/// Multicast group the devices announce themselves on.
+pub const GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 77);
+
+/// UDP port of the announcements.
+pub const PORT: u16 = 47474;
+
+/// Time between two announcements.
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Marks the announcements of Secure Notes.
+const APP: &str = "secure-notes";
+
+/// A device found on the network.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Announcement {
+    app: String,
+
+    /// Name of the device, e.g. its host name.
+    pub name: String,
+
+    /// Fingerprint of the device's key, see [`fingerprint`](super::fingerprint).
+    pub fingerprint: String,
+
+    /// TCP port the device listens on.
+    pub port: u16,
+
+    /// Address the announcement came from, set when it's received.
+    #[serde(skip)]
+    pub address: Option<SocketAddr>,
+}
+
+impl Announcement {
+    pub fn new(name: &str, fingerprint: &str, port: u16) -> Self {
+        Self {
+            app: APP.to_string(),
+            name: name.to_string(),
+            fingerprint: fingerprint.to_string(),
+            port,
+            address: None,
+        }
+    }
+
+    /// Address to connect to.
+    pub fn peer_address(&self) -> Option<SocketAddr> {
+        self.address.map(|a| SocketAddr::new(a.ip(), self.port))
+    }
+}
+
+/// Announces the device until `stop` is set, in another thread.
+pub fn announce(announcement: Announcement, stop: Arc<AtomicBool>) -> Result<(), VaultError> {
+    let io_error = |e| VaultError::io("Couldn't announce the device on the network", e);
+    let socket = UdpSocket::bind(( Ipv4Addr::UNSPECIFIED, 0 )).map_err(io_error)?;
+
+    // Devices on this computer are found too, e.g. for testing
+    socket.set_multicast_loop_v4(true)
+        .and_then(|()| socket.set_multicast_ttl_v4(1))
+        .map_err(io_error)?;
+
+    let data = serde_json::to_vec(&announcement).expect("an announcement is always valid JSON");
+
+    thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            if let Err(e) = socket.send_to(&data, SocketAddrV4::new(GROUP, PORT)) {
+                warn!(error = %e, "could not announce the device on the network");
+            }
+
+            thread::sleep(ANNOUNCE_INTERVAL);
+        }
+    });
+
+    Ok(())
+}
+
+/// Listens to the announcements for `duration`, and returns the devices found,
+/// except the one with the fingerprint `own`.
+pub fn discover(duration: Duration, own: &str) -> Result<Vec<Announcement>, VaultError> {
+    let io_error = |e| VaultError::io("Couldn't listen for devices on the network", e);
+    let socket = listen().map_err(io_error)?;
+    let until = Instant::now() + duration;
+    let mut found = BTreeMap::new();
+    let mut buffer = [ 0u8; 1024 ];
+
+    while let Some(left) = until.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+        socket.set_read_timeout(Some(left)).map_err(io_error)?;
+
+        let ( len, address ) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => { break; }
+            Err(e) => { return Err(io_error(e)); }
+        };
+
+        let mut announcement = match serde_json::from_slice::<Announcement>(&buffer[..len]) {
+            Ok(a) if a.app == APP && a.fingerprint != own => a,
+            _ => { continue; }
+        };
+
+        debug!(device = %announcement.name, address = %address, "found a device");
+
+        announcement.address = Some(address);
+        found.insert(announcement.fingerprint.clone(), announcement);
+    }
+
+    Ok(found.into_values().collect())
+}
+
+/// Joins the multicast group, other programs can listen to it too.
+fn listen() -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+
+    socket.set_reuse_address(true)?;
+
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT).into())?;
+    socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+
+    Ok(socket.into())
+}
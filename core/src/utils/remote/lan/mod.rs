@@ -0,0 +1,901 @@
+///
+/// Syncing a vault directly with another device on the local network, without
+/// any server.
+///
+/// One device shares the vault with a [`LanServer`], the other one finds it
+/// (see [`discovery`]) and syncs with it like with a server: [`LanPeer`] is a
+/// [`Remote`], see [`sync_with_peer`]. Only the files of the vault are
+/// exchanged, as they are stored on the disk, over a connection that is
+/// encrypted as well (see [`channel`]).
+///
+/// Every device has a key pair, made the first time it syncs over the network,
+/// and only syncs with the devices it was paired with. The first time two
+/// devices connect, both show a verification code (see
+/// [`Channel::exchange_code`]): the user checks that the codes are the same
+/// and accepts on both devices, and each device only saves the other one once
+/// both accepted. The keys are local to the device and stored in the "lan"
+/// directory inside the secure-notes local directory:
+///
+/// + lan\
+///   + identity
+///   + peers
+///
+/// The sync state (see [`sync_vault`]) of every paired device is kept apart
+/// from the server's and the other devices'.
+///
+pub mod channel;
+pub mod discovery;
+
+use std::{
+    fs::{ OpenOptions, create_dir_all, read, rename, remove_file }, io::Write, net::{ SocketAddr, TcpListener, TcpStream },
+    path::PathBuf, sync::{ Arc, Mutex, atomic::{ AtomicBool, Ordering } }, thread, time::Duration,
+};
+use serde::{ Serialize, Deserialize };
+use flexbuffers::{ FlexbufferSerializer, Reader };
+use tracing::{ debug, info, warn };
+use zeroize::Zeroizing;
+
+use crate::{
+    types::vault_error::VaultError,
+    utils::{
+        get_local_dir, unix_now,
+        crypto::generate_salt,
+        remote::{ Remote, RemoteError, RemoteFile, RemoteSyncReport, sync_vault },
+        storage::{ FileStorage, VaultStorage, check_path },
+        sync::content_hash,
+    },
+};
+use channel::{ Channel, generate_keypair };
+use discovery::Announcement;
+
+/// Shows the verification code of a device that isn't paired yet, with the
+/// device's name, and returns whether the user accepted it.
+pub type ConfirmPairing = dyn Fn(&str, &str) -> bool + Send + Sync;
+
+/// The key pair of this device.
+pub struct LanIdentity {
+    private_key: Zeroizing<Vec<u8>>,
+    public_key: Vec<u8>,
+
+    /// Name shown to the other devices.
+    pub name: String,
+}
+
+impl LanIdentity {
+    /// Reads the key pair of this device, making it on first use.
+    pub fn load() -> Result<Self, VaultError> {
+        let path = get_lan_path("identity").ok_or(VaultError::LocalDirNotFound)?;
+
+        let stored = match read(&path) {
+            Ok(bytes) => Zeroizing::new(bytes),
+            Err(_) => {
+                let ( private_key, public_key ) = generate_keypair()?;
+                let identity = StoredIdentity { private_key, public_key };
+
+                write_flexbuffer(&path, &identity)?;
+                info!("made the key of this device for syncing over the network");
+
+                return Ok(Self::from_stored(identity));
+            }
+        };
+
+        // Written readable by anyone by older versions
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            if let Err(e) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)) {
+                warn!(error = %e, "could not make the device key private");
+            }
+        }
+
+        let identity = Reader::get_root(stored.as_slice())
+            .map_err(|e| VaultError::corrupt("device key", e))
+            .and_then(|reader| {
+                StoredIdentity::deserialize(reader).map_err(|e| VaultError::corrupt("device key", e))
+            })?;
+
+        Ok(Self::from_stored(identity))
+    }
+
+    fn from_stored(identity: StoredIdentity) -> Self {
+        Self {
+            private_key: Zeroizing::new(identity.private_key),
+            public_key: identity.public_key,
+            name: device_name(),
+        }
+    }
+
+    /// Fingerprint of the device's key, see [`fingerprint`].
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.public_key)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredIdentity {
+    private_key: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+/// A device this device was paired with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PairedPeer {
+    pub name: String,
+
+    /// Public key of the device.
+    pub key: Vec<u8>,
+
+    /// Time of the pairing, in seconds since the unix epoch.
+    pub paired: u64,
+}
+
+impl PairedPeer {
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.key)
+    }
+}
+
+/// Short hex name of a device's public key, the same on every device.
+pub fn fingerprint(public_key: &[u8]) -> String {
+    content_hash(public_key)[..16].to_string()
+}
+
+/// Lists the devices this device was paired with.
+pub fn read_peers() -> Vec<PairedPeer> {
+    let bytes = match get_lan_path("peers").map(read) {
+        Some(Ok(b)) => b,
+        _ => { return vec![]; }
+    };
+
+    match Reader::get_root(bytes.as_slice()).map(Vec::<PairedPeer>::deserialize) {
+        Ok(Ok(peers)) => peers,
+        _ => {
+            warn!("could not read the paired devices");
+            vec![]
+        }
+    }
+}
+
+/// Forgets the paired device with the fingerprint, returns whether it was
+/// paired.
+pub fn unpair(fingerprint: &str) -> Result<bool, VaultError> {
+    let mut peers = read_peers();
+    let count = peers.len();
+
+    peers.retain(|p| p.fingerprint() != fingerprint);
+
+    if peers.len() == count {
+        return Ok(false);
+    }
+
+    write_peers(&peers)?;
+
+    Ok(true)
+}
+
+fn add_peer(name: &str, key: &[u8]) -> Result<(), VaultError> {
+    let mut peers = read_peers();
+
+    peers.retain(|p| p.key != key);
+    peers.push(PairedPeer { name: name.to_string(), key: key.to_vec(), paired: unix_now() });
+
+    info!(device = %name, "paired with a device");
+
+    write_peers(&peers)
+}
+
+fn write_peers(peers: &[PairedPeer]) -> Result<(), VaultError> {
+    let path = get_lan_path("peers").ok_or(VaultError::LocalDirNotFound)?;
+
+    write_flexbuffer(&path, &peers)
+}
+
+fn is_paired(key: &[u8]) -> bool {
+    read_peers().iter().any(|p| p.key == key)
+}
+
+/// Syncs the vault with the device, see [`sync_vault`].
+pub fn sync_with_peer(
+    name: &str,
+    storage: &dyn VaultStorage,
+    peer: &LanPeer,
+) -> Result<RemoteSyncReport, VaultError> {
+    // Every device has a sync state of its own
+    sync_vault(&format!("{}.peer-{}", name, peer.fingerprint), storage, peer)
+}
+
+/// What a device asks the one sharing the vault.
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    /// Opens the vault, first thing after connecting.
+    Hello {
+        vault: String,
+
+        /// Name of the device.
+        name: String,
+
+        /// Whether the device asks to be paired.
+        pair: bool,
+    },
+
+    /// Whether the user accepted the verification code, after
+    /// [`Response::Pair`].
+    Confirm { accepted: bool },
+
+    List { dir: String },
+    Get { path: String },
+    Put { path: String, data: Vec<u8>, etag: Option<String> },
+    Delete { path: String, etag: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Response {
+    /// Answers [`Request::Hello`] with the name of the device.
+    Welcome { name: String },
+
+    /// Answers [`Request::Hello`] of a device that asks to be paired with the
+    /// name of the device, then both exchange the verification code.
+    Pair { name: String },
+
+    Files(Vec<( String, String )>),
+    File { data: Vec<u8>, etag: String },
+    Etag(String),
+    Done,
+
+    /// Status codes like HTTP's, e.g. 412 when the file has changed.
+    Error { code: u16, message: String },
+}
+
+/// Shares a vault with the paired devices on the local network.
+pub struct LanServer {
+    listener: TcpListener,
+    vault: String,
+    storage: FileStorage,
+    identity: LanIdentity,
+    pairing: Option<Box<ConfirmPairing>>,
+}
+
+impl LanServer {
+    /// Listens on `address`, e.g. "0.0.0.0:0" for any free port.
+    pub fn bind(
+        vault: &str,
+        storage: FileStorage,
+        identity: LanIdentity,
+        address: SocketAddr,
+    ) -> Result<Self, VaultError> {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| VaultError::io("Couldn't listen on the network", e))?;
+
+        Ok(Self { listener, vault: vault.to_string(), storage, identity, pairing: None })
+    }
+
+    /// Port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|a| a.port()).unwrap_or_default()
+    }
+
+    /// Lets devices that aren't paired yet pair with this one, after the user
+    /// accepted the verification code.
+    pub fn allow_pairing(&mut self, confirm: Box<ConfirmPairing>) {
+        self.pairing = Some(confirm);
+    }
+
+    /// Announces the device on the network and serves the devices that connect
+    /// until `stop` is set.
+    pub fn run(self, stop: Arc<AtomicBool>) -> Result<(), VaultError> {
+        let announcement = Announcement::new(&self.identity.name, &self.identity.fingerprint(), self.port());
+        discovery::announce(announcement, stop.clone())?;
+
+        if let Err(e) = self.listener.set_nonblocking(true) {
+            return Err(VaultError::io("Couldn't listen on the network", e));
+        }
+
+        let listener = self.listener;
+        let server = Arc::new(Shared {
+            vault: self.vault,
+            storage: self.storage,
+            identity: self.identity,
+            pairing: self.pairing,
+        });
+
+        info!(vault = %server.vault, "sharing vault on the network");
+
+        while !stop.load(Ordering::Relaxed) {
+            let ( stream, address ) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+
+                Err(e) => { return Err(VaultError::io("Couldn't accept a connection", e)); }
+            };
+
+            let server = server.clone();
+
+            thread::spawn(move || {
+                debug!(address = %address, "device connected");
+
+                if let Err(e) = server.serve(stream) {
+                    warn!(address = %address, error = %e, "connection with a device failed");
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// What the connections of a [`LanServer`] share.
+struct Shared {
+    vault: String,
+    storage: FileStorage,
+    identity: LanIdentity,
+    pairing: Option<Box<ConfirmPairing>>,
+}
+
+impl Shared {
+    fn serve(&self, stream: TcpStream) -> Result<(), RemoteError> {
+        stream.set_nonblocking(false).map_err(|e| RemoteError::Offline(e.to_string()))?;
+
+        let mut channel = Channel::accept(stream, &self.identity.private_key)?;
+
+        let response = match receive(&mut channel)? {
+            Request::Hello { vault, name, pair } => self.hello(&mut channel, &vault, &name, pair)?,
+            _ => Response::Error { code: 400, message: String::from("Expected a hello") },
+        };
+
+        let welcomed = matches!(response, Response::Welcome { .. });
+        send(&mut channel, &response)?;
+
+        if !welcomed {
+            return Ok(());
+        }
+
+        channel.allow_large_messages();
+
+        loop {
+            let request = match receive(&mut channel) {
+                Ok(request) => request,
+
+                // The device is done
+                Err(RemoteError::Offline(_)) => { return Ok(()); }
+                Err(e) => { return Err(e); }
+            };
+
+            let response = self.handle(request);
+            send(&mut channel, &response)?;
+        }
+    }
+
+    /// Opens the vault for a paired device, or pairs the device first if it
+    /// asks to be: the device is only saved once the users of both devices
+    /// accepted the verification code.
+    fn hello(
+        &self,
+        channel: &mut Channel,
+        vault: &str,
+        name: &str,
+        pair: bool,
+    ) -> Result<Response, RemoteError> {
+        if vault != self.vault {
+            return Ok(Response::Error { code: 404, message: String::from("This vault isn't shared") });
+        }
+
+        let key = channel.remote_key();
+        let refused = Response::Error { code: 403, message: String::from("The devices aren't paired") };
+
+        if !pair {
+            return match is_paired(&key) {
+                true => Ok(Response::Welcome { name: self.identity.name.clone() }),
+                false => Ok(refused),
+            };
+        }
+
+        let confirm = match &self.pairing {
+            Some(confirm) => confirm,
+            None => { return Ok(refused); }
+        };
+
+        send(channel, &Response::Pair { name: self.identity.name.clone() })?;
+
+        let code = channel.exchange_code(false)?;
+        let accepted = confirm(name, &code);
+
+        // The other device's user answers as well
+        let other_accepted = matches!(receive(channel)?, Request::Confirm { accepted: true });
+
+        if !accepted || !other_accepted {
+            return Ok(Response::Error { code: 403, message: String::from("The pairing was refused") });
+        }
+
+        if let Err(e) = add_peer(name, &key) {
+            return Ok(Response::Error { code: 500, message: e.to_string() });
+        }
+
+        Ok(Response::Welcome { name: self.identity.name.clone() })
+    }
+
+    fn handle(&self, request: Request) -> Response {
+        let storage = &self.storage;
+
+        let result = match request {
+            Request::Hello { .. } | Request::Confirm { .. } => Err(( 400, String::from("Already opened") )),
+
+            Request::List { dir } => {
+                if !super::DIRS.contains(&dir.as_str()) {
+                    return Response::Error { code: 404, message: String::from("Unknown directory") };
+                }
+
+                list_files(storage, &dir).map(Response::Files)
+            }
+
+            Request::Get { path } => {
+                check_file_path(&path).and_then(|()| read_file(storage, &path)).and_then(|data| {
+                    match data {
+                        Some(data) => Ok(Response::File { etag: content_hash(&data), data }),
+                        None => Err(( 404, String::from("Not found") )),
+                    }
+                })
+            }
+
+            Request::Put { path, data, etag } => {
+                check_file_path(&path)
+                    .and_then(|()| check_etag(storage, &path, etag.as_deref()))
+                    .and_then(|()| match storage.replace(&path, &data) {
+                        Ok(()) => Ok(Response::Etag(content_hash(&data))),
+                        Err(e) => Err(( 500, e.to_string() )),
+                    })
+            }
+
+            Request::Delete { path, etag } => {
+                check_file_path(&path)
+                    .and_then(|()| match storage.exists(&path) {
+                        true => check_etag(storage, &path, Some(&etag)),
+                        false => Err(( 404, String::from("Not found") )),
+                    })
+                    .and_then(|()| match storage.delete(&path) {
+                        Ok(()) => Ok(Response::Done),
+                        Err(e) => Err(( 500, e.to_string() )),
+                    })
+            }
+        };
+
+        result.unwrap_or_else(|( code, message )| Response::Error { code, message })
+    }
+}
+
+/// Only the files of the vault can be read or written, no hidden file.
+fn check_file_path(path: &str) -> Result<(), ( u16, String )> {
+    let valid = check_path(path, false).is_ok()
+        && !path.split('/').any(|part| part.starts_with('.'))
+        && match path.split_once('/') {
+            Some(( dir, name )) => super::DIRS.contains(&dir) && !name.contains('/'),
+            None => true,
+        };
+
+    match valid {
+        true => Ok(()),
+        false => Err(( 403, format!("\"{}\" is not a file of the vault", path) )),
+    }
+}
+
+fn list_files(storage: &FileStorage, dir: &str) -> Result<Vec<( String, String )>, ( u16, String )> {
+    let names = storage.list(dir).map_err(|e| ( 500, e.to_string() ))?;
+    let mut files = vec![];
+
+    for name in names {
+        let path = super::join_path(dir, &name);
+
+        if let Some(data) = read_file(storage, &path)? {
+            files.push(( name, content_hash(&data) ));
+        }
+    }
+
+    Ok(files)
+}
+
+fn read_file(storage: &FileStorage, path: &str) -> Result<Option<Vec<u8>>, ( u16, String )> {
+    match storage.read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(( 500, e.to_string() )),
+    }
+}
+
+/// Fails with 412 unless the file has the ETag, `None` meaning that it
+/// doesn't exist.
+fn check_etag(storage: &FileStorage, path: &str, etag: Option<&str>) -> Result<(), ( u16, String )> {
+    let current = read_file(storage, path)?.map(|data| content_hash(&data));
+
+    match current.as_deref() == etag {
+        true => Ok(()),
+        false => Err(( 412, String::from("The file has changed") )),
+    }
+}
+
+/// A device sharing a vault, connected to.
+pub struct LanPeer {
+    channel: Mutex<Channel>,
+
+    /// Name the device gave itself.
+    pub name: String,
+
+    /// Fingerprint of the device's key.
+    pub fingerprint: String,
+}
+
+impl LanPeer {
+    /// Connects to the device at `address` and opens the vault on it.
+    ///
+    /// A device that isn't paired yet is only accepted with `confirm`, and
+    /// saved once the user of each device accepted the verification code.
+    pub fn connect(
+        address: SocketAddr,
+        vault: &str,
+        identity: &LanIdentity,
+        confirm: Option<&ConfirmPairing>,
+    ) -> Result<Self, VaultError> {
+        let stream = TcpStream::connect_timeout(&address, Duration::from_secs(10))
+            .map_err(|e| RemoteError::Offline(e.to_string()))?;
+
+        let mut channel = Channel::connect(stream, &identity.private_key)?;
+        let key = channel.remote_key();
+        let paired = is_paired(&key);
+
+        if !paired && confirm.is_none() {
+            return Err(VaultError::Invalid(String::from(
+                "The device isn't paired with this one, please pair them first"
+            )));
+        }
+
+        let hello = Request::Hello {
+            vault: vault.to_string(),
+            name: identity.name.clone(),
+            pair: !paired,
+        };
+
+        send(&mut channel, &hello)?;
+
+        let name = match ( receive(&mut channel)?, confirm ) {
+            ( Response::Welcome { name }, _ ) if paired => name,
+            ( Response::Pair { name }, Some(confirm) ) if !paired => {
+                let code = channel.exchange_code(true)?;
+                let accepted = confirm(&name, &code);
+
+                send(&mut channel, &Request::Confirm { accepted })?;
+
+                match receive(&mut channel)? {
+                    Response::Welcome { .. } if accepted => {}
+                    Response::Error { message, .. } => { return Err(VaultError::Invalid(message)); }
+                    _ if !accepted => {
+                        return Err(VaultError::Invalid(String::from("The pairing was refused")));
+                    }
+
+                    _ => { return Err(unexpected().into()); }
+                }
+
+                // The other device saved this one as well
+                add_peer(&name, &key)?;
+
+                name
+            }
+
+            ( Response::Error { message, .. }, _ ) => { return Err(VaultError::Invalid(message)); }
+            _ => { return Err(RemoteError::InvalidResponse(String::from("Expected a welcome")).into()); }
+        };
+
+        channel.allow_large_messages();
+
+        debug!(device = %name, "connected to device");
+
+        Ok(Self { channel: Mutex::new(channel), name, fingerprint: fingerprint(&key) })
+    }
+
+    fn request(&self, request: &Request) -> Result<Response, RemoteError> {
+        let mut channel = match self.channel.lock() {
+            Ok(channel) => channel,
+            Err(_) => { return Err(RemoteError::Offline(String::from("The connection broke"))); }
+        };
+
+        send(&mut channel, request)?;
+
+        match receive(&mut channel)? {
+            Response::Error { code: 412, .. } => Err(RemoteError::Changed),
+            Response::Error { code: 404, .. } => Err(RemoteError::NotFound),
+            Response::Error { code, message } => Err(RemoteError::Status { code, message }),
+            response => Ok(response),
+        }
+    }
+}
+
+impl Remote for LanPeer {
+    fn id(&self) -> String {
+        format!("lan:{}", self.fingerprint)
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<RemoteFile>, RemoteError> {
+        match self.request(&Request::List { dir: dir.to_string() }) {
+            Ok(Response::Files(files)) => Ok(files.into_iter()
+                .map(|( name, etag )| RemoteFile { name, etag })
+                .collect()),
+
+            Ok(_) => Err(unexpected()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<(Vec<u8>, String), RemoteError> {
+        match self.request(&Request::Get { path: path.to_string() })? {
+            Response::File { data, etag } => Ok(( data, etag )),
+            _ => Err(unexpected()),
+        }
+    }
+
+    fn put(&self, path: &str, data: &[u8], etag: Option<&str>) -> Result<String, RemoteError> {
+        let request = Request::Put {
+            path: path.to_string(),
+            data: data.to_vec(),
+            etag: etag.map(String::from),
+        };
+
+        match self.request(&request)? {
+            Response::Etag(etag) => Ok(etag),
+            _ => Err(unexpected()),
+        }
+    }
+
+    fn delete(&self, path: &str, etag: &str) -> Result<(), RemoteError> {
+        match self.request(&Request::Delete { path: path.to_string(), etag: etag.to_string() })? {
+            Response::Done => Ok(()),
+            _ => Err(unexpected()),
+        }
+    }
+}
+
+fn send<T: Serialize>(channel: &mut Channel, message: &T) -> Result<(), RemoteError> {
+    match flexbuffers::to_vec(message) {
+        Ok(data) => channel.send(&data),
+        Err(e) => Err(RemoteError::InvalidResponse(e.to_string())),
+    }
+}
+
+fn receive<T: for<'de> Deserialize<'de>>(channel: &mut Channel) -> Result<T, RemoteError> {
+    let data = channel.receive()?;
+
+    flexbuffers::from_slice(&data).map_err(|e| RemoteError::InvalidResponse(e.to_string()))
+}
+
+fn unexpected() -> RemoteError {
+    RemoteError::InvalidResponse(String::from("Unexpected answer from the device"))
+}
+
+/// Name of this device shown to the others, its host name.
+fn device_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("Secure Notes"))
+}
+
+fn get_lan_path(file: &str) -> Option<PathBuf> {
+    match get_local_dir() {
+        Some(mut path) => {
+            path.push("lan");
+            path.push(file);
+            Some(path)
+        }
+
+        None => None,
+    }
+}
+
+fn write_flexbuffer<T: Serialize>(path: &PathBuf, value: &T) -> Result<(), VaultError> {
+    if let Some(dir) = path.parent() {
+        if let Err(e) = create_dir_all(dir) {
+            return Err(VaultError::io("Couldn't create the network sync directory", e));
+        }
+    }
+
+    let mut serializer = FlexbufferSerializer::new();
+
+    if let Err(e) = value.serialize(&mut serializer) {
+        debug!(error = %e, "could not serialise the network sync keys");
+        return Err(VaultError::Invalid(String::from("Could not serialize the network sync keys")));
+    }
+
+    // Only readable by the user, and written whole or not at all
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(format!(".{}.tmp", &content_hash(&generate_salt())[..8]));
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let result = options.open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(serializer.view())?;
+            file.sync_all()
+        })
+        .and_then(|()| rename(&temp_path, path));
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = remove_file(&temp_path);
+            Err(VaultError::io("Couldn't write the network sync keys", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ env, process };
+
+    use super::*;
+    use crate::utils::set_data_dir;
+
+    /// The tests share the paired devices of the local directory.
+    static PEERS: Mutex<()> = Mutex::new(());
+
+    fn identity() -> LanIdentity {
+        let ( private_key, public_key ) = generate_keypair().unwrap();
+
+        LanIdentity::from_stored(StoredIdentity { private_key, public_key })
+    }
+
+    /// Serves one connection of a device sharing "Vault", whose user answers
+    /// `accept` to the pairing, and returns its key.
+    fn serve_once(accept: bool, codes: Arc<Mutex<Vec<String>>>) -> ( SocketAddr, Vec<u8>, thread::JoinHandle<()> ) {
+        let _ = set_data_dir(&env::temp_dir().join(format!("secure-notes-test-{}", process::id())));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let identity = identity();
+        let key = identity.public_key.clone();
+
+        let dir = env::temp_dir().join(format!("secure-notes-test-{}-lan-{}", process::id(), fingerprint(&key)));
+
+        let confirm: Box<ConfirmPairing> = Box::new(move |_, code| {
+            codes.lock().unwrap().push(code.to_string());
+            accept
+        });
+
+        let server = Shared {
+            vault: String::from("Vault"),
+            storage: FileStorage::new(dir),
+            identity,
+            pairing: Some(confirm),
+        };
+
+        let handle = thread::spawn(move || {
+            let _ = server.serve(listener.accept().unwrap().0);
+        });
+
+        ( address, key, handle )
+    }
+
+    fn confirm(accept: bool, codes: Arc<Mutex<Vec<String>>>) -> impl Fn(&str, &str) -> bool + Send + Sync {
+        move |_, code| {
+            codes.lock().unwrap().push(code.to_string());
+            accept
+        }
+    }
+
+    #[test]
+    fn pairs_once_both_accept_and_syncs() {
+        let _peers = PEERS.lock().unwrap_or_else(|e| e.into_inner());
+        let codes = Arc::new(Mutex::new(vec![]));
+        let client = identity();
+
+        let ( address, key, server ) = serve_once(true, codes.clone());
+        let peer = LanPeer::connect(address, "Vault", &client, Some(&confirm(true, codes.clone()))).unwrap();
+
+        let etag = peer.put("notes/1", b"note", None).unwrap();
+        assert_eq!(peer.list("notes").unwrap(), vec![ RemoteFile { name: String::from("1"), etag: etag.clone() } ]);
+        assert_eq!(peer.get("notes/1").unwrap(), ( b"note".to_vec(), etag.clone() ));
+        assert!(matches!(peer.put("notes/1", b"other", None), Err(RemoteError::Changed)));
+        assert!(matches!(peer.get(".hidden"), Err(RemoteError::Status { code: 403, .. })));
+
+        drop(peer);
+        server.join().unwrap();
+
+        let codes = codes.lock().unwrap().clone();
+        assert_eq!(codes.len(), 2);
+        assert_eq!(codes[0], codes[1]);
+        assert!(is_paired(&key) && is_paired(&client.public_key));
+        assert_eq!(fingerprint(&key).len(), 16);
+    }
+
+    #[test]
+    fn paired_devices_connect_without_confirming() {
+        let _peers = PEERS.lock().unwrap_or_else(|e| e.into_inner());
+        let codes = Arc::new(Mutex::new(vec![]));
+        let client = identity();
+
+        let ( address, key, server ) = serve_once(true, codes.clone());
+        add_peer("server", &key).unwrap();
+        add_peer("client", &client.public_key).unwrap();
+
+        let peer = LanPeer::connect(address, "Vault", &client, None).unwrap();
+        assert!(peer.list("").unwrap().is_empty());
+
+        drop(peer);
+        server.join().unwrap();
+
+        assert!(codes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn pairs_only_if_both_accept() {
+        let _peers = PEERS.lock().unwrap_or_else(|e| e.into_inner());
+
+        for ( server_accepts, client_accepts ) in [ ( false, true ), ( true, false ), ( false, false ) ] {
+            let codes = Arc::new(Mutex::new(vec![]));
+            let client = identity();
+
+            let ( address, key, server ) = serve_once(server_accepts, codes.clone());
+            let result = LanPeer::connect(address, "Vault", &client, Some(&confirm(client_accepts, codes.clone())));
+            server.join().unwrap();
+
+            assert!(result.is_err());
+            assert_eq!(codes.lock().unwrap().len(), 2);
+            assert!(!is_paired(&key) && !is_paired(&client.public_key));
+        }
+    }
+
+    #[test]
+    fn refuses_unknown_vaults_before_pairing() {
+        let _peers = PEERS.lock().unwrap_or_else(|e| e.into_inner());
+        let codes = Arc::new(Mutex::new(vec![]));
+        let client = identity();
+
+        let ( address, key, server ) = serve_once(true, codes.clone());
+        let result = LanPeer::connect(address, "Other", &client, Some(&confirm(true, codes.clone())));
+        server.join().unwrap();
+
+        assert!(matches!(result, Err(VaultError::Invalid(_))));
+        assert!(codes.lock().unwrap().is_empty());
+        assert!(!is_paired(&key) && !is_paired(&client.public_key));
+    }
+
+    #[test]
+    fn refuses_unpaired_devices_without_confirming() {
+        let _peers = PEERS.lock().unwrap_or_else(|e| e.into_inner());
+        let codes = Arc::new(Mutex::new(vec![]));
+        let client = identity();
+
+        let ( address, key, server ) = serve_once(true, codes.clone());
+
+        // Paired on this device only, the other one doesn't know it
+        add_peer("server", &key).unwrap();
+
+        let result = LanPeer::connect(address, "Vault", &client, None);
+        server.join().unwrap();
+
+        assert!(matches!(result, Err(VaultError::Invalid(_))));
+        assert!(codes.lock().unwrap().is_empty());
+        assert!(!is_paired(&client.public_key));
+    }
+
+    #[test]
+    fn checks_the_paths_of_files() {
+        assert!(check_file_path("index").is_ok());
+        assert!(check_file_path("notes/12").is_ok());
+        assert!(check_file_path("attachments/3").is_ok());
+
+        assert!(check_file_path(".git/config").is_err());
+        assert!(check_file_path("notes/.12.tmp").is_err());
+        assert!(check_file_path("other/12").is_err());
+        assert!(check_file_path("notes/12/13").is_err());
+        assert!(check_file_path("../index").is_err());
+    }
+}
@@ -0,0 +1,988 @@
+///
+/// Mirroring a vault to a server: a self-hosted WebDAV server (see [`webdav`])
+/// or an object storage that speaks the S3 API (see [`s3`]), or to another
+/// device on the local network (see [`lan`]).
+///
+/// Only the files of the vault are sent, as they are stored on the disk: the
+/// info file, the encrypted index and the encrypted notes and attachments. The
+/// vault doesn't need to be unlocked to be synced, the server never sees a key
+/// or the text of a note.
+///
+/// [`sync_vault`] compares the files of the vault with the server's, and with
+/// how they were after the last sync. This sync state is local to this device
+/// and stored per vault in the "sync-state" directory inside the secure-notes
+/// local directory:
+///
+/// + sync-state\
+///   + <vault-name>
+///
+/// - A file changed on one side only is copied to the other side, a file
+///   deleted on one side is deleted on the other side.
+/// - A file changed on both sides is a conflict: the server's version is stored
+///   next to the local file as a conflict copy, the way sync tools do (see
+///   [`sync`](crate::utils::sync)). The local file is uploaded once the
+///   conflict copy is gone: copies of the index are merged when the vault is
+///   unlocked, for notes the user chooses the version to keep.
+///
+/// Uploads and deletions only succeed if the server's file still has the ETag
+/// seen at the last sync, so that a change made by another device in the
+/// meantime is never overwritten. Requests that fail because of the network or
+/// a busy server are retried [`RETRIES`] times.
+///
+/// When the server can't be reached, the local changes stay queued: they are
+/// found again by comparing the files with the sync state (see
+/// [`pending_changes`]) and sent by the next sync. The state is written after
+/// every file, so an interrupted sync goes on where it stopped.
+///
+pub mod webdav;
+pub mod s3;
+pub mod lan;
+
+use std::{
+    collections::{ BTreeMap, BTreeSet }, error::Error, fmt,
+    fs::{ File, create_dir_all, read }, io::Write, path::PathBuf, thread::sleep, time::Duration,
+};
+use serde::{ Serialize, Deserialize };
+use flexbuffers::{ FlexbufferSerializer, Reader };
+use tracing::{ debug, info, warn };
+
+use crate::{
+    types::vault_error::VaultError,
+    utils::{
+        get_local_dir, unix_now,
+        storage::VaultStorage,
+        sync::{ content_hash, conflict_copy_of },
+        vault::check_vault_name,
+    },
+};
+
+pub use webdav::WebDav;
+pub use s3::S3;
+
+/// Directories of the vault that are synced, "" is the vault itself.
+const DIRS: [&str; 3] = [ "", "notes", "attachments" ];
+
+/// Times a request is sent again when the server couldn't be reached or was
+/// busy.
+pub const RETRIES: u32 = 3;
+
+/// Time to wait before the first retry, doubled for every other one.
+pub const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A file on the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteFile {
+    /// Name of the file inside its directory.
+    pub name: String,
+
+    /// ETag of the file, it changes whenever the file does.
+    pub etag: String,
+}
+
+/// Why a request to the server failed.
+#[derive(Debug)]
+pub enum RemoteError {
+    /// The server couldn't be reached, or the connection broke.
+    Offline(String),
+
+    /// The server didn't accept the user name or password.
+    Unauthorized,
+
+    /// The file was changed, created or deleted on the server since its ETag
+    /// was read.
+    Changed,
+
+    /// The file doesn't exist on the server.
+    NotFound,
+
+    /// The server answered with another error.
+    Status {
+        code: u16,
+        message: String,
+    },
+
+    /// The server's answer couldn't be understood.
+    InvalidResponse(String),
+}
+
+impl RemoteError {
+    /// Whether the request may succeed when it's sent again.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Offline(_) => true,
+            Self::Status { code, .. } => *code == 429 || *code >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Offline(message) => write!(f, "Couldn't reach the server: {}", message),
+            Self::Unauthorized => f.write_str("The server didn't accept the user name or password"),
+            Self::Changed => f.write_str("The file was changed on the server"),
+            Self::NotFound => f.write_str("The file doesn't exist on the server"),
+            Self::Status { code, message } => write!(f, "The server answered {} {}", code, message),
+            Self::InvalidResponse(message) => write!(f, "Unexpected answer from the server: {}", message),
+        }
+    }
+}
+
+impl Error for RemoteError {}
+
+/// A server that vaults are mirrored to.
+///
+/// Paths are relative to the vault's directory on the server, with "/" between
+/// the directories, like the paths of a [`VaultStorage`].
+pub trait Remote: Send + Sync {
+    /// Names the server and the vault's directory on it, e.g. its URL. The
+    /// sync state is started over when it changes.
+    fn id(&self) -> String;
+
+    /// Lists the files in the directory, "" is the vault's directory. A
+    /// directory that doesn't exist is empty.
+    fn list(&self, dir: &str) -> Result<Vec<RemoteFile>, RemoteError>;
+
+    /// Downloads the file, returns its contents and ETag.
+    fn get(&self, path: &str) -> Result<(Vec<u8>, String), RemoteError>;
+
+    /// Uploads the file and returns its new ETag.
+    ///
+    /// Fails with [`RemoteError::Changed`] if the file doesn't have the ETag
+    /// `etag` anymore, `None` means that the file must not exist yet.
+    fn put(&self, path: &str, data: &[u8], etag: Option<&str>) -> Result<String, RemoteError>;
+
+    /// Deletes the file, fails with [`RemoteError::Changed`] if it doesn't have
+    /// the ETag `etag` anymore.
+    fn delete(&self, path: &str, etag: &str) -> Result<(), RemoteError>;
+}
+
+/// How the files of a vault were after the last sync.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SyncState {
+    /// [`Remote::id`] of the server the vault was synced with.
+    #[serde(default)]
+    pub remote: String,
+
+    /// The synced files by path.
+    #[serde(default)]
+    pub files: BTreeMap<String, SyncedFile>,
+
+    /// Time of the last complete sync, in seconds since the unix epoch.
+    #[serde(default)]
+    pub last_sync: u64,
+}
+
+/// A file as it was after the last sync.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SyncedFile {
+    /// [`content_hash`] of the local file. Empty when the local file has to be
+    /// uploaded once its conflict is resolved.
+    pub hash: String,
+
+    /// ETag of the server's file.
+    pub etag: String,
+}
+
+/// Summary of what [`sync_vault`] did.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RemoteSyncReport {
+    pub uploaded: usize,
+    pub downloaded: usize,
+
+    /// Files deleted on this device or on the server.
+    pub deleted: usize,
+
+    /// Files that were changed on both sides, the server's version was stored
+    /// in a conflict copy.
+    pub conflicts: Vec<String>,
+
+    /// Files that are uploaded once their conflict copy is gone.
+    pub waiting: Vec<String>,
+}
+
+impl fmt::Display for RemoteSyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Uploaded:   {}", self.uploaded)?;
+        writeln!(f, "Downloaded: {}", self.downloaded)?;
+        write!(f, "Deleted:    {}", self.deleted)?;
+
+        if !self.conflicts.is_empty() {
+            write!(f, "\nConflicts:  {}", self.conflicts.len())?;
+
+            for path in &self.conflicts {
+                write!(f, "\n  - {}", path)?;
+            }
+        }
+
+        if !self.waiting.is_empty() {
+            write!(f, "\nWaiting for a conflict to be resolved: {}", self.waiting.len())?;
+
+            for path in &self.waiting {
+                write!(f, "\n  - {}", path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Files of the vault on this device.
+#[derive(Debug, Default)]
+struct LocalFiles {
+    files: BTreeSet<String>,
+
+    /// Files that have a conflict copy.
+    conflicted: BTreeSet<String>,
+}
+
+/// Syncs the files of the vault with the server.
+///
+/// `name` is the name of the vault, its sync state is read from and written
+/// to the local directory.
+pub fn sync_vault(
+    name: &str,
+    storage: &dyn VaultStorage,
+    remote: &dyn Remote,
+) -> Result<RemoteSyncReport, VaultError> {
+    let mut state = read_sync_state(name);
+    let remote_id = remote.id();
+
+    if state.remote != remote_id {
+        if !state.remote.is_empty() {
+            info!(vault = %name, "syncing with another server, starting over");
+        }
+
+        state = SyncState { remote: remote_id, ..SyncState::default() };
+    }
+
+    let local = list_local_files(storage)?;
+    let mut remote_files = BTreeMap::new();
+
+    for dir in DIRS {
+        for file in retry(|| remote.list(dir))? {
+            if !file.name.starts_with('.') && conflict_copy_of(&file.name).is_none() {
+                remote_files.insert(join_path(dir, &file.name), file.etag);
+            }
+        }
+    }
+
+    let paths: BTreeSet<String> = local.files.iter()
+        .chain(remote_files.keys())
+        .chain(state.files.keys())
+        .cloned()
+        .collect();
+
+    let mut report = RemoteSyncReport::default();
+
+    for path in paths {
+        let data = match local.files.contains(&path) {
+            true => match storage.read(&path) {
+                Ok(data) => Some(data),
+                Err(e) => { return Err(VaultError::io("Couldn't read a file of the vault", e)); }
+            },
+
+            false => None,
+        };
+
+        let synced = state.files.get(&path).cloned();
+
+        let file = SyncFile {
+            path: &path,
+            data: data.as_deref(),
+            etag: remote_files.get(&path).map(String::as_str),
+            synced: synced.as_ref(),
+            conflicted: local.conflicted.contains(&path),
+        };
+
+        let new_state = sync_file(storage, remote, file, &mut report)?;
+
+        if new_state == synced {
+            continue;
+        }
+
+        match new_state {
+            Some(synced) => { state.files.insert(path, synced); }
+            None => { state.files.remove(&path); }
+        }
+
+        write_sync_state(name, &state)?;
+    }
+
+    state.last_sync = unix_now();
+    write_sync_state(name, &state)?;
+
+    info!(
+        vault = %name,
+        uploaded = report.uploaded,
+        downloaded = report.downloaded,
+        deleted = report.deleted,
+        conflicts = report.conflicts.len(),
+        "synced vault",
+    );
+
+    Ok(report)
+}
+
+/// Returns the paths of the files that were changed on this device since the
+/// last sync, the next sync uploads them or deletes them on the server.
+pub fn pending_changes(name: &str, storage: &dyn VaultStorage) -> Result<Vec<String>, VaultError> {
+    let state = read_sync_state(name);
+    let local = list_local_files(storage)?;
+
+    let paths: BTreeSet<&String> = local.files.iter().chain(state.files.keys()).collect();
+    let mut pending = vec![];
+
+    for path in paths {
+        let hash = match local.files.contains(path) {
+            true => match storage.read(path) {
+                Ok(data) => Some(content_hash(&data)),
+                Err(e) => { return Err(VaultError::io("Couldn't read a file of the vault", e)); }
+            },
+
+            false => None,
+        };
+
+        if hash.as_deref() != state.files.get(path).map(|f| f.hash.as_str()) {
+            pending.push(path.clone());
+        }
+    }
+
+    Ok(pending)
+}
+
+/// A file to sync.
+struct SyncFile<'a> {
+    path: &'a str,
+
+    /// Contents of the local file, `None` if it doesn't exist.
+    data: Option<&'a [u8]>,
+
+    /// ETag of the server's file, `None` if it doesn't exist.
+    etag: Option<&'a str>,
+
+    /// The file after the last sync.
+    synced: Option<&'a SyncedFile>,
+
+    /// Whether the local file has a conflict copy.
+    conflicted: bool,
+}
+
+/// Syncs a single file and returns its new state, `None` when it was deleted
+/// on both sides.
+fn sync_file(
+    storage: &dyn VaultStorage,
+    remote: &dyn Remote,
+    file: SyncFile,
+    report: &mut RemoteSyncReport,
+) -> Result<Option<SyncedFile>, VaultError> {
+    let SyncFile { path, data, etag, synced, conflicted } = file;
+
+    let hash = data.map(content_hash);
+    let local_changed = hash.as_deref() != synced.map(|s| s.hash.as_str());
+    let remote_changed = etag != synced.map(|s| s.etag.as_str());
+
+    match ( data, etag, local_changed, remote_changed ) {
+        ( _, _, false, false ) => Ok(synced.cloned()),
+
+        // Deleted on both sides
+        ( None, None, _, _ ) => Ok(None),
+
+        // Deleted on the server
+        ( Some(_), None, false, true ) => {
+            match storage.delete(path) {
+                Ok(()) => {
+                    debug!(file = %path, "deleted file that was deleted on the server");
+                    report.deleted += 1;
+                    Ok(None)
+                }
+
+                Err(e) => Err(VaultError::io("Couldn't delete a file of the vault", e)),
+            }
+        }
+
+        // Changed on the server only, or deleted here but changed on the server
+        ( _, Some(_), false, true ) | ( None, Some(_), true, true ) => {
+            let ( data, etag ) = retry(|| remote.get(path))?;
+
+            if let Err(e) = storage.replace(path, &data) {
+                return Err(VaultError::io("Couldn't write a file of the vault", e));
+            }
+
+            debug!(file = %path, "downloaded file");
+            report.downloaded += 1;
+
+            Ok(Some(SyncedFile { hash: content_hash(&data), etag }))
+        }
+
+        // Deleted here
+        ( None, Some(etag), true, false ) => {
+            match retry(|| remote.delete(path, etag)) {
+                Ok(()) | Err(RemoteError::NotFound) => {
+                    debug!(file = %path, "deleted file on the server");
+                    report.deleted += 1;
+                    Ok(None)
+                }
+
+                // Downloaded by the next sync
+                Err(RemoteError::Changed) => {
+                    debug!(file = %path, "file changed on the server before it was deleted");
+                    Ok(synced.cloned())
+                }
+
+                Err(e) => Err(e.into()),
+            }
+        }
+
+        // Changed both here and on the server
+        ( Some(_), Some(_), true, true ) => {
+            let ( remote_data, etag ) = retry(|| remote.get(path))?;
+            let hash = hash.unwrap_or_default();
+
+            if content_hash(&remote_data) == hash {
+                return Ok(Some(SyncedFile { hash, etag }));
+            }
+
+            let copy = format!("{}.sync-conflict-{}-server", path, unix_now());
+
+            if let Err(e) = storage.replace(&copy, &remote_data) {
+                return Err(VaultError::io("Couldn't write a conflict copy", e));
+            }
+
+            warn!(file = %path, copy = %copy, "file was changed here and on the server");
+            report.conflicts.push(path.to_string());
+
+            // The local file is uploaded once the conflict is resolved
+            Ok(Some(SyncedFile { hash: String::new(), etag }))
+        }
+
+        // Changed here only, or changed here and deleted on the server
+        ( Some(data), etag, _, _ ) => {
+            if conflicted {
+                debug!(file = %path, "file has a conflict copy, not uploading it");
+                report.waiting.push(path.to_string());
+                return Ok(synced.cloned());
+            }
+
+            match retry(|| remote.put(path, data, etag)) {
+                Ok(etag) => {
+                    debug!(file = %path, "uploaded file");
+                    report.uploaded += 1;
+                    Ok(Some(SyncedFile { hash: hash.unwrap_or_default(), etag }))
+                }
+
+                // A conflict for the next sync
+                Err(RemoteError::Changed) => {
+                    debug!(file = %path, "file changed on the server before it was uploaded");
+                    Ok(synced.cloned())
+                }
+
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Sends the request again while it fails because of the network or a busy
+/// server, up to [`RETRIES`] times.
+fn retry<T>(mut request: impl FnMut() -> Result<T, RemoteError>) -> Result<T, RemoteError> {
+    let mut delay = RETRY_DELAY;
+
+    for _ in 0..RETRIES {
+        match request() {
+            Err(e) if e.is_transient() => {
+                debug!(error = %e, delay = ?delay, "request failed, retrying");
+                sleep(delay);
+                delay *= 2;
+            }
+
+            result => { return result; }
+        }
+    }
+
+    request()
+}
+
+fn list_local_files(storage: &dyn VaultStorage) -> Result<LocalFiles, VaultError> {
+    let mut local = LocalFiles::default();
+
+    for dir in DIRS {
+        let names = match storage.list(dir) {
+            Ok(names) => names,
+            Err(e) => { return Err(VaultError::io("Couldn't list the vault's files", e)); }
+        };
+
+        for name in names {
+            match conflict_copy_of(&name) {
+                Some(original) => { local.conflicted.insert(join_path(dir, original)); }
+                None => { local.files.insert(join_path(dir, &name)); }
+            }
+        }
+    }
+
+    Ok(local)
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    match dir.is_empty() {
+        true => name.to_string(),
+        false => format!("{}/{}", dir, name),
+    }
+}
+
+fn get_sync_state_path(name: &str) -> Option<PathBuf> {
+    check_vault_name(name).ok()?;
+
+    match get_local_dir() {
+        Some(mut path) => {
+            path.push("sync-state");
+            path.push(name);
+            Some(path)
+        }
+
+        None => None,
+    }
+}
+
+/// Reads the sync state of the vault.
+///
+/// A missing or unreadable state file is read as a vault that was never
+/// synced, the files that differ from the server's then become conflicts.
+pub fn read_sync_state(name: &str) -> SyncState {
+    let bytes = match get_sync_state_path(name).map(read) {
+        Some(Ok(b)) => b,
+        _ => { return SyncState::default(); }
+    };
+
+    match Reader::get_root(bytes.as_slice()) {
+        Ok(reader) => {
+            match SyncState::deserialize(reader) {
+                Ok(state) => state,
+                Err(e) => {
+                    warn!(vault = %name, error = %e, "could not de-serialise the sync state");
+                    SyncState::default()
+                }
+            }
+        }
+
+        Err(e) => {
+            warn!(vault = %name, error = %e, "could not read the sync state");
+            SyncState::default()
+        }
+    }
+}
+
+pub fn write_sync_state(name: &str, state: &SyncState) -> Result<(), VaultError> {
+    let state_path = match get_sync_state_path(name) {
+        Some(p) => p,
+        None => { return Err(VaultError::LocalDirNotFound); }
+    };
+
+    if let Some(dir) = state_path.parent() {
+        if let Err(e) = create_dir_all(dir) {
+            return Err(VaultError::io("Couldn't create the sync state directory", e));
+        }
+    }
+
+    let mut serializer = FlexbufferSerializer::new();
+
+    if let Err(e) = state.serialize(&mut serializer) {
+        debug!(vault = %name, error = %e, "could not serialise the sync state");
+        return Err(VaultError::Invalid(String::from("Could not serialize the sync state")));
+    }
+
+    match File::create(state_path).and_then(|mut file| file.write_all(serializer.view())) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(VaultError::io("Couldn't write the sync state", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ env, process, sync::{ Mutex, atomic::{ AtomicUsize, Ordering } } };
+
+    use super::*;
+    use crate::utils::{ set_data_dir, storage::MemoryStorage };
+
+    /// A server keeping the files in memory, the ETag of a file changes with
+    /// every write.
+    #[derive(Default)]
+    struct MemoryRemote {
+        files: Mutex<BTreeMap<String, ( Vec<u8>, String )>>,
+        writes: AtomicUsize,
+    }
+
+    impl MemoryRemote {
+        /// Writes the file the way another device would, returns its ETag.
+        fn write(&self, path: &str, data: &[u8]) -> String {
+            let etag = format!("\"{}\"", self.writes.fetch_add(1, Ordering::Relaxed));
+            self.files.lock().unwrap().insert(path.to_string(), ( data.to_vec(), etag.clone() ));
+
+            etag
+        }
+
+        fn read(&self, path: &str) -> Option<Vec<u8>> {
+            self.files.lock().unwrap().get(path).map(|( data, _ )| data.clone())
+        }
+
+        fn etag(&self, path: &str) -> Option<String> {
+            self.files.lock().unwrap().get(path).map(|( _, etag )| etag.clone())
+        }
+    }
+
+    impl Remote for MemoryRemote {
+        fn id(&self) -> String {
+            String::from("memory")
+        }
+
+        fn list(&self, dir: &str) -> Result<Vec<RemoteFile>, RemoteError> {
+            let files = self.files.lock().unwrap();
+
+            Ok(files.iter()
+                .filter_map(|( path, ( _, etag ) )| {
+                    let name = match dir.is_empty() {
+                        true => path.as_str(),
+                        false => path.strip_prefix(dir)?.strip_prefix('/')?,
+                    };
+
+                    match name.contains('/') {
+                        true => None,
+                        false => Some(RemoteFile { name: name.to_string(), etag: etag.clone() }),
+                    }
+                })
+                .collect())
+        }
+
+        fn get(&self, path: &str) -> Result<(Vec<u8>, String), RemoteError> {
+            self.files.lock().unwrap().get(path).cloned().ok_or(RemoteError::NotFound)
+        }
+
+        fn put(&self, path: &str, data: &[u8], etag: Option<&str>) -> Result<String, RemoteError> {
+            if self.etag(path).as_deref() != etag {
+                return Err(RemoteError::Changed);
+            }
+
+            Ok(self.write(path, data))
+        }
+
+        fn delete(&self, path: &str, etag: &str) -> Result<(), RemoteError> {
+            match self.etag(path) {
+                Some(current) if current == etag => {
+                    self.files.lock().unwrap().remove(path);
+                    Ok(())
+                }
+
+                Some(_) => Err(RemoteError::Changed),
+                None => Err(RemoteError::NotFound),
+            }
+        }
+    }
+
+    fn synced(data: &[u8], etag: &str) -> SyncedFile {
+        SyncedFile { hash: content_hash(data), etag: etag.to_string() }
+    }
+
+    /// Syncs "notes/1" between `storage` and `remote`, as listed with the
+    /// ETag `etag` on the server.
+    fn sync(
+        storage: &MemoryStorage,
+        remote: &MemoryRemote,
+        etag: Option<&str>,
+        state: Option<&SyncedFile>,
+        conflicted: bool,
+        report: &mut RemoteSyncReport,
+    ) -> Option<SyncedFile> {
+        let data = storage.read("notes/1").ok();
+
+        let file = SyncFile {
+            path: "notes/1",
+            data: data.as_deref(),
+            etag,
+            synced: state,
+            conflicted,
+        };
+
+        sync_file(storage, remote, file, report).unwrap()
+    }
+
+    /// Uses a local directory of its own, the sync states are written to it.
+    fn use_test_dir() {
+        let _ = set_data_dir(&env::temp_dir().join(format!("secure-notes-test-{}", process::id())));
+    }
+
+    #[test]
+    fn unchanged_files_are_left_alone() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        storage.replace("notes/1", b"note").unwrap();
+        let etag = remote.write("notes/1", b"note");
+        let state = synced(b"note", &etag);
+        let mut report = RemoteSyncReport::default();
+
+        assert_eq!(sync(&storage, &remote, Some(&etag), Some(&state), false, &mut report), Some(state));
+        assert_eq!(report, RemoteSyncReport::default());
+    }
+
+    #[test]
+    fn files_deleted_on_both_sides_are_forgotten() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        let state = synced(b"note", "\"0\"");
+        let mut report = RemoteSyncReport::default();
+
+        assert_eq!(sync(&storage, &remote, None, Some(&state), false, &mut report), None);
+        assert_eq!(report, RemoteSyncReport::default());
+    }
+
+    #[test]
+    fn files_deleted_on_the_server_are_deleted() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        storage.replace("notes/1", b"note").unwrap();
+        let state = synced(b"note", "\"0\"");
+        let mut report = RemoteSyncReport::default();
+
+        assert_eq!(sync(&storage, &remote, None, Some(&state), false, &mut report), None);
+        assert!(!storage.exists("notes/1"));
+        assert_eq!(report.deleted, 1);
+    }
+
+    #[test]
+    fn files_changed_on_the_server_are_downloaded() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        storage.replace("notes/1", b"note").unwrap();
+        let state = synced(b"note", "\"old\"");
+        let etag = remote.write("notes/1", b"changed");
+        let mut report = RemoteSyncReport::default();
+
+        let new_state = sync(&storage, &remote, Some(&etag), Some(&state), false, &mut report);
+
+        assert_eq!(new_state, Some(synced(b"changed", &etag)));
+        assert_eq!(storage.read("notes/1").unwrap(), b"changed");
+        assert_eq!(report.downloaded, 1);
+    }
+
+    #[test]
+    fn files_deleted_here_but_changed_on_the_server_are_downloaded() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        let state = synced(b"note", "\"old\"");
+        let etag = remote.write("notes/1", b"changed");
+        let mut report = RemoteSyncReport::default();
+
+        let new_state = sync(&storage, &remote, Some(&etag), Some(&state), false, &mut report);
+
+        assert_eq!(new_state, Some(synced(b"changed", &etag)));
+        assert_eq!(storage.read("notes/1").unwrap(), b"changed");
+        assert_eq!(report.downloaded, 1);
+    }
+
+    #[test]
+    fn files_deleted_here_are_deleted_on_the_server() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        let etag = remote.write("notes/1", b"note");
+        let state = synced(b"note", &etag);
+        let mut report = RemoteSyncReport::default();
+
+        assert_eq!(sync(&storage, &remote, Some(&etag), Some(&state), false, &mut report), None);
+        assert_eq!(remote.read("notes/1"), None);
+        assert_eq!(report.deleted, 1);
+    }
+
+    #[test]
+    fn files_changed_on_the_server_meanwhile_are_not_deleted() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        let etag = remote.write("notes/1", b"note");
+        let state = synced(b"note", &etag);
+        let mut report = RemoteSyncReport::default();
+
+        // Changed by another device after the files were listed
+        remote.write("notes/1", b"changed");
+
+        assert_eq!(sync(&storage, &remote, Some(&etag), Some(&state), false, &mut report), Some(state));
+        assert_eq!(remote.read("notes/1").unwrap(), b"changed");
+        assert_eq!(report.deleted, 0);
+    }
+
+    #[test]
+    fn files_changed_the_same_way_on_both_sides_are_synced() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        storage.replace("notes/1", b"changed").unwrap();
+        let state = synced(b"note", "\"old\"");
+        let etag = remote.write("notes/1", b"changed");
+        let mut report = RemoteSyncReport::default();
+
+        let new_state = sync(&storage, &remote, Some(&etag), Some(&state), false, &mut report);
+
+        assert_eq!(new_state, Some(synced(b"changed", &etag)));
+        assert_eq!(report, RemoteSyncReport::default());
+        assert_eq!(storage.paths(), vec![ "notes/1" ]);
+    }
+
+    #[test]
+    fn files_changed_on_both_sides_get_a_conflict_copy() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        storage.replace("notes/1", b"changed here").unwrap();
+        let state = synced(b"note", "\"old\"");
+        let etag = remote.write("notes/1", b"changed there");
+        let mut report = RemoteSyncReport::default();
+
+        let new_state = sync(&storage, &remote, Some(&etag), Some(&state), false, &mut report);
+
+        // Uploaded once the conflict copy is gone
+        assert_eq!(new_state, Some(SyncedFile { hash: String::new(), etag }));
+        assert_eq!(report.conflicts, vec![ "notes/1" ]);
+        assert_eq!(storage.read("notes/1").unwrap(), b"changed here");
+
+        let paths = storage.paths();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(conflict_copy_of(&paths[1]), Some("notes/1"));
+        assert_eq!(storage.read(&paths[1]).unwrap(), b"changed there");
+    }
+
+    #[test]
+    fn conflicted_files_wait_for_their_copy() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        storage.replace("notes/1", b"changed here").unwrap();
+        let etag = remote.write("notes/1", b"changed there");
+        let state = SyncedFile { hash: String::new(), etag: etag.clone() };
+        let mut report = RemoteSyncReport::default();
+
+        let new_state = sync(&storage, &remote, Some(&etag), Some(&state), true, &mut report);
+
+        assert_eq!(new_state, Some(state));
+        assert_eq!(report.waiting, vec![ "notes/1" ]);
+        assert_eq!(remote.read("notes/1").unwrap(), b"changed there");
+    }
+
+    #[test]
+    fn files_changed_here_are_uploaded() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        storage.replace("notes/1", b"changed").unwrap();
+        let etag = remote.write("notes/1", b"note");
+        let state = synced(b"note", &etag);
+        let mut report = RemoteSyncReport::default();
+
+        let new_state = sync(&storage, &remote, Some(&etag), Some(&state), false, &mut report);
+
+        assert_eq!(new_state, Some(synced(b"changed", &remote.etag("notes/1").unwrap())));
+        assert_eq!(remote.read("notes/1").unwrap(), b"changed");
+        assert_eq!(report.uploaded, 1);
+    }
+
+    #[test]
+    fn new_files_and_files_deleted_on_the_server_are_uploaded() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        storage.replace("notes/1", b"changed").unwrap();
+        let mut report = RemoteSyncReport::default();
+
+        // New, then changed here and deleted on the server
+        for state in [ None, Some(synced(b"note", "\"old\"")) ] {
+            remote.files.lock().unwrap().clear();
+
+            let new_state = sync(&storage, &remote, None, state.as_ref(), false, &mut report);
+
+            assert_eq!(new_state, Some(synced(b"changed", &remote.etag("notes/1").unwrap())));
+            assert_eq!(remote.read("notes/1").unwrap(), b"changed");
+        }
+
+        assert_eq!(report.uploaded, 2);
+    }
+
+    #[test]
+    fn files_changed_on_the_server_meanwhile_are_not_overwritten() {
+        let storage = MemoryStorage::new();
+        let remote = MemoryRemote::default();
+        storage.replace("notes/1", b"changed here").unwrap();
+        let etag = remote.write("notes/1", b"note");
+        let state = synced(b"note", &etag);
+        let mut report = RemoteSyncReport::default();
+
+        remote.write("notes/1", b"changed there");
+
+        assert_eq!(sync(&storage, &remote, Some(&etag), Some(&state), false, &mut report), Some(state));
+        assert_eq!(remote.read("notes/1").unwrap(), b"changed there");
+        assert_eq!(report.uploaded, 0);
+    }
+
+    #[test]
+    fn sync_vault_syncs_two_devices() {
+        use_test_dir();
+
+        let remote = MemoryRemote::default();
+        let first = MemoryStorage::new();
+        let second = MemoryStorage::new();
+
+        first.replace("info", b"info").unwrap();
+        first.replace("notes/1", b"note").unwrap();
+        first.replace("notes/2", b"other note").unwrap();
+
+        let report = sync_vault("sync-two-devices-1", &first, &remote).unwrap();
+        assert_eq!(report.uploaded, 3);
+
+        let report = sync_vault("sync-two-devices-2", &second, &remote).unwrap();
+        assert_eq!(report.downloaded, 3);
+        assert_eq!(second.paths(), first.paths());
+
+        // Changed on both, deleted on the second
+        first.replace("notes/1", b"changed on the first").unwrap();
+        second.replace("notes/1", b"changed on the second").unwrap();
+        second.delete("notes/2").unwrap();
+
+        assert_eq!(pending_changes("sync-two-devices-2", &second).unwrap(), vec![ "notes/1", "notes/2" ]);
+
+        let report = sync_vault("sync-two-devices-2", &second, &remote).unwrap();
+        assert_eq!(( report.uploaded, report.deleted ), ( 1, 1 ));
+        assert!(pending_changes("sync-two-devices-2", &second).unwrap().is_empty());
+
+        let report = sync_vault("sync-two-devices-1", &first, &remote).unwrap();
+        assert_eq!(report.deleted, 1);
+        assert_eq!(report.conflicts, vec![ "notes/1" ]);
+        assert!(!first.exists("notes/2"));
+
+        // Uploaded once the copy is resolved
+        let copy = first.paths().into_iter().find(|p| conflict_copy_of(p).is_some()).unwrap();
+        assert_eq!(first.read(&copy).unwrap(), b"changed on the second");
+
+        let report = sync_vault("sync-two-devices-1", &first, &remote).unwrap();
+        assert_eq!(report.waiting, vec![ "notes/1" ]);
+
+        first.delete(&copy).unwrap();
+
+        let report = sync_vault("sync-two-devices-1", &first, &remote).unwrap();
+        assert_eq!(report.uploaded, 1);
+        assert_eq!(remote.read("notes/1").unwrap(), b"changed on the first");
+    }
+
+    #[test]
+    fn sync_vault_starts_over_with_another_server() {
+        use_test_dir();
+
+        let storage = MemoryStorage::new();
+        storage.replace("notes/1", b"note").unwrap();
+
+        write_sync_state("sync-another-server", &SyncState {
+            remote: String::from("another"),
+            files: BTreeMap::from([ ( String::from("notes/2"), synced(b"gone", "\"0\"") ) ]),
+            last_sync: 1,
+        }).unwrap();
+
+        let remote = MemoryRemote::default();
+        let report = sync_vault("sync-another-server", &storage, &remote).unwrap();
+
+        assert_eq!(( report.uploaded, report.deleted ), ( 1, 0 ));
+
+        let state = read_sync_state("sync-another-server");
+        assert_eq!(state.remote, "memory");
+        assert_eq!(state.files.keys().collect::<Vec<_>>(), vec![ "notes/1" ]);
+    }
+}
@@ -0,0 +1,629 @@
+///
+/// Mirroring vaults to a WebDAV server, e.g. Nextcloud, Apache's mod_dav or
+/// `rclone serve webdav`.
+///
+/// A vault is stored in a directory (collection) on the server, given by its
+/// URL, e.g. "https://dav.example.com/vaults/personal/". The directories are
+/// created with the first upload.
+///
+/// Files are listed with PROPFIND requests, which also return their ETags, and
+/// uploaded with conditional PUT requests ("If-Match", or "If-None-Match: *"
+/// for new files).
+///
+/// Files larger than [`CHUNK_SIZE`] are uploaded in chunks to a hidden file
+/// next to them, which is moved in place once complete. The MOVE request is
+/// conditional as well: "If-Match" would apply to the hidden file, so it sends
+/// the ETag in an "If" header tagged with the file it replaces, or
+/// "Overwrite: F" for new files. The hidden file is named after the contents,
+/// so an upload that was interrupted goes on from the last chunk the server
+/// received. Servers that don't accept partial PUT requests ("Content-Range")
+/// get the whole file in one request instead.
+///
+use std::{ fmt, io::Read, time::Duration };
+use base64::{ engine::general_purpose::STANDARD, Engine as _ };
+use quick_xml::{ events::Event, Reader };
+use tracing::{ debug, warn };
+
+use crate::{
+    types::vault_error::VaultError,
+    utils::{
+        crypto::SecretString,
+        remote::{ Remote, RemoteError, RemoteFile },
+        sync::content_hash,
+    },
+};
+
+/// Size of the chunks larger files are uploaded in.
+pub const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Properties asked for when listing files.
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:resourcetype/><d:getetag/><d:getcontentlength/></d:prop>
+</d:propfind>"#;
+
+/// A vault's directory on a WebDAV server.
+pub struct WebDav {
+    agent: ureq::Agent,
+
+    /// URL of the directory, ends with a "/".
+    url: String,
+
+    /// "Authorization" header for basic authentication.
+    authorization: Option<SecretString>,
+}
+
+/// A file or directory listed by a PROPFIND request.
+#[derive(Debug, Default)]
+struct Resource {
+    href: String,
+    etag: Option<String>,
+    length: Option<u64>,
+    collection: bool,
+}
+
+impl WebDav {
+    /// Connects to the vault's directory at `url`, with basic authentication if
+    /// a user is given.
+    pub fn new(url: &str, user: Option<&str>, password: Option<&SecretString>) -> Result<Self, VaultError> {
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(VaultError::Invalid(String::from("The WebDAV URL must start with https://")));
+        }
+
+        let authorization = user.map(|user| {
+            let password = password.map(|p| p.as_str()).unwrap_or_default();
+            let credentials = SecretString::new(format!("{}:{}", user, password));
+
+            SecretString::new(format!("Basic {}", STANDARD.encode(credentials.as_bytes())))
+        });
+
+        if authorization.is_some() && url.starts_with("http://") {
+            warn!("the WebDAV password is sent unencrypted, use an https:// URL");
+        }
+
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(15))
+            .timeout_read(Duration::from_secs(60))
+            .timeout_write(Duration::from_secs(60))
+            .build();
+
+        let url = match url.ends_with('/') {
+            true => url.to_string(),
+            false => format!("{}/", url),
+        };
+
+        Ok(Self { agent, url, authorization })
+    }
+
+    fn url_of(&self, path: &str) -> String {
+        format!("{}{}", self.url, encode_path(path))
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self.agent.request(method, &self.url_of(path));
+
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+
+    /// Sends a PROPFIND request for the file or directory, and the files in
+    /// the directory with `depth` "1".
+    fn propfind(&self, path: &str, depth: &str) -> Result<Vec<Resource>, RemoteError> {
+        let request = self.request("PROPFIND", path)
+            .set("Depth", depth)
+            .set("Content-Type", "application/xml; charset=utf-8");
+
+        let response = send(request, PROPFIND_BODY.as_bytes())?;
+
+        match response.into_string() {
+            Ok(xml) => parse_multistatus(&xml),
+            Err(e) => Err(RemoteError::Offline(e.to_string())),
+        }
+    }
+
+    /// Returns the ETag and size of the file, `None` if it doesn't exist.
+    fn stat(&self, path: &str) -> Result<Option<(String, u64)>, RemoteError> {
+        let resource = match self.propfind(path, "0") {
+            Ok(mut resources) if !resources.is_empty() => resources.remove(0),
+            Ok(_) | Err(RemoteError::NotFound) => { return Ok(None); }
+            Err(e) => { return Err(e); }
+        };
+
+        match resource.etag {
+            Some(etag) => Ok(Some(( etag, resource.length.unwrap_or_default() ))),
+            None => Err(RemoteError::InvalidResponse(String::from("the server didn't return an ETag"))),
+        }
+    }
+
+    /// ETag of the file that was just written, from the response or asked for
+    /// when the server didn't send it.
+    fn etag_of(&self, response: ureq::Response, path: &str) -> Result<String, RemoteError> {
+        if let Some(etag) = response.header("ETag") {
+            return Ok(etag.to_string());
+        }
+
+        match self.stat(path)? {
+            Some(( etag, _ )) => Ok(etag),
+            None => Err(RemoteError::NotFound),
+        }
+    }
+
+    /// Creates the vault's directory and the directories of `path`.
+    fn create_dirs(&self, path: &str) -> Result<(), RemoteError> {
+        let mut dirs = vec![ String::new() ];
+        let parts: Vec<&str> = path.split('/').collect();
+
+        for i in 1..parts.len() {
+            dirs.push(format!("{}/", parts[..i].join("/")));
+        }
+
+        for dir in dirs {
+            match send(self.request("MKCOL", &dir), &[]) {
+                // 405: the directory exists already
+                Ok(_) | Err(RemoteError::Status { code: 405, .. }) => {}
+                Err(e) => { return Err(e); }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Uploads the file in a single request.
+    fn put_whole(&self, path: &str, data: &[u8], etag: Option<&str>) -> Result<String, RemoteError> {
+        let put = || {
+            let request = self.request("PUT", path).set("Content-Type", "application/octet-stream");
+
+            let request = match etag {
+                Some(etag) => request.set("If-Match", etag),
+                None => request.set("If-None-Match", "*"),
+            };
+
+            send(request, data)
+        };
+
+        let response = match put() {
+            // The directory doesn't exist yet
+            Err(RemoteError::Status { code: 409, .. }) => {
+                self.create_dirs(path)?;
+                put()?
+            }
+
+            result => result?,
+        };
+
+        self.etag_of(response, path)
+    }
+
+    /// Uploads the file in chunks to a hidden file, then moves it in place.
+    fn put_chunked(&self, path: &str, data: &[u8], etag: Option<&str>) -> Result<String, RemoteError> {
+        let upload = upload_path(path, data);
+
+        let mut offset = match self.stat(&upload)? {
+            Some(( _, length )) if length as usize <= data.len() => length as usize,
+            _ => 0,
+        };
+
+        if offset > 0 {
+            debug!(file = %path, offset, "resuming upload");
+        }
+
+        let mut created_dirs = false;
+
+        while offset < data.len() {
+            let end = (offset + CHUNK_SIZE).min(data.len());
+            let range = format!("bytes {}-{}/{}", offset, end - 1, data.len());
+            let request = self.request("PUT", &upload).set("Content-Range", &range);
+
+            match send(request, &data[offset..end]) {
+                Ok(_) => { offset = end; }
+
+                Err(RemoteError::Status { code: 409, .. }) if !created_dirs => {
+                    self.create_dirs(&upload)?;
+                    created_dirs = true;
+                }
+
+                // Partial uploads aren't supported
+                Err(RemoteError::Status { code: 400 | 405 | 416 | 501, .. }) => {
+                    return self.put_unchunked(path, &upload, data, etag);
+                }
+
+                Err(e) => { return Err(e); }
+            }
+        }
+
+        // Some servers ignore "Content-Range" and write the chunk as the file
+        match self.stat(&upload)? {
+            Some(( _, length )) if length as usize == data.len() => {}
+            _ => { return self.put_unchunked(path, &upload, data, etag); }
+        }
+
+        // Fails with 412 if the file changed since its ETag was read
+        let destination = self.url_of(path);
+        let request = self.request("MOVE", &upload).set("Destination", &destination);
+
+        let request = match etag {
+            Some(etag) => request
+                .set("If", &format!("<{}> ([{}])", destination, etag))
+                .set("Overwrite", "T"),
+
+            None => request.set("Overwrite", "F"),
+        };
+
+        send(request, &[])?;
+
+        match self.stat(path)? {
+            Some(( etag, _ )) => Ok(etag),
+            None => Err(RemoteError::NotFound),
+        }
+    }
+
+    /// Uploads the file in a single request after a chunked upload failed,
+    /// and deletes what was uploaded of it.
+    fn put_unchunked(
+        &self,
+        path: &str,
+        upload: &str,
+        data: &[u8],
+        etag: Option<&str>,
+    ) -> Result<String, RemoteError> {
+        debug!(file = %path, "the server doesn't accept partial uploads");
+
+        if let Err(e) = send(self.request("DELETE", upload), &[]) {
+            debug!(file = %upload, error = %e, "could not delete partial upload");
+        }
+
+        self.put_whole(path, data, etag)
+    }
+}
+
+impl Remote for WebDav {
+    fn id(&self) -> String {
+        self.url.clone()
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<RemoteFile>, RemoteError> {
+        let path = match dir.is_empty() {
+            true => String::new(),
+            false => format!("{}/", dir),
+        };
+
+        let resources = match self.propfind(&path, "1") {
+            Ok(resources) => resources,
+            Err(RemoteError::NotFound) => { return Ok(vec![]); }
+            Err(e) => { return Err(e); }
+        };
+
+        let mut files = vec![];
+
+        for resource in resources {
+            if resource.collection {
+                continue;
+            }
+
+            let name = match resource.href.rsplit('/').next().map(decode_path) {
+                Some(name) if !name.is_empty() => name,
+                _ => { continue; }
+            };
+
+            match resource.etag {
+                Some(etag) => files.push(RemoteFile { name, etag }),
+                None => {
+                    return Err(RemoteError::InvalidResponse(String::from(
+                        "the server didn't return an ETag"
+                    )));
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn get(&self, path: &str) -> Result<(Vec<u8>, String), RemoteError> {
+        let response = send(self.request("GET", path), &[])?;
+        let etag = response.header("ETag").map(String::from);
+        let mut data = vec![];
+
+        if let Err(e) = response.into_reader().read_to_end(&mut data) {
+            return Err(RemoteError::Offline(e.to_string()));
+        }
+
+        let etag = match etag {
+            Some(etag) => etag,
+            None => match self.stat(path)? {
+                Some(( etag, _ )) => etag,
+                None => { return Err(RemoteError::NotFound); }
+            },
+        };
+
+        Ok(( data, etag ))
+    }
+
+    fn put(&self, path: &str, data: &[u8], etag: Option<&str>) -> Result<String, RemoteError> {
+        match data.len() > CHUNK_SIZE {
+            true => self.put_chunked(path, data, etag),
+            false => self.put_whole(path, data, etag),
+        }
+    }
+
+    fn delete(&self, path: &str, etag: &str) -> Result<(), RemoteError> {
+        let request = self.request("DELETE", path).set("If-Match", etag);
+
+        send(request, &[]).map(|_| ())
+    }
+}
+
+impl fmt::Debug for WebDav {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebDav").field("url", &self.url).finish_non_exhaustive()
+    }
+}
+
+fn send(request: ureq::Request, body: &[u8]) -> Result<ureq::Response, RemoteError> {
+    match request.send_bytes(body) {
+        Ok(response) => Ok(response),
+
+        Err(ureq::Error::Status(code, response)) => Err(match code {
+            401 | 403 => RemoteError::Unauthorized,
+            404 => RemoteError::NotFound,
+            412 => RemoteError::Changed,
+            _ => RemoteError::Status { code, message: response.status_text().to_string() },
+        }),
+
+        Err(ureq::Error::Transport(e)) => Err(RemoteError::Offline(e.to_string())),
+    }
+}
+
+/// Path of the hidden file a large file is uploaded to, e.g.
+/// "attachments/.3.<hash>.upload".
+fn upload_path(path: &str, data: &[u8]) -> String {
+    let hash = content_hash(data);
+
+    match path.rsplit_once('/') {
+        Some(( dir, name )) => format!("{}/.{}.{}.upload", dir, name, &hash[..16]),
+        None => format!(".{}.{}.upload", path, &hash[..16]),
+    }
+}
+
+/// Reads the files and directories in a PROPFIND response.
+fn parse_multistatus(xml: &str) -> Result<Vec<Resource>, RemoteError> {
+    let mut reader = Reader::from_str(xml);
+    let mut resources = vec![];
+    let mut resource: Option<Resource> = None;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                text.clear();
+
+                if e.local_name().as_ref() == b"response" {
+                    resource = Some(Resource::default());
+                }
+            }
+
+            Ok(Event::Empty(e)) => {
+                if let Some(r) = resource.as_mut() {
+                    if e.local_name().as_ref() == b"collection" {
+                        r.collection = true;
+                    }
+                }
+            }
+
+            Ok(Event::Text(e)) => {
+                match e.unescape() {
+                    Ok(t) => text.push_str(&t),
+                    Err(e) => { return Err(RemoteError::InvalidResponse(e.to_string())); }
+                }
+            }
+
+            Ok(Event::End(e)) => {
+                let value = std::mem::take(&mut text);
+                let value = value.trim();
+
+                match ( e.local_name().as_ref(), resource.as_mut() ) {
+                    ( b"href", Some(r) ) => { r.href = value.trim_end_matches('/').to_string(); }
+                    ( b"getetag", Some(r) ) if !value.is_empty() => { r.etag = Some(value.to_string()); }
+                    ( b"getcontentlength", Some(r) ) => { r.length = value.parse().ok(); }
+                    ( b"collection", Some(r) ) => { r.collection = true; }
+
+                    ( b"response", Some(_) ) => {
+                        resources.extend(resource.take());
+                    }
+
+                    _ => {}
+                }
+            }
+
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => { return Err(RemoteError::InvalidResponse(e.to_string())); }
+        }
+    }
+
+    Ok(resources)
+}
+
+/// Percent-encodes the path for a URL, keeping the "/" between directories.
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char);
+            }
+
+            _ => { encoded.push_str(&format!("%{:02X}", byte)); }
+        }
+    }
+
+    encoded
+}
+
+fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match ( bytes[i], hex ) {
+            ( b'%', Some(byte) ) => {
+                decoded.push(byte);
+                i += 3;
+            }
+
+            ( byte, _ ) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// As answered by Nextcloud, with the ETags escaped.
+    const NEXTCLOUD: &str = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:s="http://sabredav.org/ns" xmlns:oc="http://owncloud.org/ns">
+  <d:response>
+    <d:href>/remote.php/dav/files/alice/Vault/notes/</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:getetag>&quot;65b9a2f1c3d4e&quot;</d:getetag>
+        <d:resourcetype><d:collection/></d:resourcetype>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+    <d:propstat>
+      <d:prop>
+        <d:getcontentlength/>
+      </d:prop>
+      <d:status>HTTP/1.1 404 Not Found</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/files/alice/Vault/notes/12</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:getetag>&quot;8f1b2c3d&quot;</d:getetag>
+        <d:getcontentlength>1024</d:getcontentlength>
+        <d:resourcetype/>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/files/alice/Vault/notes/12%20%28conflicted%20copy%29</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:getetag>"5e6f"</d:getetag>
+        <d:getcontentlength>7</d:getcontentlength>
+        <d:resourcetype/>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+
+    /// As answered by Apache's mod_dav, with the default namespace and a
+    /// directory without an ETag.
+    const APACHE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<multistatus xmlns="DAV:">
+<response>
+<href>/dav/vault/</href>
+<propstat>
+<prop>
+<resourcetype>
+<collection></collection>
+</resourcetype>
+<getetag></getetag>
+</prop>
+<status>HTTP/1.1 200 OK</status>
+</propstat>
+</response>
+<response>
+<href>/dav/vault/index</href>
+<propstat>
+<prop>
+<resourcetype/>
+<getetag>W/"2a-5f0c1"</getetag>
+<getcontentlength>42</getcontentlength>
+</prop>
+<status>HTTP/1.1 200 OK</status>
+</propstat>
+</response>
+</multistatus>"#;
+
+    #[test]
+    fn parses_nextcloud_listings() {
+        let resources = parse_multistatus(NEXTCLOUD).unwrap();
+
+        assert_eq!(resources.len(), 3);
+
+        assert_eq!(resources[0].href, "/remote.php/dav/files/alice/Vault/notes");
+        assert!(resources[0].collection);
+        assert_eq!(resources[0].length, None);
+
+        assert_eq!(resources[1].href, "/remote.php/dav/files/alice/Vault/notes/12");
+        assert_eq!(resources[1].etag.as_deref(), Some("\"8f1b2c3d\""));
+        assert_eq!(resources[1].length, Some(1024));
+        assert!(!resources[1].collection);
+
+        let name = resources[2].href.rsplit('/').next().map(decode_path);
+        assert_eq!(name.as_deref(), Some("12 (conflicted copy)"));
+        assert_eq!(resources[2].etag.as_deref(), Some("\"5e6f\""));
+    }
+
+    #[test]
+    fn parses_apache_listings() {
+        let resources = parse_multistatus(APACHE).unwrap();
+
+        assert_eq!(resources.len(), 2);
+
+        assert_eq!(resources[0].href, "/dav/vault");
+        assert!(resources[0].collection);
+        assert_eq!(resources[0].etag, None);
+
+        assert_eq!(resources[1].href, "/dav/vault/index");
+        assert_eq!(resources[1].etag.as_deref(), Some("W/\"2a-5f0c1\""));
+        assert_eq!(resources[1].length, Some(42));
+        assert!(!resources[1].collection);
+    }
+
+    #[test]
+    fn rejects_invalid_listings() {
+        assert!(parse_multistatus("<d:multistatus xmlns:d=\"DAV:\"><d:response></d:multistatus>").is_err());
+        assert!(parse_multistatus("<d:href>&unknown;</d:href>").is_err());
+        assert!(parse_multistatus("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn encodes_and_decodes_paths() {
+        assert_eq!(encode_path("notes/12 (copy)"), "notes/12%20%28copy%29");
+        assert_eq!(encode_path("attachments/é"), "attachments/%C3%A9");
+        assert_eq!(decode_path("notes/12%20%28copy%29"), "notes/12 (copy)");
+        assert_eq!(decode_path("attachments/%C3%A9"), "attachments/é");
+
+        // Not an escape
+        assert_eq!(decode_path("100%"), "100%");
+        assert_eq!(decode_path("%zz"), "%zz");
+    }
+
+    #[test]
+    fn uploads_to_hidden_files() {
+        let hash = content_hash(b"data");
+
+        assert_eq!(upload_path("attachments/3", b"data"), format!("attachments/.3.{}.upload", &hash[..16]));
+        assert_eq!(upload_path("index", b"data"), format!(".index.{}.upload", &hash[..16]));
+    }
+}
@@ -0,0 +1,291 @@
+///
+/// Reading and writing the app's settings.
+///
+/// The settings are stored as JSON in the "settings.json" file of the local
+/// directory, so they can also be edited by hand. Reading them never fails:
+/// - A missing file or setting gets the default value.
+/// - Unknown settings are ignored, and kept when the settings are written.
+/// - Settings of the wrong type or with an invalid value are reset to their
+///   default value and reported.
+///
+use std::{ fs::{ read_to_string, write }, io::ErrorKind, path::PathBuf };
+use serde_json::{ Map, Value };
+use tracing::{ debug, warn };
+
+use crate::{
+    types::{ settings::Settings, vault_error::VaultError },
+    utils::{ create_secure_notes_directories, get_local_dir },
+};
+
+/// Returns the path of the settings file.
+pub fn get_settings_path() -> Option<PathBuf> {
+    let mut path = get_local_dir()?;
+    path.push("settings.json");
+    Some(path)
+}
+
+/// Reads the settings, and returns them along with the problems found in the
+/// settings file.
+pub fn read_settings() -> ( Settings, Vec<String> ) {
+    load_settings(read_settings_file())
+}
+
+/// Makes the settings from the settings file as read by
+/// [`read_settings_file`].
+fn load_settings(file: Result<Option<Value>, String>) -> ( Settings, Vec<String> ) {
+    let mut problems = vec![];
+
+    let mut settings = match file {
+        Ok(Some(Value::Object(file))) => {
+            let defaults = Settings::default();
+            let mut settings = serialize(&defaults);
+
+            merge_settings(&mut settings, file, &mut problems);
+
+            match serde_json::from_value(Value::Object(settings)) {
+                Ok(s) => s,
+                Err(e) => {
+                    problems.push(format!("The settings couldn't be read: {}", e));
+                    defaults
+                }
+            }
+        }
+
+        Ok(Some(_)) => {
+            problems.push(String::from("The settings file doesn't hold an object"));
+            Settings::default()
+        }
+
+        Ok(None) => Settings::default(),
+
+        Err(e) => {
+            problems.push(e);
+            Settings::default()
+        }
+    };
+
+    problems.extend(settings.validate());
+
+    for problem in &problems {
+        warn!(problem = %problem, "invalid setting");
+    }
+
+    ( settings, problems )
+}
+
+/// Writes the settings to the settings file, keeping the settings in the file
+/// that this version of the app doesn't know.
+pub fn write_settings(settings: &Settings) -> Result<(), VaultError> {
+    let path = match get_settings_path() {
+        Some(p) => p,
+        None => { return Err(VaultError::LocalDirNotFound); }
+    };
+
+    let mut file = match read_settings_file() {
+        Ok(Some(Value::Object(file))) => file,
+        _ => Map::new(),
+    };
+
+    overwrite_settings(&mut file, serialize(settings));
+
+    let json = match serde_json::to_string_pretty(&file) {
+        Ok(j) => j,
+        Err(e) => {
+            return Err(VaultError::Invalid(format!("Could not serialize the settings: {}", e)));
+        }
+    };
+
+    if let Some(dir) = path.parent() {
+        create_secure_notes_directories(&dir.to_path_buf())?;
+    }
+
+    match write(&path, json) {
+        Ok(()) => {
+            debug!(path = %path.display(), "saved settings");
+            Ok(())
+        }
+
+        Err(e) => Err(VaultError::io("Could not write the settings file", e)),
+    }
+}
+
+/// Reads the settings file as JSON, `None` if there is no settings file.
+fn read_settings_file() -> Result<Option<Value>, String> {
+    let path = match get_settings_path() {
+        Some(p) => p,
+        None => { return Ok(None); }
+    };
+
+    let json = match read_to_string(&path) {
+        Ok(j) => j,
+        Err(e) if e.kind() == ErrorKind::NotFound => { return Ok(None); }
+        Err(e) => { return Err(format!("Could not read the settings file: {}", e)); }
+    };
+
+    match serde_json::from_str(&json) {
+        Ok(v) => Ok(Some(v)),
+        Err(e) => Err(format!("The settings file is not valid JSON: {}", e)),
+    }
+}
+
+fn serialize(settings: &Settings) -> Map<String, Value> {
+    match serde_json::to_value(settings) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Copies the known settings from the settings file over the default ones.
+///
+/// Each setting is only taken if the settings can still be read with it, so a
+/// setting of the wrong type only resets that setting.
+fn merge_settings(
+    settings: &mut Map<String, Value>,
+    file: Map<String, Value>,
+    problems: &mut Vec<String>,
+) {
+    let mut file_settings = vec![];
+    let defaults = settings.clone();
+
+    find_settings(&defaults, file, &[], &mut file_settings, problems);
+
+    for ( path, value ) in file_settings {
+        let mut candidate = settings.clone();
+
+        if let Some(setting) = get_setting(&mut candidate, &path) {
+            *setting = value;
+        }
+
+        match serde_json::from_value::<Settings>(Value::Object(candidate.clone())) {
+            Ok(_) => { *settings = candidate; }
+            Err(e) => { problems.push(format!("{}: {}", path.join("."), e)); }
+        }
+    }
+}
+
+/// Collects the settings in the settings file that are known, with their path.
+fn find_settings(
+    defaults: &Map<String, Value>,
+    file: Map<String, Value>,
+    prefix: &[String],
+    found: &mut Vec<( Vec<String>, Value )>,
+    problems: &mut Vec<String>,
+) {
+    for ( key, value ) in file {
+        let default = defaults.get(&key);
+        let mut path = prefix.to_vec();
+        path.push(key);
+
+        match ( default, value ) {
+            ( None, _ ) => {
+                debug!(setting = %path.join("."), "ignoring unknown setting");
+            }
+
+            ( Some(Value::Object(section)), Value::Object(file_section) ) => {
+                find_settings(section, file_section, &path, found, problems);
+            }
+
+            ( Some(Value::Object(_)), _ ) => {
+                problems.push(format!("{} must be a group of settings", path.join(".")));
+            }
+
+            ( Some(_), value ) => { found.push(( path, value )); }
+        }
+    }
+}
+
+fn get_setting<'a>(settings: &'a mut Map<String, Value>, path: &[String]) -> Option<&'a mut Value> {
+    let ( last, sections ) = path.split_last()?;
+    let mut section = settings;
+
+    for key in sections {
+        match section.get_mut(key) {
+            Some(Value::Object(s)) => { section = s; }
+            _ => { return None; }
+        }
+    }
+
+    section.get_mut(last)
+}
+
+/// Writes the values of `settings` into the settings of the file.
+fn overwrite_settings(file: &mut Map<String, Value>, settings: Map<String, Value>) {
+    for ( key, value ) in settings {
+        match ( file.get_mut(&key), value ) {
+            ( Some(Value::Object(file_section)), Value::Object(section) ) => {
+                overwrite_settings(file_section, section);
+            }
+
+            ( _, value ) => { file.insert(key, value); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::settings::{ EditorSettings, HexColor };
+
+    #[test]
+    fn missing_settings_get_their_default_value() {
+        assert_eq!(load_settings(Ok(None)), ( Settings::default(), vec![] ));
+
+        let ( settings, problems ) = load_settings(Ok(Some(json!({
+            "editor": { "text_size": 20 },
+            "unknown": { "setting": true },
+        }))));
+
+        assert!(problems.is_empty());
+        assert_eq!(settings.editor, EditorSettings { text_size: 20, ..EditorSettings::default() });
+        assert_eq!(settings.auto_lock, Settings::default().auto_lock);
+    }
+
+    #[test]
+    fn invalid_settings_are_reset_and_reported() {
+        let ( settings, problems ) = load_settings(Ok(Some(json!({
+            "editor": { "text_size": "large", "autosave": true, "autosave_delay": 0 },
+            "auto_lock": 10,
+            "appearance": { "background": "blue", "editor_background": "#102030" },
+        }))));
+
+        let defaults = Settings::default();
+        assert_eq!(settings.editor.text_size, defaults.editor.text_size);
+        assert_eq!(settings.editor.autosave_delay, defaults.editor.autosave_delay);
+        assert!(settings.editor.autosave);
+        assert_eq!(settings.auto_lock, defaults.auto_lock);
+        assert_eq!(settings.appearance.background, defaults.appearance.background);
+        assert_eq!(settings.appearance.editor_background, HexColor::new(0x10, 0x20, 0x30));
+
+        assert_eq!(problems.len(), 4);
+        assert!(problems.iter().any(|p| p.starts_with("editor.text_size:")));
+        assert!(problems.contains(&String::from("auto_lock must be a group of settings")));
+        assert!(problems.contains(&String::from("editor.autosave_delay must be between 1 and 3600")));
+    }
+
+    #[test]
+    fn an_unreadable_file_gives_the_default_settings() {
+        let ( settings, problems ) = load_settings(Ok(Some(json!([ 1, 2 ]))));
+        assert_eq!(settings, Settings::default());
+        assert_eq!(problems, vec![ String::from("The settings file doesn't hold an object") ]);
+
+        let ( settings, problems ) = load_settings(Err(String::from("The settings file is not valid JSON")));
+        assert_eq!(settings, Settings::default());
+        assert_eq!(problems.len(), 1);
+    }
+
+    #[test]
+    fn keeps_the_unknown_settings_of_the_file() {
+        let mut file = match json!({ "editor": { "text_size": 20, "font": "mono" }, "theme": "dark" }) {
+            Value::Object(file) => file,
+            _ => unreachable!(),
+        };
+
+        overwrite_settings(&mut file, serialize(&Settings::default()));
+
+        assert_eq!(file["editor"]["text_size"], json!(16));
+        assert_eq!(file["editor"]["font"], json!("mono"));
+        assert_eq!(file["theme"], json!("dark"));
+    }
+}
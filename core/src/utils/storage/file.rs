@@ -0,0 +1,129 @@
+///
+/// Vault storage in a directory of the file system.
+///
+use std::{
+    fs::{ self, OpenOptions, create_dir_all, read_dir, remove_file },
+    io::{ ErrorKind, Result, Write },
+    path::PathBuf,
+};
+
+use crate::utils::{ crypto::generate_salt, storage::{ VaultStorage, check_path } };
+
+/// Stores the files of the vault in `root`, which is created when the first
+/// file is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    fn path(&self, path: &str) -> Result<PathBuf> {
+        check_path(path, false)?;
+        Ok(self.root.join(path))
+    }
+
+    /// Returns the path of the file, after creating its directory.
+    fn path_for_writing(&self, path: &str) -> Result<PathBuf> {
+        let path = self.path(path)?;
+
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+
+        Ok(path)
+    }
+}
+
+impl VaultStorage for FileStorage {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        fs::read(self.path(path)?)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.path_for_writing(path)?)?;
+
+        file.write_all(data)?;
+        file.set_len(data.len() as u64)?;
+        file.sync_data()
+    }
+
+    fn replace(&self, path: &str, data: &[u8]) -> Result<()> {
+        let path = self.path_for_writing(path)?;
+
+        // Hidden files are left out by `list`
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(path.file_name().unwrap_or_default());
+        temp_name.push(format!(".{}.tmp", hex(&generate_salt()[..4])));
+        let temp_path = path.with_file_name(temp_name);
+
+        let result = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .and_then(|mut file| {
+                file.write_all(data)?;
+                file.sync_all()
+            })
+            .and_then(|()| fs::rename(&temp_path, &path));
+
+        if result.is_err() {
+            let _ = remove_file(&temp_path);
+        }
+
+        result
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<String>> {
+        check_path(dir, true)?;
+
+        let entries = match read_dir(self.root.join(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => { return Ok(vec![]); }
+            Err(e) => { return Err(e); }
+        };
+
+        let mut names = vec![];
+
+        for entry in entries {
+            let entry = entry?;
+
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+
+            if let Ok(name) = entry.file_name().into_string() {
+                if !name.starts_with('.') {
+                    names.push(name);
+                }
+            }
+        }
+
+        names.sort();
+
+        Ok(names)
+    }
+
+    fn delete(&self, path: &str) -> Result<()> {
+        remove_file(self.path(path)?)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.path(path).is_ok_and(|p| p.is_file())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,82 @@
+///
+/// Vault storage in memory.
+///
+use std::{
+    collections::BTreeMap,
+    io::{ Error, ErrorKind, Result },
+    sync::{ Mutex, MutexGuard },
+};
+
+use crate::utils::storage::{ VaultStorage, check_path };
+
+/// Keeps the files of the vault in memory, they're lost when it's dropped.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the paths of all the files, sorted.
+    pub fn paths(&self) -> Vec<String> {
+        self.files().keys().cloned().collect()
+    }
+
+    fn files(&self) -> MutexGuard<'_, BTreeMap<String, Vec<u8>>> {
+        // The map stays consistent even if another thread panicked
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl VaultStorage for MemoryStorage {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        check_path(path, false)?;
+
+        match self.files().get(path) {
+            Some(data) => Ok(data.clone()),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        check_path(path, false)?;
+        self.files().insert(path.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn replace(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.write(path, data)
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<String>> {
+        check_path(dir, true)?;
+
+        let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+
+        Ok(self.files().keys()
+            .filter_map(|path| path.strip_prefix(&prefix))
+            .filter(|name| !name.contains('/'))
+            .map(String::from)
+            .collect())
+    }
+
+    fn delete(&self, path: &str) -> Result<()> {
+        check_path(path, false)?;
+
+        match self.files().remove(path) {
+            Some(_) => Ok(()),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files().contains_key(path)
+    }
+}
+
+fn not_found(path: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("{} not found", path))
+}
@@ -0,0 +1,142 @@
+///
+/// Where the files of a vault are stored.
+///
+/// The vault functions don't access the file system themselves, they read and
+/// write the vault's files through a [`VaultStorage`]:
+/// - [`FileStorage`] stores them in a directory, normally the vault's
+///   directory (see [`get_vault_storage`](crate::utils::vault::get_vault_storage)).
+/// - [`MemoryStorage`] keeps them in memory, e.g. for tests.
+///
+/// Files are named by their path relative to the vault, with "/" between the
+/// directories, e.g. "info" or "notes/12".
+///
+pub mod file;
+pub mod memory;
+
+use std::io::{ Error, ErrorKind, Result };
+
+pub use file::FileStorage;
+pub use memory::MemoryStorage;
+
+/// Storage of the files of a single vault.
+pub trait VaultStorage: Send + Sync {
+    /// Reads the whole file, fails with [`ErrorKind::NotFound`] if it doesn't
+    /// exist.
+    fn read(&self, path: &str) -> Result<Vec<u8>>;
+
+    /// Writes the file, creating it if needed. An existing file is overwritten
+    /// in place.
+    fn write(&self, path: &str, data: &[u8]) -> Result<()>;
+
+    /// Replaces the file atomically with `data`: it has either the old or the
+    /// new contents, even if the app is stopped while writing.
+    fn replace(&self, path: &str, data: &[u8]) -> Result<()>;
+
+    /// Returns the names of the files in the directory, sorted. An empty
+    /// `dir` is the vault itself, a directory that doesn't exist is empty.
+    fn list(&self, dir: &str) -> Result<Vec<String>>;
+
+    /// Deletes the file, fails with [`ErrorKind::NotFound`] if it doesn't
+    /// exist.
+    fn delete(&self, path: &str) -> Result<()>;
+
+    fn exists(&self, path: &str) -> bool;
+}
+
+/// Checks that the path names a file inside the vault, so that it can't be
+/// used to reach other files, e.g. "../default-vault".
+pub fn check_path(path: &str, allow_empty: bool) -> Result<()> {
+    if path.is_empty() && allow_empty {
+        return Ok(());
+    }
+
+    let valid = path.split('/').all(|part| {
+        !part.is_empty() && part != "." && part != ".." && !part.contains(['\\', ':'])
+    });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::InvalidInput, format!("Invalid vault path \"{}\"", path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ env, fs, process };
+
+    use super::*;
+
+    fn file_storage(name: &str) -> FileStorage {
+        let root = env::temp_dir().join(format!("secure-notes-test-{}-storage-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+
+        FileStorage::new(root)
+    }
+
+    /// What every storage does the same way.
+    fn check_storage(storage: &dyn VaultStorage) {
+        assert_eq!(storage.list("").unwrap(), Vec::<String>::new());
+        assert_eq!(storage.list("notes").unwrap(), Vec::<String>::new());
+        assert_eq!(storage.read("info").unwrap_err().kind(), ErrorKind::NotFound);
+
+        storage.write("info", b"info").unwrap();
+        storage.write("notes/2", b"a longer note").unwrap();
+        storage.write("notes/2", b"a note").unwrap();
+        storage.replace("notes/10", b"another note").unwrap();
+        storage.replace("notes/10", b"changed").unwrap();
+
+        assert_eq!(storage.read("notes/2").unwrap(), b"a note");
+        assert_eq!(storage.read("notes/10").unwrap(), b"changed");
+        assert_eq!(storage.list("").unwrap(), vec![ "info" ]);
+        assert_eq!(storage.list("notes").unwrap(), vec![ "10", "2" ]);
+        assert!(storage.exists("notes/2"));
+        assert!(!storage.exists("notes"));
+
+        storage.delete("notes/2").unwrap();
+
+        assert!(!storage.exists("notes/2"));
+        assert_eq!(storage.delete("notes/2").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(storage.list("notes").unwrap(), vec![ "10" ]);
+
+        for path in [ "", "../info", "notes/../info", "notes//2", "/info", "notes\\2", "C:info" ] {
+            assert_eq!(storage.read(path).unwrap_err().kind(), ErrorKind::InvalidInput, "{}", path);
+            assert_eq!(storage.write(path, b"").unwrap_err().kind(), ErrorKind::InvalidInput, "{}", path);
+            assert!(!storage.exists(path));
+        }
+
+        assert_eq!(storage.list("..").unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn memory_storage_stores_files() {
+        check_storage(&MemoryStorage::new());
+    }
+
+    #[test]
+    fn file_storage_stores_files() {
+        let storage = file_storage("files");
+        check_storage(&storage);
+
+        assert_eq!(fs::read(storage.root().join("notes").join("10")).unwrap(), b"changed");
+    }
+
+    #[test]
+    fn file_storage_leaves_out_hidden_files() {
+        let storage = file_storage("hidden");
+        storage.write("notes/1", b"note").unwrap();
+        fs::write(storage.root().join("notes").join(".1.tmp"), b"").unwrap();
+        fs::create_dir(storage.root().join("notes").join("dir")).unwrap();
+
+        assert_eq!(storage.list("notes").unwrap(), vec![ "1" ]);
+    }
+
+    #[test]
+    fn checks_paths() {
+        assert!(check_path("notes/12", false).is_ok());
+        assert!(check_path("", true).is_ok());
+        assert!(check_path("", false).is_err());
+        assert!(check_path("notes/./12", false).is_err());
+        assert!(check_path("notes/", true).is_err());
+    }
+}
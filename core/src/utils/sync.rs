@@ -0,0 +1,766 @@
+///
+/// Keeping a vault consistent when its directory is synced between devices by
+/// another tool, e.g. Syncthing or Nextcloud.
+///
+/// These tools don't know how a vault is laid out. When a file was changed on
+/// two devices before they synced, they keep one version of it and store the
+/// other one next to it as a "conflict copy", e.g.
+/// "notes/12.sync-conflict-20240131-093000-ABCDEFG" or
+/// "index (conflicted copy 2024-01-31 093000)".
+///
+/// [`reconcile`] takes care of them once the vault is unlocked:
+/// - Conflict copies of the index are merged into the index, so that no note
+///   or folder is ever lost. Only those deleted on one device (see
+///   [`VaultIndex::deleted`]) and left alone on the other are left out, so
+///   that they don't come back. When both have the same note, the entry whose
+///   hash (see [`NoteMetadata::hash`](crate::types::note_metadata::NoteMetadata::hash))
+///   matches the note's file is kept, otherwise the one updated last.
+/// - Conflict copies of notes mean the note was edited on both devices. Their
+///   edits are merged into the note (see [`document`](crate::utils::document)),
+///   unless one of them was written by an older version: they are then
+///   returned as [`NoteConflict`]s, and stay until the user chooses which
+///   version to keep with [`resolve_conflict`].
+///
+use std::{ collections::HashMap, fmt, iter };
+use sha2::{ Digest, Sha256 };
+use tracing::{ debug, info, warn };
+
+use crate::{
+    types::{
+        vault_index::VaultIndex, vault_index_entry::VaultIndexEntry,
+        note_metadata::NoteMetadata, vault_error::VaultError,
+    },
+    utils::{
+        crypto::VaultKey,
+        notes::{
+            read_index, read_index_file, write_index, read_note_file, read_document_file,
+            write_note_document, save_note, note_path,
+        },
+        storage::VaultStorage,
+        unix_now,
+    },
+};
+
+/// A note that was edited on two devices, the other version is stored in a
+/// conflict copy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteConflict {
+    /// Id of the note.
+    pub id: u32,
+
+    /// Name of the note in the index.
+    pub name: String,
+
+    /// Path of the conflict copy inside the vault.
+    pub copy: String,
+}
+
+/// How to resolve a [`NoteConflict`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictResolution {
+    /// Keep the note as it is and delete the conflict copy.
+    KeepCurrent,
+
+    /// Replace the note with the conflict copy.
+    KeepCopy,
+
+    /// Keep the note and add the conflict copy as a new note next to it.
+    KeepBoth,
+}
+
+/// Summary of what [`reconcile`] did.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SyncReport {
+    /// Conflict copies of the index that were merged into the index.
+    pub merged_indexes: usize,
+
+    /// Conflict copies of notes whose edits were merged into the notes.
+    pub merged_notes: usize,
+
+    /// Notes with a conflict copy.
+    pub conflicts: Vec<NoteConflict>,
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Merged index copies: {}", self.merged_indexes)?;
+        write!(f, "Merged note copies:  {}", self.merged_notes)?;
+
+        if !self.conflicts.is_empty() {
+            write!(f, "\nConflicted notes:    {}", self.conflicts.len())?;
+
+            for conflict in &self.conflicts {
+                write!(f, "\n  - {} ({})", conflict.name, conflict.copy)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Hash of a file of the vault, as hex.
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns the name of the file that `name` is a conflict copy of, if it is
+/// one.
+///
+/// Recognizes the copies made by Syncthing ("12.sync-conflict-<date>-<time>-
+/// <device>") and by Nextcloud, ownCloud and Dropbox ("12 (conflicted copy
+/// <date>)", "12 (<user>'s conflicted copy <date>)").
+pub fn conflict_copy_of(name: &str) -> Option<&str> {
+    if let Some(i) = name.find(".sync-conflict-") {
+        return Some(&name[..i]).filter(|original| !original.is_empty());
+    }
+
+    let i = name.find(" (")?;
+    let suffix = name[i..].to_lowercase();
+
+    if suffix.ends_with(')') && suffix.contains("conflicted copy") && i > 0 {
+        Some(&name[..i])
+    } else {
+        None
+    }
+}
+
+/// Merges the conflict copies of the index and of the notes, and finds the
+/// notes with a conflict copy that couldn't be merged.
+///
+/// Merged copies are deleted once the merged index is written. Copies that
+/// can't be read are left alone.
+pub fn reconcile(storage: &dyn VaultStorage, key: &VaultKey) -> Result<SyncReport, VaultError> {
+    let files = match storage.list("") {
+        Ok(files) => files,
+        Err(e) => { return Err(VaultError::io("Couldn't list the vault's files", e)); }
+    };
+
+    let mut index = read_index(storage, key)?;
+    let mut merged = vec![];
+    let mut moved = vec![];
+
+    for file in &files {
+        match conflict_copy_of(file) {
+            Some("index") => {
+                match read_index_file(storage, key, file) {
+                    Ok(copy) => {
+                        moved.extend(merge_index(storage, &mut index, copy)?);
+                        merged.push(file.clone());
+                    }
+
+                    Err(e) => { warn!(file = %file, error = %e, "could not read index copy"); }
+                }
+            }
+
+            Some("info") => {
+                // Key slots are never merged, a removed password must not come
+                // back
+                warn!(file = %file, "the info file has a conflict copy, it is not merged");
+            }
+
+            _ => {}
+        }
+    }
+
+    // After the index, so that notes that got a new id aren't merged with
+    // another note
+    let notes = merge_note_copies(storage, key, &mut index, &moved)?;
+
+    if !merged.is_empty() || !notes.is_empty() {
+        write_index(storage, key, &index)?;
+
+        for file in merged.iter().chain(moved.iter()).chain(notes.iter()) {
+            if let Err(e) = storage.delete(file) {
+                warn!(file = %file, error = %e, "could not delete merged conflict copy");
+            }
+        }
+
+        info!(indexes = merged.len(), notes = notes.len(), "merged conflict copies");
+    }
+
+    let conflicts = find_conflicts(storage, &index)?;
+
+    if !conflicts.is_empty() {
+        info!(notes = conflicts.len(), "found conflicted notes");
+    }
+
+    Ok(SyncReport { merged_indexes: merged.len(), merged_notes: notes.len(), conflicts })
+}
+
+/// Finds the notes that have a conflict copy in the "notes" directory.
+pub fn find_conflicts(
+    storage: &dyn VaultStorage,
+    index: &VaultIndex,
+) -> Result<Vec<NoteConflict>, VaultError> {
+    let files = match storage.list("notes") {
+        Ok(files) => files,
+        Err(e) => { return Err(VaultError::io("Couldn't list the notes", e)); }
+    };
+
+    let mut conflicts = vec![];
+
+    for file in files {
+        let id = match conflict_copy_of(&file).and_then(|original| original.parse().ok()) {
+            Some(id) => id,
+            None => { continue; }
+        };
+
+        let name = match index.notes.iter().find(|n| n.id == id) {
+            Some(note) => note.name.clone(),
+            None => String::from("Untitled Note"),
+        };
+
+        conflicts.push(NoteConflict { id, name, copy: format!("notes/{}", file) });
+    }
+
+    Ok(conflicts)
+}
+
+/// Reads the text of the conflict copy.
+pub fn read_conflict_copy(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    conflict: &NoteConflict,
+) -> Result<String, VaultError> {
+    read_note_file(storage, key, &conflict.copy)
+}
+
+/// Resolves the conflict and deletes the conflict copy.
+///
+/// The copy is only deleted after its text was saved, if it's kept.
+pub fn resolve_conflict(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    conflict: &NoteConflict,
+    resolution: ConflictResolution,
+) -> Result<(), VaultError> {
+    if resolution != ConflictResolution::KeepCurrent {
+        let text = read_conflict_copy(storage, key, conflict)?;
+        let index = read_index(storage, key)?;
+        let note = index.notes.iter().find(|n| n.id == conflict.id);
+
+        let mut entry = match ( resolution, note ) {
+            ( ConflictResolution::KeepCopy, Some(note) ) => note.clone(),
+
+            ( ConflictResolution::KeepCopy, None ) => VaultIndexEntry {
+                id: conflict.id,
+                name: conflict.name.clone(),
+                parent_folder: None,
+                metadata: NoteMetadata::default(),
+            },
+
+            // Saved as a new note
+            ( _, note ) => VaultIndexEntry {
+                id: 0,
+                name: format!("{} (conflicted copy)", conflict.name),
+                parent_folder: note.and_then(|n| n.parent_folder),
+                metadata: NoteMetadata {
+                    tags: note.map(|n| n.metadata.tags.clone()).unwrap_or_default(),
+                    ..NoteMetadata::default()
+                },
+            },
+        };
+
+        save_note(storage, key, &mut entry, &text)?;
+    }
+
+    match storage.delete(&conflict.copy) {
+        Ok(()) => {
+            debug!(note = conflict.id, resolution = ?resolution, "resolved conflict");
+            Ok(())
+        }
+
+        Err(e) => Err(VaultError::io("Couldn't delete the conflict copy", e)),
+    }
+}
+
+/// Merges the edits of the conflict copies of notes into the notes, and updates
+/// their hashes in `index`. Returns the merged copies, to be deleted once the
+/// index is written.
+///
+/// The `moved` copies hold notes that got a new id, they are left out.
+///
+/// Copies of notes written by older versions have no history to merge with,
+/// they are left for the user, and so are copies that can't be merged.
+fn merge_note_copies(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    index: &mut VaultIndex,
+    moved: &[String],
+) -> Result<Vec<String>, VaultError> {
+    let files = match storage.list("notes") {
+        Ok(files) => files,
+        Err(e) => { return Err(VaultError::io("Couldn't list the notes", e)); }
+    };
+
+    let mut merged = vec![];
+
+    for file in files {
+        let id = match conflict_copy_of(&file).and_then(|original| original.parse::<u32>().ok()) {
+            Some(id) => id,
+            None => { continue; }
+        };
+
+        let path = format!("notes/{}", file);
+
+        if moved.contains(&path) {
+            continue;
+        }
+
+        let documents = read_document_file(storage, key, &note_path(id))
+            .and_then(|note| Ok(( note, read_document_file(storage, key, &path)? )));
+
+        let ( mut note, mut copy ) = match documents {
+            Ok(( note, copy )) if !note.is_converted() && !copy.is_converted() => ( note, copy ),
+            Ok(_) => { continue; }
+            Err(e) => {
+                warn!(file = %path, error = %e, "could not read note copy");
+                continue;
+            }
+        };
+
+        if let Err(e) = note.merge(&mut copy) {
+            warn!(file = %path, error = %e, "could not merge note copy");
+            continue;
+        }
+
+        let hash = write_note_document(storage, key, id, &mut note)?;
+
+        if let Some(entry) = index.notes.iter_mut().find(|n| n.id == id) {
+            entry.metadata.hash = Some(hash);
+            entry.metadata.updated = unix_now();
+        }
+
+        debug!(note = id, "merged the edits of a note copy");
+        merged.push(path);
+    }
+
+    Ok(merged)
+}
+
+/// Adds the folders and notes of `copy` to `index`.
+///
+/// Folders and notes deleted in one of them and unchanged in the other since
+/// are left out, and the deletions of both are kept. Folders and notes that
+/// were created on two devices with the same id get a new id. Returns the
+/// conflict copies of notes that were moved to the new ids, to be deleted once
+/// the index is written.
+pub(crate) fn merge_index(
+    storage: &dyn VaultStorage,
+    index: &mut VaultIndex,
+    mut copy: VaultIndex,
+) -> Result<Vec<String>, VaultError> {
+    copy.folders.retain(|f| !index.is_deleted(f));
+    copy.notes.retain(|n| !index.is_deleted(n));
+    index.folders.retain(|f| !copy.is_deleted(f));
+    index.notes.retain(|n| !copy.is_deleted(n));
+
+    for deletion in copy.deleted.drain(..) {
+        if !index.deleted.contains(&deletion) {
+            index.deleted.push(deletion);
+        }
+    }
+
+    let mut next_id = index.next_id().max(copy.next_id());
+    let mut folder_ids = HashMap::new();
+
+    for folder in copy.folders.iter_mut() {
+        let collides = index.folders.iter()
+            .any(|f| f.id == folder.id && f.metadata.created != folder.metadata.created);
+
+        if collides {
+            folder_ids.insert(folder.id, next_id);
+            folder.id = next_id;
+            next_id += 1;
+        }
+    }
+
+    for entry in copy.folders.iter_mut().chain(copy.notes.iter_mut()) {
+        if let Some(id) = entry.parent_folder.and_then(|p| folder_ids.get(&p)) {
+            entry.parent_folder = Some(*id);
+        }
+    }
+
+    for folder in copy.folders {
+        match index.folders.iter_mut().find(|f| f.id == folder.id) {
+            Some(existing) => {
+                if folder.metadata.updated > existing.metadata.updated {
+                    *existing = folder;
+                }
+            }
+
+            None => { index.folders.push(folder); }
+        }
+    }
+
+    let mut moved = vec![];
+
+    for mut note in copy.notes {
+        let existing = match index.notes.iter_mut().find(|n| n.id == note.id) {
+            Some(existing) => existing,
+            None => {
+                index.notes.push(note);
+                continue;
+            }
+        };
+
+        if *existing == note {
+            continue;
+        }
+
+        if existing.metadata.created != note.metadata.created {
+            // Another note that got the same id on the other device
+            moved.extend(move_note_file(storage, note.id, next_id, &note, existing)?);
+            debug!(note = note.id, new_id = next_id, "gave a new id to a note from another device");
+
+            note.id = next_id;
+            next_id += 1;
+            index.notes.push(note);
+            continue;
+        }
+
+        let file_hash = file_hash(storage, &note_path(note.id));
+        let existing_matches = file_hash.is_some() && existing.metadata.hash == file_hash;
+        let copy_matches = file_hash.is_some() && note.metadata.hash == file_hash;
+
+        let use_copy = match ( existing_matches, copy_matches ) {
+            ( true, false ) => false,
+            ( false, true ) => true,
+            _ => note.metadata.updated > existing.metadata.updated,
+        };
+
+        if use_copy {
+            *existing = note;
+        }
+    }
+
+    Ok(moved)
+}
+
+/// Copies the file of `moved`, which shares its id with `staying`, to the
+/// new id. The files are told apart by their hashes.
+///
+/// Returns the conflict copy the file was moved from, if any.
+fn move_note_file(
+    storage: &dyn VaultStorage,
+    id: u32,
+    new_id: u32,
+    moved: &VaultIndexEntry,
+    staying: &VaultIndexEntry,
+) -> Result<Option<String>, VaultError> {
+    let main = note_path(id);
+
+    let copies = match storage.list("notes") {
+        Ok(files) => files.into_iter()
+            .filter(|f| conflict_copy_of(f) == Some(id.to_string().as_str()))
+            .map(|f| format!("notes/{}", f))
+            .collect::<Vec<_>>(),
+
+        Err(e) => { return Err(VaultError::io("Couldn't list the notes", e)); }
+    };
+
+    let find = |hash: &Option<String>| {
+        iter::once(&main)
+            .chain(copies.iter())
+            .find(|path| hash.is_some() && file_hash(storage, path) == *hash)
+            .cloned()
+    };
+
+    let source = match find(&moved.metadata.hash) {
+        Some(source) => source,
+        None => {
+            warn!(note = id, "could not find the file of a note from another device");
+            return Ok(None);
+        }
+    };
+
+    copy_file(storage, &source, &note_path(new_id))?;
+
+    if source != main {
+        return Ok(Some(source));
+    }
+
+    // The staying note's file is the conflict copy
+    match find(&staying.metadata.hash).filter(|path| *path != main) {
+        Some(path) => {
+            copy_file(storage, &path, &main)?;
+            Ok(Some(path))
+        }
+
+        None => Ok(None),
+    }
+}
+
+fn copy_file(storage: &dyn VaultStorage, from: &str, to: &str) -> Result<(), VaultError> {
+    match storage.read(from).and_then(|data| storage.replace(to, &data)) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(VaultError::io("Couldn't copy the note's file", e)),
+    }
+}
+
+fn file_hash(storage: &dyn VaultStorage, path: &str) -> Option<String> {
+    storage.read(path).ok().map(|data| content_hash(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        types::vault_index::DeletedEntry,
+        utils::{
+            crypto::{ encrypt, decrypt }, document::NoteDocument, notes::read_note, storage::MemoryStorage,
+        },
+    };
+
+    const COPY: &str = "notes/5.sync-conflict-20240131-093000-ABCDEFG";
+
+    fn entry(id: u32, created: u64, updated: u64) -> VaultIndexEntry {
+        VaultIndexEntry {
+            id,
+            name: format!("Note {}", id),
+            parent_folder: None,
+            metadata: NoteMetadata { created, updated, ..NoteMetadata::default() },
+        }
+    }
+
+    fn index(folders: Vec<VaultIndexEntry>, notes: Vec<VaultIndexEntry>) -> VaultIndex {
+        VaultIndex { folders, notes, deleted: vec![] }
+    }
+
+    /// Writes the document made from `base` and edited to `text` at `path`.
+    fn write_edited(storage: &MemoryStorage, key: &VaultKey, path: &str, base: &str, text: &str) {
+        let mut document = NoteDocument::from_text(base);
+        document.update(text).unwrap();
+
+        storage.replace(path, &encrypt(key, &document.save()).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn recognizes_conflict_copies() {
+        assert_eq!(conflict_copy_of("12.sync-conflict-20240131-093000-ABCDEFG"), Some("12"));
+        assert_eq!(conflict_copy_of("index (conflicted copy 2024-01-31 093000)"), Some("index"));
+        assert_eq!(conflict_copy_of("12 (Alice's conflicted copy 2024-01-31)"), Some("12"));
+        assert_eq!(conflict_copy_of("12"), None);
+        assert_eq!(conflict_copy_of("12 (copy)"), None);
+        assert_eq!(conflict_copy_of(".sync-conflict-20240131-093000-ABCDEFG"), None);
+    }
+
+    #[test]
+    fn merge_index_adds_the_entries_of_both() {
+        let storage = MemoryStorage::new();
+        let mut ours = index(vec![ entry(1, 10, 10) ], vec![ entry(2, 10, 10) ]);
+        let theirs = index(vec![ entry(1, 10, 10) ], vec![ entry(2, 10, 10), entry(3, 20, 20) ]);
+
+        let moved = merge_index(&storage, &mut ours, theirs).unwrap();
+
+        assert!(moved.is_empty());
+        assert_eq!(ours.folders, vec![ entry(1, 10, 10) ]);
+        assert_eq!(ours.notes, vec![ entry(2, 10, 10), entry(3, 20, 20) ]);
+    }
+
+    #[test]
+    fn merge_index_keeps_the_entry_updated_last() {
+        let storage = MemoryStorage::new();
+        let mut ours = index(vec![ entry(1, 10, 30) ], vec![ entry(2, 10, 20) ]);
+        let mut theirs = index(vec![ entry(1, 10, 20) ], vec![ entry(2, 10, 30) ]);
+        theirs.folders[0].name = String::from("Renamed there");
+        theirs.notes[0].name = String::from("Renamed there");
+
+        merge_index(&storage, &mut ours, theirs).unwrap();
+
+        assert_eq!(ours.folders[0].name, "Note 1");
+        assert_eq!(ours.notes[0].name, "Renamed there");
+    }
+
+    #[test]
+    fn merge_index_keeps_the_entry_matching_the_file() {
+        let storage = MemoryStorage::new();
+        storage.replace("notes/2", b"written here").unwrap();
+
+        let mut ours = index(vec![], vec![ entry(2, 10, 20) ]);
+        ours.notes[0].metadata.hash = Some(content_hash(b"written here"));
+
+        // Updated last, but its file was lost in the sync
+        let mut theirs = index(vec![], vec![ entry(2, 10, 30) ]);
+        theirs.notes[0].metadata.hash = Some(content_hash(b"written there"));
+
+        let expected = ours.notes.clone();
+        merge_index(&storage, &mut ours, theirs.clone()).unwrap();
+        assert_eq!(ours.notes, expected);
+
+        // And the other way round
+        storage.replace("notes/2", b"written there").unwrap();
+        merge_index(&storage, &mut ours, theirs.clone()).unwrap();
+        assert_eq!(ours.notes, theirs.notes);
+    }
+
+    #[test]
+    fn merge_index_leaves_out_deleted_entries() {
+        let storage = MemoryStorage::new();
+        let deleted = |id, created| DeletedEntry { id, created, deleted: 50 };
+
+        // Note 2 was deleted here, note 3 there, note 4 deleted there but
+        // edited here since
+        let mut ours = index(vec![], vec![ entry(3, 10, 10), entry(4, 10, 60) ]);
+        ours.deleted = vec![ deleted(2, 10) ];
+
+        let mut theirs = index(vec![], vec![ entry(2, 10, 10) ]);
+        theirs.deleted = vec![ deleted(3, 10), deleted(4, 10) ];
+
+        merge_index(&storage, &mut ours, theirs).unwrap();
+
+        assert_eq!(ours.notes, vec![ entry(4, 10, 60) ]);
+        assert_eq!(ours.deleted, vec![ deleted(2, 10), deleted(3, 10), deleted(4, 10) ]);
+        assert_eq!(ours.next_id(), 5);
+    }
+
+    #[test]
+    fn merge_index_keeps_another_entry_with_a_deleted_id() {
+        let storage = MemoryStorage::new();
+
+        let mut ours = index(vec![], vec![]);
+        ours.deleted = vec![ DeletedEntry { id: 2, created: 10, deleted: 50 } ];
+
+        // Created on the other device with the same id
+        let theirs = index(vec![], vec![ entry(2, 20, 20) ]);
+
+        merge_index(&storage, &mut ours, theirs).unwrap();
+
+        assert_eq!(ours.notes, vec![ entry(2, 20, 20) ]);
+    }
+
+    #[test]
+    fn merge_index_gives_new_ids_to_entries_made_on_both_devices() {
+        let storage = MemoryStorage::new();
+        storage.replace("notes/3", b"ours").unwrap();
+        storage.replace("notes/3.sync-conflict-20240131-093000-ABCDEFG", b"theirs").unwrap();
+
+        let mut our_note = entry(3, 10, 10);
+        our_note.metadata.hash = Some(content_hash(b"ours"));
+        let mut ours = index(vec![ entry(1, 10, 10) ], vec![ our_note.clone() ]);
+
+        let mut their_note = entry(3, 20, 20);
+        their_note.parent_folder = Some(1);
+        their_note.metadata.hash = Some(content_hash(b"theirs"));
+        let theirs = index(vec![ entry(1, 20, 20) ], vec![ their_note ]);
+
+        let moved = merge_index(&storage, &mut ours, theirs).unwrap();
+
+        assert_eq!(moved, vec![ String::from("notes/3.sync-conflict-20240131-093000-ABCDEFG") ]);
+        assert_eq!(ours.folders.iter().map(|f| f.id).collect::<Vec<_>>(), vec![ 1, 4 ]);
+        assert_eq!(ours.notes[0], our_note);
+        assert_eq!(ours.notes[1].id, 5);
+        assert_eq!(ours.notes[1].parent_folder, Some(4));
+        assert_eq!(storage.read("notes/3").unwrap(), b"ours");
+        assert_eq!(storage.read("notes/5").unwrap(), b"theirs");
+    }
+
+    #[test]
+    fn merge_note_copies_merges_the_edits_of_both() {
+        let storage = MemoryStorage::new();
+        let key = VaultKey::generate();
+
+        write_edited(&storage, &key, "notes/5", "one\n", "one\ntwo\n");
+        write_edited(&storage, &key, COPY, "one\n", "zero\none\n");
+
+        let mut index = index(vec![], vec![ entry(5, 10, 10) ]);
+        let merged = merge_note_copies(&storage, &key, &mut index, &[]).unwrap();
+
+        assert_eq!(merged, vec![ String::from(COPY) ]);
+        assert_eq!(read_note(&storage, &key, 5).unwrap(), "zero\none\ntwo\n");
+        assert_eq!(index.notes[0].metadata.hash, file_hash(&storage, "notes/5"));
+        assert!(index.notes[0].metadata.updated > 10);
+    }
+
+    #[test]
+    fn merge_note_copies_leaves_copies_without_history() {
+        let storage = MemoryStorage::new();
+        let key = VaultKey::generate();
+
+        write_edited(&storage, &key, "notes/5", "one\n", "one\ntwo\n");
+        storage.replace(COPY, &encrypt(&key, b"written by an older version").unwrap()).unwrap();
+
+        let mut index = index(vec![], vec![ entry(5, 10, 10) ]);
+        let merged = merge_note_copies(&storage, &key, &mut index, &[]).unwrap();
+
+        assert!(merged.is_empty());
+        assert_eq!(read_note(&storage, &key, 5).unwrap(), "one\ntwo\n");
+        assert_eq!(index.notes[0], entry(5, 10, 10));
+    }
+
+    #[test]
+    fn merge_note_copies_leaves_copies_that_cannot_be_merged() {
+        use automerge::{ AutoCommit, ObjType, ROOT, transaction::Transactable };
+
+        let storage = MemoryStorage::new();
+        let key = VaultKey::generate();
+
+        write_edited(&storage, &key, "notes/5", "one\n", "one\ntwo\n");
+        write_edited(&storage, &key, "notes/6", "six\n", "six\n");
+        write_edited(&storage, &key, "notes/6.sync-conflict-20240131-093000-ABCDEFG", "six\n", "6\n");
+
+        // Another first change by the author of the note's first change, which
+        // Automerge refuses to merge
+        let mut note = AutoCommit::load(&decrypt(&key, &storage.read("notes/5").unwrap()).unwrap()).unwrap();
+        let actor = note.get_changes(&[])[0].actor_id().clone();
+
+        let mut copy = AutoCommit::new().with_actor(actor);
+        let text = copy.put_object(ROOT, "text", ObjType::Text).unwrap();
+        copy.splice_text(&text, 0, 0, "other\n").unwrap();
+        storage.replace(COPY, &encrypt(&key, &copy.save()).unwrap()).unwrap();
+
+        let mut index = index(vec![], vec![ entry(5, 10, 10), entry(6, 10, 10) ]);
+        write_index(&storage, &key, &index).unwrap();
+
+        let report = reconcile(&storage, &key).unwrap();
+
+        assert_eq!(report.merged_notes, 1);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].id, 5);
+        assert_eq!(report.conflicts[0].copy, COPY);
+        assert_eq!(read_note(&storage, &key, 5).unwrap(), "one\ntwo\n");
+        assert_eq!(read_note(&storage, &key, 6).unwrap(), "6\n");
+
+        index = read_index(&storage, &key).unwrap();
+        assert_eq!(index.notes[0], entry(5, 10, 10));
+    }
+
+    #[test]
+    fn merge_note_copies_leaves_moved_copies() {
+        let storage = MemoryStorage::new();
+        let key = VaultKey::generate();
+
+        write_edited(&storage, &key, "notes/5", "one\n", "one\ntwo\n");
+        write_edited(&storage, &key, COPY, "one\n", "zero\none\n");
+
+        let mut index = index(vec![], vec![ entry(5, 10, 10) ]);
+        let merged = merge_note_copies(&storage, &key, &mut index, &[ String::from(COPY) ]).unwrap();
+
+        assert!(merged.is_empty());
+        assert_eq!(read_note(&storage, &key, 5).unwrap(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn reconcile_merges_and_deletes_the_copies() {
+        let storage = MemoryStorage::new();
+        let key = VaultKey::generate();
+
+        write_index(&storage, &key, &index(vec![], vec![ entry(6, 10, 10) ])).unwrap();
+        let copy = storage.read("index").unwrap();
+        storage.replace("index (conflicted copy 2024-01-31 093000)", &copy).unwrap();
+
+        write_index(&storage, &key, &index(vec![], vec![ entry(5, 10, 10) ])).unwrap();
+        write_edited(&storage, &key, "notes/5", "one\n", "one\ntwo\n");
+        write_edited(&storage, &key, COPY, "one\n", "zero\none\n");
+
+        let report = reconcile(&storage, &key).unwrap();
+
+        assert_eq!(report.merged_indexes, 1);
+        assert_eq!(report.merged_notes, 1);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(storage.paths(), vec![ "index", "notes/5" ]);
+
+        let ids = read_index(&storage, &key).unwrap().notes.iter().map(|n| n.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![ 5, 6 ]);
+    }
+}
@@ -0,0 +1,359 @@
+///
+/// Keeps track of failed attempts to unlock vaults, to slow down guessing of
+/// passwords.
+///
+/// The state is local to this device and stored per vault in the
+/// "unlock-state" directory inside the secure-notes local directory:
+///
+/// + unlock-state\
+///   + <vault-name>
+///
+/// The first [`FREE_ATTEMPTS`] failed attempts are not delayed. After that,
+/// every failed attempt doubles the time the user has to wait before trying
+/// again, starting at [`BASE_DELAY`] seconds, up to [`MAX_DELAY`] seconds.
+///
+/// A state file that exists but can't be read counts as the longest delay,
+/// so that damaging it doesn't give more attempts.
+///
+use std::{
+    fs::{ OpenOptions, create_dir_all, metadata, read, remove_file, rename },
+    io::{ ErrorKind, Write },
+    path::{ Path, PathBuf },
+    time::UNIX_EPOCH,
+};
+use serde::{ Serialize, Deserialize };
+use flexbuffers::{ FlexbufferSerializer, Reader };
+use tracing::{ debug, warn };
+
+use crate::utils::{
+    crypto::generate_salt, get_local_dir, sync::content_hash, unix_now, vault::check_vault_name,
+};
+
+/// Failed attempts allowed before the user has to wait between attempts.
+pub const FREE_ATTEMPTS: u32 = 3;
+
+/// Seconds to wait after the first delayed attempt.
+pub const BASE_DELAY: u64 = 30;
+
+/// Longest time, in seconds, the user has to wait between attempts.
+pub const MAX_DELAY: u64 = 60 * 60;
+
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct UnlockState {
+    /// Failed attempts since the last successful unlock.
+    #[serde(default)]
+    pub failed_attempts: u32,
+
+    /// Time of the last failed attempt, in seconds since the unix epoch.
+    #[serde(default)]
+    pub last_failure: u64,
+
+    /// When set, the vault's key material is wiped from this device after
+    /// this many failed attempts. Only for users who keep a backup of the
+    /// vault elsewhere.
+    #[serde(default)]
+    pub wipe_after: Option<u32>,
+
+    /// Set once the key material was wiped.
+    #[serde(default)]
+    pub key_material_wiped: bool,
+}
+
+impl UnlockState {
+    /// Seconds the user has to wait after the last failed attempt.
+    pub fn delay(&self) -> u64 {
+        if self.failed_attempts < FREE_ATTEMPTS {
+            return 0;
+        }
+
+        let doublings = (self.failed_attempts - FREE_ATTEMPTS).min(16);
+
+        (BASE_DELAY << doublings).min(MAX_DELAY)
+    }
+
+    /// Time (in seconds since the unix epoch) after which the vault can be
+    /// unlocked again, `None` if it can be unlocked now.
+    pub fn retry_at(&self) -> Option<u64> {
+        let delay = self.delay();
+
+        if delay == 0 {
+            return None;
+        }
+
+        let now = unix_now();
+
+        // If the clock was moved back, wait for the whole delay from now.
+        if now < self.last_failure {
+            return Some(now + delay);
+        }
+
+        let retry_at = self.last_failure + delay;
+
+        if retry_at > now { Some(retry_at) } else { None }
+    }
+
+    /// Whether the key material should be wiped because of too many failed
+    /// attempts.
+    pub fn should_wipe(&self) -> bool {
+        match self.wipe_after {
+            Some(n) => n > 0 && self.failed_attempts >= n && !self.key_material_wiped,
+            None => false,
+        }
+    }
+}
+
+fn get_unlock_state_path(name: &str) -> Option<PathBuf> {
+    check_vault_name(name).ok()?;
+
+    match get_local_dir() {
+        Some(mut path) => {
+            path.push("unlock-state");
+            path.push(name);
+            Some(path)
+        }
+
+        None => None,
+    }
+}
+
+/// Reads the unlock state of the vault.
+///
+/// A missing state file is read as no failed attempts. One that can't be read
+/// is locked out for [`MAX_DELAY`] seconds from when it was last written.
+pub fn read_unlock_state(name: &str) -> UnlockState {
+    let path = match get_unlock_state_path(name) {
+        Some(p) => p,
+        None => { return UnlockState::default(); }
+    };
+
+    let bytes = match read(&path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == ErrorKind::NotFound => { return UnlockState::default(); }
+        Err(e) => {
+            warn!(vault = %name, error = %e, "could not read the unlock state");
+            return locked_out_state(&path);
+        }
+    };
+
+    match Reader::get_root(bytes.as_slice()) {
+        Ok(reader) => {
+            match UnlockState::deserialize(reader) {
+                Ok(state) => state,
+                Err(e) => {
+                    warn!(vault = %name, error = %e, "could not de-serialise the unlock state");
+                    locked_out_state(&path)
+                }
+            }
+        }
+
+        Err(e) => {
+            warn!(vault = %name, error = %e, "could not read the unlock state");
+            locked_out_state(&path)
+        }
+    }
+}
+
+/// State of a vault whose state file can't be read: enough failed attempts to
+/// wait [`MAX_DELAY`] seconds, the last one when the file was last written.
+fn locked_out_state(path: &Path) -> UnlockState {
+    let last_failure = metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or_else(unix_now, |time| time.as_secs());
+
+    UnlockState {
+        failed_attempts: FREE_ATTEMPTS + ( MAX_DELAY / BASE_DELAY ).ilog2() + 1,
+        last_failure,
+        ..UnlockState::default()
+    }
+}
+
+pub fn write_unlock_state(name: &str, state: &UnlockState) -> Result<(), String> {
+    let state_path = match get_unlock_state_path(name) {
+        Some(p) => p,
+        None => { return Err(String::from("Could not find local directory")); }
+    };
+
+    if let Some(dir) = state_path.parent() {
+        if let Err(e) = create_dir_all(dir) {
+            debug!(vault = %name, error = %e, "could not create the unlock state directory");
+            return Err(String::from("Couldn't create unlock state directory"));
+        }
+    }
+
+    let mut serializer = FlexbufferSerializer::new();
+
+    if let Err(e) = state.serialize(&mut serializer) {
+        debug!(vault = %name, error = %e, "could not serialise the unlock state");
+        return Err(String::from("Could not serialize unlock state"));
+    }
+
+    // Written whole or not at all, a half written file would be locked out
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(state_path.file_name().unwrap_or_default());
+    temp_name.push(format!(".{}.tmp", &content_hash(&generate_salt())[..8]));
+    let temp_path = state_path.with_file_name(temp_name);
+
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(serializer.view())?;
+            file.sync_all()
+        })
+        .and_then(|()| rename(&temp_path, &state_path));
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = remove_file(&temp_path);
+            debug!(vault = %name, error = %e, "could not write the unlock state");
+            Err(String::from("Error while writing unlock state"))
+        }
+    }
+}
+
+/// Records a failed attempt to unlock the vault and returns the new state.
+pub fn record_failed_unlock(name: &str) -> UnlockState {
+    let mut state = read_unlock_state(name);
+
+    state.failed_attempts = state.failed_attempts.saturating_add(1);
+    state.last_failure = unix_now();
+
+    if let Err(e) = write_unlock_state(name, &state) {
+        warn!(vault = %name, error = %e, "could not record the failed unlock");
+    }
+
+    state
+}
+
+/// Forgets the failed attempts after the vault was unlocked.
+pub fn reset_failed_unlocks(name: &str) {
+    let state = read_unlock_state(name);
+
+    if state.failed_attempts == 0 {
+        return;
+    }
+
+    let result = if state.wipe_after.is_none() {
+        match get_unlock_state_path(name).map(remove_file) {
+            Some(Ok(())) => Ok(()),
+            _ => Err(String::from("Couldn't remove unlock state file")),
+        }
+    } else {
+        write_unlock_state(name, &UnlockState {
+            wipe_after: state.wipe_after,
+            ..UnlockState::default()
+        })
+    };
+
+    if let Err(e) = result {
+        warn!(vault = %name, error = %e, "could not reset the failed unlocks");
+    }
+}
+
+/// Turns wiping the key material after `attempts` failed attempts on, or off
+/// when `None`.
+pub fn set_wipe_after(name: &str, attempts: Option<u32>) -> Result<(), String> {
+    let mut state = read_unlock_state(name);
+    state.wipe_after = attempts;
+
+    write_unlock_state(name, &state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ env, fs, process };
+
+    use super::*;
+    use crate::utils::set_data_dir;
+
+    fn failed(attempts: u32) -> UnlockState {
+        UnlockState { failed_attempts: attempts, last_failure: unix_now(), ..UnlockState::default() }
+    }
+
+    /// A vault name of its own for the test, with no state yet.
+    fn vault_name(name: &str) -> String {
+        let _ = set_data_dir(&env::temp_dir().join(format!("secure-notes-test-{}", process::id())));
+
+        let name = format!("unlock-state-{}", name);
+        let _ = remove_file(get_unlock_state_path(&name).unwrap());
+
+        name
+    }
+
+    #[test]
+    fn doubles_the_delay_after_the_free_attempts() {
+        for attempts in 0..FREE_ATTEMPTS {
+            assert_eq!(failed(attempts).delay(), 0);
+            assert_eq!(failed(attempts).retry_at(), None);
+        }
+
+        assert_eq!(failed(FREE_ATTEMPTS).delay(), BASE_DELAY);
+        assert_eq!(failed(FREE_ATTEMPTS + 1).delay(), BASE_DELAY * 2);
+        assert_eq!(failed(FREE_ATTEMPTS + 2).delay(), BASE_DELAY * 4);
+        assert_eq!(failed(FREE_ATTEMPTS + 20).delay(), MAX_DELAY);
+        assert_eq!(failed(u32::MAX).delay(), MAX_DELAY);
+
+        let state = failed(FREE_ATTEMPTS);
+        assert_eq!(state.retry_at(), Some(state.last_failure + BASE_DELAY));
+
+        let long_ago = UnlockState { last_failure: unix_now() - BASE_DELAY, ..state };
+        assert_eq!(long_ago.retry_at(), None);
+    }
+
+    #[test]
+    fn wipes_once_the_limit_is_reached() {
+        let state = |attempts, wipe_after| UnlockState { wipe_after, ..failed(attempts) };
+
+        assert!(!state(100, None).should_wipe());
+        assert!(!state(100, Some(0)).should_wipe());
+        assert!(!state(4, Some(5)).should_wipe());
+        assert!(state(5, Some(5)).should_wipe());
+        assert!(state(6, Some(5)).should_wipe());
+
+        let wiped = UnlockState { key_material_wiped: true, ..state(5, Some(5)) };
+        assert!(!wiped.should_wipe());
+    }
+
+    #[test]
+    fn records_and_resets_the_failed_attempts() {
+        let name = vault_name("record");
+        set_wipe_after(&name, Some(10)).unwrap();
+
+        for _ in 0..FREE_ATTEMPTS {
+            record_failed_unlock(&name);
+        }
+
+        let state = read_unlock_state(&name);
+        assert_eq!(( state.failed_attempts, state.wipe_after ), ( FREE_ATTEMPTS, Some(10) ));
+        assert!(state.retry_at().is_some());
+
+        reset_failed_unlocks(&name);
+        assert_eq!(read_unlock_state(&name), UnlockState { wipe_after: Some(10), ..UnlockState::default() });
+
+        // Only the state file is left in the directory
+        let dir = get_unlock_state_path(&name).unwrap().parent().unwrap().to_path_buf();
+        assert!(!fs::read_dir(dir).unwrap().any(|e| e.unwrap().file_name().to_string_lossy().ends_with(".tmp")));
+    }
+
+    #[test]
+    fn a_damaged_state_file_is_locked_out() {
+        let name = vault_name("damaged");
+        assert_eq!(read_unlock_state(&name), UnlockState::default());
+
+        let path = get_unlock_state_path(&name).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"\xff\xff").unwrap();
+
+        let state = read_unlock_state(&name);
+        assert_eq!(state.delay(), MAX_DELAY);
+        assert!(state.retry_at().is_some());
+
+        // The next failed attempt replaces it
+        assert_eq!(record_failed_unlock(&name).delay(), MAX_DELAY);
+        assert_eq!(read_unlock_state(&name).failed_attempts, state.failed_attempts + 1);
+    }
+}
@@ -0,0 +1,970 @@
+///
+/// Contains all the utilities related to vault.
+///
+/// A vault is directory a inside the "vaults" directory in the secure notes
+/// local directory. The functions here access its files through a
+/// [`VaultStorage`] (see [`get_vault_storage`]), so they work the same way for
+/// any storage.
+///
+/// It contains:
+/// - And index file named "index". It contains entries that map a note's name
+///   with it's file inside the notes directory.
+/// - An info file named "info". This contains all the information necessary to
+///   decrypt the notes: the key slots, each holding a copy of the vault's
+///   master key encrypted with a password or the recovery phrase.
+/// - A directory named "notes" that contains all the encrypted notes.
+/// - A directory named "attachments" that contains the encrypted files attached
+///   to notes. It is only created once a note has an attachment.
+///
+use std::{ fs::read_dir, path::{ Component, Path, PathBuf } };
+use serde::{ Serialize, Deserialize };
+use flexbuffers::{ FlexbufferSerializer, Reader };
+use zeroize::Zeroizing;
+use argon2:: {
+    password_hash::{ PasswordHash, PasswordVerifier },
+    Argon2, Algorithm, Version, Params,
+};
+use tracing::{ debug, error, info, warn };
+
+use crate::{
+    types::{
+        vault_info::{ VaultInfo, VAULT_VERSION }, vault_index::VaultIndex,
+        key_slot::{ KeySlot, KeySlotKind, KeySlotKdf },
+        vault_error::VaultError,
+    },
+    utils::{
+        get_local_dir, unix_now, vault_exists,
+        crypto::{
+            SecretString, VaultKey, derive_key, generate_salt, encrypt, decrypt,
+            read_keyfile, keyfile_check, generate_recovery_phrase,
+            derive_recovery_key,
+        },
+        notes::{ read_index, write_index },
+        storage::{ FileStorage, VaultStorage },
+        unlock_state::{
+            read_unlock_state, write_unlock_state, record_failed_unlock,
+            reset_failed_unlocks,
+        },
+    },
+};
+
+/// Creates vault
+///
+/// Typical vault structure:
+///
+/// C:\Users\<user>\AppData\Local\secure-notes\
+/// + default-vault
+/// + vaults\
+///   + <vault-name>\
+///     + info
+///     + index
+///     + notes\
+///
+/// The vault's files are written to `storage`, which must not already hold a
+/// vault.
+///
+/// When `keyfile` is given, the vault can only be unlocked with both the
+/// password and the keyfile.
+///
+/// Returns the vault's recovery phrase, which has to be shown to the user.
+pub fn create_vault(
+    storage: &dyn VaultStorage,
+    name: String,
+    password: &str,
+    keyfile: Option<&Path>,
+) -> Result<SecretString, VaultError> {
+    if storage.exists("info") {
+        return Err(VaultError::Invalid(format!("A vault named \"{}\" already exists", name)));
+    }
+
+    let keyfile = match keyfile {
+        Some(path) => Some(read_keyfile(path)?),
+        None => None,
+    };
+
+    debug!(vault = %name, "creating vault");
+
+    let keyfile = keyfile.as_ref().map(|k| k.as_slice());
+    let ( key, recovery_phrase ) = create_vault_info_file(storage, name.clone(), password, keyfile)?;
+    debug!(vault = %name, "created info file");
+
+    create_vault_index_file(storage, &key)?;
+    debug!(vault = %name, "created index file");
+
+    info!(vault = %name, "created vault");
+    Ok(recovery_phrase)
+}
+
+/// Creates the info file and returns the newly generated master key and
+/// recovery phrase of the vault.
+///
+/// `keyfile` is the digest of the keyfile (see
+/// [`read_keyfile`](crate::utils::crypto::read_keyfile)), if the vault uses one.
+pub fn create_vault_info_file(
+    storage: &dyn VaultStorage,
+    name: String,
+    password: &str,
+    keyfile: Option<&[u8]>,
+) -> Result<(VaultKey, SecretString), VaultError> {
+    let key = VaultKey::generate();
+    let recovery_phrase = generate_recovery_phrase()?;
+
+    let info = VaultInfo {
+        name,
+        version: VAULT_VERSION,
+        password: String::default(),
+        key_slots: vec![
+            new_password_slot(1, String::from("Password"), &key, password, keyfile)?,
+            new_recovery_slot(2, &key, &recovery_phrase)?,
+        ],
+    };
+
+    write_vault_info(storage, &info)?;
+
+    Ok(( key, recovery_phrase ))
+}
+
+/// Creates the index file containing an empty, encrypted index.
+pub fn create_vault_index_file(storage: &dyn VaultStorage, key: &VaultKey) -> Result<(), VaultError> {
+    write_index(storage, key, &VaultIndex::default())
+}
+
+/// Argon2 instance used to hash the passwords of vaults created before key
+/// slots were introduced.
+pub fn get_argon<'a>() -> Argon2<'a> {
+    Argon2::new(
+        Algorithm::Argon2id,// Algorithm: Argon2id
+        Version::V0x13,     // Version: 19
+        Params::new(
+            16384,      // m = 16MB
+            8,          // t = 2
+            1,          // p = 1
+            Some(64)    // Output size in bytes
+        ).unwrap()
+    )
+}
+
+/// Verifies the password of a vault created before key slots were introduced.
+fn verify_legacy_password(
+    storage: &dyn VaultStorage,
+    name: &str,
+    info: &VaultInfo,
+    password: &str,
+) -> Result<(), VaultError> {
+    let parsed_hash = match PasswordHash::new(&info.password) {
+        Ok(h) => h,
+        Err(e) => { return Err(VaultError::corrupt("info", e)); }
+    };
+
+    match get_argon().verify_password(password.as_bytes(), &parsed_hash) {
+        Ok(()) => {
+            reset_failed_unlocks(name);
+            Ok(())
+        }
+
+        Err(_) => {
+            on_failed_unlock(storage, name);
+            Err(VaultError::WrongPassword)
+        }
+    }
+}
+
+/// Checks that the name can be used as the name of a vault's directory, so
+/// that it can't be used to reach other directories, e.g. "../other".
+pub fn check_vault_name(name: &str) -> Result<(), VaultError> {
+    let mut components = Path::new(name).components();
+
+    let valid = matches!(
+        ( components.next(), components.next() ),
+        ( Some(Component::Normal(part)), None ) if part == name
+    );
+
+    if valid && !name.trim().is_empty() && !name.contains(['/', '\\', ':']) {
+        Ok(())
+    } else {
+        Err(VaultError::Invalid(format!("\"{}\" can't be the name of a vault", name)))
+    }
+}
+
+/// Returns the path to the vault's directory.
+pub fn get_vault_dir(name: &str) -> Result<PathBuf, VaultError> {
+    check_vault_name(name)?;
+
+    match get_local_dir() {
+        Some(mut path) => {
+            path.push("vaults");
+            path.push(name);
+            Ok(path)
+        }
+
+        None => Err(VaultError::LocalDirNotFound),
+    }
+}
+
+/// Returns the storage of the vault's directory.
+pub fn get_vault_storage(name: &str) -> Result<FileStorage, VaultError> {
+    get_vault_dir(name).map(FileStorage::new)
+}
+
+/// Reads and de-serializes the info file of the vault.
+///
+/// Fails with [`VaultError::UnsupportedVersion`] if the vault was created by a
+/// newer version of the app.
+pub fn read_vault_info(storage: &dyn VaultStorage) -> Result<VaultInfo, VaultError> {
+    let bytes = match storage.read("info") {
+        Ok(bytes) => bytes,
+        Err(e) => { return Err(VaultError::io("Could not read the info file", e)); }
+    };
+
+    let info = match Reader::get_root(bytes.as_slice()) {
+        Ok(reader) => {
+            match VaultInfo::deserialize(reader) {
+                Ok(vault_info) => vault_info,
+                Err(e) => { return Err(VaultError::corrupt("info", e)); }
+            }
+        }
+
+        Err(e) => { return Err(VaultError::corrupt("info", e)); }
+    };
+
+    if info.version > VAULT_VERSION {
+        return Err(VaultError::UnsupportedVersion { version: info.version });
+    }
+
+    Ok(info)
+}
+
+/// Serializes the info and replaces the info file with it atomically, so a
+/// failed write can't leave the vault without its key slots.
+pub(crate) fn write_vault_info(storage: &dyn VaultStorage, info: &VaultInfo) -> Result<(), VaultError> {
+    let mut serializer = FlexbufferSerializer::new();
+
+    if let Err(e) = info.serialize(&mut serializer) {
+        return Err(VaultError::corrupt("info", e));
+    }
+
+    match storage.replace("info", serializer.view()) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(VaultError::io("Error while writing info file", e)),
+    }
+}
+
+/// Merges the key slots of two versions of the info file that were changed on
+/// two devices, `base` being the version they both come from.
+///
+/// Slots are matched by their id. A slot changed on one side only takes that
+/// side's version, and one removed on one side is removed unless the other
+/// side changed it. When both sides changed or added a slot with the same id,
+/// both versions are kept, the other side's under a new id: each still opens
+/// the same master key. Fails if no password would be left to unlock the
+/// vault with, e.g. when each side removed a different one.
+pub(crate) fn merge_vault_info(
+    base: &VaultInfo,
+    ours: VaultInfo,
+    theirs: VaultInfo,
+) -> Result<VaultInfo, VaultError> {
+    let find = |slots: &[KeySlot], id: u32| slots.iter().find(|s| s.id == id).cloned();
+
+    let mut slots = vec![];
+    let mut renamed = vec![];
+
+    for slot in &ours.key_slots {
+        let base_slot = find(&base.key_slots, slot.id);
+
+        match ( base_slot, find(&theirs.key_slots, slot.id) ) {
+            ( _, Some(their) ) if their == *slot => { slots.push(their); }
+            ( Some(base_slot), Some(their) ) if base_slot == *slot => { slots.push(their); }
+            ( Some(base_slot), Some(their) ) if base_slot == their => { slots.push(slot.clone()); }
+
+            // Removed on their side only
+            ( Some(base_slot), None ) if base_slot == *slot => {}
+
+            ( _, None ) => { slots.push(slot.clone()); }
+
+            // Changed or added on both sides
+            ( _, Some(their) ) => {
+                slots.push(slot.clone());
+                renamed.push(their);
+            }
+        }
+    }
+
+    for their in &theirs.key_slots {
+        if ours.key_slots.iter().any(|s| s.id == their.id) {
+            continue;
+        }
+
+        // Kept unless it was removed on this side without being changed on
+        // theirs
+        if find(&base.key_slots, their.id).is_none_or(|base_slot| base_slot != *their) {
+            slots.push(their.clone());
+        }
+    }
+
+    for mut slot in renamed {
+        slot.id = slots.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+        slots.push(slot);
+    }
+
+    slots.sort_by_key(|s| s.id);
+
+    if !slots.iter().any(|s| s.kind == KeySlotKind::Password) {
+        return Err(VaultError::Invalid(String::from(
+            "The passwords of the vault were removed on both devices, none would be left. \
+            Please add a password on this device and sync again"
+        )));
+    }
+
+    Ok(VaultInfo {
+        version: ours.version.max(theirs.version),
+        key_slots: slots,
+        ..ours
+    })
+}
+
+/// Creates a key slot that opens with the password, and the keyfile if its
+/// digest is given.
+fn new_password_slot(
+    id: u32,
+    label: String,
+    key: &VaultKey,
+    password: &str,
+    keyfile: Option<&[u8]>,
+) -> Result<KeySlot, VaultError> {
+    let kdf = KeySlotKdf::default();
+    let salt = generate_salt();
+    let wrapped_key = encrypt(&derive_key(password, keyfile, &salt, &kdf)?, key.as_bytes())?;
+
+    let ( keyfile_salt, keyfile_check ) = match keyfile {
+        Some(digest) => {
+            let keyfile_salt = generate_salt();
+            let check = keyfile_check(digest, &keyfile_salt);
+
+            ( keyfile_salt, check )
+        }
+
+        None => ( vec![], vec![] ),
+    };
+
+    Ok(KeySlot {
+        id,
+        label,
+        kind: KeySlotKind::Password,
+        kdf,
+        salt,
+        wrapped_key,
+        keyfile_salt,
+        keyfile_check,
+        created: unix_now(),
+    })
+}
+
+/// Creates a key slot that opens with the recovery phrase.
+fn new_recovery_slot(id: u32, key: &VaultKey, phrase: &str) -> Result<KeySlot, VaultError> {
+    let salt = generate_salt();
+    let wrapped_key = encrypt(&derive_recovery_key(phrase, &salt)?, key.as_bytes())?;
+
+    Ok(KeySlot {
+        id,
+        label: String::from("Recovery phrase"),
+        kind: KeySlotKind::RecoveryPhrase,
+        kdf: KeySlotKdf::HkdfSha256,
+        salt,
+        wrapped_key,
+        keyfile_salt: vec![],
+        keyfile_check: vec![],
+        created: unix_now(),
+    })
+}
+
+/// Decrypts the master key in the slot, `secret` is the password or the
+/// recovery phrase depending on the slot's kind.
+///
+/// Returns `Ok(None)` when the secret (or keyfile) is wrong.
+fn open_slot(
+    slot: &KeySlot,
+    secret: &str,
+    keyfile: Option<&[u8]>,
+) -> Result<Option<VaultKey>, VaultError> {
+    let slot_key = match slot.kind {
+        KeySlotKind::Password => derive_key(secret, keyfile, &slot.salt, &slot.kdf)?,
+        KeySlotKind::RecoveryPhrase => derive_recovery_key(secret, &slot.salt)?,
+    };
+
+    match decrypt(&slot_key, &slot.wrapped_key) {
+        Ok(key_bytes) => Ok(Some(VaultKey::from_bytes(&Zeroizing::new(key_bytes))?)),
+        Err(VaultError::WrongPassword) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Tries every password slot that could be opened with the given keyfile (or
+/// without one) and returns the master key and the id of the slot that opened.
+///
+/// Failed attempts are recorded. A missing keyfile isn't counted as one, a
+/// wrong keyfile is.
+fn open_password_slots(
+    storage: &dyn VaultStorage,
+    name: &str,
+    info: &VaultInfo,
+    password: &str,
+    keyfile: Option<&Path>,
+) -> Result<(VaultKey, u32), VaultError> {
+    let keyfile = match keyfile {
+        Some(path) if info.uses_keyfile() => Some(read_keyfile(path)?),
+        _ => None,
+    };
+
+    let candidates = info.key_slots.iter()
+        .filter(|s| s.kind == KeySlotKind::Password)
+        .filter(|s| {
+            if !s.uses_keyfile() {
+                return true;
+            }
+
+            match &keyfile {
+                Some(digest) => keyfile_check(digest, &s.keyfile_salt) == s.keyfile_check,
+                None => false,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if candidates.is_empty() {
+        if keyfile.is_none() {
+            return Err(VaultError::KeyfileRequired);
+        }
+
+        on_failed_unlock(storage, name);
+        return Err(VaultError::WrongKeyfile);
+    }
+
+    for slot in candidates {
+        let slot_keyfile = if slot.uses_keyfile() {
+            keyfile.as_ref().map(|k| k.as_slice())
+        } else {
+            None
+        };
+
+        if let Some(key) = open_slot(slot, password, slot_keyfile)? {
+            reset_failed_unlocks(name);
+            debug!(vault = %name, slot = slot.id, "unlocked vault");
+            return Ok(( key, slot.id ));
+        }
+    }
+
+    on_failed_unlock(storage, name);
+    Err(VaultError::WrongPassword)
+}
+
+/// Returns an error if the vault can't be unlocked right now because of too
+/// many failed attempts.
+fn check_lockout(name: &str) -> Result<(), VaultError> {
+    match read_unlock_state(name).retry_at() {
+        Some(retry_at) => Err(VaultError::LockedOut {
+            retry_in: retry_at.saturating_sub(unix_now()),
+        }),
+
+        None => Ok(()),
+    }
+}
+
+/// Records the failed attempt and wipes the key material if the user asked for
+/// it after too many failed attempts.
+fn on_failed_unlock(storage: &dyn VaultStorage, name: &str) {
+    let mut state = record_failed_unlock(name);
+    warn!(vault = %name, attempts = state.failed_attempts, "failed to unlock vault");
+
+    if state.should_wipe() {
+        match wipe_vault_key_material(storage) {
+            Ok(()) => {
+                state.key_material_wiped = true;
+                warn!(vault = %name, "wiped the key material after too many failed unlocks");
+
+                if let Err(e) = write_unlock_state(name, &state) {
+                    warn!(vault = %name, error = %e, "could not record the wipe");
+                }
+            }
+
+            Err(e) => {
+                error!(vault = %name, error = %e, "could not wipe the key material");
+            }
+        }
+    }
+}
+
+/// Wipes the vault's key material from the storage by overwriting and then
+/// deleting the info file.
+///
+/// The notes can't be decrypted anymore unless the info file is restored from
+/// a backup.
+pub fn wipe_vault_key_material(storage: &dyn VaultStorage) -> Result<(), VaultError> {
+    let length = match storage.read("info") {
+        Ok(bytes) => bytes.len(),
+        Err(e) => { return Err(VaultError::io("Couldn't read info file", e)); }
+    };
+
+    if let Err(e) = storage.write("info", &vec![0u8; length]) {
+        warn!(error = %e, "could not overwrite the info file");
+    }
+
+    match storage.delete("info") {
+        Ok(()) => Ok(()),
+        Err(e) => Err(VaultError::io("Couldn't remove info file", e)),
+    }
+}
+
+/// Unlocks the vault and returns its master key.
+///
+/// `name` is only used to keep track of failed attempts on this device.
+/// `keyfile` is only needed if the vault was set up with one.
+///
+/// Vaults created before key slots were introduced only have a password hash.
+/// For these, a master key is generated and stored in a new key slot once the
+/// password is verified.
+pub fn unlock_vault(
+    storage: &dyn VaultStorage,
+    name: &str,
+    password: &str,
+    keyfile: Option<&Path>,
+) -> Result<VaultKey, VaultError> {
+    check_lockout(name)?;
+
+    let mut info = read_vault_info(storage)?;
+
+    if !info.key_slots.is_empty() {
+        return open_password_slots(storage, name, &info, password, keyfile).map(|( key, _ )| key);
+    }
+
+    verify_legacy_password(storage, name, &info, password)?;
+
+    let key = VaultKey::generate();
+    info.key_slots.push(new_password_slot(1, String::from("Password"), &key, password, None)?);
+    info.password = String::default();
+    info.version = VAULT_VERSION;
+
+    write_vault_info(storage, &info)?;
+    write_index(storage, &key, &VaultIndex::default())?;
+
+    info!(vault = %name, "migrated the vault's password to a key slot");
+    Ok(key)
+}
+
+/// Changes the password of the key slot that opens with the current password
+/// (and keyfile), and enrolls, replaces or removes its keyfile.
+///
+/// The current password is asked again before anything is changed. The
+/// master key stays the same, so the notes don't have to be encrypted again.
+pub fn change_vault_password(
+    storage: &dyn VaultStorage,
+    name: &str,
+    current_password: &str,
+    current_keyfile: Option<&Path>,
+    new_password: &str,
+    new_keyfile: Option<&Path>,
+) -> Result<(), VaultError> {
+    check_lockout(name)?;
+
+    let mut info = read_vault_info(storage)?;
+    let ( key, slot_id ) = open_password_slots(
+        storage, name, &info, current_password, current_keyfile,
+    )?;
+
+    let new_keyfile = match new_keyfile {
+        Some(path) => Some(read_keyfile(path)?),
+        None => None,
+    };
+
+    for slot in info.key_slots.iter_mut() {
+        if slot.id == slot_id {
+            *slot = new_password_slot(
+                slot_id,
+                slot.label.clone(),
+                &key,
+                new_password,
+                new_keyfile.as_ref().map(|k| k.as_slice()),
+            )?;
+        }
+    }
+
+    write_vault_info(storage, &info)?;
+    info!(vault = %name, slot = slot_id, "changed password");
+    Ok(())
+}
+
+/// Unlocks the vault with its recovery phrase and sets a new password.
+///
+/// If the vault has a single password, it's replaced by the new one along with
+/// its keyfile, which may have been lost as well. Otherwise the new password
+/// is added as a new key slot, and the forgotten one can be removed from the
+/// vault settings. The recovery phrase stays the same.
+pub fn recover_vault(
+    storage: &dyn VaultStorage,
+    name: &str,
+    phrase: &str,
+    new_password: &str,
+) -> Result<VaultKey, VaultError> {
+    check_lockout(name)?;
+
+    let mut info = read_vault_info(storage)?;
+
+    if !info.has_recovery_phrase() {
+        return Err(VaultError::Invalid(String::from("This vault doesn't have a recovery phrase")));
+    }
+
+    let mut recovered_key = None;
+
+    for slot in info.key_slots.iter().filter(|s| s.kind == KeySlotKind::RecoveryPhrase) {
+        if let Some(key) = open_slot(slot, phrase, None)? {
+            recovered_key = Some(key);
+            break;
+        }
+    }
+
+    let key = match recovered_key {
+        Some(key) => key,
+        None => {
+            on_failed_unlock(storage, name);
+            return Err(VaultError::InvalidRecoveryPhrase(String::from("Wrong recovery phrase")));
+        }
+    };
+
+    if info.password_slots() == 1 {
+        for slot in info.key_slots.iter_mut() {
+            if slot.kind == KeySlotKind::Password {
+                *slot = new_password_slot(slot.id, slot.label.clone(), &key, new_password, None)?;
+            }
+        }
+    } else {
+        let id = info.next_slot_id();
+        info.key_slots.push(
+            new_password_slot(id, String::from("Recovered password"), &key, new_password, None)?
+        );
+    }
+
+    write_vault_info(storage, &info)?;
+    reset_failed_unlocks(name);
+    info!(vault = %name, "recovered vault with its recovery phrase");
+
+    Ok(key)
+}
+
+/// Adds a key slot that opens the unlocked vault with another password, and
+/// optionally a keyfile.
+pub fn add_key_slot(
+    storage: &dyn VaultStorage,
+    key: &VaultKey,
+    label: &str,
+    password: &str,
+    keyfile: Option<&Path>,
+) -> Result<(), VaultError> {
+    let label = label.trim();
+
+    if label.is_empty() {
+        return Err(VaultError::Invalid(String::from("Label cannot be empty")));
+    }
+
+    // Make sure the key really is the vault's master key before storing it
+    if let Err(e) = read_index(storage, key) {
+        return Err(VaultError::crypto("The vault's key couldn't be verified", e));
+    }
+
+    let keyfile = match keyfile {
+        Some(path) => Some(read_keyfile(path)?),
+        None => None,
+    };
+
+    let mut info = read_vault_info(storage)?;
+    let id = info.next_slot_id();
+
+    info.key_slots.push(new_password_slot(
+        id,
+        label.to_string(),
+        key,
+        password,
+        keyfile.as_ref().map(|k| k.as_slice()),
+    )?);
+
+    write_vault_info(storage, &info)?;
+    info!(vault = %info.name, slot = id, "added key slot");
+    Ok(())
+}
+
+/// Removes the key slot with the given id.
+///
+/// The last password slot can never be removed, otherwise the vault could only
+/// be opened with the recovery phrase, if at all.
+///
+/// The slot stays in the history of a vault kept in git, where its password
+/// still opens the master key (see [`git`](crate::utils::git)).
+pub fn remove_key_slot(storage: &dyn VaultStorage, id: u32) -> Result<(), VaultError> {
+    let mut info = read_vault_info(storage)?;
+
+    let slot = match info.key_slots.iter().find(|s| s.id == id) {
+        Some(s) => s,
+        None => { return Err(VaultError::Invalid(String::from("Key slot not found"))); }
+    };
+
+    if info.key_slots.len() == 1
+        || (slot.kind == KeySlotKind::Password && info.password_slots() == 1) {
+        return Err(VaultError::Invalid(String::from(
+            "The last password of the vault can't be removed"
+        )));
+    }
+
+    info.key_slots.retain(|s| s.id != id);
+
+    write_vault_info(storage, &info)?;
+    info!(vault = %info.name, slot = id, "removed key slot");
+    Ok(())
+}
+
+/// Returns the names of all the vaults in the local directory, sorted.
+pub fn list_vaults() -> Result<Vec<String>, VaultError> {
+    let mut vaults_dir;
+
+    match get_local_dir() {
+        Some(path) => { vaults_dir = path; }
+        None => { return Err(VaultError::LocalDirNotFound); }
+    }
+
+    vaults_dir.push("vaults");
+
+    if !vaults_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    match read_dir(&vaults_dir) {
+        Ok(entries) => {
+            let mut names = entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|name| vault_exists(name))
+                .collect::<Vec<_>>();
+
+            names.sort();
+
+            Ok(names)
+        }
+
+        Err(e) => Err(VaultError::io("Couldn't read the vaults directory", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ env, fs, process };
+
+    use super::*;
+    use crate::utils::{
+        set_data_dir, storage::MemoryStorage, unlock_state::{ UnlockState, set_wipe_after },
+    };
+
+    /// A vault in memory with the password "password", and its recovery
+    /// phrase. `name` keeps track of its failed unlocks, it's unique to the
+    /// test.
+    fn memory_vault(name: &str, keyfile: Option<&Path>) -> ( MemoryStorage, SecretString ) {
+        let _ = set_data_dir(&env::temp_dir().join(format!("secure-notes-test-{}", process::id())));
+        write_unlock_state(name, &UnlockState::default()).unwrap();
+
+        let storage = MemoryStorage::new();
+        let phrase = create_vault(&storage, name.to_string(), "password", keyfile).unwrap();
+
+        ( storage, phrase )
+    }
+
+    fn slot(id: u32, label: &str, kind: KeySlotKind) -> KeySlot {
+        KeySlot {
+            id,
+            label: label.to_string(),
+            kind,
+            kdf: KeySlotKdf::HkdfSha256,
+            salt: vec![ id as u8 ],
+            wrapped_key: vec![ id as u8 ],
+            keyfile_salt: vec![],
+            keyfile_check: vec![],
+            created: 10,
+        }
+    }
+
+    fn password(id: u32, label: &str) -> KeySlot {
+        slot(id, label, KeySlotKind::Password)
+    }
+
+    fn info(version: u32, key_slots: Vec<KeySlot>) -> VaultInfo {
+        VaultInfo { name: String::from("Vault"), version, password: String::new(), key_slots }
+    }
+
+    fn labels(info: &VaultInfo) -> Vec<( u32, &str )> {
+        info.key_slots.iter().map(|s| ( s.id, s.label.as_str() )).collect()
+    }
+
+    #[test]
+    fn merge_vault_info_takes_the_changes_of_each_side() {
+        let base = info(1, vec![ password(1, "Alice"), password(2, "Bob"), password(3, "Carol") ]);
+
+        // Bob's changed here, Carol's removed there
+        let ours = info(1, vec![ password(1, "Alice"), password(2, "Bob, changed"), password(3, "Carol") ]);
+        let theirs = info(1, vec![ password(1, "Alice"), password(2, "Bob") ]);
+
+        let merged = merge_vault_info(&base, ours, theirs).unwrap();
+
+        assert_eq!(labels(&merged), vec![ ( 1, "Alice" ), ( 2, "Bob, changed" ) ]);
+    }
+
+    #[test]
+    fn merge_vault_info_keeps_slots_added_or_changed_on_both_sides() {
+        let base = info(1, vec![ password(1, "Alice") ]);
+
+        let ours = info(1, vec![ password(1, "Alice, here"), password(2, "Bob") ]);
+        let theirs = info(1, vec![ password(1, "Alice, there"), password(2, "Carol") ]);
+
+        let merged = merge_vault_info(&base, ours, theirs).unwrap();
+
+        assert_eq!(labels(&merged), vec![
+            ( 1, "Alice, here" ), ( 2, "Bob" ), ( 3, "Alice, there" ), ( 4, "Carol" ),
+        ]);
+    }
+
+    #[test]
+    fn merge_vault_info_keeps_removed_slots_changed_on_the_other_side() {
+        let base = info(1, vec![ password(1, "Alice"), password(2, "Bob") ]);
+
+        let ours = info(1, vec![ password(1, "Alice") ]);
+        let theirs = info(1, vec![ password(1, "Alice"), password(2, "Bob, changed") ]);
+
+        let merged = merge_vault_info(&base, ours, theirs).unwrap();
+
+        assert_eq!(labels(&merged), vec![ ( 1, "Alice" ), ( 2, "Bob, changed" ) ]);
+    }
+
+    #[test]
+    fn merge_vault_info_keeps_slots_added_on_the_other_side() {
+        let base = info(0, vec![ password(1, "Alice") ]);
+
+        let ours = info(0, vec![ password(1, "Alice") ]);
+        let theirs = info(1, vec![ password(1, "Alice"), slot(2, "Recovery", KeySlotKind::RecoveryPhrase) ]);
+
+        let merged = merge_vault_info(&base, ours, theirs).unwrap();
+
+        assert_eq!(labels(&merged), vec![ ( 1, "Alice" ), ( 2, "Recovery" ) ]);
+        assert_eq!(merged.version, 1);
+        assert!(merged.has_recovery_phrase());
+    }
+
+    #[test]
+    fn merge_vault_info_fails_without_a_password() {
+        let recovery = slot(3, "Recovery", KeySlotKind::RecoveryPhrase);
+        let base = info(1, vec![ password(1, "Alice"), password(2, "Bob"), recovery.clone() ]);
+
+        // Each side removed another password
+        let ours = info(1, vec![ password(2, "Bob"), recovery.clone() ]);
+        let theirs = info(1, vec![ password(1, "Alice"), recovery ]);
+
+        assert!(matches!(merge_vault_info(&base, ours, theirs), Err(VaultError::Invalid(_))));
+    }
+
+    #[test]
+    fn wipes_the_key_material_after_the_chosen_failed_attempts() {
+        let name = "vault-test-wipe";
+        let ( storage, _ ) = memory_vault(name, None);
+        set_wipe_after(name, Some(2)).unwrap();
+
+        assert!(matches!(unlock_vault(&storage, name, "wrong", None), Err(VaultError::WrongPassword)));
+        assert!(storage.exists("info"));
+
+        assert!(matches!(unlock_vault(&storage, name, "wrong", None), Err(VaultError::WrongPassword)));
+        assert!(!storage.exists("info"));
+        assert!(read_unlock_state(name).key_material_wiped);
+    }
+
+    #[test]
+    fn unlocks_with_the_password_and_the_keyfile() {
+        let dir = env::temp_dir().join(format!("secure-notes-test-{}-keyfile", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let keyfile = dir.join("keyfile");
+        let wrong = dir.join("wrong");
+        fs::write(&keyfile, b"keyfile").unwrap();
+        fs::write(&wrong, b"another file").unwrap();
+
+        let name = "vault-test-keyfile";
+        let ( storage, _ ) = memory_vault(name, Some(&keyfile));
+
+        let key = unlock_vault(&storage, name, "password", Some(&keyfile)).unwrap();
+        assert!(read_index(&storage, &key).is_ok());
+
+        assert!(matches!(unlock_vault(&storage, name, "password", None), Err(VaultError::KeyfileRequired)));
+        assert!(matches!(unlock_vault(&storage, name, "password", Some(&dir.join("missing"))), Err(VaultError::Io { .. })));
+        assert!(matches!(unlock_vault(&storage, name, "password", Some(&wrong)), Err(VaultError::WrongKeyfile)));
+        assert!(matches!(unlock_vault(&storage, name, "wrong", Some(&keyfile)), Err(VaultError::WrongPassword)));
+
+        // A missing keyfile isn't a failed attempt, a wrong one is
+        assert_eq!(read_unlock_state(name).failed_attempts, 2);
+
+        // The keyfile is removed with the password change
+        change_vault_password(&storage, name, "password", Some(&keyfile), "password", None).unwrap();
+        assert!(!read_vault_info(&storage).unwrap().uses_keyfile());
+        assert_eq!(unlock_vault(&storage, name, "password", None).unwrap().as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn recovers_the_vault_with_its_recovery_phrase() {
+        let name = "vault-test-recovery";
+        let ( storage, phrase ) = memory_vault(name, None);
+        let key = unlock_vault(&storage, name, "password", None).unwrap();
+
+        let result = recover_vault(&storage, name, "not a phrase", "new password");
+        assert!(matches!(result, Err(VaultError::InvalidRecoveryPhrase(_))));
+
+        let result = recover_vault(&storage, name, &generate_recovery_phrase().unwrap(), "new password");
+        assert!(matches!(result, Err(VaultError::InvalidRecoveryPhrase(_))));
+        assert_eq!(read_unlock_state(name).failed_attempts, 1);
+
+        let recovered = recover_vault(&storage, name, &phrase, "new password").unwrap();
+        assert_eq!(recovered.as_bytes(), key.as_bytes());
+        assert_eq!(read_unlock_state(name).failed_attempts, 0);
+
+        // The only password was replaced, the phrase still works
+        assert!(matches!(unlock_vault(&storage, name, "password", None), Err(VaultError::WrongPassword)));
+        assert!(unlock_vault(&storage, name, "new password", None).is_ok());
+        assert!(recover_vault(&storage, name, &phrase, "newer password").is_ok());
+    }
+
+    #[test]
+    fn adds_and_removes_key_slots() {
+        let name = "vault-test-key-slots";
+        let ( storage, _ ) = memory_vault(name, None);
+        let key = unlock_vault(&storage, name, "password", None).unwrap();
+
+        assert!(matches!(add_key_slot(&storage, &key, "  ", "other", None), Err(VaultError::Invalid(_))));
+        assert!(add_key_slot(&storage, &VaultKey::generate(), "Bob", "other", None).is_err());
+
+        add_key_slot(&storage, &key, "Bob", "other", None).unwrap();
+        let info = read_vault_info(&storage).unwrap();
+        assert_eq!(labels(&info), vec![ ( 1, "Password" ), ( 2, "Recovery phrase" ), ( 3, "Bob" ) ]);
+        assert_eq!(unlock_vault(&storage, name, "other", None).unwrap().as_bytes(), key.as_bytes());
+
+        remove_key_slot(&storage, 1).unwrap();
+        assert!(matches!(unlock_vault(&storage, name, "password", None), Err(VaultError::WrongPassword)));
+
+        // Neither the last password nor a slot that doesn't exist
+        assert!(matches!(remove_key_slot(&storage, 3), Err(VaultError::Invalid(_))));
+        assert!(matches!(remove_key_slot(&storage, 9), Err(VaultError::Invalid(_))));
+
+        remove_key_slot(&storage, 2).unwrap();
+        assert_eq!(labels(&read_vault_info(&storage).unwrap()), vec![ ( 3, "Bob" ) ]);
+        assert!(matches!(remove_key_slot(&storage, 3), Err(VaultError::Invalid(_))));
+    }
+
+    #[test]
+    fn vault_names_stay_inside_the_vaults_directory() {
+        for name in [ "personal", "Work notes", "café", ".hidden", "a..b" ] {
+            assert!(check_vault_name(name).is_ok(), "{}", name);
+        }
+
+        for name in [ "", " ", ".", "..", "../other", "a/b", "a\\b", "/etc", "C:", "C:\\vault", "a/" ] {
+            assert!(check_vault_name(name).is_err(), "{}", name);
+            assert!(get_vault_storage(name).is_err(), "{}", name);
+        }
+    }
+}
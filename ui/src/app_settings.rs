@@ -0,0 +1,391 @@
+///
+/// Settings of the app.
+///
+/// Changes are applied right away and saved to the settings file, see
+/// [`secure_notes_core::utils::settings`]. Values that are typed in are only
+/// applied once they're valid.
+///
+use iced::{
+    Element, Center, Fill, Color, Task,
+    widget::{ column, row, text, Space, button, text_input, checkbox, slider, scrollable },
+};
+
+use crate::{
+    types::settings::{ Settings, EditorSettings, HexColor },
+    utils::settings::{ read_settings, write_settings },
+};
+
+#[derive(Debug, Clone)]
+pub enum AppSettingsMessage {
+    ExplorerRatioChanged(f64),
+    ExplorerRatioReleased,
+    TextSizeChanged(String),
+    AutosaveToggled(bool),
+    AutosaveDelayChanged(String),
+    IdleTimeoutChanged(String),
+    LockOnMinimizeToggled(bool),
+    LockOnFocusLossToggled(bool),
+    ColorChanged(ColorSetting, String),
+    ResetToDefaults,
+    Close,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSetting {
+    Background,
+    EditorBackground,
+    TitleBarBackground,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum AppSettingsStatus {
+    #[default]
+    None,
+
+    Error(String),
+}
+
+#[derive(Debug, Default)]
+pub struct AppSettings {
+    settings: Settings,
+
+    // Values as typed in, which may not be valid yet
+    text_size: String,
+    autosave_delay: String,
+    idle_timeout: String,
+    background: String,
+    editor_background: String,
+    title_bar_background: String,
+
+    /// Problems found in the settings file when it was read
+    problems: Vec<String>,
+
+    pub status: AppSettingsStatus,
+}
+
+impl AppSettings {
+    /// Reads the settings file again, in case it was edited by hand.
+    pub fn load(&mut self) {
+        let ( settings, problems ) = read_settings();
+
+        self.set_settings(settings);
+        self.problems = problems;
+        self.status = AppSettingsStatus::None;
+    }
+
+    /// The settings as they're currently applied.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    fn set_settings(&mut self, settings: Settings) {
+        self.text_size = settings.editor.text_size.to_string();
+        self.autosave_delay = settings.editor.autosave_delay.to_string();
+        self.idle_timeout = settings.auto_lock.idle_timeout.to_string();
+        self.background = settings.appearance.background.to_string();
+        self.editor_background = settings.appearance.editor_background.to_string();
+        self.title_bar_background = settings.appearance.title_bar_background.to_string();
+        self.settings = settings;
+    }
+
+    pub fn view(&self) -> Element<'_, AppSettingsMessage> {
+        let editor = &self.settings.editor;
+        let auto_lock = &self.settings.auto_lock;
+
+        let mut settings_column = column![
+            text("Settings")
+                .width(Fill)
+                .size(32)
+                .align_x(Center),
+            Space::new(Fill, 20),
+        ];
+
+        if !self.problems.is_empty() {
+            settings_column = settings_column.push(
+                text("Some settings in the settings file were invalid and were reset:")
+                    .color(Color::new(0.9, 0.0, 0.0, 1.0))
+            );
+
+            for problem in &self.problems {
+                settings_column = settings_column.push(
+                    text!("- {}", problem)
+                        .size(14)
+                        .color(Color::new(0.9, 0.0, 0.0, 1.0))
+                );
+            }
+
+            settings_column = settings_column.push(Space::new(Fill, 20));
+        }
+
+        settings_column = settings_column.extend([
+            text("Editor").size(24).into(),
+            Space::new(Fill, 10).into(),
+            text!("Explorer width: {:.0}%", editor.explorer_ratio * 100.0).into(),
+            slider(
+                EditorSettings::EXPLORER_RATIOS,
+                editor.explorer_ratio,
+                AppSettingsMessage::ExplorerRatioChanged,
+            )
+                .step(0.01)
+                .on_release(AppSettingsMessage::ExplorerRatioReleased)
+                .width(300)
+                .into(),
+            Space::new(Fill, 10).into(),
+            setting_input(
+                "Text size",
+                &self.text_size,
+                AppSettingsMessage::TextSizeChanged,
+            ),
+            Space::new(Fill, 10).into(),
+            checkbox("Save notes automatically", editor.autosave)
+                .on_toggle(AppSettingsMessage::AutosaveToggled)
+                .into(),
+            Space::new(Fill, 10).into(),
+            setting_input(
+                "Seconds after the last change",
+                &self.autosave_delay,
+                AppSettingsMessage::AutosaveDelayChanged,
+            ),
+            Space::new(Fill, 20).into(),
+            text("Locking").size(24).into(),
+            Space::new(Fill, 10).into(),
+            setting_input(
+                "Lock after minutes without activity (0 for never)",
+                &self.idle_timeout,
+                AppSettingsMessage::IdleTimeoutChanged,
+            ),
+            Space::new(Fill, 10).into(),
+            checkbox("Lock when the window is minimized", auto_lock.lock_on_minimize)
+                .on_toggle(AppSettingsMessage::LockOnMinimizeToggled)
+                .into(),
+            Space::new(Fill, 10).into(),
+            checkbox("Lock when the window loses focus", auto_lock.lock_on_focus_loss)
+                .on_toggle(AppSettingsMessage::LockOnFocusLossToggled)
+                .into(),
+            Space::new(Fill, 20).into(),
+            text("Colors").size(24).into(),
+            Space::new(Fill, 10).into(),
+            setting_input("Background", &self.background, |c| {
+                AppSettingsMessage::ColorChanged(ColorSetting::Background, c)
+            }),
+            Space::new(Fill, 10).into(),
+            setting_input("Note background", &self.editor_background, |c| {
+                AppSettingsMessage::ColorChanged(ColorSetting::EditorBackground, c)
+            }),
+            Space::new(Fill, 10).into(),
+            setting_input("Explorer title background", &self.title_bar_background, |c| {
+                AppSettingsMessage::ColorChanged(ColorSetting::TitleBarBackground, c)
+            }),
+        ]);
+
+        if let AppSettingsStatus::Error(e) = &self.status {
+            settings_column = settings_column.push(Space::new(Fill, 10));
+            settings_column = settings_column.push(
+                text(e).color(Color::new(0.9, 0.0, 0.0, 1.0))
+            );
+        }
+
+        settings_column = settings_column.push(Space::new(Fill, 20));
+        settings_column = settings_column.push(row![
+            button(text("Reset to Defaults"))
+                .style(button::secondary)
+                .on_press(AppSettingsMessage::ResetToDefaults),
+            Space::new(10, 0),
+            button(text("Back"))
+                .style(button::secondary)
+                .on_press(AppSettingsMessage::Close),
+        ]);
+
+        scrollable(
+            settings_column
+                .padding(40)
+                .max_width(800)
+        )
+            .width(Fill)
+            .into()
+    }
+
+    pub fn update(&mut self, message: AppSettingsMessage) -> Task<AppSettingsMessage> {
+        let mut settings = self.settings.clone();
+
+        match message {
+            AppSettingsMessage::ExplorerRatioChanged(ratio) => {
+                // Saved once the slider is released
+                settings.editor.explorer_ratio = ratio;
+                self.apply(settings, false);
+                return Task::none();
+            }
+
+            AppSettingsMessage::ExplorerRatioReleased => {}
+
+            AppSettingsMessage::TextSizeChanged(value) => {
+                match value.trim().parse() {
+                    Ok(size) => { settings.editor.text_size = size; }
+                    Err(_) => {
+                        self.text_size = value;
+                        self.status = AppSettingsStatus::Error(
+                            String::from("Text size must be a number")
+                        );
+                        return Task::none();
+                    }
+                }
+
+                self.text_size = value;
+            }
+
+            AppSettingsMessage::AutosaveToggled(autosave) => {
+                settings.editor.autosave = autosave;
+            }
+
+            AppSettingsMessage::AutosaveDelayChanged(value) => {
+                match value.trim().parse() {
+                    Ok(delay) => { settings.editor.autosave_delay = delay; }
+                    Err(_) => {
+                        self.autosave_delay = value;
+                        self.status = AppSettingsStatus::Error(
+                            String::from("Autosave delay must be a number")
+                        );
+                        return Task::none();
+                    }
+                }
+
+                self.autosave_delay = value;
+            }
+
+            AppSettingsMessage::IdleTimeoutChanged(value) => {
+                match value.trim().parse() {
+                    Ok(minutes) => { settings.auto_lock.idle_timeout = minutes; }
+                    Err(_) => {
+                        self.idle_timeout = value;
+                        self.status = AppSettingsStatus::Error(
+                            String::from("Lock timeout must be a number")
+                        );
+                        return Task::none();
+                    }
+                }
+
+                self.idle_timeout = value;
+            }
+
+            AppSettingsMessage::LockOnMinimizeToggled(lock) => {
+                settings.auto_lock.lock_on_minimize = lock;
+            }
+
+            AppSettingsMessage::LockOnFocusLossToggled(lock) => {
+                settings.auto_lock.lock_on_focus_loss = lock;
+            }
+
+            AppSettingsMessage::ColorChanged(setting, value) => {
+                let color = value.parse::<HexColor>();
+
+                match setting {
+                    ColorSetting::Background => { self.background = value; }
+                    ColorSetting::EditorBackground => { self.editor_background = value; }
+                    ColorSetting::TitleBarBackground => { self.title_bar_background = value; }
+                }
+
+                let color = match color {
+                    Ok(c) => c,
+                    Err(e) => {
+                        self.status = AppSettingsStatus::Error(e);
+                        return Task::none();
+                    }
+                };
+
+                let appearance = &mut settings.appearance;
+
+                match setting {
+                    ColorSetting::Background => { appearance.background = color; }
+                    ColorSetting::EditorBackground => { appearance.editor_background = color; }
+                    ColorSetting::TitleBarBackground => { appearance.title_bar_background = color; }
+                }
+            }
+
+            AppSettingsMessage::ResetToDefaults => {
+                self.set_settings(Settings::default());
+                settings = Settings::default();
+            }
+
+            AppSettingsMessage::Close => { return Task::none(); }
+        }
+
+        self.apply(settings, true);
+
+        Task::none()
+    }
+
+    /// Applies the settings if they're valid, and saves them.
+    fn apply(&mut self, settings: Settings, save: bool) {
+        let mut validated = settings.clone();
+
+        if let Some(problem) = validated.validate().into_iter().next() {
+            self.status = AppSettingsStatus::Error(problem);
+            return;
+        }
+
+        self.settings = settings;
+        self.status = AppSettingsStatus::None;
+
+        if save {
+            if let Err(e) = write_settings(&self.settings) {
+                self.status = AppSettingsStatus::Error(e.to_string());
+            }
+        }
+    }
+}
+
+/// A labelled text input for a setting.
+fn setting_input<'a>(
+    label: &'a str,
+    value: &'a str,
+    on_input: impl Fn(String) -> AppSettingsMessage + 'a,
+) -> Element<'a, AppSettingsMessage> {
+    row![
+        text(label).width(Fill),
+        text_input("", value)
+            .width(150)
+            .on_input(on_input),
+    ]
+        .align_y(Center)
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_settings_until_the_typed_value_is_valid() {
+        let mut app_settings = AppSettings::default();
+        app_settings.set_settings(Settings::default());
+
+        let _ = app_settings.update(AppSettingsMessage::TextSizeChanged(String::from("1x")));
+        assert_eq!(app_settings.text_size, "1x");
+        assert_eq!(app_settings.status, AppSettingsStatus::Error(String::from("Text size must be a number")));
+
+        // A number, but too large
+        let _ = app_settings.update(AppSettingsMessage::TextSizeChanged(String::from("100")));
+        assert_eq!(app_settings.text_size, "100");
+        assert!(matches!(app_settings.status, AppSettingsStatus::Error(_)));
+
+        let _ = app_settings.update(AppSettingsMessage::ColorChanged(ColorSetting::Background, String::from("#12")));
+        assert_eq!(app_settings.background, "#12");
+        assert!(matches!(app_settings.status, AppSettingsStatus::Error(_)));
+
+        assert_eq!(app_settings.settings(), &Settings::default());
+    }
+
+    #[test]
+    fn applies_the_explorer_ratio_while_it_is_dragged() {
+        let mut app_settings = AppSettings::default();
+        app_settings.set_settings(Settings::default());
+
+        let _ = app_settings.update(AppSettingsMessage::ExplorerRatioChanged(0.4));
+        assert_eq!(app_settings.settings().editor.explorer_ratio, 0.4);
+        assert_eq!(app_settings.status, AppSettingsStatus::None);
+
+        let _ = app_settings.update(AppSettingsMessage::ExplorerRatioChanged(0.95));
+        assert_eq!(app_settings.settings().editor.explorer_ratio, 0.4);
+        assert!(matches!(app_settings.status, AppSettingsStatus::Error(_)));
+    }
+}
@@ -0,0 +1,329 @@
+///
+/// Screen to change the password of the opened vault.
+///
+/// The same screen is used to add, replace or remove the vault's keyfile. The
+/// current password is asked again before anything is changed.
+///
+use std::path::PathBuf;
+
+use iced::{
+    Element, Center, Fill, Color, Task,
+    widget::{ column, row, text, container, Space, button, text_input },
+};
+
+use zeroize::Zeroize;
+
+use crate::{
+    dialogs::{ pick_keyfile, create_keyfile },
+    utils::{
+        crypto::SecretString,
+        git::commit_vault,
+        vault::{ change_vault_password, get_vault_storage },
+    },
+};
+
+#[derive(Debug, Clone)]
+pub enum ChangePasswordMessage {
+    CurrentPasswordChanged(String),
+    NewPasswordChanged(String),
+    ConfirmPasswordChanged(String),
+    PickKeyfile,
+    CreateKeyfile,
+    KeyfileChosen(Result<Option<PathBuf>, String>),
+    RemoveKeyfile,
+    Submit,
+    Done(Result<(), String>),
+    Close,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum ChangePasswordStatus {
+    #[default]
+    None,
+
+    /// When the password is being changed
+    Loading,
+
+    /// When the password was changed
+    Changed,
+
+    Error(String),
+}
+
+#[derive(Debug, Default)]
+pub struct ChangePassword {
+    current_password: SecretString,
+    new_password: SecretString,
+    confirm_password: SecretString,
+
+    /// Keyfile the vault was unlocked with
+    current_keyfile: Option<PathBuf>,
+
+    /// Keyfile the vault will need once the password is changed
+    pub keyfile: Option<PathBuf>,
+    pub status: ChangePasswordStatus,
+}
+
+impl ChangePassword {
+    /// Resets the screen, `keyfile` is the keyfile the vault currently uses.
+    pub fn reset(&mut self, keyfile: Option<PathBuf>) {
+        self.current_password.zeroize();
+        self.new_password.zeroize();
+        self.confirm_password.zeroize();
+        self.current_keyfile = keyfile.clone();
+        self.keyfile = keyfile;
+        self.status = ChangePasswordStatus::None;
+    }
+
+    pub fn view(&self) -> Element<'_, ChangePasswordMessage> {
+        let title = text("Change Password")
+            .width(Fill)
+            .size(32)
+            .align_x(Center);
+
+        let password_input = |
+            placeholder,
+            value: &SecretString,
+            on_input: fn(String) -> ChangePasswordMessage,
+        | {
+            container(
+                text_input(placeholder, value)
+                    .secure(true)
+                    .width(300)
+                    .on_input(on_input)
+                    .on_submit(ChangePasswordMessage::Submit)
+            )
+                .align_x(Center)
+                .width(Fill)
+        };
+
+        let keyfile_text = match &self.keyfile {
+            Some(path) => format!("Keyfile: {}", path.display()),
+            None => String::from("No keyfile"),
+        };
+
+        let mut keyfile_buttons = row![
+            button(text("Choose Keyfile"))
+                .style(button::secondary)
+                .on_press(ChangePasswordMessage::PickKeyfile),
+            Space::new(10, 0),
+            button(text("Create New Keyfile"))
+                .style(button::secondary)
+                .on_press(ChangePasswordMessage::CreateKeyfile),
+        ];
+
+        if self.keyfile.is_some() {
+            keyfile_buttons = keyfile_buttons.push(Space::new(10, 0));
+            keyfile_buttons = keyfile_buttons.push(
+                button(text("Remove Keyfile"))
+                    .style(button::secondary)
+                    .on_press(ChangePasswordMessage::RemoveKeyfile)
+            );
+        }
+
+        let mut cols = column![
+            Space::new(Fill, 60),
+            title,
+            Space::new(Fill, 20),
+            password_input(
+                "Current Password",
+                &self.current_password,
+                ChangePasswordMessage::CurrentPasswordChanged,
+            ),
+            Space::new(Fill, 10),
+            password_input(
+                "New Password",
+                &self.new_password,
+                ChangePasswordMessage::NewPasswordChanged,
+            ),
+            Space::new(Fill, 10),
+            password_input(
+                "Confirm New Password",
+                &self.confirm_password,
+                ChangePasswordMessage::ConfirmPasswordChanged,
+            ),
+            Space::new(Fill, 20),
+            text(keyfile_text)
+                .width(Fill)
+                .align_x(Center),
+            Space::new(Fill, 10),
+            container(keyfile_buttons)
+                .align_x(Center)
+                .width(Fill),
+            Space::new(Fill, 20),
+            container(row![
+                button(text("Change Password"))
+                    .style(button::primary)
+                    .on_press_maybe(match self.status {
+                        ChangePasswordStatus::Loading => None,
+                        _ => Some(ChangePasswordMessage::Submit),
+                    }),
+                Space::new(10, 0),
+                button(text("Back"))
+                    .style(button::secondary)
+                    .on_press(ChangePasswordMessage::Close),
+            ])
+                .align_x(Center)
+                .width(Fill),
+        ];
+
+        match &self.status {
+            ChangePasswordStatus::None => {}
+
+            ChangePasswordStatus::Loading => {
+                cols = cols.push(Space::new(Fill, 16));
+                cols = cols.push(
+                    text("Please wait...")
+                        .align_x(Center)
+                        .width(Fill)
+                );
+            }
+
+            ChangePasswordStatus::Changed => {
+                cols = cols.push(Space::new(Fill, 16));
+                cols = cols.push(
+                    text("The password was changed.")
+                        .align_x(Center)
+                        .width(Fill)
+                );
+            }
+
+            ChangePasswordStatus::Error(e) => {
+                cols = cols.push(Space::new(Fill, 16));
+                cols = cols.push(
+                    text(e)
+                        .align_x(Center)
+                        .width(Fill)
+                        .color(Color::new(0.9, 0.0, 0.0, 1.0))
+                );
+            }
+        }
+
+        cols.into()
+    }
+
+    /// Updates the screen, `vault_name` is the opened vault.
+    pub fn update(
+        &mut self,
+        message: ChangePasswordMessage,
+        vault_name: Option<&str>,
+    ) -> Task<ChangePasswordMessage> {
+        match message {
+            ChangePasswordMessage::CurrentPasswordChanged(password) => {
+                self.current_password = SecretString::new(password);
+            }
+
+            ChangePasswordMessage::NewPasswordChanged(password) => {
+                self.new_password = SecretString::new(password);
+            }
+
+            ChangePasswordMessage::ConfirmPasswordChanged(password) => {
+                self.confirm_password = SecretString::new(password);
+            }
+
+            ChangePasswordMessage::PickKeyfile => {
+                return Task::perform(
+                    async { Ok(pick_keyfile().await) },
+                    ChangePasswordMessage::KeyfileChosen,
+                );
+            }
+
+            ChangePasswordMessage::CreateKeyfile => {
+                return Task::perform(create_keyfile(), ChangePasswordMessage::KeyfileChosen);
+            }
+
+            ChangePasswordMessage::KeyfileChosen(result) => {
+                match result {
+                    Ok(Some(path)) => { self.keyfile = Some(path); }
+                    Ok(None) => {}
+                    Err(e) => { self.status = ChangePasswordStatus::Error(e); }
+                }
+            }
+
+            ChangePasswordMessage::RemoveKeyfile => {
+                self.keyfile = None;
+            }
+
+            ChangePasswordMessage::Submit => {
+                if self.status == ChangePasswordStatus::Loading {
+                    return Task::none();
+                }
+
+                if let Err(e) = check_new_password(&self.new_password, &self.confirm_password) {
+                    self.status = ChangePasswordStatus::Error(e);
+                    return Task::none();
+                }
+
+                let vault_name = match vault_name {
+                    Some(name) => name.to_string(),
+                    None => {
+                        self.status = ChangePasswordStatus::Error(String::from("Vault is locked"));
+                        return Task::none();
+                    }
+                };
+
+                let current_password = self.current_password.clone();
+                let current_keyfile = self.current_keyfile.clone();
+                let new_password = self.new_password.clone();
+                let keyfile = self.keyfile.clone();
+
+                self.status = ChangePasswordStatus::Loading;
+
+                return Task::perform(
+                    async move {
+                        get_vault_storage(&vault_name)
+                            .and_then(|storage| {
+                                change_vault_password(
+                                    &storage,
+                                    &vault_name,
+                                    &current_password,
+                                    current_keyfile.as_deref(),
+                                    &new_password,
+                                    keyfile.as_deref(),
+                                )?;
+
+                                commit_vault(&storage, "Change password")
+                            })
+                            .map(|_| ())
+                            .map_err(|e| e.to_string())
+                    },
+                    ChangePasswordMessage::Done,
+                );
+            }
+
+            ChangePasswordMessage::Done(result) => {
+                self.current_password.zeroize();
+
+                match result {
+                    Ok(()) => {
+                        self.current_keyfile = self.keyfile.clone();
+                        self.new_password.zeroize();
+                        self.confirm_password.zeroize();
+                        self.status = ChangePasswordStatus::Changed;
+                    }
+
+                    Err(e) => {
+                        self.status = ChangePasswordStatus::Error(e);
+                    }
+                }
+            }
+
+            ChangePasswordMessage::Close => {}
+        }
+
+        Task::none()
+    }
+}
+
+/// Checks that the new password is valid and was typed the same way twice.
+pub fn check_new_password(new_password: &str, confirm_password: &str) -> Result<(), String> {
+    if new_password.len() > 32 || new_password.len() < 8 {
+        return Err(String::from("Vault password must be between 8 and 32 characters."));
+    }
+
+    if new_password != confirm_password {
+        return Err(String::from("The new passwords don't match."));
+    }
+
+    Ok(())
+}
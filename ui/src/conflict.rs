@@ -0,0 +1,194 @@
+///
+/// Resolving a note that was edited on two devices.
+///
+/// Shows the note next to the version that the sync tool stored in a conflict
+/// copy (see [`secure_notes_core::utils::sync`]), the user keeps either of them
+/// or both.
+///
+use iced::{
+    Element, Center, Fill, Color, Task,
+    widget::{ column, row, text, Space, button, scrollable, container },
+};
+
+use zeroize::Zeroize;
+
+use crate::utils::{
+    crypto::VaultKey,
+    git::commit_vault,
+    notes::read_note,
+    sync::{ NoteConflict, ConflictResolution, read_conflict_copy, resolve_conflict },
+    vault::get_vault_storage,
+};
+
+#[derive(Debug, Clone)]
+pub enum ConflictMessage {
+    Resolve(ConflictResolution),
+    Close,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum ConflictStatus {
+    #[default]
+    None,
+
+    Error(String),
+}
+
+#[derive(Debug, Default)]
+pub struct Conflict {
+    conflict: Option<NoteConflict>,
+
+    /// Text of the note
+    current: String,
+
+    /// Text of the conflict copy
+    copy: String,
+
+    pub status: ConflictStatus,
+}
+
+impl Conflict {
+    /// Reads both versions of the note.
+    ///
+    /// `vault` is the name and master key of the opened vault.
+    pub fn load(&mut self, vault: (&str, &VaultKey), conflict: NoteConflict) {
+        self.reset();
+
+        let ( name, key ) = vault;
+
+        let result = get_vault_storage(name).and_then(|storage| {
+            // The note's own file may not have been synced yet
+            let current = read_note(&storage, key, conflict.id).unwrap_or_default();
+            let copy = read_conflict_copy(&storage, key, &conflict)?;
+
+            Ok(( current, copy ))
+        });
+
+        match result {
+            Ok(( current, copy )) => {
+                self.current = current;
+                self.copy = copy;
+            }
+
+            Err(e) => { self.status = ConflictStatus::Error(e.to_string()); }
+        }
+
+        self.conflict = Some(conflict);
+    }
+
+    pub fn reset(&mut self) {
+        self.conflict = None;
+        self.current.zeroize();
+        self.copy.zeroize();
+        self.status = ConflictStatus::None;
+    }
+
+    /// Id of the conflicted note.
+    pub fn note_id(&self) -> Option<u32> {
+        self.conflict.as_ref().map(|c| c.id)
+    }
+
+    pub fn view(&self) -> Element<'_, ConflictMessage> {
+        let name = match &self.conflict {
+            Some(conflict) => conflict.name.as_str(),
+            None => "",
+        };
+
+        let version = |title, note_text| {
+            column![
+                text(title).size(18),
+                Space::new(Fill, 10),
+                container(scrollable(text(note_text).width(Fill)))
+                    .padding(10)
+                    .height(Fill)
+                    .style(container::bordered_box),
+            ]
+                .width(Fill)
+        };
+
+        let mut conflict_column = column![
+            text!("\"{}\" was edited on two devices", name)
+                .width(Fill)
+                .size(32)
+                .align_x(Center),
+            Space::new(Fill, 10),
+            text("Choose the version to keep, the other one is deleted.")
+                .width(Fill)
+                .align_x(Center),
+            Space::new(Fill, 20),
+            row![
+                version("This version", self.current.as_str()),
+                Space::new(20, 0),
+                version("Other version", self.copy.as_str()),
+            ]
+                .height(Fill),
+        ];
+
+        if let ConflictStatus::Error(e) = &self.status {
+            conflict_column = conflict_column.push(Space::new(Fill, 10));
+            conflict_column = conflict_column.push(
+                text(e).color(Color::new(0.9, 0.0, 0.0, 1.0))
+            );
+        }
+
+        conflict_column = conflict_column.push(Space::new(Fill, 20));
+        conflict_column = conflict_column.push(row![
+            button(text("Keep This Version"))
+                .style(button::primary)
+                .on_press(ConflictMessage::Resolve(ConflictResolution::KeepCurrent)),
+            Space::new(10, 0),
+            button(text("Keep Other Version"))
+                .style(button::primary)
+                .on_press(ConflictMessage::Resolve(ConflictResolution::KeepCopy)),
+            Space::new(10, 0),
+            button(text("Keep Both"))
+                .style(button::secondary)
+                .on_press(ConflictMessage::Resolve(ConflictResolution::KeepBoth)),
+            Space::new(10, 0),
+            button(text("Back"))
+                .style(button::secondary)
+                .on_press(ConflictMessage::Close),
+        ]);
+
+        conflict_column
+            .padding(40)
+            .into()
+    }
+
+    /// Updates the screen.
+    ///
+    /// `vault` is the name and master key of the opened vault, needed to
+    /// resolve the conflict.
+    pub fn update(
+        &mut self,
+        message: ConflictMessage,
+        vault: Option<(&str, &VaultKey)>,
+    ) -> Task<ConflictMessage> {
+        match message {
+            ConflictMessage::Resolve(resolution) => {
+                let ( conflict, ( name, key ) ) = match ( &self.conflict, vault ) {
+                    ( Some(conflict), Some(vault) ) => ( conflict, vault ),
+                    _ => {
+                        self.status = ConflictStatus::Error(String::from("Vault is locked"));
+                        return Task::none();
+                    }
+                };
+
+                let result = get_vault_storage(name)
+                    .and_then(|storage| {
+                        resolve_conflict(&storage, key, conflict, resolution)?;
+                        commit_vault(&storage, &format!("Resolve conflict of note {}", conflict.id))
+                    });
+
+                match result {
+                    Ok(_) => { return Task::done(ConflictMessage::Close); }
+                    Err(e) => { self.status = ConflictStatus::Error(e.to_string()); }
+                }
+            }
+
+            ConflictMessage::Close => {}
+        }
+
+        Task::none()
+    }
+}
@@ -0,0 +1,41 @@
+///
+/// Native file dialogs.
+///
+/// The dialogs are async so they can be shown from a `Task` without blocking
+/// the UI.
+///
+use std::path::PathBuf;
+use rfd::AsyncFileDialog;
+
+use secure_notes_core::utils::crypto::generate_keyfile;
+
+/// Asks the user for an existing keyfile.
+pub async fn pick_keyfile() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .set_title("Choose Keyfile")
+        .pick_file()
+        .await
+        .map(|file| file.path().to_path_buf())
+}
+
+/// Asks the user where to save a new keyfile and writes it there.
+///
+/// Returns `None` if the user cancelled the dialog.
+pub async fn create_keyfile() -> Result<Option<PathBuf>, String> {
+    let file = AsyncFileDialog::new()
+        .set_title("Save Keyfile")
+        .set_file_name("secure-notes.key")
+        .save_file()
+        .await;
+
+    match file {
+        Some(file) => {
+            let path = file.path().to_path_buf();
+            generate_keyfile(&path)?;
+
+            Ok(Some(path))
+        }
+
+        None => Ok(None),
+    }
+}
@@ -1,31 +1,58 @@
-use std::{ thread, sync::Arc };
+use std::{ thread, path::PathBuf, collections::BTreeMap, time::{ Duration, Instant } };
 use futures::executor;
+use zeroize::Zeroize;
 
 use iced::{
     futures::{
         channel::{ mpsc, mpsc::Sender },
-        Stream, SinkExt
+        Stream, SinkExt, StreamExt
     },
-    keyboard::{ key::Key, Event::KeyPressed },
+    keyboard::{ key::{ Key, Named }, Event::KeyPressed },
+    mouse,
     widget::{
         button, column, row, container, pane_grid, responsive, text, text_editor,
-        text_editor::{Action, Content}, text_input, Space, svg,
+        text_editor::Action, text_input, Space, svg,
     },
     event::{ self, Event },
-    Background, Center, Color, Element, Fill, Subscription, stream::channel,
-    Task,
+    advanced::widget::{ Id, operate, operation::{ Focusable, Operation } },
+    Background, Border, Center, Color, Element, Fill, Rectangle, Subscription, stream::channel,
+    Task, Theme, time, window,
 };
+use tracing::{ debug, error, info, warn };
 
 use crate::{
-    types::{ vault_index_entry::VaultIndexEntry, DefaultVaultFileError },
-    utils::{get_default_vault_name, vault::authenticate_vault},
+    dialogs::pick_keyfile,
+    change_password::{ ChangePassword, ChangePasswordMessage },
+    recovery::{ Recovery, RecoveryMessage },
+    vault_settings::{ VaultSettings, VaultSettingsMessage },
+    app_settings::{ AppSettings, AppSettingsMessage },
+    conflict::{ Conflict, ConflictMessage, ConflictStatus },
+    preview::{ Preview, PreviewMessage },
+    highlighter::MarkdownHighlighter,
+    tabs::{ Tab, Tabs, TabMessage },
+    types::{
+        vault_index_entry::VaultIndexEntry, note_metadata::NoteMetadata,
+        vault_error::VaultError, settings::{ Settings, HexColor }, DefaultVaultFileError,
+    },
+    utils::{
+        get_default_vault_name,
+        crypto::{ SecretString, VaultKey },
+        git::commit_vault,
+        document::NoteDocument,
+        notes::{ save_note_document, read_note_document },
+        settings::read_settings,
+        sync::{ NoteConflict, ConflictResolution, reconcile },
+        unlock_state::read_unlock_state,
+        unix_now,
+        vault::{ unlock_vault, get_vault_storage, read_vault_info },
+    },
 };
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub enum EditorVaultPasswordStatus {
     /// When password has not been entered
     #[default]
-    NONE,
+    None,
 
     /// When password field was empty when submit button was pressed
     Empty,
@@ -38,6 +65,17 @@ pub enum EditorVaultPasswordStatus {
 
     /// When password is authenticated
     Authenticated,
+
+    /// When there were too many failed attempts, holds the time (in seconds
+    /// since the unix epoch) after which the user can try again
+    LockedOut(u64),
+
+    /// When the vault's key material was wiped after too many failed attempts
+    KeyMaterialWiped,
+
+    /// When the vault couldn't be opened for another reason, e.g. its keyfile
+    /// is missing or wrong
+    Error(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -50,6 +88,21 @@ pub enum EditorScreen {
 
     /// New session, shows a blank right hand side
     Editor,
+
+    /// Shows the screen to change the password of the opened vault
+    ChangePassword,
+
+    /// Shows the screen to open the vault with its recovery phrase
+    Recovery,
+
+    /// Shows the settings of the opened vault
+    VaultSettings,
+
+    /// Shows the settings of the app
+    AppSettings,
+
+    /// Shows both versions of a note that was edited on two devices
+    Conflict,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -57,39 +110,67 @@ pub enum EditorMessage {
     #[default]
     None,
     Event(Event),
-    // Action performed on the text editor
-    ActionPerformed(Action),
+    // Action performed on the text editor of the pane with the given id
+    ActionPerformed(usize, Action),
     Resized(pane_grid::ResizeEvent),
-    // Close(pane_grid::Pane),
-    // CloseFocused,
+    Split(pane_grid::Axis, pane_grid::Pane),
+    Close(pane_grid::Pane),
+    CloseFocused,
+    FocusPane(pane_grid::Pane),
     ToggleExplorer,
+    TogglePreview,
+    Preview(PreviewMessage),
     Clicked(pane_grid::Pane),
 
     // Messages related to vault creation
     VaultPasswordChanged(String),
     VaultPasswordSubmitted,
+    PickKeyfile,
+    KeyfilePicked(Option<PathBuf>),
+    ChangePassword(ChangePasswordMessage),
+    ForgotPassword,
+    Recovery(RecoveryMessage),
+    VaultSettings(VaultSettingsMessage),
+    AppSettings(AppSettingsMessage),
+    OpenConflict(usize),
+    Conflict(ConflictMessage),
 
     // Messages related to notes
     EditNoteName(bool),
     NoteNameChanged(String),
     SaveNoteName,
-    // Save,
+    Save,
     New,
+    Tab(usize, TabMessage),
+    AutosaveTick,
+    PreviewTick,
+
+    // Messages related to locking the vault
+    Lock,
+    LockTick,
+    MinimizedChecked(Option<bool>),
 
     // Messages related to password validation
     PVVaultEmpty,
     PVPasswordEmpty,
     PVVaultAndPasswordEmpty,
     PVDoesNotMatch,
+    PVLockedOut(u64),
+    PVKeyMaterialWiped,
+    PVError(String),
+    LockoutTick,
     // PVLoading,
-    PVAuthenticated,
-    PVInitSender(Arc<thread::JoinHandle<()>>, Sender<(String, String)>),
+    PVAuthenticated(VaultKey),
+    PVInitSender(Sender<(String, SecretString, Option<PathBuf>)>),
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum PaneType {
     Explorer,
     TextEditor,
+
+    /// The Markdown preview of the opened note
+    Preview,
 }
 
 #[derive(Clone, Copy)]
@@ -99,23 +180,68 @@ pub struct Pane {
     pub is_pinned: bool,
 }
 
+/// Time without edits after which the preview shows them.
+const PREVIEW_DELAY: Duration = Duration::from_millis(300);
+
 pub struct Editor {
-    pub vault_password: String,
+    /// Password typed in the password prompt, wiped once authentication
+    /// finishes
+    pub vault_password: SecretString,
     pub vault_password_status: EditorVaultPasswordStatus,
+
+    /// Keyfile chosen in the password prompt, kept when the vault is locked
+    pub vault_keyfile: Option<PathBuf>,
+
+    /// When the opened vault needs a keyfile to be unlocked
+    pub vault_uses_keyfile: bool,
+
+    /// When the opened vault can be recovered with a recovery phrase
+    pub vault_has_recovery: bool,
     pub screen: EditorScreen,
 
     /// When name is being edited
     pub edit_name: bool,
     pub temp_note_name: String,
     pub opened_vault: Option<String>,
-    pub opened_file: Option<VaultIndexEntry>,
+
+    /// The notes opened in every text editor pane, by the pane's id
+    pub editor_tabs: BTreeMap<usize, Tabs>,
     pub explorer_files: Vec<VaultIndexEntry>,
-    pub content: Content,
+
+    /// Notes of the opened vault that were edited on two devices
+    pub conflicts: Vec<NoteConflict>,
     pub panes: pane_grid::State<Pane>,
+
+    /// Split between the explorer and the note
+    pub explorer_split: Option<pane_grid::Split>,
+
+    /// Ids given to the text editor panes so far
     pub panes_created: usize,
     pub focused_pane: Option<pane_grid::Pane>,
+
+    /// The text editor pane that was focused last, new notes are opened in it
+    /// and the shortcuts act on it
+    pub editor_pane: Option<pane_grid::Pane>,
     pub show_explorer: bool,
-    pub initialized: bool,
+    pub show_preview: bool,
+    pub preview: Preview,
+
+    /// When the note shown in the preview was last edited, until the preview
+    /// shows the edits
+    pub preview_outdated: Option<Instant>,
+
+    /// Master key of the opened vault, only available when it's unlocked
+    pub vault_key: Option<VaultKey>,
+
+    /// Why the vault couldn't be locked, shown until the notes are saved
+    pub lock_error: Option<String>,
+    pub settings: Settings,
+    pub last_activity: Instant,
+    pub change_password: ChangePassword,
+    pub recovery: Recovery,
+    pub vault_settings: VaultSettings,
+    pub app_settings: AppSettings,
+    pub conflict: Conflict,
 }
 
 impl Pane {
@@ -128,20 +254,22 @@ impl Editor {
     // pub fn new() -> (Self, Task<EditorMessage>) {
     pub fn new() -> Self {
         // (Editor::default(), Task::none())
+        let ( settings, _ ) = read_settings();
+        let mut explorer_split = None;
+        let mut editor_pane = None;
+
         let ( mut pane_state, explorer_pane ) = pane_grid::State::new(
             Pane::new(0, PaneType::Explorer)
         );
 
-        match pane_state.split(
+        if let Some((pane, split)) = pane_state.split(
             pane_grid::Axis::Vertical,
             explorer_pane,
             Pane::new(0, PaneType::TextEditor)
         ) {
-            Some((_pane, split)) => {
-                pane_state.resize(split, 0.25);
-            }
-
-            None => {}
+            pane_state.resize(split, settings.editor.explorer_ratio as f32);
+            explorer_split = Some(split);
+            editor_pane = Some(pane);
         }
 
         let screen;
@@ -152,25 +280,26 @@ impl Editor {
                 screen = EditorScreen::PasswordPrompt;
                 opened_vault= Some(vault_name.clone());
 
-                println!("default vault: {}", vault_name);
+                debug!(vault = %vault_name, "opening the default vault");
             }
 
             Err(e) => {
                 match e {
-                    DefaultVaultFileError::OSError(_s) => {
+                    DefaultVaultFileError::OSError(s) => {
                         // TODO: show an error message to the user
+                        warn!(error = %s, "could not read the default vault file");
                     }
 
                     DefaultVaultFileError::FirstLineEmpty => {
-                        eprintln!("First line empty!");
+                        warn!("the default vault file is empty");
                     }
 
                     DefaultVaultFileError::VaultDoesNotExist => {
-                        eprintln!("Vault does not exist!");
+                        warn!("the default vault does not exist");
                     }
 
                     DefaultVaultFileError::FileDoesNotExist => {
-                        eprintln!("Default vault file does not exist!");
+                        debug!("there is no default vault");
                     }
                 }
 
@@ -179,102 +308,286 @@ impl Editor {
             }
         }
 
-        Self {
-            vault_password: String::default(),
-            vault_password_status: EditorVaultPasswordStatus::default(),
+        let vault_password_status = match opened_vault.as_deref()
+            .and_then(|name| read_unlock_state(name).retry_at()) {
+            Some(retry_at) => EditorVaultPasswordStatus::LockedOut(retry_at),
+            None => EditorVaultPasswordStatus::default(),
+        };
+
+        let mut editor = Self {
+            vault_password: SecretString::default(),
+            vault_password_status,
+            vault_keyfile: None,
+            vault_uses_keyfile: false,
+            vault_has_recovery: false,
             screen,
             edit_name: false,
             temp_note_name: String::default(),
             opened_vault,
-            opened_file: None,
+            editor_tabs: BTreeMap::from([ ( 0, Tabs::default() ) ]),
             explorer_files: vec![],
-            content: Content::default(),
+            conflicts: vec![],
             panes: pane_state,
-            panes_created: 0,
-            focused_pane: None,
+            explorer_split,
+            panes_created: 1,
+            focused_pane: editor_pane,
+            editor_pane,
             show_explorer: true,
-            initialized: false,
-        }
+            show_preview: false,
+            preview: Preview::default(),
+            preview_outdated: None,
+            vault_key: None,
+            lock_error: None,
+            settings,
+            last_activity: Instant::now(),
+            change_password: ChangePassword::default(),
+            recovery: Recovery::default(),
+            vault_settings: VaultSettings::default(),
+            app_settings: AppSettings::default(),
+            conflict: Conflict::default(),
+        };
+
+        editor.refresh_vault_info();
+
+        editor
     }
 
     // pub fn update(&mut self, editor_state: EditorMessage) -> Task<EditorMessage> {
     pub fn update(&mut self, editor_state: EditorMessage) -> Task<EditorMessage> {
+        match editor_state {
+            EditorMessage::LockTick
+            | EditorMessage::AutosaveTick
+            | EditorMessage::PreviewTick
+            | EditorMessage::MinimizedChecked(_) => {}
+            _ => { self.last_activity = Instant::now(); }
+        }
+
         match editor_state {
             EditorMessage::Event(event) => {
                 match event {
                     Event::Keyboard(KeyPressed {
                         key, modifiers, ..
-                    }) => {
-                        if modifiers.control() {
-                            match key {
-                                Key::Character(k) => {
-                                    match k.as_str() {
-                                        "s" => {
+                    }) if modifiers.control() => {
+                        // Only the vault can be locked from the other screens,
+                        // the other shortcuts act on the opened notes
+                        let in_editor = self.screen == EditorScreen::Editor && self.is_unlocked();
+
+                        match key {
+                            Key::Character(k) => {
+                                match k.as_str() {
+                                    "l" => {
+                                        return Task::done(EditorMessage::Lock);
+                                    }
+
+                                    _ if !in_editor => {}
+
+                                    "s" => {
+                                        return Task::done(EditorMessage::Save);
+                                    }
+
+                                    "e" => {
+                                        return Task::done(EditorMessage::ToggleExplorer);
+                                    }
+
+                                    "m" => {
+                                        return Task::done(EditorMessage::TogglePreview);
+                                    }
+
+                                    "n" => {
+                                        return Task::done(EditorMessage::New);
+                                    }
+
+                                    // Closes the note, or the pane when it has no notes
+                                    "w" => {
+                                        let active = self.tabs().and_then(Tabs::active_index);
+
+                                        return match ( self.editor_pane_id(), active ) {
+                                            ( Some(id), Some(i) ) => Task::done(
+                                                EditorMessage::Tab(id, TabMessage::Close(i))
+                                            ),
+
+                                            _ => Task::done(EditorMessage::CloseFocused),
+                                        };
+                                    }
+
+                                    "\\" | "|" => {
+                                        let axis = match modifiers.shift() {
+                                            true => pane_grid::Axis::Horizontal,
+                                            false => pane_grid::Axis::Vertical,
+                                        };
+
+                                        if let Some(pane) = self.editor_pane {
+                                            return Task::done(EditorMessage::Split(axis, pane));
                                         }
+                                    }
 
-                                        "e" => {
-                                            return Task::done(EditorMessage::ToggleExplorer);
+                                    "]" | "[" => {
+                                        let panes = self.editor_panes();
+
+                                        let current = panes.iter()
+                                            .position(|pane| Some(*pane) == self.editor_pane);
+
+                                        // The next or previous split, around
+                                        // the ends
+                                        let next = match ( current, k.as_str() ) {
+                                            ( Some(i), "]" ) => panes.get(i + 1).or(panes.first()),
+                                            ( Some(i), _ ) => i.checked_sub(1)
+                                                .and_then(|i| panes.get(i))
+                                                .or(panes.last()),
+                                            ( None, _ ) => panes.first(),
+                                        };
+
+                                        if let Some(pane) = next {
+                                            return Task::done(EditorMessage::FocusPane(*pane));
                                         }
+                                    }
 
-                                        "n" => {
-                                            return Task::done(EditorMessage::New);
+                                    "p" => {
+                                        self.open_change_password();
+                                    }
+
+                                    "," => {
+                                        if let Some(name) = &self.opened_vault {
+                                            self.vault_settings.load(name);
+                                            self.screen = EditorScreen::VaultSettings;
                                         }
+                                    }
 
-                                        _ => {}
+                                    "." => {
+                                        self.app_settings.load();
+                                        self.screen = EditorScreen::AppSettings;
                                     }
+
+                                    _ => {}
                                 }
+                            }
 
-                                _ => {}
+                            Key::Named(Named::Tab) if in_editor => {
+                                if let Some(id) = self.editor_pane_id() {
+                                    return Task::done(EditorMessage::Tab(
+                                        id,
+                                        if modifiers.shift() { TabMessage::Previous } else { TabMessage::Next },
+                                    ));
+                                }
                             }
+
+                            _ => {}
+                        }
+                    }
+
+                    // Ends dragging a tab, wherever the mouse button is released
+                    Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                        for tabs in self.editor_tabs.values_mut() {
+                            tabs.update(TabMessage::Released);
+                        }
+                    }
+
+                    Event::Window(window::Event::Unfocused) if self.is_unlocked() => {
+                        if self.settings.auto_lock.lock_on_focus_loss {
+                            return Task::done(EditorMessage::Lock);
+                        }
+
+                        if self.settings.auto_lock.lock_on_minimize {
+                            return window::get_latest()
+                                .and_then(window::get_minimized)
+                                .map(EditorMessage::MinimizedChecked);
                         }
                     }
 
+                    // Windows resizes minimized windows to zero
+                    Event::Window(window::Event::Resized(size))
+                        if self.settings.auto_lock.lock_on_minimize
+                            && size.width == 0.0 && size.height == 0.0 => {
+                        return Task::done(EditorMessage::Lock);
+                    }
+
                     _ => {}
                 }
             }
 
-            EditorMessage::ActionPerformed(action) => {
-                self.content.perform(action);
+            EditorMessage::ActionPerformed(id, action) => {
+                let is_edit = action.is_edit();
+
+                let scrolled_lines = match action {
+                    Action::Scroll { lines } => Some(lines),
+                    _ => None,
+                };
+
+                let tab = match self.editor_tabs.get_mut(&id).and_then(Tabs::active_mut) {
+                    Some(tab) => tab,
+                    None => { return Task::none(); }
+                };
+
+                tab.perform(action);
+
+                if is_edit {
+                    tab.dirty = true;
+                    tab.last_edit = Instant::now();
+
+                    // Shown once the typing pauses, see `PreviewTick`
+                    if self.show_preview && self.editor_pane_id() == Some(id) {
+                        self.preview_outdated = Some(Instant::now());
+                    }
+                }
+
+                // The preview follows the focused pane
+                if self.show_preview && self.editor_pane_id() == Some(id) {
+                    if let Some(tab) = self.editor_tabs.get(&id).and_then(Tabs::active) {
+                        return self.preview.follow_editor(scrolled_lines, &tab.content)
+                            .map(EditorMessage::Preview);
+                    }
+                }
             }
 
-            // EditorMessage::Split(axis, pane) => {
-            //     let result = self.panes.split(axis, pane, Pane::new(self.panes_created));
+            EditorMessage::Split(axis, pane) => {
+                match self.panes.get(pane) {
+                    Some(state) if state.pane_type == PaneType::TextEditor => {}
+                    _ => { return Task::none(); }
+                }
+
+                let result = self.panes.split(
+                    axis,
+                    pane,
+                    Pane::new(self.panes_created, PaneType::TextEditor)
+                );
 
-            //     if let Some((pane, _)) = result {
-            //         self.focus = Some(pane);
-            //         self.panes_created += 1;
-            //     }
-            // }
+                if let Some((pane, _)) = result {
+                    self.editor_tabs.insert(self.panes_created, Tabs::default());
+                    self.panes_created += 1;
+
+                    return self.focus_editor(pane);
+                }
+            }
 
             EditorMessage::Resized(pane_grid::ResizeEvent{ split, ratio }) => {
                 self.panes.resize(split, ratio);
             }
 
-            // EditorMessage::Close(pane) => {
-            //     if let Some((_, sibling)) = self.panes.close(pane) {
-            //         self.focused_pane = Some(sibling);
-            //     }
-            // }
+            EditorMessage::Close(pane) => {
+                return self.close_editor(pane);
+            }
 
-            // EditorMessage::CloseFocused => {
-            //     if let Some(pane) = self.focused_pane {
-            //         if let Some(Pane { is_pinned, ..}) = self.panes.get(pane) {
-            //             if !is_pinned {
-            //                 if let Some((_, sibling)) = self.panes.close(pane) {
-            //                     self.focused_pane = Some(sibling);
-            //                 }
-            //             }
-            //         }
-            //     }
-            // }
+            EditorMessage::CloseFocused => {
+                if let Some(pane) = self.editor_pane {
+                    return self.close_editor(pane);
+                }
+            }
+
+            EditorMessage::FocusPane(pane) => {
+                return self.focus_editor(pane);
+            }
 
             EditorMessage::Clicked(pane) => {
                 self.focused_pane = Some(pane);
+
+                // The click focuses the text editor itself
+                if let Some(PaneType::TextEditor) = self.panes.get(pane).map(|state| state.pane_type) {
+                    self.select_editor(pane);
+                }
             }
 
             EditorMessage::ToggleExplorer => {
                 if self.show_explorer {
-                    println!("hiding explorer");
                     let panes = self.panes.clone();
 
                     for (pane, state) in panes.iter() {
@@ -284,8 +597,6 @@ impl Editor {
                             }
                         }
                     }
-                } else {
-                    println!("showing explorer");
                 }
 
                 self.show_explorer = !self.show_explorer;
@@ -306,22 +617,248 @@ impl Editor {
                 // }
             }
 
+            EditorMessage::TogglePreview => {
+                let panes = self.panes.clone();
+
+                for (pane, state) in panes.iter() {
+                    match ( self.show_preview, state.pane_type ) {
+                        ( true, PaneType::Preview ) => {
+                            if let Some((_, sibling)) = self.panes.close(*pane) {
+                                self.focused_pane = Some(sibling);
+                            }
+                        }
+
+                        // Next to the focused text editor
+                        ( false, PaneType::TextEditor ) if Some(*pane) == self.editor_pane => {
+                            self.panes.split(
+                                pane_grid::Axis::Vertical,
+                                *pane,
+                                Pane::new(0, PaneType::Preview)
+                            );
+                        }
+
+                        _ => {}
+                    }
+                }
+
+                self.show_preview = !self.show_preview;
+
+                match self.show_preview {
+                    true => { self.refresh_preview(); }
+                    false => { self.preview.clear(); }
+                }
+            }
+
+            EditorMessage::Preview(message) => {
+                if let PreviewMessage::Scrolled(viewport) = &message {
+                    let text_size = self.settings.editor.text_size as f32;
+
+                    let tab = self.editor_pane_id()
+                        .and_then(|id| self.editor_tabs.get_mut(&id))
+                        .and_then(Tabs::active_mut);
+
+                    if let Some(tab) = tab {
+                        if let Some(lines) = self.preview.follow_preview(*viewport, text_size, &tab.content) {
+                            tab.content.perform(Action::Scroll { lines });
+                        }
+                    }
+                }
+
+                self.preview.update(message);
+            }
+
             EditorMessage::None => {}
 
             EditorMessage::VaultPasswordChanged(updated_password) => {
-                self.vault_password = updated_password;
-                self.vault_password_status = EditorVaultPasswordStatus::NONE;
+                self.vault_password = SecretString::new(updated_password);
+
+                match self.vault_password_status {
+                    EditorVaultPasswordStatus::LockedOut(_)
+                    | EditorVaultPasswordStatus::KeyMaterialWiped => {}
+
+                    _ => {
+                        self.vault_password_status = EditorVaultPasswordStatus::None;
+                    }
+                }
             }
 
             EditorMessage::VaultPasswordSubmitted => {
-                self.vault_password_status = EditorVaultPasswordStatus::Loading;
+                match self.vault_password_status {
+                    EditorVaultPasswordStatus::LockedOut(_)
+                    | EditorVaultPasswordStatus::KeyMaterialWiped => {}
+
+                    _ => {
+                        self.vault_password_status = EditorVaultPasswordStatus::Loading;
+                    }
+                }
+            }
+
+            EditorMessage::PickKeyfile => {
+                return Task::perform(pick_keyfile(), EditorMessage::KeyfilePicked);
+            }
+
+            EditorMessage::KeyfilePicked(keyfile) => {
+                if keyfile.is_some() {
+                    self.vault_keyfile = keyfile;
+
+                    if let EditorVaultPasswordStatus::Error(_) = self.vault_password_status {
+                        self.vault_password_status = EditorVaultPasswordStatus::None;
+                    }
+                }
+            }
+
+            EditorMessage::ChangePassword(message) => {
+                match message {
+                    ChangePasswordMessage::Close => {
+                        self.change_password.reset(None);
+                        self.screen = EditorScreen::Editor;
+                    }
+
+                    ChangePasswordMessage::Done(Ok(())) => {
+                        self.vault_keyfile = self.change_password.keyfile.clone();
+                        self.refresh_vault_info();
+                    }
+
+                    _ => {}
+                }
+
+                return self.change_password.update(message, self.opened_vault.as_deref())
+                    .map(EditorMessage::ChangePassword);
+            }
+
+            EditorMessage::ForgotPassword => {
+                if self.screen == EditorScreen::PasswordPrompt {
+                    self.vault_password.zeroize();
+                    self.recovery.reset();
+                    self.screen = EditorScreen::Recovery;
+                }
+            }
+
+            EditorMessage::Recovery(message) => {
+                match &message {
+                    RecoveryMessage::Cancel => {
+                        self.screen = EditorScreen::PasswordPrompt;
+                    }
+
+                    RecoveryMessage::Done(Ok(key)) => {
+                        info!("vault recovered");
+                        self.vault_key = Some(key.clone());
+                        self.vault_keyfile = None;
+                        self.refresh_vault_info();
+                        self.reconcile_vault();
+                        self.screen = EditorScreen::Editor;
+                        self.vault_password_status
+                            = EditorVaultPasswordStatus::Authenticated;
+                    }
+
+                    _ => {}
+                }
+
+                return self.recovery.update(message, self.opened_vault.as_deref())
+                    .map(EditorMessage::Recovery);
+            }
+
+            EditorMessage::VaultSettings(message) => {
+                match message {
+                    VaultSettingsMessage::Close => {
+                        self.vault_settings.reset();
+                        self.screen = EditorScreen::Editor;
+                        return Task::none();
+                    }
+
+                    VaultSettingsMessage::ChangePassword => {
+                        self.vault_settings.reset();
+                        self.open_change_password();
+                        return Task::none();
+                    }
+
+                    _ => {}
+                }
+
+                let refresh = matches!(
+                    message,
+                    VaultSettingsMessage::SlotAdded(_) | VaultSettingsMessage::RemoveSlot(_)
+                );
+
+                let vault = match ( self.opened_vault.as_deref(), &self.vault_key ) {
+                    ( Some(name), Some(key) ) => Some(( name, key )),
+                    _ => None,
+                };
+
+                let task = self.vault_settings.update(message, vault)
+                    .map(EditorMessage::VaultSettings);
+
+                if refresh {
+                    self.refresh_vault_info();
+                }
+
+                return task;
+            }
+
+            EditorMessage::AppSettings(message) => {
+                if let AppSettingsMessage::Close = message {
+                    self.screen = EditorScreen::Editor;
+                    return Task::none();
+                }
+
+                let task = self.app_settings.update(message)
+                    .map(EditorMessage::AppSettings);
+
+                self.apply_settings(self.app_settings.settings().clone());
+
+                return task;
+            }
+
+            EditorMessage::OpenConflict(i) => {
+                let conflict = match self.conflicts.get(i) {
+                    Some(conflict) => conflict.clone(),
+                    None => { return Task::none(); }
+                };
+
+                // The note may be an opened one
+                self.save_all_tabs();
+
+                if let ( Some(name), Some(key) ) = ( self.opened_vault.as_deref(), &self.vault_key ) {
+                    self.conflict.load(( name, key ), conflict);
+                    self.screen = EditorScreen::Conflict;
+                }
+            }
+
+            EditorMessage::Conflict(message) => {
+                if let ConflictMessage::Close = message {
+                    self.conflict.reset();
+                    self.reconcile_vault();
+                    self.screen = EditorScreen::Editor;
+                    return Task::none();
+                }
+
+                let resolved_note = match message {
+                    ConflictMessage::Resolve(ConflictResolution::KeepCopy) => self.conflict.note_id(),
+                    _ => None,
+                };
+
+                let vault = match ( self.opened_vault.as_deref(), &self.vault_key ) {
+                    ( Some(name), Some(key) ) => Some(( name, key )),
+                    _ => None,
+                };
+
+                let task = self.conflict.update(message, vault)
+                    .map(EditorMessage::Conflict);
+
+                if self.conflict.status == ConflictStatus::None {
+                    if let Some(id) = resolved_note {
+                        self.reload_opened_note(id);
+                    }
+                }
+
+                return task;
             }
 
             EditorMessage::EditNoteName(should_edit) => {
                 self.edit_name = should_edit;
                 if should_edit {
-                    if let Some(note_name) = &self.opened_file {
-                        self.temp_note_name = note_name.name.clone();
+                    if let Some(tab) = self.active_tab() {
+                        self.temp_note_name = tab.note.name.clone();
                     }
                 }
             }
@@ -331,32 +868,144 @@ impl Editor {
             }
 
             EditorMessage::SaveNoteName => {
-                match self.opened_file.clone() {
-                    Some(file_index_entry) => {
-                        let new_index_entry = VaultIndexEntry {
-                            id: file_index_entry.id,
-                            name: self.temp_note_name.clone(),
-                            parent_folder: file_index_entry.parent_folder,
-                        };
+                let name = self.temp_note_name.clone();
+
+                if let Some(tab) = self.active_tab_mut() {
+                    let new_index_entry = VaultIndexEntry {
+                        id: tab.note.id,
+                        name,
+                        parent_folder: tab.note.parent_folder,
+                        metadata: tab.note.metadata.clone(),
+                    };
+
+                    tab.note = new_index_entry;
+                    tab.dirty = true;
+                    self.edit_name = false;
+                }
+            }
+
+            EditorMessage::Save => {
+                let tab = self.editor_pane_id()
+                    .zip(self.tabs().and_then(Tabs::active_index));
 
-                        self.opened_file = Some(new_index_entry);
-                        self.edit_name = false;
+                if let Some(( id, i )) = tab {
+                    if let Err(e) = self.save_tab(id, i) {
+                        error!(error = %e, "could not save note");
                     }
+                }
+            }
 
-                    None => {}
+            EditorMessage::AutosaveTick => {
+                let delay = Duration::from_secs(self.settings.editor.autosave_delay);
+
+                for ( id, i ) in self.tab_indexes() {
+                    let due = self.editor_tabs.get(&id)
+                        .and_then(|tabs| tabs.get(i))
+                        .is_some_and(|tab| tab.dirty && tab.last_edit.elapsed() >= delay);
+
+                    if due {
+                        if let Err(e) = self.save_tab(id, i) {
+                            error!(error = %e, "could not save note automatically");
+                        }
+                    }
                 }
             }
 
-            // EditorMessage::Save => {
-            //     // let text = self.content.text();
-            // }
+            EditorMessage::PreviewTick => {
+                if self.preview_outdated.is_some_and(|edited| edited.elapsed() >= PREVIEW_DELAY) {
+                    self.refresh_preview();
+                }
+            }
 
             EditorMessage::New => {
-                self.opened_file = Some(VaultIndexEntry {
-                    id: 0,
-                    name: String::from("Untitled Note"),
-                    parent_folder: None,
-                });
+                if let Some(tabs) = self.tabs_mut() {
+                    tabs.open(
+                        VaultIndexEntry {
+                            id: 0,
+                            name: String::from("Untitled Note"),
+                            parent_folder: None,
+                            metadata: NoteMetadata::default(),
+                        },
+                        NoteDocument::default(),
+                    );
+                }
+
+                self.edit_name = false;
+                self.refresh_preview();
+            }
+
+            EditorMessage::Tab(id, message) => {
+                // Pressing a tab of another pane focuses the pane
+                if let TabMessage::Pressed(_) = message {
+                    if let Some(pane) = self.editor_pane_with_id(id) {
+                        self.select_editor(pane);
+                    }
+                }
+
+                let active = self.active_tab().map(|tab| tab.id);
+
+                let tabs = match self.editor_tabs.get_mut(&id) {
+                    Some(tabs) => tabs,
+                    None => { return Task::none(); }
+                };
+
+                match message {
+                    TabMessage::Close(i) => {
+                        match tabs.get(i) {
+                            Some(tab) if !tab.is_pinned => {}
+                            _ => { return Task::none(); }
+                        }
+
+                        // The note stays opened if its changes couldn't be saved
+                        if let Err(e) = self.save_tab(id, i) {
+                            error!(error = %e, "could not save note before closing it");
+                            return Task::none();
+                        }
+
+                        if let Some(tabs) = self.editor_tabs.get_mut(&id) {
+                            tabs.remove(i);
+                        }
+                    }
+
+                    message => { tabs.update(message); }
+                }
+
+                if self.active_tab().map(|tab| tab.id) != active {
+                    self.edit_name = false;
+                    self.refresh_preview();
+                }
+            }
+
+            EditorMessage::Lock => {
+                if self.is_unlocked() {
+                    match self.lock() {
+                        Ok(()) => {
+                            return text_input::focus(text_input::Id::new("vault-password"));
+                        }
+
+                        // The notes stay opened, locking is tried again when
+                        // the vault is idle for a while again
+                        Err(e) => {
+                            error!(error = %e, "could not save note before locking the vault");
+                            self.lock_error = Some(e);
+                        }
+                    }
+                }
+            }
+
+            EditorMessage::LockTick => {
+                if let Some(idle_timeout) = self.settings.auto_lock.idle_timeout() {
+                    if self.is_unlocked()
+                        && self.last_activity.elapsed() >= idle_timeout {
+                        return Task::done(EditorMessage::Lock);
+                    }
+                }
+            }
+
+            EditorMessage::MinimizedChecked(minimized) => {
+                if minimized == Some(true) {
+                    return Task::done(EditorMessage::Lock);
+                }
             }
 
             // EditorMessage::PVLoading => {
@@ -364,59 +1013,87 @@ impl Editor {
             // }
 
             EditorMessage::PVVaultEmpty => {
-                println!("Password Validation: vault empty");
+                debug!("password validation: no vault selected");
             }
 
             EditorMessage::PVDoesNotMatch => {
-                println!("Password Validation: password does not match");
+                debug!("password validation: wrong password");
+                self.vault_password.zeroize();
                 self.vault_password_status
                     = EditorVaultPasswordStatus::DoesNotMatch;
             }
 
+            EditorMessage::PVLockedOut(retry_at) => {
+                debug!("password validation: locked out");
+                self.vault_password.zeroize();
+                self.vault_password_status
+                    = EditorVaultPasswordStatus::LockedOut(retry_at);
+            }
+
+            EditorMessage::PVKeyMaterialWiped => {
+                warn!("password validation: key material wiped");
+                self.vault_password.zeroize();
+                self.vault_password_status
+                    = EditorVaultPasswordStatus::KeyMaterialWiped;
+            }
+
+            EditorMessage::PVError(e) => {
+                warn!(error = %e, "password validation failed");
+                self.vault_password.zeroize();
+                self.vault_password_status = EditorVaultPasswordStatus::Error(e);
+            }
+
+            EditorMessage::LockoutTick => {
+                if let EditorVaultPasswordStatus::LockedOut(retry_at)
+                    = self.vault_password_status {
+                    if unix_now() >= retry_at {
+                        self.vault_password_status = EditorVaultPasswordStatus::None;
+
+                        return text_input::focus(text_input::Id::new("vault-password"));
+                    }
+                }
+            }
+
             EditorMessage::PVPasswordEmpty => {
                 self.vault_password_status = EditorVaultPasswordStatus::Empty;
-                println!("Password Validation password field is empty");
+                debug!("password validation: password is empty");
             }
 
-            EditorMessage::PVAuthenticated => {
-                println!("Password Validation authenticated");
+            EditorMessage::PVAuthenticated(key) => {
+                debug!("password validation: authenticated");
+                self.vault_key = Some(key);
+                self.vault_password.zeroize();
+                self.reconcile_vault();
                 self.screen = EditorScreen::Editor;
                 self.vault_password_status
                     = EditorVaultPasswordStatus::Authenticated;
             }
 
             EditorMessage::PVVaultAndPasswordEmpty => {
-                println!("Password Validation vault name and password are empty");
+                debug!("password validation: vault name and password are empty");
             }
 
-            EditorMessage::PVInitSender(t_handle, mut sender) => {
-                match self.opened_vault.clone() {
-                    Some(vault_name) => {
-                        let password = self.vault_password.clone();
-                        println!(
-                            "Got the sender from the worker, vault_name: {}, vault_password: {}",
-                            vault_name,
-                            password,
+            EditorMessage::PVInitSender(mut sender) => {
+                if let Some(vault_name) = self.opened_vault.clone() {
+                    let password = self.vault_password.clone();
+                    let keyfile = self.vault_keyfile.clone();
+                    debug!(vault = %vault_name, "sending the password to the unlock worker");
+
+                    async fn send_values(
+                        sender: &mut Sender<(String, SecretString, Option<PathBuf>)>,
+                        vault_name: String, password: SecretString,
+                        keyfile: Option<PathBuf>,
+                    ) {
+                        let _ = sender.send((vault_name, password, keyfile)).await;
+                    }
+
+                    let thread_handle = thread::spawn(move || {
+                        executor::block_on(
+                            send_values(&mut sender, vault_name, password, keyfile)
                         );
+                    });
 
-                        async fn send_values(
-                            sender: &mut Sender<(String, String)>,
-                            vault_name: String, password: String
-                        ) {
-                            let _ = sender.send((vault_name, password)).await;
-                        }
-
-                        let thread_handle = thread::spawn(move || {
-                            executor::block_on(send_values(&mut sender, vault_name, password));
-                            println!("** Thread 2: Sending the vault name and password");
-                        });
-
-                        let _ = thread_handle.join();
-
-                        t_handle.thread().unpark();
-                    }
-
-                    None => {}
+                    let _ = thread_handle.join();
                 }
             }
         }
@@ -424,138 +1101,197 @@ impl Editor {
         Task::none()
     }
 
-    pub fn view(&self) -> Element<EditorMessage> {
-        let vault_name;
+    pub fn view(&self) -> Element<'_, EditorMessage> {
+        let vault_name = self.opened_vault.clone().unwrap_or_default();
 
-        match self.opened_vault.clone() {
-            Some(v_name) => { vault_name = v_name; }
-            None => { vault_name = String::default(); }
-        }
+        let appearance = &self.settings.appearance;
 
         let style = container::Style {
-            background: Some(Background::Color(Color {
-                r: 0.05,
-                g: 0.09,
-                b: 0.11,
-                a: 1.0
-            })),
+            background: Some(Background::Color(to_color(appearance.background))),
             ..container::Style::default()
         };
 
         match self.screen {
             EditorScreen::Editor => {
-                let pane_grid = pane_grid::PaneGrid::new(&self.panes, |_id, pane, _is_maximized|{
-                    // let is_focused = self.focused_pane == Some(id);
+                let pane_grid = pane_grid::PaneGrid::new(&self.panes, |id, pane, _is_maximized|{
+                    let is_focused = self.editor_pane == Some(id);
+                    let pane_id = pane.id;
 
                     let mut pane_grid_content = pane_grid::Content::new(responsive(move |_size|{
                         if pane.pane_type == PaneType::TextEditor {
                             let _style = container::Style {
-                                background: Some(Background::Color(Color {
-                                    r: 0.054,
-                                    g: 0.1,
-                                    b: 0.14,
-                                    a: 1.0,
-                                })),
+                                background: Some(Background::Color(
+                                    to_color(appearance.editor_background)
+                                )),
                                 ..container::Style::default()
                             };
 
-                            if self.opened_file == None {
-                                container(column![
-                                    text("Select a file from the explorer on the left to view/edit!")
-                                        .align_x(Center)
-                                        // .align_y(Center)
+                            let tabs = self.editor_tabs.get(&pane_id);
+
+                            match tabs.zip(tabs.and_then(Tabs::active)) {
+                                None => {
+                                    let mut hints = column![
+                                        text("Select a file from the explorer on the left to view/edit!")
+                                            .align_x(Center)
+                                            // .align_y(Center)
+                                            .width(Fill)
+                                            // .height(Fill)
+                                            .center(),
+                                        text("Ctrl + N for new note")
+                                            .align_x(Center)
+                                            // .align_y(Center)
+                                            .width(Fill)
+                                            .center(),
+                                        text("Ctrl + M to preview the note's Markdown")
+                                            .align_x(Center)
+                                            .width(Fill)
+                                            .center(),
+                                        text("Ctrl + Tab to switch notes, Ctrl + W to close one")
+                                            .align_x(Center)
+                                            .width(Fill)
+                                            .center(),
+                                        text("Right click a note's tab to pin it")
+                                            .align_x(Center)
+                                            .width(Fill)
+                                            .center(),
+                                        text("Ctrl + \\ to split the editor, Ctrl + ] to go to the next split")
+                                            .align_x(Center)
+                                            .width(Fill)
+                                            .center(),
+                                        text("Ctrl + P to change the vault's password")
+                                            .align_x(Center)
+                                            .width(Fill)
+                                            .center(),
+                                        text("Ctrl + , for the vault's settings")
+                                            .align_x(Center)
+                                            .width(Fill)
+                                            .center(),
+                                        text("Ctrl + . for the app's settings")
+                                            .align_x(Center)
+                                            .width(Fill)
+                                            .center(),
+                                    ];
+
+                                    // Another pane shows the notes when this one is closed
+                                    if self.editor_tabs.len() > 1 {
+                                        hints = hints.push(Space::new(0, 16));
+                                        hints = hints.push(
+                                            container(
+                                                button(text("Close this split"))
+                                                    .style(button::secondary)
+                                                    .on_press(EditorMessage::Close(id))
+                                            )
+                                                .align_x(Center)
+                                                .width(Fill)
+                                        );
+                                    }
+
+                                    container(hints)
+                                        .style(move |_| _style)
                                         .width(Fill)
-                                        // .height(Fill)
-                                        .center(),
-                                    text("Ctrl + N for new note")
+                                        .height(Fill)
                                         .align_x(Center)
-                                        // .align_y(Center)
-                                        .width(Fill)
-                                        .center(),
-                                ])
-                                    .style(move |_| _style)
-                                    .width(Fill)
-                                    .height(Fill)
-                                    .align_x(Center)
-                                    .align_y(Center)
-                                    .into()
-                            } else {
-                                let opened_file;
-
-                                if let Some(o_file) = &self.opened_file {
-                                    opened_file = o_file.name.as_str();
-                                } else {
-                                    opened_file = ""; 
+                                        .align_y(Center)
+                                        .into()
                                 }
 
-                                let mut ui_column = column![];
+                                Some(( tabs, tab )) => {
+                                    let opened_file = tab.note.name.as_str();
 
-                                if self.edit_name {
-                                    ui_column = ui_column.push(row![
-                                        text_input("", self.temp_note_name.as_str())
-                                            .size(18)
-                                            .on_input(
-                                                EditorMessage::NoteNameChanged
-                                            )
-                                            .on_submit(
-                                                EditorMessage::SaveNoteName
-                                            ),
-                                        Space::new(4, 0),
-                                        button(
-                                            svg("./assets/check-line.svg")
-                                                .width(16)
-                                                .height(22)
+                                    let mut ui_column = column![
+                                        tabs.view(
+                                            to_color(appearance.title_bar_background),
+                                            to_color(appearance.editor_background),
                                         )
-                                            .on_press(EditorMessage::SaveNoteName),
-                                        Space::new(4, 0),
-                                        button(
-                                            svg("./assets/close-line.svg")
-                                                .width(16)
-                                                .height(22)
-                                        )
-                                            .on_press(
-                                                EditorMessage::EditNoteName(
-                                                    false
-                                            ))
-                                    ]);
-                                } else {
-                                    ui_column = ui_column.push(column![
-                                        Space::new(0, 2),
-                                        row![
-                                            Space::new(5, 0),
-                                            column![
-                                                Space::new(0, 3),
-                                                text(opened_file)
-                                                    .size(18),
-                                            ],
-                                            Space::new(16, 0),
+                                            .map(move |message| EditorMessage::Tab(pane_id, message)),
+                                    ];
+
+                                    if self.edit_name && is_focused {
+                                        ui_column = ui_column.push(row![
+                                            text_input("", self.temp_note_name.as_str())
+                                                .size(18)
+                                                .on_input(
+                                                    EditorMessage::NoteNameChanged
+                                                )
+                                                .on_submit(
+                                                    EditorMessage::SaveNoteName
+                                                ),
+                                            Space::new(4, 0),
                                             button(
-                                                svg("./assets/pencil-fill.svg")
+                                                svg("./assets/check-line.svg")
                                                     .width(16)
-                                                    .height(18)
+                                                    .height(22)
+                                            )
+                                                .on_press(EditorMessage::SaveNoteName),
+                                            Space::new(4, 0),
+                                            button(
+                                                svg("./assets/close-line.svg")
+                                                    .width(16)
+                                                    .height(22)
                                             )
                                                 .on_press(
                                                     EditorMessage::EditNoteName(
-                                                        !self.edit_name
-                                                    )
+                                                        false
+                                                ))
+                                        ]);
+                                    } else {
+                                        ui_column = ui_column.push(column![
+                                            Space::new(0, 2),
+                                            row![
+                                                Space::new(5, 0),
+                                                column![
+                                                    Space::new(0, 3),
+                                                    text(opened_file)
+                                                        .size(18),
+                                                ],
+                                                Space::new(16, 0),
+                                                button(
+                                                    svg("./assets/pencil-fill.svg")
+                                                        .width(16)
+                                                        .height(18)
                                                 )
-                                        ],
-                                        Space::new(0, 2),
-                                    ]);
-                                }
+                                                    .on_press(
+                                                        EditorMessage::EditNoteName(
+                                                            !self.edit_name
+                                                        )
+                                                    )
+                                            ],
+                                            Space::new(0, 2),
+                                        ]);
+                                    }
 
-                                ui_column = ui_column.push(
-                                    text_editor(&self.content)
-                                        .on_action(EditorMessage::ActionPerformed)
-                                        .height(Fill)
-                                );
+                                    ui_column = ui_column.push(
+                                        text_editor(&tab.content)
+                                            .size(self.settings.editor.text_size)
+                                            .on_action(move |action| EditorMessage::ActionPerformed(pane_id, action))
+                                            .highlight_with::<MarkdownHighlighter>(
+                                                ( pane_id, tab.id ),
+                                                |highlight, theme| highlight.to_format(theme),
+                                            )
+                                            .height(Fill)
+                                    );
 
-                                container(ui_column)
-                                    .style(move |_| _style)
-                                    .into()
+                                    container(ui_column)
+                                        .style(move |_| _style)
+                                        .into()
+                                }
                             }
+                        } else if pane.pane_type == PaneType::Preview {
+                            container(
+                                self.preview.view(self.settings.editor.text_size)
+                                    .map(EditorMessage::Preview)
+                            )
+                                .style(move |_| container::Style {
+                                    background: Some(Background::Color(
+                                        to_color(appearance.editor_background)
+                                    )),
+                                    ..container::Style::default()
+                                })
+                                .width(Fill)
+                                .height(Fill)
+                                .into()
                         } else {
-                            if self.explorer_files.is_empty() {
+                            let notes: Element<EditorMessage> = if self.explorer_files.is_empty() {
                                 container(text!("This shows the notes here..."))
                                     .style(move |_| style)
                                     .height(Fill)
@@ -567,33 +1303,101 @@ impl Editor {
                                 container(text!("WIP"))
                                     .style(move |_| style)
                                     .into()
+                            };
+
+                            if self.conflicts.is_empty() {
+                                notes
+                            } else {
+                                column![self.conflicts_view(), notes].into()
                             }
                         }
                     }));
 
+                    // Shows which split the shortcuts act on
+                    if is_focused && self.editor_tabs.len() > 1 {
+                        pane_grid_content = pane_grid_content.style(|theme: &Theme| container::Style {
+                            border: Border {
+                                color: theme.extended_palette().primary.base.color,
+                                width: 1.0,
+                                ..Border::default()
+                            },
+                            ..container::Style::default()
+                        });
+                    }
+
                     if pane.pane_type == PaneType::Explorer {
                         pane_grid_content = pane_grid_content.title_bar(
                             pane_grid::TitleBar::new(text!("Vault: {}", vault_name))
-                                .style(|_| container::Style {
-                                    background: Some(Background::Color(Color{
-                                        r: 0.04,
-                                        g: 0.05,
-                                        b: 0.07,
-                                        a: 1.0,
-                                    })),
+                                .style(move |_| container::Style {
+                                    background: Some(Background::Color(
+                                        to_color(appearance.title_bar_background)
+                                    )),
                                     ..container::Style::default()
                                 })
                         );
                     }
 
-                    return pane_grid_content;
+                    pane_grid_content
                 })
                 .width(Fill)
                 .height(Fill)
                 .on_click(EditorMessage::Clicked)
                 .on_resize(10, EditorMessage::Resized);
 
-                container(pane_grid)
+                let mut cols = column![pane_grid];
+
+                if let Some(e) = &self.lock_error {
+                    cols = cols.push(
+                        text!("The vault was not locked, a note could not be saved: {}", e)
+                            .width(Fill)
+                            .align_x(Center)
+                            .color(Color::new(0.9, 0.0, 0.0, 1.0))
+                    );
+                }
+
+                container(cols)
+                    .width(Fill)
+                    .height(Fill)
+                    .into()
+            }
+
+            EditorScreen::ChangePassword => {
+                container(self.change_password.view().map(EditorMessage::ChangePassword))
+                    .style(move |_| style)
+                    .width(Fill)
+                    .height(Fill)
+                    .into()
+            }
+
+            EditorScreen::Recovery => {
+                container(self.recovery.view().map(EditorMessage::Recovery))
+                    .style(move |_| style)
+                    .width(Fill)
+                    .height(Fill)
+                    .into()
+            }
+
+            EditorScreen::VaultSettings => {
+                container(self.vault_settings.view().map(EditorMessage::VaultSettings))
+                    .style(move |_| style)
+                    .width(Fill)
+                    .height(Fill)
+                    .align_x(Center)
+                    .into()
+            }
+
+            EditorScreen::AppSettings => {
+                container(self.app_settings.view().map(EditorMessage::AppSettings))
+                    .style(move |_| style)
+                    .width(Fill)
+                    .height(Fill)
+                    .align_x(Center)
+                    .into()
+            }
+
+            EditorScreen::Conflict => {
+                container(self.conflict.view().map(EditorMessage::Conflict))
+                    .style(move |_| style)
                     .width(Fill)
                     .height(Fill)
                     .into()
@@ -609,12 +1413,7 @@ impl Editor {
             }
 
             EditorScreen::PasswordPrompt => {
-                let vault_name;
-
-                match self.opened_vault.clone() {
-                    Some(v_name) => { vault_name = v_name; }
-                    None => { vault_name = String::default(); }
-                }
+                let vault_name = self.opened_vault.clone().unwrap_or_default();
 
                 let mut cols = column![
                     Space::new(Fill, 100),
@@ -632,14 +1431,59 @@ impl Editor {
                     )
                         .align_x(Center)
                         .width(Fill),
-                    Space::new(Fill, 16),
+                ];
+
+                if self.vault_uses_keyfile {
+                    let keyfile_text = match &self.vault_keyfile {
+                        Some(path) => format!("Keyfile: {}", path.display()),
+                        None => String::from("This vault also needs its keyfile"),
+                    };
+
+                    cols = cols.push(Space::new(Fill, 16));
+                    cols = cols.push(
+                        text(keyfile_text)
+                            .align_x(Center)
+                            .width(Fill)
+                    );
+                    cols = cols.push(Space::new(Fill, 8));
+                    cols = cols.push(
+                        container(
+                            button(text("Choose Keyfile"))
+                                .style(button::secondary)
+                                .on_press(EditorMessage::PickKeyfile)
+                        )
+                            .align_x(Center)
+                            .width(Fill)
+                    );
+                }
+
+                cols = cols.push(Space::new(Fill, 16));
+                cols = cols.push(
                     container(
                         button(text("Open Vault"))
-                            .on_press(EditorMessage::VaultPasswordSubmitted)
+                            .on_press_maybe(match self.vault_password_status {
+                                EditorVaultPasswordStatus::LockedOut(_)
+                                | EditorVaultPasswordStatus::KeyMaterialWiped => None,
+
+                                _ => Some(EditorMessage::VaultPasswordSubmitted),
+                            })
                     )
                         .align_x(Center)
-                        .width(Fill),
-                ];
+                        .width(Fill)
+                );
+
+                if self.vault_has_recovery {
+                    cols = cols.push(Space::new(Fill, 8));
+                    cols = cols.push(
+                        container(
+                            button(text("Forgot password?"))
+                                .style(button::text)
+                                .on_press(EditorMessage::ForgotPassword)
+                        )
+                            .align_x(Center)
+                            .width(Fill)
+                    );
+                }
 
                 match self.vault_password_status {
                     EditorVaultPasswordStatus::DoesNotMatch => {
@@ -664,10 +1508,9 @@ impl Editor {
                         );
                     }
 
-                    EditorVaultPasswordStatus::NONE => {}
+                    EditorVaultPasswordStatus::None => {}
 
                     EditorVaultPasswordStatus::Loading => {
-                        println!("editor vault password status: loading");
                         cols = column![
                             text!("Please wait...")
                                 .align_x(Center)
@@ -676,6 +1519,42 @@ impl Editor {
                     }
 
                     EditorVaultPasswordStatus::Authenticated => {}
+
+                    EditorVaultPasswordStatus::LockedOut(retry_at) => {
+                        cols = cols.push(Space::new(Fill, 16));
+
+                        cols = cols.push(
+                            text!(
+                                "Too many failed attempts, try again in {} s",
+                                retry_at.saturating_sub(unix_now()),
+                            )
+                                .align_x(Center)
+                                .width(Fill)
+                                .color(Color::new(0.9, 0.0, 0.0, 1.0))
+                        );
+                    }
+
+                    EditorVaultPasswordStatus::KeyMaterialWiped => {
+                        cols = cols.push(Space::new(Fill, 16));
+
+                        cols = cols.push(
+                            text("Too many failed attempts, the vault's keys were wiped from this device. Restore the vault from your backup.")
+                                .align_x(Center)
+                                .width(Fill)
+                                .color(Color::new(0.9, 0.0, 0.0, 1.0))
+                        );
+                    }
+
+                    EditorVaultPasswordStatus::Error(ref e) => {
+                        cols = cols.push(Space::new(Fill, 16));
+
+                        cols = cols.push(
+                            text(e)
+                                .align_x(Center)
+                                .width(Fill)
+                                .color(Color::new(0.9, 0.0, 0.0, 1.0))
+                        );
+                    }
                 }
 
                 container(cols)
@@ -689,19 +1568,405 @@ impl Editor {
 
     pub fn subscription(&self) -> Subscription<EditorMessage> {
         let event_subscription = event::listen().map(EditorMessage::Event);
-        let auth_sub;
+        let auth_sub = if self.vault_password_status == EditorVaultPasswordStatus::Loading {
+            Subscription::run(auth_worker)
+        } else {
+            Subscription::none()
+        };
 
-        if self.vault_password_status == EditorVaultPasswordStatus::Loading {
-            auth_sub = Subscription::run(auth_worker);
+        let lock_sub = if self.is_unlocked() && self.settings.auto_lock.idle_timeout().is_some() {
+            time::every(Duration::from_secs(1))
+                .map(|_| EditorMessage::LockTick)
+        } else if let EditorVaultPasswordStatus::LockedOut(_) = self.vault_password_status {
+            time::every(Duration::from_secs(1))
+                .map(|_| EditorMessage::LockoutTick)
         } else {
-            auth_sub = Subscription::none();
-        }
+            Subscription::none()
+        };
+
+        let dirty = self.editor_tabs.values().flat_map(Tabs::iter).any(|tab| tab.dirty);
+
+        let autosave_sub = if self.is_unlocked() && dirty && self.settings.editor.autosave {
+            time::every(Duration::from_secs(1))
+                .map(|_| EditorMessage::AutosaveTick)
+        } else {
+            Subscription::none()
+        };
+
+        let preview_sub = if self.preview_outdated.is_some() {
+            time::every(PREVIEW_DELAY / 3)
+                .map(|_| EditorMessage::PreviewTick)
+        } else {
+            Subscription::none()
+        };
 
         Subscription::batch([
             event_subscription,
             auth_sub,
+            lock_sub,
+            autosave_sub,
+            preview_sub,
         ])
     }
+
+    /// Reads what the password prompt needs to know about the opened vault.
+    fn refresh_vault_info(&mut self) {
+        let info = self.opened_vault.as_deref()
+            .map(|name| get_vault_storage(name).and_then(|storage| read_vault_info(&storage)));
+
+        match info {
+            Some(Ok(info)) => {
+                self.vault_uses_keyfile = info.uses_keyfile();
+                self.vault_has_recovery = info.has_recovery_phrase();
+            }
+
+            _ => {
+                self.vault_uses_keyfile = false;
+                self.vault_has_recovery = false;
+            }
+        }
+    }
+
+    /// Merges what the sync tool left in the opened vault and finds the
+    /// conflicted notes, see [`reconcile`].
+    fn reconcile_vault(&mut self) {
+        let result = match ( self.opened_vault.as_deref(), &self.vault_key ) {
+            ( Some(name), Some(key) ) => {
+                get_vault_storage(name).and_then(|storage| {
+                    let report = reconcile(&storage, key)?;
+
+                    if report.merged_indexes > 0 || report.merged_notes > 0 {
+                        commit_vault(&storage, "Merge conflict copies")?;
+                    }
+
+                    Ok(report)
+                })
+            }
+
+            _ => { return; }
+        };
+
+        match result {
+            Ok(report) => { self.conflicts = report.conflicts; }
+            Err(e) => { error!(error = %e, "could not check the vault for sync conflicts"); }
+        }
+    }
+
+    /// Lists the conflicted notes at the top of the explorer.
+    fn conflicts_view(&self) -> Element<'_, EditorMessage> {
+        let mut conflicts_column = column![
+            text("Conflicted notes")
+                .size(14)
+                .color(Color::new(0.9, 0.6, 0.0, 1.0)),
+        ]
+            .padding(8)
+            .spacing(4);
+
+        for ( i, conflict ) in self.conflicts.iter().enumerate() {
+            conflicts_column = conflicts_column.push(
+                button(text!("{} (conflicted)", conflict.name).size(14))
+                    .style(button::text)
+                    .on_press(EditorMessage::OpenConflict(i))
+            );
+        }
+
+        conflicts_column.into()
+    }
+
+    /// Reads the note with the given id again if it's opened, e.g. after it
+    /// was replaced by its conflict copy.
+    fn reload_opened_note(&mut self, id: u32) {
+        let ( pane_id, i ) = match self.editor_tabs.iter()
+            .find_map(|( pane_id, tabs )| tabs.find(id).map(|i| ( *pane_id, i ))) {
+            Some(tab) => tab,
+            None => { return; }
+        };
+
+        let result = match ( self.opened_vault.as_deref(), &self.vault_key ) {
+            ( Some(name), Some(key) ) => {
+                get_vault_storage(name).and_then(|storage| read_note_document(&storage, key, id))
+            }
+
+            _ => { return; }
+        };
+
+        match result {
+            Ok(document) => {
+                if let Some(tab) = self.editor_tabs.get_mut(&pane_id).and_then(|tabs| tabs.get_mut(i)) {
+                    tab.set_document(document);
+                    tab.dirty = false;
+                }
+
+                self.refresh_preview();
+            }
+
+            Err(e) => { error!(error = %e, "could not read the note again"); }
+        }
+    }
+
+    /// Shows the note of the focused text editor in the preview, if it's
+    /// shown.
+    fn refresh_preview(&mut self) {
+        self.preview_outdated = None;
+
+        if !self.show_preview {
+            return;
+        }
+
+        match self.active_tab() {
+            Some(tab) => { self.preview.set_text(&tab.content.text()); }
+            None => { self.preview.clear(); }
+        }
+    }
+
+    /// Shows the screen to change the password of the opened vault.
+    fn open_change_password(&mut self) {
+        self.change_password.reset(
+            if self.vault_uses_keyfile { self.vault_keyfile.clone() } else { None }
+        );
+
+        self.screen = EditorScreen::ChangePassword;
+    }
+
+    /// Applies changed settings to the editor.
+    fn apply_settings(&mut self, settings: Settings) {
+        if settings.editor.explorer_ratio != self.settings.editor.explorer_ratio {
+            if let Some(split) = self.explorer_split {
+                self.panes.resize(split, settings.editor.explorer_ratio as f32);
+            }
+        }
+
+        self.settings = settings;
+    }
+
+    /// Whether the vault is unlocked, i.e. any screen after the password
+    /// prompt is shown.
+    fn is_unlocked(&self) -> bool {
+        self.vault_key.is_some()
+    }
+
+    /// Id of the focused text editor pane.
+    fn editor_pane_id(&self) -> Option<usize> {
+        self.editor_pane.and_then(|pane| self.panes.get(pane)).map(|pane| pane.id)
+    }
+
+    /// The notes opened in the focused text editor pane.
+    fn tabs(&self) -> Option<&Tabs> {
+        self.editor_pane_id().and_then(|id| self.editor_tabs.get(&id))
+    }
+
+    fn tabs_mut(&mut self) -> Option<&mut Tabs> {
+        let id = self.editor_pane_id()?;
+        self.editor_tabs.get_mut(&id)
+    }
+
+    /// The note shown in the focused text editor pane.
+    fn active_tab(&self) -> Option<&Tab> {
+        self.tabs().and_then(Tabs::active)
+    }
+
+    fn active_tab_mut(&mut self) -> Option<&mut Tab> {
+        self.tabs_mut().and_then(Tabs::active_mut)
+    }
+
+    /// The pane id and index of every opened note.
+    fn tab_indexes(&self) -> Vec<( usize, usize )> {
+        self.editor_tabs.iter()
+            .flat_map(|( id, tabs )| ( 0..tabs.len() ).map(|i| ( *id, i )))
+            .collect()
+    }
+
+    /// The text editor panes, from left to right and top to bottom.
+    fn editor_panes(&self) -> Vec<pane_grid::Pane> {
+        let mut panes = vec![];
+        panes_in_order(self.panes.layout(), &mut panes);
+
+        panes.retain(|pane| {
+            self.panes.get(*pane).is_some_and(|state| state.pane_type == PaneType::TextEditor)
+        });
+
+        panes
+    }
+
+    fn editor_pane_with_id(&self, id: usize) -> Option<pane_grid::Pane> {
+        self.panes.iter()
+            .find(|( _, state )| state.pane_type == PaneType::TextEditor && state.id == id)
+            .map(|( pane, _ )| *pane)
+    }
+
+    /// Makes the text editor pane the one the shortcuts act on and the preview
+    /// shows.
+    fn select_editor(&mut self, pane: pane_grid::Pane) {
+        if self.editor_pane == Some(pane) {
+            return;
+        }
+
+        self.editor_pane = Some(pane);
+        self.edit_name = false;
+        self.refresh_preview();
+    }
+
+    /// Focuses the text editor pane, and its text editor to type in it.
+    fn focus_editor(&mut self, pane: pane_grid::Pane) -> Task<EditorMessage> {
+        self.focused_pane = Some(pane);
+        self.select_editor(pane);
+
+        // The text editors are the only widgets of the panes that can be
+        // focused, in the order of the panes
+        let index = self.panes.iter()
+            .filter(|( _, state )| {
+                state.pane_type == PaneType::TextEditor
+                    && self.editor_tabs.get(&state.id).is_some_and(|tabs| tabs.active().is_some())
+            })
+            .position(|( p, _ )| *p == pane);
+
+        operate(FocusEditor { index, current: 0 })
+    }
+
+    /// Closes the text editor pane after saving its notes. The last text
+    /// editor pane and pinned panes aren't closed.
+    fn close_editor(&mut self, pane: pane_grid::Pane) -> Task<EditorMessage> {
+        let id = match self.panes.get(pane) {
+            Some(state) if state.pane_type == PaneType::TextEditor && !state.is_pinned => state.id,
+            _ => { return Task::none(); }
+        };
+
+        if self.editor_tabs.len() <= 1 {
+            return Task::none();
+        }
+
+        // The pane stays if one of its notes couldn't be saved
+        for i in 0..self.editor_tabs.get(&id).map_or(0, Tabs::len) {
+            if let Err(e) = self.save_tab(id, i) {
+                error!(error = %e, "could not save note before closing the pane");
+                return Task::none();
+            }
+        }
+
+        self.editor_tabs.remove(&id);
+
+        let sibling = self.panes.close(pane).map(|( _, sibling )| sibling);
+        self.focused_pane = sibling;
+
+        if self.editor_pane != Some(pane) {
+            return Task::none();
+        }
+
+        // The sibling if it's a text editor, else the first text editor
+        let next = sibling
+            .filter(|sibling| self.editor_panes().contains(sibling))
+            .or_else(|| self.editor_panes().first().copied());
+
+        match next {
+            Some(next) => self.focus_editor(next),
+            None => Task::none(),
+        }
+    }
+
+    /// Saves the note opened in the `i`th tab of the text editor pane with the
+    /// given id, if it has unsaved changes.
+    fn save_tab(&mut self, pane_id: usize, i: usize) -> Result<(), String> {
+        let shown_in_preview = self.show_preview
+            && self.editor_pane_id() == Some(pane_id)
+            && self.tabs().and_then(Tabs::active_index) == Some(i);
+
+        let tab = match self.editor_tabs.get_mut(&pane_id).and_then(|tabs| tabs.get_mut(i)) {
+            Some(tab) if tab.dirty => tab,
+            _ => { return Ok(()); }
+        };
+
+        let storage = match self.opened_vault.as_deref().map(get_vault_storage) {
+            Some(storage) => storage?,
+            None => { return Err(String::from("No vault is opened")); }
+        };
+
+        match &self.vault_key {
+            Some(key) => {
+                // The edits are recorded in the document as they're made, the
+                // difference with the text only covers one that couldn't be
+                let text = tab.content.text();
+                let document = tab.document.get_or_insert_with(|| NoteDocument::from_text(&text));
+
+                if document.text() != text {
+                    warn!(note = tab.note.id, "the note's document is behind its text");
+                    document.update(&text)?;
+                }
+
+                save_note_document(&storage, key, &mut tab.note, document)?;
+                tab.dirty = false;
+
+                // Edits merged from another device since the note was opened
+                if let Some(document) = tab.document.take_if(|document| document.text() != text) {
+                    tab.set_document(document);
+
+                    if shown_in_preview {
+                        self.preview.set_text(&tab.content.text());
+                    }
+                }
+
+                commit_vault(&storage, &format!("Save note {}", tab.note.id))?;
+
+                if self.editor_tabs.values().flat_map(Tabs::iter).all(|tab| !tab.dirty) {
+                    self.lock_error = None;
+                }
+
+                Ok(())
+            }
+
+            None => Err(String::from("Vault is locked")),
+        }
+    }
+
+    /// Saves the notes of all the tabs that have unsaved changes.
+    fn save_all_tabs(&mut self) {
+        for ( pane_id, i ) in self.tab_indexes() {
+            if let Err(e) = self.save_tab(pane_id, i) {
+                error!(error = %e, "could not save note");
+            }
+        }
+    }
+
+    /// Locks the vault.
+    ///
+    /// Unsaved changes are saved first, then everything that was decrypted and
+    /// the vault's key are dropped and the password prompt is shown again. If
+    /// a note couldn't be saved, the vault stays unlocked so its changes aren't
+    /// lost.
+    fn lock(&mut self) -> Result<(), String> {
+        let mut result = Ok(());
+
+        for ( pane_id, i ) in self.tab_indexes() {
+            if let Err(e) = self.save_tab(pane_id, i) {
+                result = Err(e);
+            }
+        }
+
+        result?;
+
+        self.vault_key = None;
+        self.lock_error = None;
+
+        for tabs in self.editor_tabs.values_mut() {
+            tabs.clear();
+        }
+
+        self.preview.clear();
+        self.preview_outdated = None;
+        self.explorer_files.clear();
+        self.conflicts.clear();
+        self.conflict.reset();
+        self.edit_name = false;
+        self.temp_note_name.clear();
+        self.change_password.reset(None);
+        self.recovery.reset();
+        self.vault_settings.reset();
+
+        self.vault_password.zeroize();
+        self.vault_password_status = EditorVaultPasswordStatus::None;
+        self.screen = EditorScreen::PasswordPrompt;
+
+        Ok(())
+    }
 }
 
 impl Default for Editor {
@@ -710,6 +1975,49 @@ impl Default for Editor {
     }
 }
 
+fn to_color(color: HexColor) -> Color {
+    Color::from_rgb8(color.r, color.g, color.b)
+}
+
+/// Lists the panes of the layout from left to right and top to bottom.
+fn panes_in_order(node: &pane_grid::Node, panes: &mut Vec<pane_grid::Pane>) {
+    match node {
+        pane_grid::Node::Split { a, b, .. } => {
+            panes_in_order(a, panes);
+            panes_in_order(b, panes);
+        }
+
+        pane_grid::Node::Pane(pane) => { panes.push(*pane); }
+    }
+}
+
+/// Focuses the `index`th widget that can be focused, and unfocuses the
+/// others.
+struct FocusEditor {
+    index: Option<usize>,
+    current: usize,
+}
+
+impl<T> Operation<T> for FocusEditor {
+    fn focusable(&mut self, state: &mut dyn Focusable, _id: Option<&Id>) {
+        match self.index == Some(self.current) {
+            true => state.focus(),
+            false => state.unfocus(),
+        }
+
+        self.current += 1;
+    }
+
+    fn container(
+        &mut self,
+        _id: Option<&Id>,
+        _bounds: Rectangle,
+        operate_on_children: &mut dyn FnMut(&mut dyn Operation<T>),
+    ) {
+        operate_on_children(self);
+    }
+}
+
 fn send_async_message(sender: &mut Sender<EditorMessage>, msg: EditorMessage) {
     async fn send_values(
         sender: &mut Sender<EditorMessage>,
@@ -721,32 +2029,63 @@ fn send_async_message(sender: &mut Sender<EditorMessage>, msg: EditorMessage) {
     executor::block_on(send_values(sender, msg));
 }
 
+/// Message shown on the password prompt when the vault couldn't be unlocked.
+fn unlock_error_message(error: &VaultError) -> String {
+    match error {
+        VaultError::LocalDirNotFound => String::from(
+            "Could not find the directory the vaults are stored in."
+        ),
+
+        VaultError::Io { context, source } => format!("{}: {}", context, source),
+
+        VaultError::Corrupt { file, .. } => format!(
+            "The vault's {} file is corrupted. Restore the vault from your backup.", file,
+        ),
+
+        VaultError::UnsupportedVersion { .. } => String::from(
+            "This vault was created by a newer version of Secure Notes, please update the app to open it."
+        ),
+
+        VaultError::WrongPassword => String::from("Wrong password!"),
+        VaultError::KeyfileRequired => String::from("This vault needs its keyfile, please choose it."),
+        VaultError::WrongKeyfile => String::from("Wrong keyfile!"),
+        VaultError::InvalidRecoveryPhrase(message) => message.clone(),
+
+        VaultError::LockedOut { retry_in } => format!(
+            "Too many failed attempts, try again in {} s", retry_in,
+        ),
+
+        VaultError::Crypto { context, source: Some(source) } => format!("{}: {}", context, source),
+        VaultError::Crypto { context, source: None } => context.to_string(),
+        VaultError::Invalid(message) => message.clone(),
+        VaultError::Remote(e) => e.to_string(),
+        VaultError::Git { .. } => error.to_string(),
+    }
+}
+
 fn auth_worker() -> impl Stream<Item = EditorMessage> {
     channel(1, move | mut sender | async move {
-        let ( pv_sender, mut pv_receiver ) = mpsc::channel::<(String, String)>(1);
+        let ( pv_sender, mut pv_receiver )
+            = mpsc::channel::<(String, SecretString, Option<PathBuf>)>(1);
 
         let mut sender_clone = sender.clone();
 
-        let t_handle = thread::spawn(move || {
+        thread::spawn(move || {
             let mut vault_name: String = String::default();
-            let mut password: String = String::default();
-
-            thread::park();
-
-            match pv_receiver.try_next() {
-                Ok(receiver_option) => {
-                    match receiver_option {
-                        Some((_vault_name, _password)) => {
-                            vault_name = _vault_name;
-                            password = _password;
-                        }
-
-                        None => {}
-                    }
+            let mut password: SecretString = SecretString::default();
+            let mut keyfile: Option<PathBuf> = None;
+
+            // Waits until the password is sent, the channel only ends when
+            // the editor dropped the sender without sending it
+            match executor::block_on(pv_receiver.next()) {
+                Some((_vault_name, _password, _keyfile)) => {
+                    vault_name = _vault_name;
+                    password = _password;
+                    keyfile = _keyfile;
                 }
 
-                Err(e) => {
-                    eprintln!("Thread 1: Error: {}", e);
+                None => {
+                    warn!("unlock worker could not receive the password");
                 }
             }
 
@@ -754,11 +2093,31 @@ fn auth_worker() -> impl Stream<Item = EditorMessage> {
             let password_empty = password.is_empty();
 
             if !vault_empty && !password_empty {
-                println!("Thread 1: authenticating");
-                if authenticate_vault(vault_name.as_str(), password.as_str()) {
-                    send_async_message(&mut sender, EditorMessage::PVAuthenticated);
-                } else {
-                    send_async_message(&mut sender, EditorMessage::PVDoesNotMatch);
+                debug!(vault = %vault_name, "unlocking vault");
+                let result = get_vault_storage(&vault_name).and_then(|storage| {
+                    unlock_vault(&storage, &vault_name, &password, keyfile.as_deref())
+                });
+
+                match result {
+                    Ok(key) => {
+                        send_async_message(&mut sender, EditorMessage::PVAuthenticated(key));
+                    }
+
+                    Err(e) => {
+                        let state = read_unlock_state(vault_name.as_str());
+
+                        let msg = if state.key_material_wiped {
+                            EditorMessage::PVKeyMaterialWiped
+                        } else if let Some(retry_at) = state.retry_at() {
+                            EditorMessage::PVLockedOut(retry_at)
+                        } else if let VaultError::WrongPassword = e {
+                            EditorMessage::PVDoesNotMatch
+                        } else {
+                            EditorMessage::PVError(unlock_error_message(&e))
+                        };
+
+                        send_async_message(&mut sender, msg);
+                    }
                 }
             } else {
                 if vault_empty && password_empty {
@@ -771,7 +2130,68 @@ fn auth_worker() -> impl Stream<Item = EditorMessage> {
             }
         });
 
-        let _ = sender_clone.send(EditorMessage::PVInitSender(Arc::new(t_handle), pv_sender)).await;
+        let _ = sender_clone.send(EditorMessage::PVInitSender(pv_sender)).await;
     })
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::{ env, process };
+
+    use super::*;
+
+    /// An unlocked editor without a vault, so saving a note fails, showing a
+    /// note with unsaved changes.
+    fn editor_with_unsaved_note() -> Editor {
+        let _ = crate::utils::set_data_dir(
+            &env::temp_dir().join(format!("secure-notes-test-{}", process::id()))
+        );
+
+        let mut editor = Editor::new();
+        editor.opened_vault = None;
+        editor.vault_key = Some(VaultKey::generate());
+        editor.screen = EditorScreen::Editor;
+
+        let note = VaultIndexEntry {
+            id: 1,
+            name: String::from("Note"),
+            parent_folder: None,
+            metadata: NoteMetadata::default(),
+        };
+
+        let tabs = editor.tabs_mut().unwrap();
+        tabs.open(note, NoteDocument::from_text("note"));
+        tabs.active_mut().unwrap().dirty = true;
+
+        editor
+    }
+
+    #[test]
+    fn keeps_the_vault_unlocked_when_a_note_cannot_be_saved() {
+        let mut editor = editor_with_unsaved_note();
+        let _ = editor.update(EditorMessage::Lock);
+
+        assert!(editor.is_unlocked());
+        assert_eq!(editor.screen, EditorScreen::Editor);
+        assert!(editor.lock_error.is_some());
+
+        let tab = editor.active_tab().unwrap();
+        assert!(tab.dirty);
+        assert_eq!(tab.content.text().trim_end(), "note");
+    }
+
+    #[test]
+    fn locks_the_vault_once_the_notes_are_saved() {
+        let mut editor = editor_with_unsaved_note();
+        assert!(editor.lock().is_err());
+
+        editor.tabs_mut().unwrap().active_mut().unwrap().dirty = false;
+        assert!(editor.lock().is_ok());
+
+        assert!(!editor.is_unlocked());
+        assert_eq!(editor.screen, EditorScreen::PasswordPrompt);
+        assert!(editor.lock_error.is_none());
+        assert!(editor.active_tab().is_none());
+    }
+}
@@ -5,31 +5,48 @@
 /// 2. Information about vaults and where to find them.
 ///     - Enter name of a vault parent folder.
 ///     - Enter password for vault.
+///     - Optionally choose or create a keyfile, needed together with the
+///       password to open the vault.
 ///     - Option to make this default vault (default selected for the first
-///     vault).
-/// 3. A "Done" page.
+///       vault).
+/// 3. A "Done" page, showing the recovery phrase of the new vault.
 ///
 /// ### Notes
 /// - At the bottom of each page, there are "Back" and "Next" buttons (except
-/// the third a.k.a "Done" page).
+///   the third a.k.a "Done" page).
 /// - Vault only created when user clicks on "Next" button on the second page
-
+///
 use std::{
-    env::args, thread::sleep, time::Duration, process::{ Command, exit },
+    env::{ args, current_exe }, thread::sleep, time::Duration, process::{ Command, exit },
+    path::PathBuf,
 };
 
 use iced::{
-    Element, Center, Fill, Padding, Color,
-    widget::{ column, text, container, Space, button, text_input, TextInput },
+    Element, Center, Fill, Font, Padding, Color, Task,
+    widget::{ column, row, text, container, Space, button, text_input, TextInput },
 };
 
-use crate::utils::vault::create_vault;
+use zeroize::Zeroize;
+use tracing::{ debug, error };
+
+use crate::{
+    dialogs::{ pick_keyfile, create_keyfile },
+    utils::{
+        crypto::SecretString,
+        vault::{ create_vault, get_vault_storage },
+        get_default_vault_name, create_default_vault_file,
+    },
+};
 
 #[derive(Debug, Clone)]
 pub enum Message {
     Page(Page),
     VaultNameChanged(String),
     VaultPasswordChanged(String),
+    PickKeyfile,
+    CreateKeyfile,
+    KeyfileChosen(Result<Option<PathBuf>, String>),
+    RemoveKeyfile,
     CreateVault,
     Restart,
     Exit,
@@ -49,11 +66,15 @@ pub struct FirstStart {
     password_error: bool,
     name_error: bool,
     vault_name: String,
-    vault_password: String,
+    vault_password: SecretString,
+    keyfile: Option<PathBuf>,
+    keyfile_error: Option<String>,
+    create_error: Option<String>,
+    recovery_phrase: SecretString,
 }
 
 impl FirstStart {
-    pub fn view(&self) -> Element<Message> {
+    pub fn view(&self) -> Element<'_, Message> {
         match self.current_page {
             Page::P1 => {
                 let title = text("Welcome to Secure Notes!")
@@ -160,6 +181,81 @@ impl FirstStart {
                     password_row = password_input;
                 }
 
+                let mut keyfile_column = column![];
+
+                match &self.keyfile {
+                    Some(path) => {
+                        keyfile_column = keyfile_column.push(
+                            text!("Keyfile: {}", path.display())
+                                .width(Fill)
+                                .align_x(Center)
+                        );
+
+                        keyfile_column = keyfile_column.push(
+                            text("Keep the keyfile safe and never change it, the vault can't be opened without it.")
+                                .width(Fill)
+                                .size(14)
+                                .align_x(Center)
+                        );
+
+                        keyfile_column = keyfile_column.push(Space::new(Fill, 10));
+
+                        keyfile_column = keyfile_column.push(
+                            container(
+                                button(text("Remove Keyfile"))
+                                    .style(button::secondary)
+                                    .on_press(Message::RemoveKeyfile)
+                            )
+                                .align_x(Center)
+                                .width(Fill)
+                        );
+                    }
+
+                    None => {
+                        keyfile_column = keyfile_column.push(
+                            text("Optionally, the vault can also require a keyfile to be opened.")
+                                .width(Fill)
+                                .align_x(Center)
+                        );
+
+                        keyfile_column = keyfile_column.push(Space::new(Fill, 10));
+
+                        keyfile_column = keyfile_column.push(
+                            container(row![
+                                button(text("Use Existing File"))
+                                    .style(button::secondary)
+                                    .on_press(Message::PickKeyfile),
+                                Space::new(10, 0),
+                                button(text("Create New Keyfile"))
+                                    .style(button::secondary)
+                                    .on_press(Message::CreateKeyfile),
+                            ])
+                                .align_x(Center)
+                                .width(Fill)
+                        );
+                    }
+                }
+
+                if let Some(e) = &self.keyfile_error {
+                    keyfile_column = keyfile_column.push(
+                        text(e)
+                            .width(Fill)
+                            .align_x(Center)
+                            .color(Color::new(0.9, 0.0, 0.0, 1.0))
+                    );
+                }
+
+                if let Some(e) = &self.create_error {
+                    keyfile_column = keyfile_column.push(Space::new(Fill, 10));
+
+                    keyfile_column = keyfile_column.push(
+                        text!("Could not create the vault: {}", e)
+                            .width(Fill)
+                            .align_x(Center)
+                            .color(Color::new(0.9, 0.0, 0.0, 1.0))
+                    );
+                }
+
                 let control_row_padding = Padding::from([ 50, 200 ]);
 
                 column![
@@ -169,6 +265,8 @@ impl FirstStart {
                     name_row,
                     Space::new(Fill, 20),
                     password_row,
+                    Space::new(Fill, 20),
+                    keyfile_column,
                     column![
                         container(
                             button(text("Create Vault"))
@@ -199,6 +297,35 @@ impl FirstStart {
                     .width(Fill)
                     .align_x(Center);
 
+                let recovery_text = text("Write down this recovery phrase and keep it somewhere safe. It's the only way to open the vault if you forget the password.")
+                    .width(Fill)
+                    .align_x(Center);
+
+                let mut recovery_words = column![];
+
+                // Four numbered words per row
+                let words = self.recovery_phrase.split_whitespace()
+                    .enumerate()
+                    .collect::<Vec<_>>();
+
+                for chunk in words.chunks(4) {
+                    let mut words_row = row![];
+
+                    for (i, word) in chunk {
+                        words_row = words_row.push(
+                            text!("{:>2}. {}", i + 1, word)
+                                .font(Font::MONOSPACE)
+                                .width(140)
+                        );
+                    }
+
+                    recovery_words = recovery_words.push(
+                        container(words_row)
+                            .align_x(Center)
+                            .width(Fill)
+                    );
+                }
+
                 let next_step_text = text("Click on restart to restart the app.")
                     .width(Fill)
                     .align_x(Center);
@@ -223,7 +350,11 @@ impl FirstStart {
                     title,
                     Space::new(Fill, 10),
                     success_text,
+                    Space::new(Fill, 20),
+                    recovery_text,
                     Space::new(Fill, 10),
+                    recovery_words,
+                    Space::new(Fill, 20),
                     next_step_text,
                     Space::new(Fill, 10),
                     get_started_button,
@@ -234,7 +365,7 @@ impl FirstStart {
         }
     }
 
-    pub fn update(&mut self, message: Message) {
+    pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::Page(p) => {
                 self.current_page = p;
@@ -246,42 +377,88 @@ impl FirstStart {
             }
 
             Message::VaultPasswordChanged(updated_vault_password) => {
-                self.vault_password = updated_vault_password;
+                self.vault_password = SecretString::new(updated_vault_password);
                 self.password_error = false;
             }
 
-            Message::CreateVault => {
-                if self.vault_name.len() > 32 || self.vault_name.len() < 4 {
-                    self.name_error = true;
-                } else {
-                    self.name_error = false;
-                }
+            Message::PickKeyfile => {
+                return Task::perform(
+                    async { Ok(pick_keyfile().await) },
+                    Message::KeyfileChosen,
+                );
+            }
 
-                if self.vault_password.len() > 32 || self.vault_name.len() < 8 {
-                    self.password_error = true;
-                } else {
-                    self.password_error = false;
+            Message::CreateKeyfile => {
+                return Task::perform(create_keyfile(), Message::KeyfileChosen);
+            }
+
+            Message::KeyfileChosen(result) => {
+                match result {
+                    Ok(Some(path)) => {
+                        self.keyfile = Some(path);
+                        self.keyfile_error = None;
+                    }
+
+                    Ok(None) => {}
+
+                    Err(e) => {
+                        self.keyfile_error = Some(e);
+                    }
                 }
+            }
+
+            Message::RemoveKeyfile => {
+                self.keyfile = None;
+                self.keyfile_error = None;
+            }
+
+            Message::CreateVault => {
+                self.name_error = self.vault_name.len() > 32 || self.vault_name.len() < 4;
+                self.password_error = self.vault_password.len() > 32 || self.vault_name.len() < 8;
 
                 if !(self.name_error && self.password_error) {
-                    match create_vault(self.vault_name.clone(), self.vault_password.clone(), true) {
-                        Ok(_) => {
+                    let result = get_vault_storage(&self.vault_name)
+                        .and_then(|storage| create_vault(
+                            &storage,
+                            self.vault_name.clone(),
+                            &self.vault_password,
+                            self.keyfile.as_deref(),
+                        ))
+                        .and_then(|recovery_phrase| {
+                            // The first vault is opened on start
+                            if get_default_vault_name().is_err() {
+                                create_default_vault_file(&self.vault_name)?;
+                            }
+
+                            Ok(recovery_phrase)
+                        });
+
+                    match result {
+                        Ok(recovery_phrase) => {
+                            self.vault_password.zeroize();
+                            self.recovery_phrase = recovery_phrase;
+                            self.create_error = None;
                             self.current_page = Page::P3;
                         }
 
-                        Err(_) => {}
+                        Err(e) => {
+                            error!(error = %e, "could not create vault");
+                            self.create_error = Some(e.to_string());
+                        }
                     }
                 }
             }
 
             Message::Restart => {
-                let program = args().collect::<String>();
-
-                println!("Opening: {}", program);
-
-                match Command::new(program).spawn() {
+                self.recovery_phrase.zeroize();
+                // Started with the same arguments, which may choose the data
+                // directory
+                match current_exe().and_then(|program| {
+                    debug!(program = %program.display(), "restarting");
+                    Command::new(program).args(args().skip(1)).spawn()
+                }) {
                     Ok(_) => {}
-                    Err(e) => { eprintln!("{}", e); }
+                    Err(e) => { error!(error = %e, "could not restart"); }
                 }
 
                 sleep(Duration::from_millis(1000));
@@ -293,6 +470,8 @@ impl FirstStart {
                 exit(0);
             }
         }
+
+        Task::none()
     }
 }
 
@@ -0,0 +1,653 @@
+///
+/// Highlighting of the Markdown of the opened note in the editor, and of the
+/// code in its fenced code blocks by their language, e.g. "```rust".
+///
+/// The Markdown is highlighted line by line by a small scanner, which is fast
+/// enough for notes of several megabytes. Only the code blocks are parsed with
+/// [syntect](https://github.com/trishume/syntect), with the syntax of their
+/// language. The parts of the note are only sorted into kinds (see
+/// [`Highlight`]), the colors are taken from the app's theme when they are
+/// drawn.
+///
+/// The editor only asks for the lines it shows, and the lines after an edit.
+/// The state of the highlighter is kept every [`LINES_PER_SNAPSHOT`] lines,
+/// so an edit only highlights again the lines from the snapshot before it, and
+/// only down to the lines shown. The state belongs to the text editor, not to
+/// the note it shows, so the note is highlighted again from its start when the
+/// editor shows another tab or pane.
+///
+use std::{ ops::Range, sync::LazyLock };
+
+use iced::{
+    Font, Theme, font,
+    advanced::text::highlighter::{ self, Format },
+};
+use syntect::{
+    easy::ScopeRangeIterator,
+    parsing::{ ParseState, Scope, ScopeStack, SyntaxSet },
+};
+
+/// Lines between two snapshots of the highlighter's state.
+const LINES_PER_SNAPSHOT: usize = 50;
+
+/// Longer lines of code are not highlighted, parsing them would make typing
+/// slow.
+const MAX_CODE_LINE_LENGTH: usize = 4 * 1024;
+
+/// Lines of a code block highlighted with the syntax of its language, the
+/// rest of a longer block is plain code. Parsing a line takes a few tenths of
+/// a millisecond, so scrolling down a long block would freeze the app.
+const MAX_CODE_BLOCK_LINES: usize = 1000;
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// Kinds of the scopes of the code syntaxes, the first that matches a scope
+/// wins.
+static SCOPE_KINDS: LazyLock<Vec<( Scope, Highlight )>> = LazyLock::new(|| {
+    [
+        ( "comment", Highlight::Comment ),
+        ( "string", Highlight::String ),
+        ( "constant", Highlight::Constant ),
+        ( "keyword", Highlight::Keyword ),
+        ( "storage", Highlight::Keyword ),
+        ( "entity.name.function", Highlight::Function ),
+        ( "support.function", Highlight::Function ),
+        ( "variable.function", Highlight::Function ),
+        ( "entity.name", Highlight::Type ),
+        ( "support.type", Highlight::Type ),
+        ( "support.class", Highlight::Type ),
+    ]
+        .into_iter()
+        .filter_map(|( scope, kind )| Scope::new(scope).ok().map(|scope| ( scope, kind )))
+        .collect()
+});
+
+/// Kind of a part of the note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Heading,
+    Strong,
+    Emphasis,
+    Link,
+    ListMarker,
+    Quote,
+
+    /// Inline code and the lines of a code block in an unknown language
+    Code,
+
+    /// Marks of the Markdown syntax, e.g. "#" or "**"
+    Punctuation,
+
+    Comment,
+    String,
+    Constant,
+    Keyword,
+    Function,
+    Type,
+}
+
+impl Highlight {
+    /// Color and font of the kind, from the theme's palette.
+    pub fn to_format(self, theme: &Theme) -> Format<Font> {
+        let palette = theme.extended_palette();
+
+        let ( color, weight, style ) = match self {
+            Highlight::Heading => ( Some(palette.primary.strong.color), font::Weight::Bold, None ),
+            Highlight::Strong => ( None, font::Weight::Bold, None ),
+            Highlight::Emphasis => ( None, font::Weight::Normal, Some(font::Style::Italic) ),
+            Highlight::Link | Highlight::ListMarker => {
+                ( Some(palette.primary.base.color), font::Weight::Normal, None )
+            }
+
+            Highlight::Quote => {
+                ( Some(palette.secondary.strong.color), font::Weight::Normal, Some(font::Style::Italic) )
+            }
+
+            Highlight::Code => ( Some(palette.success.strong.color), font::Weight::Normal, None ),
+            Highlight::Punctuation => ( Some(palette.secondary.base.color), font::Weight::Normal, None ),
+            Highlight::Comment => {
+                ( Some(palette.secondary.base.color), font::Weight::Normal, Some(font::Style::Italic) )
+            }
+
+            Highlight::String => ( Some(palette.success.base.color), font::Weight::Normal, None ),
+            Highlight::Constant => ( Some(palette.danger.base.color), font::Weight::Normal, None ),
+            Highlight::Keyword => ( Some(palette.primary.strong.color), font::Weight::Bold, None ),
+            Highlight::Function => ( Some(palette.primary.base.color), font::Weight::Normal, None ),
+            Highlight::Type => ( Some(palette.success.strong.color), font::Weight::Normal, None ),
+        };
+
+        let font = match ( weight, style ) {
+            ( font::Weight::Normal, None ) => None,
+            ( weight, style ) => Some(Font {
+                weight,
+                style: style.unwrap_or_default(),
+                ..Font::default()
+            }),
+        };
+
+        Format { color, font }
+    }
+
+    /// Kind of the innermost scope of the code that has one.
+    fn of(stack: &ScopeStack) -> Option<Self> {
+        stack.as_slice().iter().rev().find_map(|scope| {
+            SCOPE_KINDS.iter()
+                .find(|( prefix, _ )| prefix.is_prefix_of(*scope))
+                .map(|( _, kind )| *kind)
+        })
+    }
+}
+
+/// Where the highlighter is at the start of a line.
+#[derive(Clone)]
+enum State {
+    Text,
+
+    /// Inside a fenced code block
+    Code {
+        /// The fence, e.g. "```", closed by at least as many of its character
+        fence: char,
+        fence_len: usize,
+
+        /// Parser of the block's language, if it's known and the block isn't
+        /// longer than [`MAX_CODE_BLOCK_LINES`]
+        parser: Option<( ParseState, ScopeStack )>,
+
+        /// Lines of the block so far
+        lines: usize,
+    },
+}
+
+/// Highlights the Markdown of a note, see the module's docs.
+pub struct MarkdownHighlighter {
+    /// State at the start of every [`LINES_PER_SNAPSHOT`]th line
+    snapshots: Vec<State>,
+
+    /// State at the start of the current line
+    state: State,
+    current_line: usize,
+}
+
+impl highlighter::Highlighter for MarkdownHighlighter {
+    /// Ids of the pane and of the tab the note is opened in
+    type Settings = ( usize, usize );
+    type Highlight = Highlight;
+    type Iterator<'a> = std::vec::IntoIter<( Range<usize>, Highlight )>;
+
+    fn new(_settings: &Self::Settings) -> Self {
+        Self { snapshots: vec![], state: State::Text, current_line: 0 }
+    }
+
+    fn update(&mut self, _new_settings: &Self::Settings) {
+        self.snapshots.clear();
+        self.state = State::Text;
+        self.current_line = 0;
+    }
+
+    fn change_line(&mut self, line: usize) {
+        if line >= self.current_line {
+            return;
+        }
+
+        // Go on from the last snapshot before the changed line
+        let snapshot = line / LINES_PER_SNAPSHOT;
+
+        match self.snapshots.get(snapshot) {
+            Some(state) => {
+                self.state = state.clone();
+                self.snapshots.truncate(snapshot + 1);
+                self.current_line = snapshot * LINES_PER_SNAPSHOT;
+            }
+
+            None => {
+                self.snapshots.clear();
+                self.state = State::Text;
+                self.current_line = 0;
+            }
+        }
+    }
+
+    fn highlight_line(&mut self, line: &str) -> Self::Iterator<'_> {
+        if self.current_line.is_multiple_of(LINES_PER_SNAPSHOT)
+            && self.current_line / LINES_PER_SNAPSHOT == self.snapshots.len() {
+            self.snapshots.push(self.state.clone());
+        }
+
+        self.current_line += 1;
+
+        let mut highlights = Highlights::default();
+
+        match &mut self.state {
+            State::Text => {
+                if let Some(state) = highlight_text(line, &mut highlights) {
+                    self.state = state;
+                }
+            }
+
+            State::Code { fence, fence_len, parser, lines } => {
+                if is_closing_fence(line, *fence, *fence_len) {
+                    highlights.push(0..line.len(), Highlight::Punctuation);
+                    self.state = State::Text;
+                } else {
+                    highlight_code(line, parser.as_mut(), &mut highlights);
+
+                    *lines += 1;
+
+                    if *lines == MAX_CODE_BLOCK_LINES {
+                        *parser = None;
+                    }
+                }
+            }
+        }
+
+        highlights.0.into_iter()
+    }
+
+    fn current_line(&self) -> usize {
+        self.current_line
+    }
+}
+
+/// The highlighted parts of a line, in order.
+#[derive(Default)]
+struct Highlights(Vec<( Range<usize>, Highlight )>);
+
+impl Highlights {
+    fn push(&mut self, range: Range<usize>, kind: Highlight) {
+        if range.is_empty() {
+            return;
+        }
+
+        match self.0.last_mut() {
+            Some(( last, last_kind )) if *last_kind == kind && last.end == range.start => {
+                last.end = range.end;
+            }
+
+            _ => { self.0.push(( range, kind )); }
+        }
+    }
+}
+
+/// Highlights a line of Markdown, returns the new state when it opens a code
+/// block.
+fn highlight_text(line: &str, highlights: &mut Highlights) -> Option<State> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+
+    // Indented by 4 spaces, it's code or the text of a list item
+    if indent >= 4 {
+        inline(line, indent, None, highlights);
+        return None;
+    }
+
+    let rest = &line[indent..];
+
+    if let Some(( fence, fence_len )) = fence_of(rest) {
+        let info = rest[fence_len..].trim();
+        let language = info.split_whitespace().next().unwrap_or_default();
+
+        highlights.push(indent..indent + fence_len, Highlight::Punctuation);
+
+        let info_start = line.len() - rest[fence_len..].trim_start().len();
+        highlights.push(info_start..info_start + info.len(), Highlight::Constant);
+
+        let parser = SYNTAXES.find_syntax_by_token(language)
+            .filter(|_| !language.is_empty())
+            .map(|syntax| ( ParseState::new(syntax), ScopeStack::new() ));
+
+        return Some(State::Code { fence, fence_len, parser, lines: 0 });
+    }
+
+    // Heading
+    let level = rest.bytes().take_while(|&b| b == b'#').count();
+
+    if ( 1..=6 ).contains(&level) && rest[level..].chars().next().is_none_or(|c| c == ' ') {
+        highlights.push(indent..indent + level, Highlight::Punctuation);
+        inline(line, indent + level, Some(Highlight::Heading), highlights);
+        return None;
+    }
+
+    // Rule, or the underline of a heading
+    if is_rule(rest) {
+        highlights.push(indent..line.len(), Highlight::Punctuation);
+        return None;
+    }
+
+    // Quote
+    if let Some(quoted) = rest.strip_prefix('>') {
+        highlights.push(indent..indent + 1, Highlight::Punctuation);
+        inline(line, line.len() - quoted.len(), Some(Highlight::Quote), highlights);
+        return None;
+    }
+
+    // List item, maybe a task
+    if let Some(marker_len) = list_marker(rest) {
+        let mut end = indent + marker_len;
+
+        for task in [ "[ ] ", "[x] ", "[X] " ] {
+            if line[end..].starts_with(task) {
+                end += task.len();
+            }
+        }
+
+        highlights.push(indent..end, Highlight::ListMarker);
+        inline(line, end, None, highlights);
+        return None;
+    }
+
+    inline(line, 0, None, highlights);
+
+    None
+}
+
+/// Highlights the emphasis, code spans and links of the text from `start`.
+/// The rest of the text is of the `base` kind.
+fn inline(line: &str, start: usize, base: Option<Highlight>, highlights: &mut Highlights) {
+    let bytes = line.as_bytes();
+    let mut strong = false;
+    let mut emphasis = false;
+    let mut i = start;
+
+    // Start of the text of the current kind
+    let mut text_start = start;
+
+    let kind = |strong: bool, emphasis: bool| {
+        match ( strong, emphasis ) {
+            ( true, _ ) => Some(Highlight::Strong),
+            ( false, true ) => Some(Highlight::Emphasis),
+            _ => base,
+        }
+    };
+
+    let flush = |highlights: &mut Highlights, from: usize, to: usize, strong: bool, emphasis: bool| {
+        if let Some(kind) = kind(strong, emphasis) {
+            highlights.push(from..to, kind);
+        }
+    };
+
+    while i < bytes.len() {
+        match bytes[i] {
+            // Escaped character
+            b'\\' => { i += 2; }
+
+            // Code span, closed by as many backticks
+            b'`' => {
+                let ticks = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+                let fence = &line[i..i + ticks];
+
+                let close = match line[i + ticks..].find(fence) {
+                    Some(close) => i + ticks + close,
+                    None => { i += ticks; continue; }
+                };
+
+                flush(highlights, text_start, i, strong, emphasis);
+                highlights.push(i..i + ticks, Highlight::Punctuation);
+                highlights.push(i + ticks..close, Highlight::Code);
+                highlights.push(close..close + ticks, Highlight::Punctuation);
+
+                i = close + ticks;
+                text_start = i;
+            }
+
+            b'*' | b'_' => {
+                let marker = bytes[i];
+                let count = bytes[i..].iter().take_while(|&&b| b == marker).count();
+
+                // "_" inside a word is just a character, e.g. snake_case
+                let in_word = marker == b'_'
+                    && line[..i].chars().next_back().is_some_and(char::is_alphanumeric)
+                    && line[i + count..].chars().next().is_some_and(char::is_alphanumeric);
+
+                if in_word {
+                    i += count;
+                    continue;
+                }
+
+                flush(highlights, text_start, i, strong, emphasis);
+                highlights.push(i..i + count, Highlight::Punctuation);
+
+                if count >= 2 {
+                    strong = !strong;
+                }
+
+                if count % 2 == 1 {
+                    emphasis = !emphasis;
+                }
+
+                i += count;
+                text_start = i;
+            }
+
+            // Link, "[text](url)" or "![alt](url)"
+            b'[' => {
+                let link = line[i..].find("](")
+                    .and_then(|text_len| {
+                        let url_start = i + text_len + 2;
+                        line[url_start..].find(')').map(|url_len| ( i + text_len, url_start + url_len ))
+                    });
+
+                let ( text_end, url_end ) = match link {
+                    Some(link) => link,
+                    None => { i += 1; continue; }
+                };
+
+                let start = if i > 0 && bytes[i - 1] == b'!' { i - 1 } else { i };
+
+                flush(highlights, text_start, start, strong, emphasis);
+                highlights.push(start..i + 1, Highlight::Punctuation);
+                highlights.push(i + 1..text_end, Highlight::Link);
+                highlights.push(text_end..text_end + 2, Highlight::Punctuation);
+                highlights.push(text_end + 2..url_end, Highlight::Link);
+                highlights.push(url_end..url_end + 1, Highlight::Punctuation);
+
+                i = url_end + 1;
+                text_start = i;
+            }
+
+            // Web address
+            b'h' if line[i..].starts_with("https://") || line[i..].starts_with("http://") => {
+                let end = line[i..].find(|c: char| c.is_whitespace() || c == '>')
+                    .map_or(line.len(), |len| i + len);
+
+                flush(highlights, text_start, i, strong, emphasis);
+                highlights.push(i..end, Highlight::Link);
+
+                i = end;
+                text_start = i;
+            }
+
+            _ => { i += 1; }
+        }
+    }
+
+    flush(highlights, text_start, line.len(), strong, emphasis);
+}
+
+/// Highlights a line of a code block with the syntax of its language.
+fn highlight_code(
+    line: &str,
+    parser: Option<&mut ( ParseState, ScopeStack )>,
+    highlights: &mut Highlights,
+) {
+    let ( parser, stack ) = match parser {
+        Some(parser) if line.len() <= MAX_CODE_LINE_LENGTH => parser,
+        _ => {
+            highlights.push(0..line.len(), Highlight::Code);
+            return;
+        }
+    };
+
+    // The syntaxes expect the lines with their end
+    let line = format!("{}\n", line);
+    let end = line.len() - 1;
+
+    let ops = match parser.parse_line(&line, &SYNTAXES) {
+        Ok(ops) => ops,
+        Err(_) => { return; }
+    };
+
+    for ( range, op ) in ScopeRangeIterator::new(&ops, &line) {
+        if stack.apply(op).is_err() {
+            break;
+        }
+
+        if let Some(kind) = Highlight::of(stack) {
+            highlights.push(range.start.min(end)..range.end.min(end), kind);
+        }
+    }
+}
+
+/// The fence of a code block that `text` opens, its character and length.
+fn fence_of(text: &str) -> Option<( char, usize )> {
+    let fence = text.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let len = text.chars().take_while(|&c| c == fence).count();
+
+    // The language of a "```" block can't have a backtick
+    let valid = len >= 3 && !( fence == '`' && text[len..].contains('`') );
+
+    valid.then_some(( fence, len ))
+}
+
+fn is_closing_fence(line: &str, fence: char, fence_len: usize) -> bool {
+    let text = line.trim();
+    let len = text.chars().take_while(|&c| c == fence).count();
+
+    line.len() - line.trim_start().len() < 4 && len >= fence_len && len == text.chars().count()
+}
+
+/// Whether the text is a rule ("---", "***", "___") or the underline of a
+/// heading ("===").
+fn is_rule(text: &str) -> bool {
+    let mut chars = text.chars().filter(|c| !c.is_whitespace());
+
+    match chars.next() {
+        Some(c @ ( '-' | '*' | '_' )) => chars.clone().count() >= 2 && chars.all(|d| d == c),
+        Some('=') => chars.all(|d| d == '='),
+        _ => false,
+    }
+}
+
+/// Length of the marker of the list item `text` starts, e.g. "- " or "1. ".
+fn list_marker(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+
+    let len = match bytes.first()? {
+        b'-' | b'*' | b'+' => 1,
+        b'0'..=b'9' => {
+            let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+
+            match bytes.get(digits) {
+                Some(b'.' | b')') if digits <= 9 => digits + 1,
+                _ => { return None; }
+            }
+        }
+
+        _ => { return None; }
+    };
+
+    match bytes.get(len) {
+        Some(b' ') => Some(len + 1),
+        None => Some(len),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iced::advanced::text::highlighter::Highlighter;
+
+    /// Highlights `line` as text, with the parts as they are in the line.
+    fn highlight(line: &str) -> Vec<( &str, Highlight )> {
+        let mut highlights = Highlights::default();
+        highlight_text(line, &mut highlights);
+
+        highlights.0.into_iter().map(|( range, kind )| ( &line[range], kind )).collect()
+    }
+
+    #[test]
+    fn highlights_the_blocks() {
+        assert_eq!(highlight("## Tïtle"), vec![
+            ( "##", Highlight::Punctuation ),
+            ( " Tïtle", Highlight::Heading ),
+        ]);
+
+        assert_eq!(highlight("#hashtag"), vec![]);
+        assert_eq!(highlight("> «quoted»"), vec![
+            ( ">", Highlight::Punctuation ),
+            ( " «quoted»", Highlight::Quote ),
+        ]);
+
+        assert_eq!(highlight("- [x] fäit"), vec![ ( "- [x] ", Highlight::ListMarker ) ]);
+        assert_eq!(highlight("12. douze"), vec![ ( "12. ", Highlight::ListMarker ) ]);
+        assert_eq!(highlight(" ---"), vec![ ( "---", Highlight::Punctuation ) ]);
+        assert_eq!(highlight("``` rust"), vec![
+            ( "```", Highlight::Punctuation ),
+            ( "rust", Highlight::Constant ),
+        ]);
+    }
+
+    #[test]
+    fn highlights_the_inline_text() {
+        assert_eq!(highlight("**grüß** and *ça*"), vec![
+            ( "**", Highlight::Punctuation ),
+            ( "grüß", Highlight::Strong ),
+            ( "**", Highlight::Punctuation ),
+            ( "*", Highlight::Punctuation ),
+            ( "ça", Highlight::Emphasis ),
+            ( "*", Highlight::Punctuation ),
+        ]);
+
+        assert_eq!(highlight("`ü` or ``a ` b``"), vec![
+            ( "`", Highlight::Punctuation ),
+            ( "ü", Highlight::Code ),
+            ( "`", Highlight::Punctuation ),
+            ( "``", Highlight::Punctuation ),
+            ( "a ` b", Highlight::Code ),
+            ( "``", Highlight::Punctuation ),
+        ]);
+
+        assert_eq!(highlight("![ïmage](ü.png) at https://exämple.org/ä"), vec![
+            ( "![", Highlight::Punctuation ),
+            ( "ïmage", Highlight::Link ),
+            ( "](", Highlight::Punctuation ),
+            ( "ü.png", Highlight::Link ),
+            ( ")", Highlight::Punctuation ),
+            ( "https://exämple.org/ä", Highlight::Link ),
+        ]);
+    }
+
+    #[test]
+    fn skips_the_escaped_and_the_in_word_markers() {
+        assert_eq!(highlight(r"\*not emphasis\* nor \`code\`"), vec![]);
+        assert_eq!(highlight("snake_case and café_au_lait"), vec![]);
+
+        // The escaped character is longer than a byte
+        assert_eq!(highlight(r"\é*x*"), vec![
+            ( "*", Highlight::Punctuation ),
+            ( "x", Highlight::Emphasis ),
+            ( "*", Highlight::Punctuation ),
+        ]);
+
+        // Nothing is escaped at the end of the line
+        assert_eq!(highlight(r"**é\"), vec![
+            ( "**", Highlight::Punctuation ),
+            ( r"é\", Highlight::Strong ),
+        ]);
+    }
+
+    #[test]
+    fn highlights_the_lines_of_a_code_block() {
+        let mut highlighter = MarkdownHighlighter::new(&( 0, 0 ));
+        let mut highlight_line = |line: &str| highlighter.highlight_line(line).collect::<Vec<_>>();
+
+        assert_eq!(highlight_line("~~~~"), vec![ ( 0..4, Highlight::Punctuation ) ]);
+        assert_eq!(highlight_line("# ünknown"), vec![ ( 0..10, Highlight::Code ) ]);
+        assert_eq!(highlight_line("~~~"), vec![ ( 0..3, Highlight::Code ) ]);
+        assert_eq!(highlight_line("  ~~~~"), vec![ ( 0..6, Highlight::Punctuation ) ]);
+        assert_eq!(highlight_line("*text*"), vec![
+            ( 0..1, Highlight::Punctuation ),
+            ( 1..5, Highlight::Emphasis ),
+            ( 5..6, Highlight::Punctuation ),
+        ]);
+    }
+}
@@ -1,11 +1,22 @@
 mod editor;
-mod utils;
 mod first_start;
-mod types;
+mod change_password;
+mod recovery;
+mod vault_settings;
+mod app_settings;
+mod conflict;
+mod preview;
+mod highlighter;
+mod tabs;
+mod dialogs;
+
+use std::{ env::args, path::PathBuf, process::exit };
+
+use secure_notes_core::{ types, utils };
 
 use crate::{
     editor::Editor, first_start::FirstStart,
-    utils::is_first_start,
+    utils::{ is_first_start, set_data_dir, logging::init_logging },
 };
 
 use iced::{
@@ -13,6 +24,15 @@ use iced::{
 };
 
 fn main() -> IcedResult{
+    if let Some(data_dir) = data_dir_arg() {
+        if let Err(e) = set_data_dir(&data_dir) {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    }
+
+    let _log_guard = init_logging();
+
     if is_first_start() {
         return application("Secure Notes", FirstStart::update, FirstStart::view)
             .run();
@@ -23,3 +43,25 @@ fn main() -> IcedResult{
     .run()
 }
 
+/// Reads the data directory from the `--data-dir <dir>` argument, if any.
+fn data_dir_arg() -> Option<PathBuf> {
+    let mut args = args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if let Some(dir) = arg.strip_prefix("--data-dir=") {
+            return Some(PathBuf::from(dir));
+        }
+
+        if arg == "--data-dir" {
+            match args.next() {
+                Some(dir) => { return Some(PathBuf::from(dir)); }
+                None => {
+                    eprintln!("error: --data-dir needs a directory");
+                    exit(2);
+                }
+            }
+        }
+    }
+
+    None
+}
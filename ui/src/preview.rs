@@ -0,0 +1,703 @@
+///
+/// The Markdown preview of the opened note, shown in a pane next to the
+/// editor.
+///
+/// The note is parsed again whenever its text changes, into the blocks the
+/// preview shows: headings, paragraphs, lists and task lists, code blocks,
+/// quotes, tables and rules. Links open in the browser when clicked.
+///
+/// The preview scrolls along with the editor, and the editor with the
+/// preview: both show the same part of the note, as a fraction of its length.
+///
+use iced::{
+    Alignment, Color, Element, Fill, Font, Task, font,
+    widget::{
+        Column, Row, checkbox, column, container, horizontal_rule, rich_text, row, scrollable, span, text,
+        vertical_rule, text_editor::Content,
+        scrollable::{ RelativeOffset, Viewport },
+    },
+};
+use pulldown_cmark::{ Event, HeadingLevel, Options, Parser, Tag, TagEnd };
+use tracing::warn;
+use zeroize::Zeroize;
+
+/// Height of a line of the editor, relative to the text size.
+const LINE_HEIGHT: f32 = 1.3;
+
+/// Schemes of the links that are opened when clicked.
+const LINK_SCHEMES: [&str; 3] = [ "http://", "https://", "mailto:" ];
+
+/// Color of the links.
+const LINK_COLOR: Color = Color::from_rgb(0.3, 0.55, 0.95);
+
+#[derive(Debug, Clone)]
+pub enum PreviewMessage {
+    LinkClicked(String),
+    Scrolled(Viewport),
+}
+
+pub struct Preview {
+    blocks: Vec<Block>,
+    scroll_id: scrollable::Id,
+
+    /// Line of the note at the top of the editor, as far as the preview can
+    /// tell
+    editor_line: f32,
+
+    /// Lines of the note the editor shows at once
+    visible_lines: f32,
+
+    /// Where the preview is scrolled to, as a fraction of the note
+    offset: f32,
+}
+
+/// A block of the note.
+#[derive(Debug, Clone, PartialEq)]
+enum Block {
+    Heading(HeadingLevel, Vec<Span>),
+    Paragraph(Vec<Span>),
+    Code(String),
+    Quote(Vec<Block>),
+    List {
+        /// Number of the first item of an ordered list
+        start: Option<u64>,
+        items: Vec<ListItem>,
+    },
+    Table {
+        alignments: Vec<pulldown_cmark::Alignment>,
+        head: Vec<Vec<Span>>,
+        rows: Vec<Vec<Vec<Span>>>,
+    },
+    Rule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ListItem {
+    /// Whether the task is done, for the items of a task list
+    task: Option<bool>,
+    blocks: Vec<Block>,
+}
+
+/// Text of a single style.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Span {
+    text: String,
+    style: SpanStyle,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct SpanStyle {
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    code: bool,
+    link: Option<String>,
+}
+
+impl Preview {
+    /// Shows `note`, the text of the opened note.
+    pub fn set_text(&mut self, note: &str) {
+        self.clear();
+        self.blocks = parse(note);
+    }
+
+    /// Forgets the note, e.g. when the vault is locked.
+    pub fn clear(&mut self) {
+        for block in self.blocks.iter_mut() {
+            block.zeroize();
+        }
+
+        self.blocks.clear();
+    }
+
+    /// Scrolls the preview to where the editor shows the note, after
+    /// `scrolled_lines` lines were scrolled in the editor or its cursor moved.
+    pub fn follow_editor(
+        &mut self,
+        scrolled_lines: Option<i32>,
+        content: &Content,
+    ) -> Task<PreviewMessage> {
+        match scrolled_lines {
+            Some(lines) => { self.editor_line += lines as f32; }
+
+            // The editor scrolls to show its cursor
+            None => {
+                let cursor_line = content.cursor_position().0 as f32;
+
+                if cursor_line < self.editor_line {
+                    self.editor_line = cursor_line;
+                } else if cursor_line >= self.editor_line + self.visible_lines {
+                    self.editor_line = cursor_line - self.visible_lines + 1.0;
+                }
+            }
+        }
+
+        let last_line = self.last_editor_line(content);
+        self.editor_line = self.editor_line.clamp(0.0, last_line);
+
+        let offset = match last_line > 0.0 {
+            true => self.editor_line / last_line,
+            false => 0.0,
+        };
+
+        if (offset - self.offset).abs() < f32::EPSILON {
+            return Task::none();
+        }
+
+        self.offset = offset;
+
+        scrollable::snap_to(self.scroll_id.clone(), RelativeOffset { x: 0.0, y: offset })
+    }
+
+    /// Returns the lines to scroll the editor by to show the part of the note
+    /// the preview was scrolled to.
+    pub fn follow_preview(
+        &mut self,
+        viewport: Viewport,
+        text_size: f32,
+        content: &Content,
+    ) -> Option<i32> {
+        self.visible_lines = (viewport.bounds().height / (text_size * LINE_HEIGHT)).max(1.0);
+
+        let offset = match viewport.relative_offset().y {
+            y if y.is_finite() => y,
+            _ => 0.0,
+        };
+
+        // The preview was snapped to where the editor is
+        if (offset - self.offset).abs() < f32::EPSILON {
+            return None;
+        }
+
+        self.offset = offset;
+
+        let line = (offset * self.last_editor_line(content)).round();
+        let lines = line - self.editor_line.round();
+        self.editor_line = line;
+
+        match lines as i32 {
+            0 => None,
+            lines => Some(lines),
+        }
+    }
+
+    /// Last line that can be at the top of the editor.
+    fn last_editor_line(&self, content: &Content) -> f32 {
+        (content.line_count() as f32 - self.visible_lines).max(0.0)
+    }
+
+    pub fn view(&self, text_size: u16) -> Element<'_, PreviewMessage> {
+        let size = text_size as f32;
+
+        scrollable(
+            blocks_view(&self.blocks, size)
+                .padding(16)
+                .width(Fill)
+        )
+            .id(self.scroll_id.clone())
+            .on_scroll(PreviewMessage::Scrolled)
+            .width(Fill)
+            .height(Fill)
+            .into()
+    }
+
+    /// Handles the messages of the preview the editor doesn't need to.
+    pub fn update(&mut self, message: PreviewMessage) {
+        match message {
+            PreviewMessage::LinkClicked(url) => {
+                if !LINK_SCHEMES.iter().any(|scheme| url.starts_with(scheme)) {
+                    warn!(url = %url, "not opening a link that isn't a web or mail link");
+                    return;
+                }
+
+                if let Err(e) = open::that_detached(&url) {
+                    warn!(error = %e, "could not open link");
+                }
+            }
+
+            PreviewMessage::Scrolled(_) => {}
+        }
+    }
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Self {
+            blocks: vec![],
+            scroll_id: scrollable::Id::unique(),
+            editor_line: 0.0,
+            visible_lines: 30.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Block {
+    /// Wipes the text of the note.
+    fn zeroize(&mut self) {
+        match self {
+            Block::Heading(_, spans) | Block::Paragraph(spans) => { zeroize_spans(spans); }
+            Block::Code(code) => { code.zeroize(); }
+            Block::Quote(blocks) => { blocks.iter_mut().for_each(Block::zeroize); }
+
+            Block::List { items, .. } => {
+                for item in items {
+                    item.blocks.iter_mut().for_each(Block::zeroize);
+                }
+            }
+
+            Block::Table { head, rows, .. } => {
+                head.iter_mut().for_each(|cell| zeroize_spans(cell));
+                rows.iter_mut().flatten().for_each(|cell| zeroize_spans(cell));
+            }
+
+            Block::Rule => {}
+        }
+    }
+}
+
+fn zeroize_spans(spans: &mut [Span]) {
+    for span in spans {
+        span.text.zeroize();
+    }
+}
+
+/// Parses the Markdown of a note, with the extensions of GitHub's: tables,
+/// task lists and strikethrough.
+fn parse(note: &str) -> Vec<Block> {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_STRIKETHROUGH;
+
+    let mut builder = Builder {
+        events: Parser::new_ext(note, options),
+        style: SpanStyle::default(),
+        task: None,
+    };
+
+    builder.blocks()
+}
+
+/// Makes the blocks from the parser's events.
+struct Builder<'a> {
+    events: Parser<'a>,
+
+    /// Style of the text being read
+    style: SpanStyle,
+
+    /// Task marker of the list item being read
+    task: Option<bool>,
+}
+
+impl Builder<'_> {
+    /// Reads blocks until the end of the block containing them.
+    fn blocks(&mut self) -> Vec<Block> {
+        let mut blocks = vec![];
+
+        // Text outside of a paragraph, e.g. in the items of a tight list
+        let mut spans = vec![];
+
+        while let Some(event) = self.events.next() {
+            let event = match self.inline(event, &mut spans) {
+                Some(event) => event,
+                None => { continue; }
+            };
+
+            if !spans.is_empty() {
+                blocks.push(Block::Paragraph(std::mem::take(&mut spans)));
+            }
+
+            match event {
+                Event::Start(Tag::Paragraph) => {
+                    blocks.push(Block::Paragraph(self.spans()));
+                }
+
+                Event::Start(Tag::Heading { level, .. }) => {
+                    blocks.push(Block::Heading(level, self.spans()));
+                }
+
+                Event::Start(Tag::CodeBlock(_)) => {
+                    let mut code = String::new();
+
+                    for event in self.events.by_ref() {
+                        match event {
+                            Event::Text(text) => { code.push_str(&text); }
+                            Event::End(_) => { break; }
+                            _ => {}
+                        }
+                    }
+
+                    // The last line ends the block, it's not an empty line
+                    if code.ends_with('\n') {
+                        code.pop();
+                    }
+
+                    blocks.push(Block::Code(code));
+                }
+
+                Event::Start(Tag::BlockQuote(_)) => {
+                    blocks.push(Block::Quote(self.blocks()));
+                }
+
+                Event::Start(Tag::List(start)) => {
+                    blocks.push(Block::List { start, items: self.list_items() });
+                }
+
+                Event::Start(Tag::Table(alignments)) => {
+                    blocks.push(self.table(alignments));
+                }
+
+                Event::Start(Tag::HtmlBlock) => {
+                    let mut html = String::new();
+
+                    for event in self.events.by_ref() {
+                        match event {
+                            Event::Html(text) | Event::Text(text) => { html.push_str(&text); }
+                            Event::End(_) => { break; }
+                            _ => {}
+                        }
+                    }
+
+                    blocks.push(Block::Code(html.trim_end().to_string()));
+                }
+
+                Event::Rule => { blocks.push(Block::Rule); }
+
+                // The contents of other blocks, e.g. footnotes, are shown as
+                // they are
+                Event::Start(_) => { blocks.extend(self.blocks()); }
+
+                Event::End(_) => { break; }
+
+                _ => {}
+            }
+        }
+
+        if !spans.is_empty() {
+            blocks.push(Block::Paragraph(spans));
+        }
+
+        blocks
+    }
+
+    /// Reads the text of a paragraph, heading or table cell until its end.
+    fn spans(&mut self) -> Vec<Span> {
+        let mut spans = vec![];
+
+        while let Some(event) = self.events.next() {
+            if let Some(Event::End(_)) = self.inline(event, &mut spans) {
+                break;
+            }
+        }
+
+        spans
+    }
+
+    /// Adds the text of an inline event to `spans`, returns the event if it
+    /// isn't one.
+    fn inline<'e>(&mut self, event: Event<'e>, spans: &mut Vec<Span>) -> Option<Event<'e>> {
+        let mut push = |text: &str, style: SpanStyle| {
+            match spans.last_mut() {
+                Some(last) if last.style == style => { last.text.push_str(text); }
+                _ => { spans.push(Span { text: text.to_string(), style }); }
+            }
+        };
+
+        match event {
+            Event::Text(text) | Event::InlineHtml(text) => { push(&text, self.style.clone()); }
+            Event::Code(code) => { push(&code, SpanStyle { code: true, ..self.style.clone() }); }
+            Event::FootnoteReference(label) => { push(&format!("[{}]", label), self.style.clone()); }
+            Event::SoftBreak => { push(" ", self.style.clone()); }
+            Event::HardBreak => { push("\n", self.style.clone()); }
+            Event::TaskListMarker(done) => { self.task = Some(done); }
+
+            Event::Start(Tag::Strong) => { self.style.strong = true; }
+            Event::Start(Tag::Emphasis) => { self.style.emphasis = true; }
+            Event::Start(Tag::Strikethrough) => { self.style.strikethrough = true; }
+            Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+                self.style.link = Some(dest_url.to_string());
+            }
+
+            Event::End(TagEnd::Strong) => { self.style.strong = false; }
+            Event::End(TagEnd::Emphasis) => { self.style.emphasis = false; }
+            Event::End(TagEnd::Strikethrough) => { self.style.strikethrough = false; }
+            Event::End(TagEnd::Link | TagEnd::Image) => { self.style.link = None; }
+
+            event => { return Some(event); }
+        }
+
+        None
+    }
+
+    /// Reads the items of a list until its end.
+    fn list_items(&mut self) -> Vec<ListItem> {
+        let mut items = vec![];
+
+        // Task marker of the item containing the list, if it's nested
+        let outer_task = self.task.take();
+
+        while let Some(event) = self.events.next() {
+            match event {
+                Event::Start(Tag::Item) => {
+                    self.task = None;
+
+                    let blocks = self.blocks();
+                    items.push(ListItem { task: self.task.take(), blocks });
+                }
+
+                Event::End(_) => { break; }
+                _ => {}
+            }
+        }
+
+        self.task = outer_task;
+
+        items
+    }
+
+    /// Reads a table until its end.
+    fn table(&mut self, alignments: Vec<pulldown_cmark::Alignment>) -> Block {
+        let mut head = vec![];
+        let mut rows = vec![];
+        let mut cells = vec![];
+
+        while let Some(event) = self.events.next() {
+            match event {
+                Event::Start(Tag::TableCell) => { cells.push(self.spans()); }
+                Event::End(TagEnd::TableHead) => { head = std::mem::take(&mut cells); }
+                Event::End(TagEnd::TableRow) => { rows.push(std::mem::take(&mut cells)); }
+                Event::End(TagEnd::Table) => { break; }
+                _ => {}
+            }
+        }
+
+        Block::Table { alignments, head, rows }
+    }
+}
+
+fn blocks_view(blocks: &[Block], size: f32) -> Column<'_, PreviewMessage> {
+    column(blocks.iter().map(|block| block_view(block, size)))
+        .spacing(size * 0.75)
+}
+
+fn block_view(block: &Block, size: f32) -> Element<'_, PreviewMessage> {
+    match block {
+        Block::Heading(level, spans) => {
+            let scale = match level {
+                HeadingLevel::H1 => 2.0,
+                HeadingLevel::H2 => 1.6,
+                HeadingLevel::H3 => 1.35,
+                HeadingLevel::H4 => 1.15,
+                HeadingLevel::H5 => 1.0,
+                HeadingLevel::H6 => 0.9,
+            };
+
+            spans_view(spans, size * scale, true)
+        }
+
+        Block::Paragraph(spans) => spans_view(spans, size, false),
+
+        Block::Code(code) => {
+            container(
+                text(code)
+                    .font(Font::MONOSPACE)
+                    .size(size * 0.9)
+            )
+                .padding(8)
+                .width(Fill)
+                .style(container::rounded_box)
+                .into()
+        }
+
+        Block::Quote(blocks) => {
+            row![
+                vertical_rule(3),
+                blocks_view(blocks, size),
+            ]
+                .spacing(10)
+                .into()
+        }
+
+        Block::List { start, items } => {
+            column(items.iter().enumerate().map(|( i, item )| {
+                let marker: Element<PreviewMessage> = match ( item.task, start ) {
+                    ( Some(done), _ ) => checkbox("", done).size(size * 0.9).into(),
+                    ( None, Some(start) ) => text!("{}.", start + i as u64).size(size).into(),
+                    ( None, None ) => text("•").size(size).into(),
+                };
+
+                row![
+                    container(marker).width(size * 1.75).align_x(Alignment::End),
+                    blocks_view(&item.blocks, size),
+                ]
+                    .spacing(size * 0.5)
+                    .into()
+            }))
+                .spacing(size * 0.25)
+                .into()
+        }
+
+        Block::Table { alignments, head, rows } => {
+            let mut table = column![
+                table_row(head, alignments, size, true),
+                horizontal_rule(1),
+            ];
+
+            for cells in rows {
+                table = table.push(table_row(cells, alignments, size, false));
+            }
+
+            container(table)
+                .padding(4)
+                .width(Fill)
+                .style(container::bordered_box)
+                .into()
+        }
+
+        Block::Rule => horizontal_rule(1).into(),
+    }
+}
+
+fn table_row<'a>(
+    cells: &'a [Vec<Span>],
+    alignments: &[pulldown_cmark::Alignment],
+    size: f32,
+    bold: bool,
+) -> Row<'a, PreviewMessage> {
+    row(cells.iter().enumerate().map(|( i, cell )| {
+        let align = match alignments.get(i) {
+            Some(pulldown_cmark::Alignment::Center) => Alignment::Center,
+            Some(pulldown_cmark::Alignment::Right) => Alignment::End,
+            _ => Alignment::Start,
+        };
+
+        container(spans_view(cell, size, bold))
+            .padding([ 4, 8 ])
+            .width(Fill)
+            .align_x(align)
+            .into()
+    }))
+}
+
+/// Shows the text in its styles, `bold` for all of it.
+fn spans_view(spans: &[Span], size: f32, bold: bool) -> Element<'_, PreviewMessage> {
+    let spans: Vec<_> = spans.iter().map(|s| {
+        let font = Font {
+            weight: if s.style.strong || bold { font::Weight::Bold } else { font::Weight::Normal },
+            style: if s.style.emphasis { font::Style::Italic } else { font::Style::Normal },
+            ..if s.style.code { Font::MONOSPACE } else { Font::DEFAULT }
+        };
+
+        let span = span(s.text.as_str())
+            .font(font)
+            .strikethrough(s.style.strikethrough);
+
+        let span = match s.style.code {
+            true => span.background(Color::from_rgba(0.5, 0.5, 0.5, 0.2)).padding([ 0, 2 ]),
+            false => span,
+        };
+
+        match &s.style.link {
+            Some(url) => span.link(url.clone()).color(LINK_COLOR).underline(true),
+            None => span,
+        }
+    }).collect();
+
+    Element::from(rich_text(spans).size(size)).map(PreviewMessage::LinkClicked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Span {
+        Span { text: text.to_string(), style: SpanStyle::default() }
+    }
+
+    fn styled(text: &str, style: SpanStyle) -> Span {
+        Span { text: text.to_string(), style }
+    }
+
+    #[test]
+    fn parses_headings_and_paragraphs() {
+        let blocks = parse("# Title\n\nFirst line\nsecond line  \nthird line\n\n---\n");
+
+        assert_eq!(blocks, vec![
+            Block::Heading(HeadingLevel::H1, vec![ plain("Title") ]),
+            Block::Paragraph(vec![ plain("First line second line\nthird line") ]),
+            Block::Rule,
+        ]);
+    }
+
+    #[test]
+    fn parses_the_styles_of_the_text() {
+        let blocks = parse("Some **bold *and* italic**, ~~gone~~, `code` and a [link](https://example.com).");
+
+        let strong = SpanStyle { strong: true, ..SpanStyle::default() };
+        assert_eq!(blocks, vec![ Block::Paragraph(vec![
+            plain("Some "),
+            styled("bold ", strong.clone()),
+            styled("and", SpanStyle { emphasis: true, ..strong.clone() }),
+            styled(" italic", strong),
+            plain(", "),
+            styled("gone", SpanStyle { strikethrough: true, ..SpanStyle::default() }),
+            plain(", "),
+            styled("code", SpanStyle { code: true, ..SpanStyle::default() }),
+            plain(" and a "),
+            styled("link", SpanStyle { link: Some(String::from("https://example.com")), ..SpanStyle::default() }),
+            plain("."),
+        ]) ]);
+    }
+
+    #[test]
+    fn parses_lists_and_task_lists() {
+        let blocks = parse("3. three\n4. four\n\n- [x] done\n- [ ] to do\n  - nested\n");
+
+        assert_eq!(blocks, vec![
+            Block::List { start: Some(3), items: vec![
+                ListItem { task: None, blocks: vec![ Block::Paragraph(vec![ plain("three") ]) ] },
+                ListItem { task: None, blocks: vec![ Block::Paragraph(vec![ plain("four") ]) ] },
+            ] },
+            Block::List { start: None, items: vec![
+                ListItem { task: Some(true), blocks: vec![ Block::Paragraph(vec![ plain("done") ]) ] },
+                ListItem { task: Some(false), blocks: vec![
+                    Block::Paragraph(vec![ plain("to do") ]),
+                    Block::List { start: None, items: vec![
+                        ListItem { task: None, blocks: vec![ Block::Paragraph(vec![ plain("nested") ]) ] },
+                    ] },
+                ] },
+            ] },
+        ]);
+    }
+
+    #[test]
+    fn parses_code_quotes_and_tables() {
+        let blocks = parse("```rust\nfn main() {}\n\n```\n\n> quoted\n\n| a | b |\n|:--|--:|\n| 1 | 2 |\n");
+
+        assert_eq!(blocks, vec![
+            Block::Code(String::from("fn main() {}\n")),
+            Block::Quote(vec![ Block::Paragraph(vec![ plain("quoted") ]) ]),
+            Block::Table {
+                alignments: vec![ pulldown_cmark::Alignment::Left, pulldown_cmark::Alignment::Right ],
+                head: vec![ vec![ plain("a") ], vec![ plain("b") ] ],
+                rows: vec![ vec![ vec![ plain("1") ], vec![ plain("2") ] ] ],
+            },
+        ]);
+    }
+
+    #[test]
+    fn shows_html_as_it_is() {
+        let blocks = parse("<div>\nhello\n</div>\n\nSome <b>inline</b> html\n");
+
+        assert_eq!(blocks, vec![
+            Block::Code(String::from("<div>\nhello\n</div>")),
+            Block::Paragraph(vec![ plain("Some <b>inline</b> html") ]),
+        ]);
+    }
+
+    #[test]
+    fn an_empty_note_has_no_blocks() {
+        assert_eq!(parse(""), vec![]);
+        assert_eq!(parse("\n\n"), vec![]);
+    }
+}
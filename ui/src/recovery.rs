@@ -0,0 +1,232 @@
+///
+/// Screen to open a vault with its recovery phrase when the password was
+/// forgotten.
+///
+/// The user has to set a new password before the vault is opened.
+///
+use iced::{
+    Element, Center, Fill, Color, Task,
+    widget::{ column, row, text, container, Space, button, text_input },
+};
+
+use zeroize::Zeroize;
+
+use crate::{
+    change_password::check_new_password,
+    utils::{
+        crypto::{ SecretString, VaultKey, RECOVERY_PHRASE_WORDS },
+        git::commit_vault,
+        vault::{ recover_vault, get_vault_storage },
+    },
+};
+
+#[derive(Debug, Clone)]
+pub enum RecoveryMessage {
+    PhraseChanged(String),
+    NewPasswordChanged(String),
+    ConfirmPasswordChanged(String),
+    Submit,
+    Done(Result<VaultKey, String>),
+    Cancel,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum RecoveryStatus {
+    #[default]
+    None,
+
+    /// When the recovery phrase is being checked
+    Loading,
+
+    Error(String),
+}
+
+#[derive(Debug, Default)]
+pub struct Recovery {
+    phrase: SecretString,
+    new_password: SecretString,
+    confirm_password: SecretString,
+    pub status: RecoveryStatus,
+}
+
+impl Recovery {
+    /// Wipes everything that was typed.
+    pub fn reset(&mut self) {
+        self.phrase.zeroize();
+        self.new_password.zeroize();
+        self.confirm_password.zeroize();
+        self.status = RecoveryStatus::None;
+    }
+
+    pub fn view(&self) -> Element<'_, RecoveryMessage> {
+        let title = text("Recover Vault")
+            .width(Fill)
+            .size(32)
+            .align_x(Center);
+
+        let password_input = |
+            placeholder,
+            value: &SecretString,
+            on_input: fn(String) -> RecoveryMessage,
+        | {
+            container(
+                text_input(placeholder, value)
+                    .secure(true)
+                    .width(300)
+                    .on_input(on_input)
+                    .on_submit(RecoveryMessage::Submit)
+            )
+                .align_x(Center)
+                .width(Fill)
+        };
+
+        let mut cols = column![
+            Space::new(Fill, 60),
+            title,
+            Space::new(Fill, 20),
+            text!(
+                "Enter the {} words of the recovery phrase you got when the vault was created.",
+                RECOVERY_PHRASE_WORDS,
+            )
+                .width(Fill)
+                .align_x(Center),
+            Space::new(Fill, 10),
+            container(
+                text_input("Recovery Phrase", &self.phrase)
+                    .width(600)
+                    .on_input(RecoveryMessage::PhraseChanged)
+                    .on_submit(RecoveryMessage::Submit)
+            )
+                .align_x(Center)
+                .width(Fill),
+            Space::new(Fill, 20),
+            text("Choose a new password for the vault.")
+                .width(Fill)
+                .align_x(Center),
+            Space::new(Fill, 10),
+            password_input(
+                "New Password",
+                &self.new_password,
+                RecoveryMessage::NewPasswordChanged,
+            ),
+            Space::new(Fill, 10),
+            password_input(
+                "Confirm New Password",
+                &self.confirm_password,
+                RecoveryMessage::ConfirmPasswordChanged,
+            ),
+            Space::new(Fill, 20),
+            container(row![
+                button(text("Recover Vault"))
+                    .style(button::primary)
+                    .on_press_maybe(match self.status {
+                        RecoveryStatus::Loading => None,
+                        _ => Some(RecoveryMessage::Submit),
+                    }),
+                Space::new(10, 0),
+                button(text("Back"))
+                    .style(button::secondary)
+                    .on_press(RecoveryMessage::Cancel),
+            ])
+                .align_x(Center)
+                .width(Fill),
+        ];
+
+        match &self.status {
+            RecoveryStatus::None => {}
+
+            RecoveryStatus::Loading => {
+                cols = cols.push(Space::new(Fill, 16));
+                cols = cols.push(
+                    text("Please wait...")
+                        .align_x(Center)
+                        .width(Fill)
+                );
+            }
+
+            RecoveryStatus::Error(e) => {
+                cols = cols.push(Space::new(Fill, 16));
+                cols = cols.push(
+                    text(e)
+                        .align_x(Center)
+                        .width(Fill)
+                        .color(Color::new(0.9, 0.0, 0.0, 1.0))
+                );
+            }
+        }
+
+        cols.into()
+    }
+
+    /// Updates the screen, `vault_name` is the vault being recovered.
+    pub fn update(
+        &mut self,
+        message: RecoveryMessage,
+        vault_name: Option<&str>,
+    ) -> Task<RecoveryMessage> {
+        match message {
+            RecoveryMessage::PhraseChanged(phrase) => {
+                self.phrase = SecretString::new(phrase);
+            }
+
+            RecoveryMessage::NewPasswordChanged(password) => {
+                self.new_password = SecretString::new(password);
+            }
+
+            RecoveryMessage::ConfirmPasswordChanged(password) => {
+                self.confirm_password = SecretString::new(password);
+            }
+
+            RecoveryMessage::Submit => {
+                if self.status == RecoveryStatus::Loading {
+                    return Task::none();
+                }
+
+                if let Err(e) = check_new_password(&self.new_password, &self.confirm_password) {
+                    self.status = RecoveryStatus::Error(e);
+                    return Task::none();
+                }
+
+                let vault_name = match vault_name {
+                    Some(name) => name.to_string(),
+                    None => {
+                        self.status = RecoveryStatus::Error(String::from("No vault is selected"));
+                        return Task::none();
+                    }
+                };
+
+                let phrase = self.phrase.clone();
+                let new_password = self.new_password.clone();
+
+                self.status = RecoveryStatus::Loading;
+
+                return Task::perform(
+                    async move {
+                        get_vault_storage(&vault_name)
+                            .and_then(|storage| {
+                                let key = recover_vault(&storage, &vault_name, &phrase, &new_password)?;
+                                commit_vault(&storage, "Recover vault")?;
+
+                                Ok(key)
+                            })
+                            .map_err(|e| e.to_string())
+                    },
+                    RecoveryMessage::Done,
+                );
+            }
+
+            RecoveryMessage::Done(result) => {
+                match result {
+                    Ok(_) => { self.reset(); }
+                    Err(e) => { self.status = RecoveryStatus::Error(e); }
+                }
+            }
+
+            RecoveryMessage::Cancel => {
+                self.reset();
+            }
+        }
+
+        Task::none()
+    }
+}
@@ -0,0 +1,571 @@
+///
+/// The tabs above the text editor, one for every opened note.
+///
+/// Every tab keeps its own note: its text, where the cursor is, how far it's
+/// scrolled and whether it has unsaved changes. Pinned tabs are kept before
+/// the others and aren't closed until they are unpinned. The tabs are
+/// reordered by dragging them.
+///
+use std::time::Instant;
+
+use iced::{
+    Background, Center, Color, Element,
+    widget::{
+        button, container, mouse_area, row, scrollable, svg, text,
+        text_editor::{ Action, Content },
+    },
+};
+use tracing::warn;
+
+use crate::{ types::vault_index_entry::VaultIndexEntry, utils::document::NoteDocument };
+
+#[derive(Debug, Clone)]
+pub enum TabMessage {
+    /// Selects the tab and starts dragging it
+    Pressed(usize),
+
+    /// The cursor is over the tab, the dragged tab is moved there
+    Entered(usize),
+    Released,
+    Close(usize),
+    TogglePin(usize),
+    Next,
+    Previous,
+}
+
+/// An opened note.
+pub struct Tab {
+    /// Stays the same when the tabs are reordered
+    pub id: usize,
+    pub note: VaultIndexEntry,
+    pub content: Content,
+
+    /// The note as a CRDT document, the edits of `content` are recorded in it
+    /// as they're made
+    pub document: Option<NoteDocument>,
+
+    /// When the note has changes that aren't saved
+    pub dirty: bool,
+
+    /// When the note was last changed, for saving it automatically
+    pub last_edit: Instant,
+    pub is_pinned: bool,
+}
+
+impl Tab {
+    /// Shows the note's document in the text editor.
+    pub fn set_document(&mut self, mut document: NoteDocument) {
+        self.content = Content::with_text(&document.text());
+
+        // The text editor drops carriage returns and always ends the text with
+        // a line break, the document is given the same text so that the edits
+        // are recorded at the same positions in both
+        let text = self.content.text();
+
+        if document.text() != text {
+            if let Err(e) = document.update(&text) {
+                warn!(note = self.note.id, error = %e, "could not update the note's document");
+            }
+        }
+
+        self.document = Some(document);
+    }
+
+    /// Makes the edit in the text editor and records it in the note's
+    /// document, as the characters it replaced.
+    pub fn perform(&mut self, action: Action) {
+        let is_edit = action.is_edit();
+
+        // An edit replaces the selection or starts at the cursor, which is at
+        // either end of the selection
+        let selection_start = cursor_offset(&self.content)
+            .saturating_sub(self.content.selection().map_or(0, |selection| selection.chars().count()));
+
+        self.content.perform(action);
+
+        let document = match self.document.as_mut() {
+            Some(document) if is_edit => document,
+            _ => { return; }
+        };
+
+        // The cursor ends after the inserted text, the text after it is the end
+        // of the text before the edit
+        let cursor = cursor_offset(&self.content);
+        let start = cursor.min(selection_start);
+        let ( length, inserted ) = text_range(&self.content, start, cursor);
+
+        let end = ( document.len() + cursor ).checked_sub(length)
+            .filter(|end| *end >= start && *end <= document.len());
+
+        let result = match end {
+            Some(end) if end == start && inserted.is_empty() => { return; }
+            Some(end) => document.splice(start, end - start, &inserted),
+
+            None => {
+                warn!(note = self.note.id, "the edit doesn't match the note's document");
+                document.update(&self.content.text())
+            }
+        };
+
+        if let Err(e) = result {
+            warn!(note = self.note.id, error = %e, "could not record the edit in the note's document");
+        }
+    }
+}
+
+/// Position of the cursor in the text editor's text, in characters.
+fn cursor_offset(content: &Content) -> usize {
+    let ( line, index ) = content.cursor_position();
+
+    let before: usize = content.lines().take(line).map(|text| text.chars().count() + 1).sum();
+
+    let column = content.line(line).map_or(0, |text| {
+        text.get(..index).unwrap_or(&text).chars().count()
+    });
+
+    before + column
+}
+
+/// Length in characters of the text editor's text, as given by
+/// `Content::text`, and its characters from `start` to `end`.
+fn text_range(content: &Content, start: usize, end: usize) -> ( usize, String ) {
+    // The text ends with a line break when its last line is empty, as
+    // `Content::text` adds one otherwise
+    let lines = content.line_count();
+    let ends_with_line_break = lines > 1 && content.line(lines - 1).is_some_and(|line| line.is_empty());
+
+    let mut length = 0;
+    let mut range = String::new();
+
+    let mut push = |c: char| {
+        if ( start..end ).contains(&length) {
+            range.push(c);
+        }
+
+        length += 1;
+    };
+
+    for ( i, line ) in content.lines().enumerate() {
+        if i > 0 {
+            push('\n');
+        }
+
+        line.chars().for_each(&mut push);
+    }
+
+    if !ends_with_line_break {
+        push('\n');
+    }
+
+    ( length, range )
+}
+
+#[derive(Default)]
+pub struct Tabs {
+    tabs: Vec<Tab>,
+    active: Option<usize>,
+
+    /// The tab being dragged, while the mouse button is held
+    dragged: Option<usize>,
+    tabs_created: usize,
+}
+
+impl Tabs {
+    /// Opens the note in a new tab after the others, and selects it.
+    pub fn open(&mut self, note: VaultIndexEntry, document: NoteDocument) {
+        let mut tab = Tab {
+            id: self.tabs_created,
+            note,
+            content: Content::new(),
+            document: None,
+            dirty: false,
+            last_edit: Instant::now(),
+            is_pinned: false,
+        };
+
+        tab.set_document(document);
+        self.tabs.push(tab);
+
+        self.tabs_created += 1;
+        self.active = Some(self.tabs.len() - 1);
+    }
+
+    /// Closes the tab, the tab after it is selected if it was the selected
+    /// one.
+    pub fn remove(&mut self, i: usize) -> Option<Tab> {
+        if i >= self.tabs.len() {
+            return None;
+        }
+
+        let tab = self.tabs.remove(i);
+        self.dragged = None;
+
+        self.active = match self.active {
+            _ if self.tabs.is_empty() => None,
+            Some(active) if active > i => Some(active - 1),
+            Some(active) if active == i => Some(i.min(self.tabs.len() - 1)),
+            active => active,
+        };
+
+        Some(tab)
+    }
+
+    /// Closes all the tabs.
+    pub fn clear(&mut self) {
+        self.tabs.clear();
+        self.active = None;
+        self.dragged = None;
+    }
+
+    pub fn active_index(&self) -> Option<usize> {
+        self.active
+    }
+
+    pub fn active(&self) -> Option<&Tab> {
+        self.active.and_then(|i| self.tabs.get(i))
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut Tab> {
+        self.active.and_then(|i| self.tabs.get_mut(i))
+    }
+
+    pub fn get(&self, i: usize) -> Option<&Tab> {
+        self.tabs.get(i)
+    }
+
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut Tab> {
+        self.tabs.get_mut(i)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tab> {
+        self.tabs.iter()
+    }
+
+    /// Index of the tab the note is opened in.
+    pub fn find(&self, note_id: u32) -> Option<usize> {
+        self.tabs.iter().position(|tab| tab.note.id == note_id)
+    }
+
+    /// Handles the messages of the tabs, except closing them: unsaved
+    /// changes are saved by the editor first.
+    pub fn update(&mut self, message: TabMessage) {
+        match message {
+            TabMessage::Pressed(i) => {
+                if i < self.tabs.len() {
+                    self.active = Some(i);
+                    self.dragged = Some(i);
+                }
+            }
+
+            TabMessage::Entered(i) => {
+                let dragged = match self.dragged {
+                    Some(dragged) if dragged != i => dragged,
+                    _ => { return; }
+                };
+
+                // Pinned tabs stay before the others
+                let same_group = match ( self.tabs.get(dragged), self.tabs.get(i) ) {
+                    ( Some(a), Some(b) ) => a.is_pinned == b.is_pinned,
+                    _ => false,
+                };
+
+                if same_group {
+                    self.move_tab(dragged, i);
+                    self.dragged = Some(i);
+                }
+            }
+
+            TabMessage::Released => { self.dragged = None; }
+
+            TabMessage::TogglePin(i) => {
+                let pinned = match self.tabs.get_mut(i) {
+                    Some(tab) => {
+                        tab.is_pinned = !tab.is_pinned;
+                        tab.is_pinned
+                    }
+
+                    None => { return; }
+                };
+
+                // Pinned at the end of the pinned tabs, unpinned right after
+                // them
+                let others_pinned = self.tabs.iter().filter(|tab| tab.is_pinned).count()
+                    - pinned as usize;
+
+                self.move_tab(i, others_pinned);
+            }
+
+            TabMessage::Next => {
+                if let Some(active) = self.active {
+                    self.active = Some(( active + 1 ) % self.tabs.len());
+                }
+            }
+
+            TabMessage::Previous => {
+                if let Some(active) = self.active {
+                    self.active = Some(( active + self.tabs.len() - 1 ) % self.tabs.len());
+                }
+            }
+
+            TabMessage::Close(_) => {}
+        }
+    }
+
+    pub fn view(&self, background: Color, active_background: Color) -> Element<'_, TabMessage> {
+        let mut tabs_row = row![].spacing(2);
+
+        for ( i, tab ) in self.tabs.iter().enumerate() {
+            let icon = match tab.is_pinned {
+                true => "./assets/pushpin-fill.svg",
+                false => "./assets/close-line.svg",
+            };
+
+            let label = row![
+                text!("{}{}", tab.note.name, if tab.dirty { " •" } else { "" })
+                    .size(14),
+                button(svg(icon).width(14).height(14))
+                    .padding(2)
+                    .style(button::text)
+                    .on_press(match tab.is_pinned {
+                        true => TabMessage::TogglePin(i),
+                        false => TabMessage::Close(i),
+                    }),
+            ]
+                .spacing(6)
+                .align_y(Center);
+
+            let background = match self.active == Some(i) {
+                true => active_background,
+                false => background,
+            };
+
+            tabs_row = tabs_row.push(
+                mouse_area(
+                    container(label)
+                        .padding([ 4, 8 ])
+                        .style(move |_| container::Style {
+                            background: Some(Background::Color(background)),
+                            ..container::Style::default()
+                        })
+                )
+                    .on_press(TabMessage::Pressed(i))
+                    .on_release(TabMessage::Released)
+                    .on_enter(TabMessage::Entered(i))
+                    .on_right_press(TabMessage::TogglePin(i))
+                    .on_middle_press(TabMessage::Close(i))
+            );
+        }
+
+        scrollable(tabs_row)
+            .direction(scrollable::Direction::Horizontal(
+                scrollable::Scrollbar::new().width(4).scroller_width(4)
+            ))
+            .into()
+    }
+
+    /// Moves the tab, the selected tab stays selected.
+    fn move_tab(&mut self, from: usize, to: usize) {
+        if from == to || from >= self.tabs.len() || to >= self.tabs.len() {
+            return;
+        }
+
+        let active = self.active.map(|i| self.tabs[i].id);
+
+        let tab = self.tabs.remove(from);
+        self.tabs.insert(to, tab);
+
+        if let Some(id) = active {
+            self.active = self.tabs.iter().position(|tab| tab.id == id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use iced::widget::text_editor::{ Edit, Motion };
+
+    use super::*;
+    use crate::types::note_metadata::NoteMetadata;
+
+    fn note(id: u32) -> VaultIndexEntry {
+        VaultIndexEntry {
+            id,
+            name: format!("Note {}", id),
+            parent_folder: None,
+            metadata: NoteMetadata::default(),
+        }
+    }
+
+    fn tab(text: &str) -> Tab {
+        let mut tabs = Tabs::default();
+        tabs.open(note(1), NoteDocument::from_text(text));
+        tabs.remove(0).unwrap()
+    }
+
+    /// Tabs of the notes 1 to `count`, the last one selected.
+    fn tabs(count: u32) -> Tabs {
+        let mut tabs = Tabs::default();
+
+        for id in 1..=count {
+            tabs.open(note(id), NoteDocument::from_text(""));
+        }
+
+        tabs
+    }
+
+    /// Ids of the notes of the tabs, in order.
+    fn notes(tabs: &Tabs) -> Vec<u32> {
+        tabs.iter().map(|tab| tab.note.id).collect()
+    }
+
+    /// Makes the edits, checking that the document follows the text editor.
+    fn perform(tab: &mut Tab, actions: Vec<Action>) {
+        for action in actions {
+            tab.perform(action);
+            assert_eq!(tab.document.as_ref().unwrap().text(), tab.content.text());
+        }
+    }
+
+    #[test]
+    fn records_the_edits_in_the_document() {
+        let mut tab = tab("Café\r\ncrème");
+        assert_eq!(tab.document.as_ref().unwrap().text(), "Café\ncrème\n");
+
+        perform(&mut tab, vec![
+            Action::Move(Motion::Right),
+            Action::Move(Motion::End),
+            Action::Edit(Edit::Insert('s')),
+            Action::Edit(Edit::Enter),
+            Action::Edit(Edit::Paste(Arc::new(String::from("thé\nau lait")))),
+            Action::Edit(Edit::Backspace),
+            Action::Select(Motion::WordLeft),
+            Action::Edit(Edit::Insert('é')),
+            Action::Move(Motion::DocumentStart),
+            Action::Edit(Edit::Delete),
+            Action::Select(Motion::Down),
+            Action::Edit(Edit::Backspace),
+            Action::Move(Motion::DocumentEnd),
+            Action::Edit(Edit::Enter),
+            Action::Edit(Edit::Enter),
+            Action::Edit(Edit::Backspace),
+            Action::SelectAll,
+            Action::Edit(Edit::Delete),
+        ]);
+    }
+
+    #[test]
+    fn keeps_the_edits_merged_from_another_device() {
+        let mut document = NoteDocument::from_text("Hello world\n");
+        let mut other = NoteDocument::load(&document.save()).unwrap();
+        other.update("Hello world!\n").unwrap();
+
+        let mut tab = tab("");
+        tab.set_document(document);
+        let mut actions = vec![Action::Move(Motion::Right); 5];
+        actions.push(Action::Edit(Edit::Insert(',')));
+        perform(&mut tab, actions);
+
+        let document = tab.document.as_mut().unwrap();
+        document.merge(&mut other).unwrap();
+        assert_eq!(document.text(), "Hello, world!\n");
+    }
+
+    #[test]
+    fn selects_the_opened_tab_and_the_next_one_when_it_is_closed() {
+        let mut tabs = tabs(3);
+        assert_eq!(tabs.len(), 3);
+        assert_eq!(tabs.active_index(), Some(2));
+        assert_eq!(tabs.find(2), Some(1));
+        assert_eq!(tabs.find(4), None);
+
+        // Closing the last tab selects the one before it
+        assert_eq!(tabs.remove(2).unwrap().note.id, 3);
+        assert_eq!(tabs.active().unwrap().note.id, 2);
+
+        // Closing a tab before the selected one keeps it selected
+        tabs.open(note(4), NoteDocument::from_text(""));
+        assert_eq!(tabs.remove(0).unwrap().note.id, 1);
+        assert_eq!(tabs.active().unwrap().note.id, 4);
+
+        // Closing the selected tab selects the one after it
+        tabs.update(TabMessage::Pressed(0));
+        assert!(tabs.remove(5).is_none());
+        assert_eq!(tabs.remove(0).unwrap().note.id, 2);
+        assert_eq!(tabs.active().unwrap().note.id, 4);
+
+        assert_eq!(tabs.remove(0).unwrap().note.id, 4);
+        assert_eq!(tabs.active_index(), None);
+
+        // Ids aren't reused
+        tabs.open(note(5), NoteDocument::from_text(""));
+        assert_eq!(tabs.active().unwrap().id, 4);
+
+        tabs.clear();
+        assert_eq!(tabs.len(), 0);
+        assert!(tabs.active().is_none());
+    }
+
+    #[test]
+    fn selects_the_next_and_previous_tabs() {
+        let mut tabs = Tabs::default();
+        tabs.update(TabMessage::Next);
+        assert_eq!(tabs.active_index(), None);
+
+        let mut tabs = self::tabs(3);
+        tabs.update(TabMessage::Next);
+        assert_eq!(tabs.active_index(), Some(0));
+        tabs.update(TabMessage::Previous);
+        assert_eq!(tabs.active_index(), Some(2));
+        tabs.update(TabMessage::Previous);
+        assert_eq!(tabs.active_index(), Some(1));
+    }
+
+    #[test]
+    fn keeps_the_pinned_tabs_first() {
+        let mut tabs = tabs(4);
+
+        tabs.update(TabMessage::TogglePin(2));
+        assert_eq!(notes(&tabs), vec![ 3, 1, 2, 4 ]);
+        tabs.update(TabMessage::TogglePin(3));
+        assert_eq!(notes(&tabs), vec![ 3, 4, 1, 2 ]);
+        assert_eq!(tabs.active().unwrap().note.id, 4);
+
+        // Unpinned, it goes right after the pinned tabs
+        tabs.update(TabMessage::TogglePin(0));
+        assert_eq!(notes(&tabs), vec![ 4, 3, 1, 2 ]);
+        assert!(tabs.get(0).unwrap().is_pinned);
+        assert!(!tabs.get(1).unwrap().is_pinned);
+        assert_eq!(tabs.active().unwrap().note.id, 4);
+
+        tabs.update(TabMessage::TogglePin(7));
+        assert_eq!(notes(&tabs), vec![ 4, 3, 1, 2 ]);
+    }
+
+    #[test]
+    fn moves_the_dragged_tab_among_its_group() {
+        let mut tabs = tabs(4);
+        tabs.update(TabMessage::TogglePin(0));
+
+        tabs.update(TabMessage::Pressed(3));
+        assert_eq!(tabs.active_index(), Some(3));
+        tabs.update(TabMessage::Entered(2));
+        tabs.update(TabMessage::Entered(1));
+        assert_eq!(notes(&tabs), vec![ 1, 4, 2, 3 ]);
+
+        // Not over the pinned tab
+        tabs.update(TabMessage::Entered(0));
+        assert_eq!(notes(&tabs), vec![ 1, 4, 2, 3 ]);
+        assert_eq!(tabs.active().unwrap().note.id, 4);
+
+        // Not once released
+        tabs.update(TabMessage::Released);
+        tabs.update(TabMessage::Entered(3));
+        assert_eq!(notes(&tabs), vec![ 1, 4, 2, 3 ]);
+    }
+}
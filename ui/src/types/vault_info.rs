@@ -1,8 +0,0 @@
-use serde::{ Serialize, Deserialize };
-
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-pub struct VaultInfo {
-    pub name: String,
-    pub password: String,
-}
-
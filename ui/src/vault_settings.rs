@@ -0,0 +1,380 @@
+///
+/// Settings of the opened vault.
+///
+/// Lists the vault's key slots, the passwords (and the recovery phrase) that
+/// can unlock the vault. Slots can be added with another password, e.g. one
+/// per person for a shared vault, and removed again, except for the last
+/// password.
+///
+use std::path::PathBuf;
+
+use iced::{
+    Element, Center, Fill, Color, Task,
+    widget::{ column, row, text, Space, button, text_input, scrollable },
+};
+
+use zeroize::Zeroize;
+
+use crate::{
+    change_password::check_new_password,
+    dialogs::{ pick_keyfile, create_keyfile },
+    types::key_slot::{ KeySlot, KeySlotKind, KeySlotKdf },
+    utils::{
+        crypto::{ SecretString, VaultKey },
+        git::{ commit_vault, is_git_vault },
+        vault::{ read_vault_info, add_key_slot, remove_key_slot, get_vault_storage },
+    },
+};
+
+#[derive(Debug, Clone)]
+pub enum VaultSettingsMessage {
+    LabelChanged(String),
+    PasswordChanged(String),
+    ConfirmPasswordChanged(String),
+    PickKeyfile,
+    CreateKeyfile,
+    KeyfileChosen(Result<Option<PathBuf>, String>),
+    RemoveKeyfile,
+    AddSlot,
+    SlotAdded(Result<(), String>),
+    RemoveSlot(u32),
+    ChangePassword,
+    Close,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum VaultSettingsStatus {
+    #[default]
+    None,
+
+    /// When a key slot is being added
+    Loading,
+
+    Error(String),
+}
+
+#[derive(Debug, Default)]
+pub struct VaultSettings {
+    slots: Vec<KeySlot>,
+
+    /// When the vault's history is kept in git, where removed slots stay
+    keeps_history: bool,
+
+    // Fields for the new key slot
+    label: String,
+    password: SecretString,
+    confirm_password: SecretString,
+    keyfile: Option<PathBuf>,
+
+    pub status: VaultSettingsStatus,
+}
+
+impl VaultSettings {
+    /// Reads the key slots of the vault and clears the new slot form.
+    pub fn load(&mut self, vault_name: &str) {
+        self.reset();
+
+        let result = get_vault_storage(vault_name).and_then(|storage| {
+            self.keeps_history = is_git_vault(&storage);
+            read_vault_info(&storage)
+        });
+
+        match result {
+            Ok(info) => { self.slots = info.key_slots; }
+            Err(e) => { self.status = VaultSettingsStatus::Error(e.to_string()); }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.slots.clear();
+        self.keeps_history = false;
+        self.label.clear();
+        self.password.zeroize();
+        self.confirm_password.zeroize();
+        self.keyfile = None;
+        self.status = VaultSettingsStatus::None;
+    }
+
+    pub fn view(&self) -> Element<'_, VaultSettingsMessage> {
+        let password_slots = self.slots.iter()
+            .filter(|s| s.kind == KeySlotKind::Password)
+            .count();
+
+        let mut slots_column = column![
+            text("Key Slots").size(24),
+            text("Each key slot is another way to open the vault.").size(14),
+            Space::new(Fill, 10),
+        ];
+
+        if self.keeps_history {
+            slots_column = slots_column.push(
+                text(
+                    "The history of this vault is kept in git: removed and changed passwords \
+                    still unlock it through its old versions. If a password leaked, move the \
+                    notes to a new vault."
+                )
+                    .size(14)
+                    .color(Color::new(0.9, 0.6, 0.0, 1.0))
+            );
+
+            slots_column = slots_column.push(Space::new(Fill, 10));
+        }
+
+        for slot in &self.slots {
+            let kind = match ( slot.kind, slot.uses_keyfile() ) {
+                ( KeySlotKind::Password, false ) => "Password",
+                ( KeySlotKind::Password, true ) => "Password and keyfile",
+                ( KeySlotKind::RecoveryPhrase, _ ) => "Recovery phrase",
+            };
+
+            let kdf = match slot.kdf {
+                KeySlotKdf::Argon2id { m_cost, t_cost, p_cost } => format!(
+                    "Argon2id, {} MiB, {} iterations, {} lanes",
+                    m_cost / 1024, t_cost, p_cost,
+                ),
+
+                KeySlotKdf::HkdfSha256 => String::from("HKDF-SHA256"),
+            };
+
+            let removable = self.slots.len() > 1
+                && !(slot.kind == KeySlotKind::Password && password_slots == 1);
+
+            slots_column = slots_column.push(
+                row![
+                    column![
+                        text(slot.label.as_str()).size(18),
+                        text!("{} ({})", kind, kdf).size(14),
+                    ]
+                        .width(Fill),
+                    button(text("Remove"))
+                        .style(button::danger)
+                        .on_press_maybe(if removable {
+                            Some(VaultSettingsMessage::RemoveSlot(slot.id))
+                        } else {
+                            None
+                        }),
+                ]
+                    .align_y(Center)
+            );
+
+            slots_column = slots_column.push(Space::new(Fill, 8));
+        }
+
+        let keyfile_text = match &self.keyfile {
+            Some(path) => format!("Keyfile: {}", path.display()),
+            None => String::from("No keyfile"),
+        };
+
+        let mut keyfile_buttons = row![
+            button(text("Choose Keyfile"))
+                .style(button::secondary)
+                .on_press(VaultSettingsMessage::PickKeyfile),
+            Space::new(10, 0),
+            button(text("Create New Keyfile"))
+                .style(button::secondary)
+                .on_press(VaultSettingsMessage::CreateKeyfile),
+        ];
+
+        if self.keyfile.is_some() {
+            keyfile_buttons = keyfile_buttons.push(Space::new(10, 0));
+            keyfile_buttons = keyfile_buttons.push(
+                button(text("Remove Keyfile"))
+                    .style(button::secondary)
+                    .on_press(VaultSettingsMessage::RemoveKeyfile)
+            );
+        }
+
+        let mut add_column = column![
+            text("Add a Password").size(24),
+            Space::new(Fill, 10),
+            text_input("Label, e.g. the name of the person", &self.label)
+                .width(300)
+                .on_input(VaultSettingsMessage::LabelChanged),
+            Space::new(Fill, 10),
+            text_input("Password", &self.password)
+                .secure(true)
+                .width(300)
+                .on_input(VaultSettingsMessage::PasswordChanged),
+            Space::new(Fill, 10),
+            text_input("Confirm Password", &self.confirm_password)
+                .secure(true)
+                .width(300)
+                .on_input(VaultSettingsMessage::ConfirmPasswordChanged)
+                .on_submit(VaultSettingsMessage::AddSlot),
+            Space::new(Fill, 10),
+            text(keyfile_text),
+            Space::new(Fill, 10),
+            keyfile_buttons,
+            Space::new(Fill, 10),
+            button(text("Add Password"))
+                .style(button::primary)
+                .on_press_maybe(match self.status {
+                    VaultSettingsStatus::Loading => None,
+                    _ => Some(VaultSettingsMessage::AddSlot),
+                }),
+        ];
+
+        match &self.status {
+            VaultSettingsStatus::None => {}
+
+            VaultSettingsStatus::Loading => {
+                add_column = add_column.push(Space::new(Fill, 10));
+                add_column = add_column.push(text("Please wait..."));
+            }
+
+            VaultSettingsStatus::Error(e) => {
+                add_column = add_column.push(Space::new(Fill, 10));
+                add_column = add_column.push(
+                    text(e).color(Color::new(0.9, 0.0, 0.0, 1.0))
+                );
+            }
+        }
+
+        scrollable(
+            column![
+                text("Vault Settings")
+                    .width(Fill)
+                    .size(32)
+                    .align_x(Center),
+                Space::new(Fill, 20),
+                slots_column,
+                Space::new(Fill, 20),
+                add_column,
+                Space::new(Fill, 20),
+                row![
+                    button(text("Change Password"))
+                        .style(button::secondary)
+                        .on_press(VaultSettingsMessage::ChangePassword),
+                    Space::new(10, 0),
+                    button(text("Back"))
+                        .style(button::secondary)
+                        .on_press(VaultSettingsMessage::Close),
+                ],
+            ]
+                .padding(40)
+                .max_width(800)
+        )
+            .width(Fill)
+            .into()
+    }
+
+    /// Updates the screen.
+    ///
+    /// `vault` is the name and master key of the opened vault, needed to add a
+    /// key slot.
+    pub fn update(
+        &mut self,
+        message: VaultSettingsMessage,
+        vault: Option<(&str, &VaultKey)>,
+    ) -> Task<VaultSettingsMessage> {
+        match message {
+            VaultSettingsMessage::LabelChanged(label) => {
+                self.label = label;
+            }
+
+            VaultSettingsMessage::PasswordChanged(password) => {
+                self.password = SecretString::new(password);
+            }
+
+            VaultSettingsMessage::ConfirmPasswordChanged(password) => {
+                self.confirm_password = SecretString::new(password);
+            }
+
+            VaultSettingsMessage::PickKeyfile => {
+                return Task::perform(
+                    async { Ok(pick_keyfile().await) },
+                    VaultSettingsMessage::KeyfileChosen,
+                );
+            }
+
+            VaultSettingsMessage::CreateKeyfile => {
+                return Task::perform(create_keyfile(), VaultSettingsMessage::KeyfileChosen);
+            }
+
+            VaultSettingsMessage::KeyfileChosen(result) => {
+                match result {
+                    Ok(Some(path)) => { self.keyfile = Some(path); }
+                    Ok(None) => {}
+                    Err(e) => { self.status = VaultSettingsStatus::Error(e); }
+                }
+            }
+
+            VaultSettingsMessage::RemoveKeyfile => {
+                self.keyfile = None;
+            }
+
+            VaultSettingsMessage::AddSlot => {
+                if self.status == VaultSettingsStatus::Loading {
+                    return Task::none();
+                }
+
+                if self.label.trim().is_empty() {
+                    self.status = VaultSettingsStatus::Error(String::from("Label cannot be empty."));
+                    return Task::none();
+                }
+
+                if let Err(e) = check_new_password(&self.password, &self.confirm_password) {
+                    self.status = VaultSettingsStatus::Error(e);
+                    return Task::none();
+                }
+
+                let ( vault_name, key ) = match vault {
+                    Some(( name, key )) => ( name.to_string(), key.clone() ),
+                    None => {
+                        self.status = VaultSettingsStatus::Error(String::from("Vault is locked"));
+                        return Task::none();
+                    }
+                };
+
+                let label = self.label.clone();
+                let password = self.password.clone();
+                let keyfile = self.keyfile.clone();
+
+                self.status = VaultSettingsStatus::Loading;
+
+                return Task::perform(
+                    async move {
+                        get_vault_storage(&vault_name)
+                            .and_then(|storage| {
+                                add_key_slot(&storage, &key, &label, &password, keyfile.as_deref())?;
+                                commit_vault(&storage, "Add key slot")
+                            })
+                            .map(|_| ())
+                            .map_err(|e| e.to_string())
+                    },
+                    VaultSettingsMessage::SlotAdded,
+                );
+            }
+
+            VaultSettingsMessage::SlotAdded(result) => {
+                match ( result, vault ) {
+                    ( Ok(()), Some(( name, _ )) ) => { self.load(name); }
+                    ( Ok(()), None ) => { self.reset(); }
+                    ( Err(e), _ ) => { self.status = VaultSettingsStatus::Error(e); }
+                }
+            }
+
+            VaultSettingsMessage::RemoveSlot(id) => {
+                if let Some(( name, _ )) = vault {
+                    let result = get_vault_storage(name).and_then(|storage| {
+                        remove_key_slot(&storage, id)?;
+                        commit_vault(&storage, &format!("Remove key slot {}", id))
+                    });
+
+                    match result {
+                        Ok(_) => {
+                            self.slots.retain(|s| s.id != id);
+                            self.status = VaultSettingsStatus::None;
+                        }
+
+                        Err(e) => { self.status = VaultSettingsStatus::Error(e.to_string()); }
+                    }
+                }
+            }
+
+            VaultSettingsMessage::ChangePassword | VaultSettingsMessage::Close => {}
+        }
+
+        Task::none()
+    }
+}